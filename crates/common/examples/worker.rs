@@ -7,7 +7,7 @@
 //!
 //! 然后访问 http://127.0.0.1:8787/
 
-use common::workers::{ServerConfig, WorkerServer};
+use common::workers::{AssetsConfig, ServerConfig, WorkerConfig, WorkerServer};
 
 fn main() {
     println!("=== Cloudflare Workers 风格的 Rust 运行时 ===\n");
@@ -15,13 +15,22 @@ fn main() {
     println!("📋 可用测试路由:");
     println!("  - http://127.0.0.1:8787/                (路由列表)");
     println!("  - http://127.0.0.1:8787/hello           (基本响应)");
+    println!("  - ws://127.0.0.1:8787/live              (WebSocket 实时状态)");
+    println!("  - http://127.0.0.1:8787/status.html     (静态资源: 实时状态页面)");
     println!("  - http://127.0.0.1:8787/test-kv         (测试 KV 绑定)");
     println!("  - http://127.0.0.1:8787/test-utils      (测试 UTILS 绑定)");
     println!("  - http://127.0.0.1:8787/test-combo      (测试组合功能)");
     println!("  - http://127.0.0.1:8787/test-no-import  (测试未导入的绑定)");
+    println!("  - http://127.0.0.1:8787/__raven/scheduled?cron=*/5+*+*+*+*  (手动触发定时任务)");
+    println!("  - http://127.0.0.1:8787/__raven/metrics (Prometheus 指标)");
     println!();
-    let mut conf = ServerConfig::default();
-    conf.script_path = "crates/common/examples/workers.js".to_string();
+    let worker = WorkerConfig::new("default", "crates/common/examples/workers.js")
+        .with_route("/*")
+        .with_cron("*/5 * * * *")
+        .with_assets(AssetsConfig::new("crates/common/examples/public"));
+    let mut conf = ServerConfig::default().with_worker(worker);
+    conf.test_scheduled = true;
+    conf.metrics = true;
     if let Ok(mut server) = WorkerServer::new(conf) {
         if let Err(e) = server.run() {
             eprintln!("服务器错误: {}", e);
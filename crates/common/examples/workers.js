@@ -2,13 +2,47 @@
 import { KV } from 'raven/kv'
 import { UTILS } from 'raven/utils'
 
+// 订阅 /live 的 WebSocket 连接
+var subscribers = [];
+
 export default {
+    // Cron 触发器入口（在 ServerConfig.crons 中配置）
+    scheduled(event, env, ctx) {
+        console.log("定时任务触发:", event.cron, event.scheduledTime);
+        KV.put("last-scheduled", event.cron + " @ " + event.scheduledTime);
+
+        // 向所有订阅者推送状态
+        var status = JSON.stringify({ cron: event.cron, time: event.scheduledTime, clients: subscribers.length });
+        subscribers.forEach(function(ws) { ws.send(status); });
+    },
+
     fetch(request, env, ctx) {
         var url = request.url;
         var method = request.method;
 
         console.log("收到请求:", method, url);
 
+        // 实时状态推送（WebSocket）
+        if (url.indexOf("/live") !== -1) {
+            if (request.headers.get("upgrade") !== "websocket") {
+                return new Response("需要 WebSocket 连接", { status: 426 });
+            }
+            var pair = new WebSocketPair();
+            var client = pair[0], server = pair[1];
+            server.accept();
+            server.send(JSON.stringify({ last: KV.get("last-scheduled"), clients: subscribers.length + 1 }));
+            server.addEventListener("message", function(event) {
+                if (event.data === "ping") {
+                    server.send("pong");
+                }
+            });
+            server.addEventListener("close", function() {
+                subscribers.splice(subscribers.indexOf(server), 1);
+            });
+            subscribers.push(server);
+            return new Response(null, { status: 101, webSocket: client });
+        }
+
         // 测试 1: 基本响应
         if (url.indexOf("/hello") !== -1) {
             return new Response("Hello, World!", {
@@ -172,6 +206,7 @@ export default {
         return new Response(
             "🔧 全局绑定测试路由:\n\n" +
             "- /hello           (基本响应)\n" +
+            "- /live            (WebSocket 实时状态)\n" +
             "- /test-kv         (测试全局 KV 绑定)\n" +
             "- /test-utils      (测试全局 UTILS 绑定)\n" +
             "- /test-combo      (测试组合功能)\n" +
@@ -0,0 +1,139 @@
+//! KV 命名空间导入导出工具
+//!
+//! 运行方式:
+//! ```bash
+//! # 按清单中的命名空间 ID 导出到标准输出
+//! cargo run --bin raven-kv -- export sessions --manifest raven.toml > sessions.ndjson
+//!
+//! # 直接指定后端：file:<日志路径> 或 sqlite:<数据库路径>
+//! cargo run --bin raven-kv -- export file:data/cache.log --prefix user: --output users.ndjson
+//! cargo run --bin raven-kv -- import sqlite:data/cache.sqlite --input users.ndjson --mode replace
+//! ```
+//!
+//! 文件后端同一时间只能由一个进程打开：服务器正在使用该命名空间时，导出和导入都会报错，
+//! 需要先停止服务器。SQLite 后端由 SQLite 自身的锁保证写入一致，但运行中的服务器
+//! 收不到导入产生的 watch 事件，导入时同样建议先停止服务器。
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::process;
+use std::sync::Arc;
+
+use common::workers::bindings::{
+    export_ndjson, import_ndjson, FileKvStore, FsyncPolicy, ImportMode, KvStore, SqliteKvStore,
+};
+use common::workers::ServerConfig;
+
+const USAGE: &str = "\
+Usage:
+  raven-kv export <namespace> [--manifest PATH] [--prefix PREFIX] [--output FILE]
+  raven-kv import <namespace> [--manifest PATH] [--mode merge|replace] [--input FILE]
+
+<namespace> is file:PATH, sqlite:PATH, or a namespace ID from the manifest (default raven.toml).";
+
+/// 命令行参数
+struct Args {
+    command: String,
+    namespace: String,
+    manifest: String,
+    prefix: Option<String>,
+    mode: ImportMode,
+    file: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let command = args.next().ok_or("Missing command")?;
+    if command != "export" && command != "import" {
+        return Err(format!("Unknown command '{}'", command));
+    }
+    let namespace = args.next().ok_or("Missing namespace")?;
+
+    let mut parsed = Args {
+        command,
+        namespace,
+        manifest: "raven.toml".to_string(),
+        prefix: None,
+        mode: ImportMode::Merge,
+        file: None,
+    };
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} requires a value", flag))?;
+        match (parsed.command.as_str(), flag.as_str()) {
+            (_, "--manifest") => parsed.manifest = value,
+            ("export", "--prefix") => parsed.prefix = Some(value),
+            ("export", "--output") | ("import", "--input") => parsed.file = Some(value),
+            ("import", "--mode") => parsed.mode = ImportMode::parse(&value)?,
+            _ => return Err(format!("Unknown option {} for {}", flag, parsed.command)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// 打开命名空间的存储
+fn open_store(namespace: &str, manifest: &str) -> Result<Arc<dyn KvStore>, String> {
+    let (backend, path) = match namespace.split_once(':') {
+        Some((backend @ ("file" | "sqlite"), path)) => (backend.to_string(), path.to_string()),
+        _ => {
+            let config = ServerConfig::from_manifest(manifest)?;
+            let ns = config
+                .workers
+                .iter()
+                .flat_map(|worker| &worker.kv_namespaces)
+                .find(|ns| ns.id == namespace)
+                .ok_or_else(|| format!("KV namespace {} is not defined in {}", namespace, manifest))?;
+            let path = ns.path.clone().ok_or_else(|| {
+                format!("KV namespace {} uses the {} backend, which only lives inside the server", namespace, ns.backend)
+            })?;
+            (ns.backend.clone(), path)
+        }
+    };
+
+    if backend == "file" {
+        Ok(FileKvStore::open(&path, FsyncPolicy::Always)?)
+    } else {
+        Ok(SqliteKvStore::open(&path)?)
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let store = open_store(&args.namespace, &args.manifest)?;
+
+    if args.command == "export" {
+        let count = match &args.file {
+            Some(path) => {
+                let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+                export_ndjson(&*store, args.prefix.as_deref(), BufWriter::new(file))?
+            }
+            None => export_ndjson(&*store, args.prefix.as_deref(), io::stdout().lock())?,
+        };
+        eprintln!("Exported {} keys from {}", count, args.namespace);
+    } else {
+        let summary = match &args.file {
+            Some(path) => {
+                let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+                import_ndjson(&*store, BufReader::new(file), args.mode)?
+            }
+            None => import_ndjson(&*store, io::stdin().lock(), args.mode)?,
+        };
+        store.flush()?;
+        eprintln!(
+            "Imported {} keys into {} ({} expired skipped, {} deleted)",
+            summary.imported, args.namespace, summary.expired, summary.deleted
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(args) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
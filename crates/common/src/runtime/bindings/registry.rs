@@ -1,6 +1,7 @@
 //! 绑定注册表和核心 trait 定义
 
 use std::collections::HashMap;
+use std::sync::Mutex;
 use super::value::BindingValue;
 
 /// 绑定方法定义
@@ -56,6 +57,18 @@ pub trait NativeBinding: Send + Sync {
     fn get_property(&self, _name: &str) -> Option<BindingValue> {
         None
     }
+
+    /// 包装 JS 绑定对象的函数源码，如 `(binding) => { ...; return binding; }`
+    ///
+    /// 用于提供无法用原生方法表达的 API，如返回构建器对象的方法
+    fn js_wrapper(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// 把缓冲的数据写入持久化存储（服务器关闭或重新加载前调用）
+    fn flush(&self) -> Result<(), String> {
+        Ok(())
+    }
 }
 
 /// 绑定注册表
@@ -63,6 +76,8 @@ pub trait NativeBinding: Send + Sync {
 /// 管理所有注册的绑定
 pub struct BindingRegistry {
     bindings: HashMap<String, Box<dyn NativeBinding>>,
+    /// (绑定名称, 方法名称) -> 调用次数
+    calls: Mutex<HashMap<(String, String), u64>>,
 }
 
 impl Default for BindingRegistry {
@@ -76,6 +91,7 @@ impl BindingRegistry {
     pub fn new() -> Self {
         Self {
             bindings: HashMap::new(),
+            calls: Mutex::new(HashMap::new()),
         }
     }
 
@@ -106,11 +122,45 @@ impl BindingRegistry {
     /// 调用绑定方法
     pub fn call(&self, binding_name: &str, method: &str, args: Vec<BindingValue>) -> BindingValue {
         match self.bindings.get(binding_name) {
-            Some(binding) => binding.call(method, args),
+            Some(binding) => {
+                if let Ok(mut calls) = self.calls.lock() {
+                    *calls
+                        .entry((binding_name.to_string(), method.to_string()))
+                        .or_insert(0) += 1;
+                }
+                binding.call(method, args)
+            }
             None => BindingValue::Error(format!("Binding '{}' not found", binding_name)),
         }
     }
 
+    /// 各绑定方法的调用次数，按 (绑定名称, 方法名称) 排序
+    pub fn call_counts(&self) -> Vec<(String, String, u64)> {
+        let Ok(calls) = self.calls.lock() else {
+            return Vec::new();
+        };
+        let mut counts: Vec<(String, String, u64)> = calls
+            .iter()
+            .map(|((binding, method), count)| (binding.clone(), method.clone(), *count))
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    /// 刷新所有绑定，返回所有失败绑定的错误信息
+    pub fn flush(&self) -> Result<(), String> {
+        let errors: Vec<String> = self
+            .bindings
+            .iter()
+            .filter_map(|(name, binding)| binding.flush().err().map(|e| format!("{}: {}", name, e)))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
     /// 移除绑定
     pub fn remove(&mut self, name: &str) -> Option<Box<dyn NativeBinding>> {
         self.bindings.remove(name)
@@ -172,5 +222,15 @@ mod tests {
 
         let result = registry.call("MOCK", "echo", vec![BindingValue::String("hello".to_string())]);
         assert_eq!(result.as_string(), Some("hello"));
+
+        registry.call("MOCK", "echo", vec![]);
+        registry.call("MISSING", "test", vec![]);
+        assert_eq!(
+            registry.call_counts(),
+            vec![
+                ("MOCK".to_string(), "echo".to_string(), 2),
+                ("MOCK".to_string(), "test".to_string(), 1),
+            ]
+        );
     }
 }
@@ -46,6 +46,31 @@ impl BindingValue {
             _ => None,
         }
     }
+
+    /// 转换为 JSON 值，字节数组和错误无法转换
+    pub fn to_json(&self) -> Result<serde_json::Value, String> {
+        Ok(match self {
+            BindingValue::Null => serde_json::Value::Null,
+            BindingValue::Bool(b) => serde_json::Value::Bool(*b),
+            BindingValue::Int(i) => serde_json::Value::from(*i),
+            BindingValue::Float(f) => serde_json::Number::from_f64(*f)
+                .map(serde_json::Value::Number)
+                .ok_or("Value contains a non-finite number")?,
+            BindingValue::String(s) => serde_json::Value::String(s.clone()),
+            BindingValue::Json(j) => serde_json::from_str(j).map_err(|e| e.to_string())?,
+            BindingValue::Array(items) => serde_json::Value::Array(
+                items.iter().map(BindingValue::to_json).collect::<Result<_, _>>()?,
+            ),
+            BindingValue::Object(fields) => serde_json::Value::Object(
+                fields
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), v.to_json()?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            BindingValue::Bytes(_) => return Err("Binary values cannot be converted to JSON".to_string()),
+            BindingValue::Error(e) => return Err(e.clone()),
+        })
+    }
 }
 
 impl fmt::Display for BindingValue {
@@ -3,8 +3,13 @@
 //! 提供基础的 JS 执行环境和绑定管理，不包含特定应用逻辑。
 
 use boa_engine::{
-    js_string, object::ObjectInitializer, property::Attribute, Context, JsString, JsValue,
-    NativeFunction, Source,
+    js_string,
+    object::{
+        builtins::{AlignedVec, JsArray, JsArrayBuffer, JsTypedArray},
+        ObjectInitializer,
+    },
+    property::Attribute,
+    Context, JsNativeError, JsObject, JsResult, JsString, JsValue, NativeFunction, Source,
 };
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
@@ -56,6 +61,11 @@ pub fn js_to_binding_value(value: &JsValue, context: &mut Context) -> BindingVal
     } else if let Some(s) = value.as_string() {
         BindingValue::String(s.to_std_string_escaped())
     } else if let Some(obj) = value.as_object() {
+        // ArrayBuffer / TypedArray 作为字节数组
+        if let Ok(Some(bytes)) = js_to_bytes(value, context) {
+            return BindingValue::Bytes(bytes);
+        }
+
         // 检查是否是数组
         if obj.is_array() {
             let length = obj
@@ -99,33 +109,24 @@ pub fn binding_value_to_js(value: BindingValue, context: &mut Context) -> JsValu
         BindingValue::Float(f) => JsValue::from(f),
         BindingValue::String(s) => JsValue::from(js_string!(s)),
         BindingValue::Bytes(b) => {
-            // 转换为 Uint8Array 或者字符串
-            match String::from_utf8(b) {
-                Ok(s) => JsValue::from(js_string!(s)),
-                Err(e) => JsValue::from(js_string!(format!(
-                    "<binary data: {} bytes>",
-                    e.into_bytes().len()
-                ))),
-            }
+            // 转换为 ArrayBuffer
+            JsArrayBuffer::from_byte_block(AlignedVec::from_slice(0, &b), context)
+                .map(JsValue::from)
+                .unwrap_or_else(|_| JsValue::null())
+        }
+        BindingValue::Json(j) => {
+            // 解析为 JS 值，不是合法 JSON 时保留原字符串
+            serde_json::from_str(&j)
+                .ok()
+                .and_then(|json| JsValue::from_json(&json, context).ok())
+                .unwrap_or_else(|| JsValue::from(js_string!(j)))
         }
-        BindingValue::Json(j) => JsValue::from(js_string!(j)),
         BindingValue::Array(arr) => {
-            let length = arr.len();
-            let js_arr = ObjectInitializer::new(context).build();
-            for (i, item) in arr.into_iter().enumerate() {
-                js_arr
-                    .set(i as u32, binding_value_to_js(item, context), false, context)
-                    .ok();
-            }
-            js_arr
-                .set(
-                    js_string!("length"),
-                    JsValue::from(length as f64),
-                    false,
-                    context,
-                )
-                .ok();
-            JsValue::from(js_arr)
+            let items: Vec<JsValue> = arr
+                .into_iter()
+                .map(|item| binding_value_to_js(item, context))
+                .collect();
+            JsValue::from(JsArray::from_iter(items, context))
         }
         BindingValue::Object(obj) => {
             let js_obj = ObjectInitializer::new(context).build();
@@ -148,6 +149,31 @@ pub fn binding_value_to_js(value: BindingValue, context: &mut Context) -> JsValu
     }
 }
 
+/// 读取 ArrayBuffer / TypedArray 中的字节，其他类型返回 `None`
+pub fn js_to_bytes(value: &JsValue, context: &mut Context) -> JsResult<Option<Vec<u8>>> {
+    let Some(obj) = value.as_object() else {
+        return Ok(None);
+    };
+
+    if let Ok(buffer) = JsArrayBuffer::from_object(obj.clone()) {
+        return Ok(Some(buffer.data().map(|d| d.to_vec()).unwrap_or_default()));
+    }
+
+    if let Ok(array) = JsTypedArray::from_object(obj.clone()) {
+        let offset = array.byte_offset(context)?;
+        let length = array.byte_length(context)?;
+        let buffer = array.buffer(context)?;
+        let data = buffer
+            .as_object()
+            .and_then(|b| JsArrayBuffer::from_object(b.clone()).ok())
+            .and_then(|b| b.data().map(|d| d[offset..offset + length].to_vec()))
+            .unwrap_or_default();
+        return Ok(Some(data));
+    }
+
+    Ok(None)
+}
+
 /// 核心 JavaScript 运行时
 ///
 /// 提供基础的 JS 执行环境，不包含特定应用的入口逻辑（如 fetch）
@@ -261,6 +287,15 @@ impl JsRuntime {
         self.imported_bindings.clear();
         
         for (imported_name, module_path) in &imports {
+            // 已手动注册的同名绑定（如清单中声明的 KV 命名空间）优先
+            if self.bindings.read().unwrap().contains(imported_name) {
+                if !self.imported_bindings.contains(imported_name) {
+                    self.imported_bindings.push(imported_name.clone());
+                    println!("  ✓ {} 使用已注册的绑定", imported_name);
+                }
+                continue;
+            }
+
             // 根据导入名称和模块路径创建绑定实例
             if let Some(binding) = create_binding_from_module(imported_name, module_path) {
                 // 注册绑定（使用导入的名称）
@@ -311,61 +346,11 @@ impl JsRuntime {
         
         println!("\n🔧 注入导入的绑定到全局作用域...");
         
-        for binding_name in &self.imported_bindings {
-            // 获取绑定的所有方法
-            let methods: Vec<(String, i32)> = {
-                let registry = self.bindings.read().unwrap();
-                if let Some(binding) = registry.get(binding_name) {
-                    binding
-                        .methods()
-                        .iter()
-                        .map(|m| (m.name.clone(), m.arity))
-                        .collect()
-                } else {
-                    vec![]
-                }
-            };
-
-            if methods.is_empty() {
+        for binding_name in self.imported_bindings.clone() {
+            let Some(binding_obj) = self.create_binding_object(&binding_name) else {
                 eprintln!("⚠️  绑定 {} 没有方法", binding_name);
                 continue;
-            }
-
-            // 创建绑定对象
-            let binding_obj = ObjectInitializer::new(&mut self.context).build();
-
-            // 为每个方法创建 JS 函数
-            for (method_name, _arity) in methods {
-                let binding_name_clone = binding_name.clone();
-                let method_name_clone = method_name.clone();
-
-                // 使用 from_closure 创建捕获闭包的原生函数
-                let method_fn = unsafe {
-                    NativeFunction::from_closure(move |_, args, ctx| {
-                        // 将 JS 参数转换为 BindingValue
-                        let binding_args: Vec<BindingValue> = args
-                            .iter()
-                            .map(|arg| js_to_binding_value(arg, ctx))
-                            .collect();
-
-                        // 调用绑定方法
-                        let result =
-                            call_binding(&binding_name_clone, &method_name_clone, binding_args);
-
-                        // 将结果转换回 JsValue
-                        Ok(binding_value_to_js(result, ctx))
-                    })
-                };
-
-                binding_obj
-                    .set(
-                        JsString::from(method_name.as_str()),
-                        method_fn.to_js_function(self.context.realm()),
-                        false,
-                        &mut self.context,
-                    )
-                    .ok();
-            }
+            };
 
             // 注册到全局作用域
             self.context
@@ -374,12 +359,81 @@ impl JsRuntime {
                     binding_obj,
                     Attribute::all(),
                 )
-                .expect(&format!("Failed to register global binding: {}", binding_name));
+                .unwrap_or_else(|_| panic!("Failed to register global binding: {}", binding_name));
 
             println!("  ✓ {} 已注入全局作用域", binding_name);
         }
     }
 
+    /// 为已注册的绑定创建 JS 对象
+    ///
+    /// 对象上的每个方法都会转发到绑定注册表，绑定不存在或没有方法时返回 `None`
+    pub fn create_binding_object(&mut self, binding_name: &str) -> Option<JsObject> {
+        // 获取绑定的所有方法
+        let (methods, wrapper): (Vec<(String, i32)>, _) = {
+            let registry = self.bindings.read().unwrap();
+            let binding = registry.get(binding_name)?;
+            let methods = binding.methods().iter().map(|m| (m.name.clone(), m.arity)).collect();
+            (methods, binding.js_wrapper())
+        };
+
+        if methods.is_empty() {
+            return None;
+        }
+
+        // 创建绑定对象
+        let binding_obj = ObjectInitializer::new(&mut self.context).build();
+
+        // 为每个方法创建 JS 函数
+        for (method_name, _arity) in methods {
+            let binding_name_clone = binding_name.to_string();
+            let method_name_clone = method_name.clone();
+
+            // 使用 from_closure 创建捕获闭包的原生函数
+            let method_fn = unsafe {
+                NativeFunction::from_closure(move |_, args, ctx| {
+                    // 将 JS 参数转换为 BindingValue
+                    let binding_args: Vec<BindingValue> = args
+                        .iter()
+                        .map(|arg| js_to_binding_value(arg, ctx))
+                        .collect();
+
+                    // 调用绑定方法
+                    let result =
+                        call_binding(&binding_name_clone, &method_name_clone, binding_args);
+
+                    // 将结果转换回 JsValue
+                    Ok(binding_value_to_js(result, ctx))
+                })
+            };
+
+            binding_obj
+                .set(
+                    JsString::from(method_name.as_str()),
+                    method_fn.to_js_function(self.context.realm()),
+                    false,
+                    &mut self.context,
+                )
+                .ok();
+        }
+
+        if let Some(wrapper) = wrapper {
+            let wrapped = self.context.eval(Source::from_bytes(wrapper)).and_then(|wrap| {
+                let wrap = wrap
+                    .as_callable()
+                    .ok_or_else(|| JsNativeError::typ().with_message("binding wrapper is not a function"))?;
+                wrap.call(&JsValue::undefined(), &[binding_obj.clone().into()], &mut self.context)
+            });
+            match wrapped.map(|w| w.as_object()) {
+                Ok(Some(wrapped)) => return Some(wrapped),
+                Ok(None) => eprintln!("⚠️  Wrapper of binding {} did not return an object", binding_name),
+                Err(e) => eprintln!("⚠️  Failed to wrap binding {}: {}", binding_name, e),
+            }
+        }
+
+        Some(binding_obj)
+    }
+
     /// 设置当前线程的绑定上下文
     pub fn set_bindings_context(&self) {
         set_current_bindings(Arc::clone(&self.bindings));
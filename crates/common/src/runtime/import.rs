@@ -1,7 +1,7 @@
 //! Import 语句解析和绑定创建
 
 use super::bindings::NativeBinding;
-use crate::workers::bindings::{KvBinding, UtilsBinding};
+use crate::workers::bindings::{CryptoBinding, KvBinding, QueueBinding, UtilsBinding};
 use crate::operator::{
     UserManagerBinding, 
     GroupManagerBinding, 
@@ -56,20 +56,32 @@ pub fn parse_imports(script: &str) -> Vec<(String, String)> {
 ///
 /// # 支持的模块
 ///
-/// - `raven/kv` -> `KV` 键值存储
+/// - `raven/kv` -> `KV` 键值存储。清单中声明了同名绑定时使用按命名空间 ID 共享的存储
+///   （见 `KvRegistry`），走不到这里；未声明时创建运行时私有的内存存储
+/// - `raven/queue` -> 消息队列，队列名与导入名称相同
 /// - `raven/utils` -> `UTILS` 工具函数
+/// - `raven/crypto` -> `CRYPTO` HMAC、AES-256-GCM、Ed25519 和密码哈希
 /// - `raven/identity` -> `UserManager`, `GroupManager`, `PermissionManager`, `SudoManager` 用户和权限管理
 /// - `raven/db` -> `DB` 数据库（未实现）
 pub fn create_binding_from_module(imported_name: &str, module_path: &str) -> Option<Box<dyn NativeBinding>> {
     match module_path {
         "raven/kv" => {
+            println!("  ⚠️ {} 未在 kv_namespaces 中声明，使用私有的内存存储", imported_name);
             let binding = Box::new(KvBinding::memory(imported_name));
             Some(binding)
         },
+        "raven/queue" => {
+            let binding = Box::new(QueueBinding::memory(imported_name));
+            Some(binding)
+        },
         "raven/utils" => {
             let binding = Box::new(UtilsBinding::new(imported_name));
             Some(binding)
         },
+        "raven/crypto" => {
+            let binding = Box::new(CryptoBinding::new(imported_name));
+            Some(binding)
+        },
         "raven/identity" => {
             // 根据导入的名称创建相应的 Manager
             match imported_name {
@@ -130,12 +142,24 @@ mod tests {
         assert!(result.is_some());
     }
 
+    #[test]
+    fn test_create_queue_binding() {
+        let result = create_binding_from_module("JOBS", "raven/queue");
+        assert!(result.is_some());
+    }
+
     #[test]
     fn test_create_utils_binding() {
         let result = create_binding_from_module("UTILS", "raven/utils");
         assert!(result.is_some());
     }
 
+    #[test]
+    fn test_create_crypto_binding() {
+        let result = create_binding_from_module("CRYPTO", "raven/crypto");
+        assert!(result.is_some());
+    }
+
     #[test]
     fn test_create_identity_bindings() {
         assert!(create_binding_from_module("UserManager", "raven/identity").is_some());
@@ -11,5 +11,5 @@ pub mod bindings;
 mod core;
 mod import;
 
-pub use core::JsRuntime;
+pub use core::{get_current_bindings, js_to_bytes, set_current_bindings, JsRuntime};
 pub use import::{parse_imports, create_binding_from_module};
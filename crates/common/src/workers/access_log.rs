@@ -0,0 +1,176 @@
+//! 访问日志
+//!
+//! 每个请求处理完成后输出一行访问日志，支持 Apache combined 格式和 JSON 格式。
+//! combined 格式在末尾追加处理请求的 Worker 名称和耗时（秒）。
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use super::http::HttpRequest;
+
+/// 访问日志格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessLogFormat {
+    /// 不输出访问日志
+    Off,
+    /// Apache combined 格式，末尾追加 Worker 名称和耗时
+    #[default]
+    Combined,
+    /// 每行一个 JSON 对象
+    Json,
+}
+
+impl AccessLogFormat {
+    /// 从配置字符串解析（`off` / `combined` / `json`）
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "off" | "none" => Ok(Self::Off),
+            "combined" => Ok(Self::Combined),
+            "json" => Ok(Self::Json),
+            other => Err(format!("Unknown access log format: '{}'", other)),
+        }
+    }
+}
+
+/// 一条访问日志
+pub struct AccessLogEntry<'a> {
+    pub request: &'a HttpRequest,
+    /// 客户端 IP（经受信任代理转发时为原始客户端地址）
+    pub client_ip: Option<IpAddr>,
+    pub status: u16,
+    /// 响应 body 的字节数
+    pub bytes: usize,
+    /// 从收到请求到生成响应的耗时
+    pub latency: Duration,
+    /// 处理请求的 Worker，内部路径或未匹配时为 `None`
+    pub worker: Option<&'a str>,
+    /// 收到请求的时间
+    pub time: DateTime<Utc>,
+}
+
+impl AccessLogEntry<'_> {
+    /// 按指定格式生成日志行，`Off` 时返回 `None`
+    pub fn format(&self, format: AccessLogFormat) -> Option<String> {
+        match format {
+            AccessLogFormat::Off => None,
+            AccessLogFormat::Combined => Some(self.combined()),
+            AccessLogFormat::Json => Some(self.json()),
+        }
+    }
+
+    fn remote_addr(&self) -> String {
+        self.client_ip
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.request.headers.get(name).map(|v| v.as_str())
+    }
+
+    fn combined(&self) -> String {
+        let quoted = |value: Option<&str>| match value {
+            Some(v) => format!("\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\"")),
+            None => "\"-\"".to_string(),
+        };
+        let request_line = format!(
+            "{} {} {}",
+            self.request.method, self.request.path, self.request.version
+        );
+
+        format!(
+            "{} - - [{}] {} {} {} {} {} {} {:.6}",
+            self.remote_addr(),
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            quoted(Some(&request_line)),
+            self.status,
+            self.bytes,
+            quoted(self.header("referer")),
+            quoted(self.header("user-agent")),
+            self.worker.unwrap_or("-"),
+            self.latency.as_secs_f64(),
+        )
+    }
+
+    fn json(&self) -> String {
+        json!({
+            "time": self.time.to_rfc3339(),
+            "remote_addr": self.client_ip.map(|ip| ip.to_string()),
+            "method": self.request.method,
+            "path": self.request.path,
+            "version": self.request.version,
+            "host": self.header("host"),
+            "status": self.status,
+            "bytes": self.bytes,
+            "latency_ms": self.latency.as_secs_f64() * 1000.0,
+            "worker": self.worker,
+            "referer": self.header("referer"),
+            "user_agent": self.header("user-agent"),
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    fn entry(request: &HttpRequest) -> AccessLogEntry<'_> {
+        AccessLogEntry {
+            request,
+            client_ip: request.remote_addr.map(|addr| addr.ip()),
+            status: 200,
+            bytes: 42,
+            latency: Duration::from_millis(15),
+            worker: Some("api"),
+            time: Utc.with_ymd_and_hms(2024, 3, 5, 8, 9, 10).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_combined_format() {
+        let mut headers = HashMap::new();
+        headers.insert("user-agent".to_string(), "curl/8.0 \"x\"".to_string());
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/api/users?id=1".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers,
+            remote_addr: Some("10.0.0.7:51234".parse().unwrap()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            entry(&request).format(AccessLogFormat::Combined).unwrap(),
+            "10.0.0.7 - - [05/Mar/2024:08:09:10 +0000] \"GET /api/users?id=1 HTTP/1.1\" 200 42 \"-\" \"curl/8.0 \\\"x\\\"\" api 0.015000"
+        );
+        assert_eq!(entry(&request).format(AccessLogFormat::Off), None);
+    }
+
+    #[test]
+    fn test_json_format() {
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            path: "/submit".to_string(),
+            version: "HTTP/1.1".to_string(),
+            ..Default::default()
+        };
+
+        let line = entry(&request).format(AccessLogFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["method"], "POST");
+        assert_eq!(value["status"], 200);
+        assert_eq!(value["bytes"], 42);
+        assert_eq!(value["worker"], "api");
+        assert_eq!(value["latency_ms"], 15.0);
+        assert!(value["remote_addr"].is_null());
+
+        assert_eq!(AccessLogFormat::parse("JSON").unwrap(), AccessLogFormat::Json);
+        assert!(AccessLogFormat::parse("xml").is_err());
+    }
+}
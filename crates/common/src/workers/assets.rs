@@ -0,0 +1,488 @@
+//! 静态资源
+//!
+//! 为 Worker 配置资源目录后，`WorkerServer` 会在调用 `fetch` 之前直接返回匹配的文件；
+//! Worker 也可以通过 `env.ASSETS.fetch(request)` 主动读取资源（回退或覆盖）。
+//!
+//! 支持 ETag / `If-None-Match`、`If-Modified-Since`、单段 `Range` 请求、
+//! 按扩展名推断 MIME 类型，以及预压缩的 `.br` / `.gz` 文件。
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use boa_engine::{
+    js_string,
+    object::{builtins::{AlignedVec, JsArrayBuffer}, ObjectInitializer},
+    property::Attribute,
+    Context, JsArgs, JsNativeError, JsObject, JsResult, JsString, JsValue, NativeFunction,
+};
+use chrono::{DateTime, Utc};
+
+use super::compression::{self, ContentEncoding};
+use super::http::{status_text, HttpRequest, HttpResponse};
+use super::workers_runtime::js_to_bytes;
+
+/// 预压缩文件：(编码, 文件扩展名)，按优先级排列
+const PRECOMPRESSED: [(ContentEncoding, &str); 2] = [(ContentEncoding::Brotli, "br"), (ContentEncoding::Gzip, "gz")];
+
+/// 静态资源目录
+#[derive(Debug, Clone)]
+pub struct AssetStore {
+    root: PathBuf,
+}
+
+impl AssetStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// 资源目录
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 处理请求，没有匹配的文件（或不是 GET/HEAD 请求）时返回 `None`
+    pub fn serve(&self, request: &HttpRequest) -> Option<HttpResponse> {
+        let head = request.method.eq_ignore_ascii_case("HEAD");
+        if !head && !request.method.eq_ignore_ascii_case("GET") {
+            return None;
+        }
+
+        let file = self.resolve(&request.decoded_path())?;
+
+        // 按 Accept-Encoding 的权重选择预压缩版本（`q=0` 表示不接受）
+        let variants: Vec<ContentEncoding> = PRECOMPRESSED
+            .iter()
+            .filter(|(_, ext)| compressed_path(&file, ext).is_file())
+            .map(|(encoding, _)| *encoding)
+            .collect();
+        let has_variants = !variants.is_empty();
+        let encoding = request
+            .headers
+            .get("accept-encoding")
+            .and_then(|accept| compression::negotiate(accept, &variants));
+
+        let served = match encoding {
+            Some(encoding) => {
+                let ext = PRECOMPRESSED
+                    .iter()
+                    .find(|(e, _)| *e == encoding)
+                    .map(|(_, ext)| *ext)
+                    .expect("negotiated from PRECOMPRESSED");
+                compressed_path(&file, ext)
+            }
+            None => file.clone(),
+        };
+
+        let metadata = fs::metadata(&served).ok()?;
+        let len = metadata.len();
+        let modified: Option<DateTime<Utc>> = metadata.modified().ok().map(DateTime::from);
+        let etag = format!(
+            "\"{:x}-{:x}\"",
+            len,
+            metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos())
+                .unwrap_or_default()
+        );
+
+        let mut response = HttpResponse::new(200, status_text(200));
+        let content_type = mime_guess::from_path(&file).first_or_octet_stream();
+        response
+            .headers
+            .insert("content-type".to_string(), content_type.essence_str().to_string());
+        response.headers.insert("etag".to_string(), etag.clone());
+        response
+            .headers
+            .insert("accept-ranges".to_string(), "bytes".to_string());
+        if let Some(modified) = modified {
+            response
+                .headers
+                .insert("last-modified".to_string(), http_date(&modified));
+        }
+        if let Some(encoding) = encoding {
+            response
+                .headers
+                .insert("content-encoding".to_string(), encoding.as_str().to_string());
+        }
+        if has_variants {
+            response
+                .headers
+                .insert("vary".to_string(), "Accept-Encoding".to_string());
+        }
+
+        if is_not_modified(request, &etag, modified) {
+            response.status = 304;
+            response.status_text = status_text(304).to_string();
+            response.headers.remove("content-type");
+            return Some(response);
+        }
+
+        let range = request.headers.get("range").and_then(|r| parse_range(r, len));
+        let (start, end) = match range {
+            Some(Ok((start, end))) => {
+                response.status = 206;
+                response.status_text = status_text(206).to_string();
+                response.headers.insert(
+                    "content-range".to_string(),
+                    format!("bytes {}-{}/{}", start, end, len),
+                );
+                (start, end + 1)
+            }
+            Some(Err(())) => {
+                let mut response = HttpResponse::error(416, "Range Not Satisfiable");
+                response
+                    .headers
+                    .insert("content-range".to_string(), format!("bytes */{}", len));
+                return Some(response);
+            }
+            None => (0, len),
+        };
+
+        response
+            .headers
+            .insert("content-length".to_string(), (end - start).to_string());
+        if !head {
+            response.body = read_range(&served, start, end).ok()?;
+        }
+        Some(response)
+    }
+
+    /// 把请求路径映射到资源目录中的文件，目录映射到其中的 `index.html`
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut file = self.root.clone();
+        for part in Path::new(path.trim_start_matches('/')).components() {
+            match part {
+                Component::Normal(name) => file.push(name),
+                Component::CurDir => {}
+                // 不允许跳出资源目录
+                _ => return None,
+            }
+        }
+
+        if file.is_dir() {
+            file.push("index.html");
+        }
+        file.is_file().then_some(file)
+    }
+}
+
+/// 创建 `env.ASSETS` 对象，`fetch(request)` 返回资源目录中的文件，不存在时返回 404
+pub fn create_binding(directory: &str, context: &mut Context) -> JsObject {
+    let fetch_fn = NativeFunction::from_copy_closure_with_captures(
+        |_, args, directory: &JsString, context| {
+            let request = js_to_request(args.get_or_undefined(0), context)?;
+            let store = AssetStore::new(directory.to_std_string_escaped());
+            let response = store
+                .serve(&request)
+                .unwrap_or_else(|| HttpResponse::error(404, "Not Found"));
+            response_to_js(&response, context)
+        },
+        JsString::from(directory),
+    );
+
+    ObjectInitializer::new(context)
+        .function(fetch_fn, js_string!("fetch"), 1)
+        .build()
+}
+
+/// 把 JS 的 Request 对象或 URL 字符串转换为 `HttpRequest`
+pub(super) fn js_to_request(value: &JsValue, context: &mut Context) -> JsResult<HttpRequest> {
+    let mut request = HttpRequest {
+        method: "GET".to_string(),
+        version: "HTTP/1.1".to_string(),
+        ..Default::default()
+    };
+
+    let Some(obj) = value.as_object() else {
+        request.path = url_path(&value.to_string(context)?.to_std_string_escaped());
+        return Ok(request);
+    };
+
+    let url = obj.get(js_string!("url"), context)?;
+    if url.is_null_or_undefined() {
+        return Err(JsNativeError::typ()
+            .with_message("fetch expects a Request or URL")
+            .into());
+    }
+    request.path = url_path(&url.to_string(context)?.to_std_string_escaped());
+
+    let method = obj.get(js_string!("method"), context)?;
+    if !method.is_null_or_undefined() {
+        request.method = method.to_string(context)?.to_std_string_escaped();
+    }
+
+    // Request 的 headers 把数据放在 `_data` 上，也接受普通对象
+    if let Some(headers) = obj.get(js_string!("headers"), context)?.as_object() {
+        let data = headers.get(js_string!("_data"), context)?;
+        let headers = data.as_object().unwrap_or(headers);
+        for key in headers.own_property_keys(context)? {
+            let value = headers.get(key.clone(), context)?;
+            request.headers.insert(
+                key.to_string().to_lowercase(),
+                value.to_string(context)?.to_std_string_escaped(),
+            );
+        }
+    }
+
+    let body = obj.get(js_string!("body"), context)?;
+    if !body.is_null_or_undefined() {
+        request.body = match js_to_bytes(&body, context)? {
+            Some(bytes) => bytes,
+            None => body.to_string(context)?.to_std_string_escaped().into_bytes(),
+        };
+    }
+
+    Ok(request)
+}
+
+/// 从完整 URL 中取出路径和查询字符串
+fn url_path(url: &str) -> String {
+    match url.split_once("://") {
+        Some((_, rest)) => rest
+            .find('/')
+            .map(|i| rest[i..].to_string())
+            .unwrap_or_else(|| "/".to_string()),
+        None => url.to_string(),
+    }
+}
+
+/// 用全局 `Response` 构造 JS 响应对象
+pub(super) fn response_to_js(response: &HttpResponse, context: &mut Context) -> JsResult<JsValue> {
+    let headers = ObjectInitializer::new(context).build();
+    for (key, value) in &response.headers {
+        headers.set(
+            JsString::from(key.as_str()),
+            JsValue::from(js_string!(value.as_str())),
+            false,
+            context,
+        )?;
+    }
+    let init = ObjectInitializer::new(context)
+        .property(js_string!("status"), response.status as i32, Attribute::all())
+        .property(js_string!("headers"), headers, Attribute::all())
+        .build();
+    let body = JsArrayBuffer::from_byte_block(AlignedVec::from_slice(0, &response.body), context)?;
+
+    let constructor = context.global_object().get(js_string!("Response"), context)?;
+    let constructor = constructor
+        .as_constructor()
+        .ok_or_else(|| JsNativeError::typ().with_message("Response is not available"))?;
+    Ok(constructor
+        .construct(&[body.into(), init.into()], None, context)?
+        .into())
+}
+
+/// 预压缩文件的路径，如 `app.js` -> `app.js.gz`
+fn compressed_path(file: &Path, ext: &str) -> PathBuf {
+    let mut path = file.as_os_str().to_os_string();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
+
+/// 判断条件请求是否命中缓存（`If-None-Match` 优先于 `If-Modified-Since`）
+fn is_not_modified(request: &HttpRequest, etag: &str, modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = request.headers.get("if-none-match") {
+        return if_none_match.split(',').any(|tag| {
+            let tag = tag.trim();
+            tag == "*" || tag.trim_start_matches("W/") == etag
+        });
+    }
+
+    match (request.headers.get("if-modified-since"), modified) {
+        (Some(since), Some(modified)) => DateTime::parse_from_rfc2822(since)
+            .map(|since| modified.timestamp() <= since.timestamp())
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// 解析单段 `Range: bytes=...`，返回闭区间 (start, end)
+///
+/// 格式不支持（如多段范围）时返回 `None` 并按完整内容响应，
+/// 范围无法满足时返回 `Some(Err(()))`
+fn parse_range(header: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let range = if start.is_empty() {
+        // bytes=-500：最后 500 字节
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            len.saturating_sub(1)
+        } else {
+            end.parse::<u64>().ok()?.min(len.saturating_sub(1))
+        };
+        if start >= len || start > end {
+            return Some(Err(()));
+        }
+        (start, end)
+    };
+    Some(Ok(range))
+}
+
+fn read_range(path: &Path, start: u64, end: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut body = Vec::with_capacity((end - start) as usize);
+    file.take(end - start).read_to_end(&mut body)?;
+    Ok(body)
+}
+
+/// HTTP 日期格式（RFC 7231）
+fn http_date(time: &DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("raven-assets-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn get(path: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_serve_and_conditional() {
+        let dir = temp_dir("serve");
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("index.html"), "<h1>home</h1>").unwrap();
+        fs::write(dir.join("docs/index.html"), "docs").unwrap();
+        fs::write(dir.join("app.js"), "console.log(1)").unwrap();
+        let store = AssetStore::new(&dir);
+
+        let response = store.serve(&get("/", &[])).unwrap();
+        assert_eq!(response.body, b"<h1>home</h1>");
+        assert_eq!(response.headers["content-type"], "text/html");
+
+        assert_eq!(store.serve(&get("/docs/", &[])).unwrap().body, b"docs");
+        let js = store.serve(&get("/app.js?v=1", &[])).unwrap();
+        assert_eq!(js.headers["content-type"], "text/javascript");
+
+        let etag = js.headers["etag"].clone();
+        let cached = store.serve(&get("/app.js", &[("if-none-match", &etag)])).unwrap();
+        assert_eq!(cached.status, 304);
+        assert!(cached.body.is_empty());
+
+        let since = js.headers["last-modified"].clone();
+        let cached = store.serve(&get("/app.js", &[("if-modified-since", &since)])).unwrap();
+        assert_eq!(cached.status, 304);
+
+        assert!(store.serve(&get("/missing.css", &[])).is_none());
+        assert!(store.serve(&get("/../etc/passwd", &[])).is_none());
+        assert!(store.serve(&get("/%2e%2e/etc/passwd", &[])).is_none());
+
+        // 按解码后的路径查找，支持绝对形式的请求目标
+        fs::write(dir.join("my file%41.txt"), "spaced").unwrap();
+        assert_eq!(store.serve(&get("/my%20file%2541.txt", &[])).unwrap().body, b"spaced");
+        assert_eq!(store.serve(&get("http://example.com/app.js?v=1", &[])).unwrap().body, b"console.log(1)");
+
+        let mut post = get("/app.js", &[]);
+        post.method = "POST".to_string();
+        assert!(store.serve(&post).is_none());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_range_requests() {
+        let dir = temp_dir("range");
+        fs::write(dir.join("data.bin"), b"0123456789").unwrap();
+        let store = AssetStore::new(&dir);
+
+        let response = store.serve(&get("/data.bin", &[("range", "bytes=2-4")])).unwrap();
+        assert_eq!(response.status, 206);
+        assert_eq!(response.body, b"234");
+        assert_eq!(response.headers["content-range"], "bytes 2-4/10");
+
+        let response = store.serve(&get("/data.bin", &[("range", "bytes=-3")])).unwrap();
+        assert_eq!(response.body, b"789");
+
+        let response = store.serve(&get("/data.bin", &[("range", "bytes=8-")])).unwrap();
+        assert_eq!(response.body, b"89");
+
+        let response = store.serve(&get("/data.bin", &[("range", "bytes=20-30")])).unwrap();
+        assert_eq!(response.status, 416);
+        assert_eq!(response.headers["content-range"], "bytes */10");
+
+        // 多段范围按完整内容返回
+        let response = store.serve(&get("/data.bin", &[("range", "bytes=0-1,4-5")])).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.len(), 10);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_precompressed_variants() {
+        let dir = temp_dir("precompressed");
+        fs::write(dir.join("style.css"), "body{}").unwrap();
+        fs::write(dir.join("style.css.gz"), b"gzip-bytes").unwrap();
+        fs::write(dir.join("style.css.br"), b"brotli-bytes").unwrap();
+        let store = AssetStore::new(&dir);
+
+        let response = store
+            .serve(&get("/style.css", &[("accept-encoding", "gzip, deflate, br")]))
+            .unwrap();
+        assert_eq!(response.headers["content-encoding"], "br");
+        assert_eq!(response.headers["content-type"], "text/css");
+        assert_eq!(response.body, b"brotli-bytes");
+
+        let response = store
+            .serve(&get("/style.css", &[("accept-encoding", "gzip")]))
+            .unwrap();
+        assert_eq!(response.headers["content-encoding"], "gzip");
+        assert_eq!(response.body, b"gzip-bytes");
+
+        let response = store.serve(&get("/style.css", &[])).unwrap();
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert_eq!(response.headers["vary"], "Accept-Encoding");
+        assert_eq!(response.body, b"body{}");
+
+        // q=0 表示不接受，权重高的优先
+        let response = store
+            .serve(&get("/style.css", &[("accept-encoding", "br;q=0, gzip")]))
+            .unwrap();
+        assert_eq!(response.headers["content-encoding"], "gzip");
+        let response = store
+            .serve(&get("/style.css", &[("accept-encoding", "br;q=0.5, gzip;q=0.8")]))
+            .unwrap();
+        assert_eq!(response.headers["content-encoding"], "gzip");
+        let response = store
+            .serve(&get("/style.css", &[("accept-encoding", "br;q=0, gzip;q=0")]))
+            .unwrap();
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert_eq!(response.body, b"body{}");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,636 @@
+//! 密码学绑定
+//!
+//! 提供 HMAC、AES-256-GCM、Ed25519、常量时间比较和密码哈希。
+//! 所有字节参数都接受 `ArrayBuffer`/`TypedArray`（字符串按 UTF-8 编码），返回值都是 `ArrayBuffer`。
+//!
+//! # JS 使用方式
+//!
+//! ```javascript
+//! import { CRYPTO } from 'raven/crypto'
+//!
+//! // HMAC：算法为 "SHA-256" 或 "SHA-512"
+//! const mac = CRYPTO.hmac("SHA-256", secret, body);
+//! const valid = CRYPTO.hmacVerify("SHA-256", secret, body, signature);
+//!
+//! // 常量时间比较
+//! CRYPTO.timingSafeEqual(a, b);
+//!
+//! // AES-256-GCM：密钥 32 字节，结果为 12 字节随机 IV + 密文 + 16 字节认证标签
+//! const key = CRYPTO.randomBytes(32);
+//! const sealed = CRYPTO.aesGcmEncrypt(key, "secret", { additionalData: "v1" });
+//! const plain = CRYPTO.aesGcmDecrypt(key, sealed, { additionalData: "v1" });
+//!
+//! // Ed25519：私钥为 32 字节种子
+//! const { publicKey, privateKey } = CRYPTO.ed25519GenerateKeyPair();
+//! const signature = CRYPTO.ed25519Sign(privateKey, message);
+//! CRYPTO.ed25519Verify(publicKey, message, signature);
+//!
+//! // 密码哈希：algorithm 为 "argon2id"（默认）或 "sha512-crypt"，
+//! // 结果为 PHC 格式（$argon2id$...）或 crypt 格式（$6$...）字符串的字节。
+//! // argon2 的 memoryCost（KiB）最大 1048576，timeCost 最大 10，parallelism 最大 16；
+//! // sha512-crypt 的 rounds 在 1000 到 1000000 之间
+//! const hash = CRYPTO.hashPassword(password);
+//! const legacy = CRYPTO.hashPassword(password, { algorithm: "sha512-crypt", rounds: 10000 });
+//! CRYPTO.verifyPassword(password, hash);
+//! ```
+
+use std::collections::HashMap;
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256, Sha512};
+use subtle::ConstantTimeEq;
+
+use crate::runtime::bindings::{BindingMethod, BindingValue, NativeBinding};
+
+/// AES-GCM 的 IV 长度
+const AES_GCM_IV_SIZE: usize = 12;
+
+/// `randomBytes` 一次最多生成的字节数
+const MAX_RANDOM_BYTES: usize = 65536;
+
+/// argon2 参数的上限，避免 Worker 申请过多内存或 CPU 时间拖垮进程
+const ARGON2_MAX_MEMORY_COST: u32 = 1024 * 1024;
+const ARGON2_MAX_TIME_COST: u32 = 10;
+const ARGON2_MAX_PARALLELISM: u32 = 16;
+
+/// SHA-512 crypt 的默认轮数，使用默认轮数时哈希中不写 `rounds=`
+const SHA512_CRYPT_DEFAULT_ROUNDS: u32 = 5000;
+const SHA512_CRYPT_MIN_ROUNDS: u32 = 1000;
+/// 规范允许 999999999 轮，这里限制为 100 万轮（约 0.5 秒），避免 Worker 或存储的哈希占满 CPU
+const SHA512_CRYPT_MAX_ROUNDS: u32 = 1_000_000;
+
+/// SHA-512 crypt 盐的最大长度
+const SHA512_CRYPT_MAX_SALT: usize = 16;
+
+/// crypt 使用的 base64 字母表
+const CRYPT_ALPHABET: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// 密码学绑定
+pub struct CryptoBinding {
+    name: String,
+}
+
+impl CryptoBinding {
+    /// 创建新的密码学绑定
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+
+    /// `hmac(algorithm, key, data)`
+    fn hmac(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let algorithm = string_arg(args, 0, "algorithm")?;
+        let key = bytes_arg(args, 1, "key")?;
+        let data = bytes_arg(args, 2, "data")?;
+        Ok(BindingValue::Bytes(hmac(algorithm, &key, &data)?))
+    }
+
+    /// `hmacVerify(algorithm, key, data, signature)`，以常量时间比较签名
+    fn hmac_verify(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let algorithm = string_arg(args, 0, "algorithm")?;
+        let key = bytes_arg(args, 1, "key")?;
+        let data = bytes_arg(args, 2, "data")?;
+        let signature = bytes_arg(args, 3, "signature")?;
+        let expected = hmac(algorithm, &key, &data)?;
+        Ok(BindingValue::Bool(bool::from(expected.ct_eq(&signature))))
+    }
+
+    /// `timingSafeEqual(a, b)`，长度不同时返回 `false`
+    fn timing_safe_equal(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let a = bytes_arg(args, 0, "a")?;
+        let b = bytes_arg(args, 1, "b")?;
+        Ok(BindingValue::Bool(bool::from(a.ct_eq(&b))))
+    }
+
+    /// `randomBytes(length)`
+    fn random_bytes(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let length = match args.first() {
+            Some(BindingValue::Int(n)) if *n >= 0 && (*n as usize) <= MAX_RANDOM_BYTES => *n as usize,
+            _ => return Err(format!("randomBytes requires a length between 0 and {}", MAX_RANDOM_BYTES)),
+        };
+        let mut bytes = vec![0u8; length];
+        OsRng.fill_bytes(&mut bytes);
+        Ok(BindingValue::Bytes(bytes))
+    }
+
+    /// `aesGcmEncrypt(key, plaintext, { iv?, additionalData? })`，返回 IV + 密文 + 认证标签
+    fn aes_gcm_encrypt(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let cipher = aes_cipher(&bytes_arg(args, 0, "key")?)?;
+        let plaintext = bytes_arg(args, 1, "plaintext")?;
+        let aad = option_bytes(args.get(2), "additionalData")?.unwrap_or_default();
+        let iv = match option_bytes(args.get(2), "iv")? {
+            Some(iv) if iv.len() == AES_GCM_IV_SIZE => iv,
+            Some(iv) => return Err(format!("iv must be {} bytes, got {}", AES_GCM_IV_SIZE, iv.len())),
+            None => {
+                let mut iv = vec![0u8; AES_GCM_IV_SIZE];
+                OsRng.fill_bytes(&mut iv);
+                iv
+            }
+        };
+
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&iv), Payload { msg: &plaintext, aad: &aad })
+            .map_err(|_| "Encryption failed".to_string())?;
+        let mut sealed = iv;
+        sealed.extend_from_slice(&ciphertext);
+        Ok(BindingValue::Bytes(sealed))
+    }
+
+    /// `aesGcmDecrypt(key, sealed, { additionalData? })`，认证失败时报错
+    fn aes_gcm_decrypt(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let cipher = aes_cipher(&bytes_arg(args, 0, "key")?)?;
+        let sealed = bytes_arg(args, 1, "ciphertext")?;
+        let aad = option_bytes(args.get(2), "additionalData")?.unwrap_or_default();
+        if sealed.len() < AES_GCM_IV_SIZE {
+            return Err("Ciphertext is too short".to_string());
+        }
+
+        let (iv, ciphertext) = sealed.split_at(AES_GCM_IV_SIZE);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(iv), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| "Decryption failed: wrong key, corrupted data or mismatched additionalData".to_string())?;
+        Ok(BindingValue::Bytes(plaintext))
+    }
+
+    /// `ed25519GenerateKeyPair()`，返回 `{ publicKey, privateKey }`
+    fn ed25519_generate_key_pair(&self) -> BindingValue {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let mut obj = HashMap::new();
+        obj.insert("publicKey".to_string(), BindingValue::Bytes(signing_key.verifying_key().to_bytes().to_vec()));
+        obj.insert("privateKey".to_string(), BindingValue::Bytes(signing_key.to_bytes().to_vec()));
+        BindingValue::Object(obj)
+    }
+
+    /// `ed25519Sign(privateKey, message)`，返回 64 字节签名
+    fn ed25519_sign(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let private_key: [u8; 32] = bytes_arg(args, 0, "privateKey")?
+            .try_into()
+            .map_err(|_| "Ed25519 private key must be 32 bytes".to_string())?;
+        let message = bytes_arg(args, 1, "message")?;
+        let signature = SigningKey::from_bytes(&private_key).sign(&message);
+        Ok(BindingValue::Bytes(signature.to_bytes().to_vec()))
+    }
+
+    /// `ed25519Verify(publicKey, message, signature)`
+    fn ed25519_verify(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let public_key: [u8; 32] = bytes_arg(args, 0, "publicKey")?
+            .try_into()
+            .map_err(|_| "Ed25519 public key must be 32 bytes".to_string())?;
+        let message = bytes_arg(args, 1, "message")?;
+        let signature = bytes_arg(args, 2, "signature")?;
+
+        let public_key = VerifyingKey::from_bytes(&public_key).map_err(|e| format!("Invalid Ed25519 public key: {}", e))?;
+        let Ok(signature) = Signature::from_slice(&signature) else {
+            return Ok(BindingValue::Bool(false));
+        };
+        Ok(BindingValue::Bool(public_key.verify(&message, &signature).is_ok()))
+    }
+
+    /// `hashPassword(password, { algorithm?, rounds?, memoryCost?, timeCost?, parallelism? })`
+    fn hash_password(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let password = bytes_arg(args, 0, "password")?;
+        let option = |name: &str| match args.get(1) {
+            Some(BindingValue::Object(opts)) => opts.get(name).cloned(),
+            _ => None,
+        };
+        let number = |name: &str| match option(name) {
+            None | Some(BindingValue::Null) => Ok(None),
+            Some(BindingValue::Int(n)) if n > 0 && n <= u32::MAX as i64 => Ok(Some(n as u32)),
+            Some(other) => Err(format!("{} must be a positive integer, got {}", name, other)),
+        };
+
+        let hash = match option("algorithm").as_ref().map(|a| a.as_string()) {
+            None | Some(Some("argon2id")) => {
+                let defaults = Params::default();
+                let params = Params::new(
+                    number("memoryCost")?.unwrap_or(defaults.m_cost()),
+                    number("timeCost")?.unwrap_or(defaults.t_cost()),
+                    number("parallelism")?.unwrap_or(defaults.p_cost()),
+                    None,
+                )
+                .map_err(|e| format!("Invalid argon2 parameters: {}", e))?;
+                check_argon2_params(&params)?;
+                let salt = SaltString::generate(&mut OsRng);
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password(&password, &salt)
+                    .map_err(|e| format!("Failed to hash password: {}", e))?
+                    .to_string()
+            }
+            Some(Some("sha512-crypt")) => {
+                let rounds = number("rounds")?.unwrap_or(SHA512_CRYPT_DEFAULT_ROUNDS);
+                if !(SHA512_CRYPT_MIN_ROUNDS..=SHA512_CRYPT_MAX_ROUNDS).contains(&rounds) {
+                    return Err(format!(
+                        "rounds must be between {} and {}",
+                        SHA512_CRYPT_MIN_ROUNDS, SHA512_CRYPT_MAX_ROUNDS
+                    ));
+                }
+                let salt: String = (0..SHA512_CRYPT_MAX_SALT)
+                    .map(|_| CRYPT_ALPHABET[OsRng.gen_range(0..CRYPT_ALPHABET.len())] as char)
+                    .collect();
+                let digest = sha512_crypt(&password, &salt, rounds);
+                if rounds == SHA512_CRYPT_DEFAULT_ROUNDS {
+                    format!("$6${}${}", salt, digest)
+                } else {
+                    format!("$6$rounds={}${}${}", rounds, salt, digest)
+                }
+            }
+            Some(other) => return Err(format!("Unsupported password hash algorithm: {:?}", other)),
+        };
+        Ok(BindingValue::Bytes(hash.into_bytes()))
+    }
+
+    /// `verifyPassword(password, hash)`，按哈希的前缀识别算法
+    fn verify_password(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let password = bytes_arg(args, 0, "password")?;
+        let hash = String::from_utf8(bytes_arg(args, 1, "hash")?).map_err(|_| "Invalid password hash".to_string())?;
+
+        if hash.starts_with("$argon2") {
+            let parsed = PasswordHash::new(&hash).map_err(|e| format!("Invalid password hash: {}", e))?;
+            let params = Params::try_from(&parsed).map_err(|e| format!("Invalid password hash: {}", e))?;
+            check_argon2_params(&params)?;
+            return Ok(BindingValue::Bool(Argon2::default().verify_password(&password, &parsed).is_ok()));
+        }
+        if let Some(rest) = hash.strip_prefix("$6$") {
+            let (rounds, rest) = match rest.strip_prefix("rounds=").and_then(|r| r.split_once('$')) {
+                Some((rounds, rest)) => {
+                    let rounds = rounds.parse::<u32>().map_err(|_| "Invalid password hash".to_string())?;
+                    if rounds > SHA512_CRYPT_MAX_ROUNDS {
+                        return Err(format!("sha512-crypt rounds must be at most {}, got {}", SHA512_CRYPT_MAX_ROUNDS, rounds));
+                    }
+                    (rounds.max(SHA512_CRYPT_MIN_ROUNDS), rest)
+                }
+                None => (SHA512_CRYPT_DEFAULT_ROUNDS, rest),
+            };
+            let (salt, digest) = rest.split_once('$').ok_or_else(|| "Invalid password hash".to_string())?;
+            let computed = sha512_crypt(&password, salt, rounds);
+            return Ok(BindingValue::Bool(bool::from(computed.as_bytes().ct_eq(digest.as_bytes()))));
+        }
+        Err("Unsupported password hash, expected $argon2id$ or $6$".to_string())
+    }
+}
+
+/// 检查 argon2 参数不超过上限
+fn check_argon2_params(params: &Params) -> Result<(), String> {
+    for (name, value, max) in [
+        ("memoryCost", params.m_cost(), ARGON2_MAX_MEMORY_COST),
+        ("timeCost", params.t_cost(), ARGON2_MAX_TIME_COST),
+        ("parallelism", params.p_cost(), ARGON2_MAX_PARALLELISM),
+    ] {
+        if value > max {
+            return Err(format!("argon2 {} must be at most {}, got {}", name, max, value));
+        }
+    }
+    Ok(())
+}
+
+/// 读取字节参数，字符串按 UTF-8 编码
+fn bytes_arg(args: &[BindingValue], index: usize, name: &str) -> Result<Vec<u8>, String> {
+    match args.get(index) {
+        Some(BindingValue::Bytes(bytes)) => Ok(bytes.clone()),
+        Some(BindingValue::String(s)) => Ok(s.as_bytes().to_vec()),
+        _ => Err(format!("{} must be an ArrayBuffer, TypedArray or string", name)),
+    }
+}
+
+fn string_arg<'a>(args: &'a [BindingValue], index: usize, name: &str) -> Result<&'a str, String> {
+    args.get(index)
+        .and_then(|arg| arg.as_string())
+        .ok_or_else(|| format!("{} must be a string", name))
+}
+
+/// 读取 options 对象中的字节字段
+fn option_bytes(options: Option<&BindingValue>, name: &str) -> Result<Option<Vec<u8>>, String> {
+    match options {
+        Some(BindingValue::Object(opts)) => match opts.get(name) {
+            None | Some(BindingValue::Null) => Ok(None),
+            Some(value) => bytes_arg(std::slice::from_ref(value), 0, name).map(Some),
+        },
+        _ => Ok(None),
+    }
+}
+
+fn hmac(algorithm: &str, key: &[u8], data: &[u8]) -> Result<Vec<u8>, String> {
+    match algorithm.to_ascii_uppercase().replace('-', "").as_str() {
+        "SHA256" => {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).map_err(|e| e.to_string())?;
+            mac.update(data);
+            Ok(mac.finalize().into_bytes().to_vec())
+        }
+        "SHA512" => {
+            let mut mac = <Hmac<Sha512> as Mac>::new_from_slice(key).map_err(|e| e.to_string())?;
+            mac.update(data);
+            Ok(mac.finalize().into_bytes().to_vec())
+        }
+        _ => Err(format!("Unsupported HMAC algorithm: {}, expected SHA-256 or SHA-512", algorithm)),
+    }
+}
+
+fn aes_cipher(key: &[u8]) -> Result<Aes256Gcm, String> {
+    Aes256Gcm::new_from_slice(key).map_err(|_| format!("AES-256-GCM key must be 32 bytes, got {}", key.len()))
+}
+
+/// SHA-512 crypt（glibc `$6$`）的摘要部分，盐超过 16 个字符时截断
+fn sha512_crypt(password: &[u8], salt: &str, rounds: u32) -> String {
+    let salt = &salt.as_bytes()[..salt.len().min(SHA512_CRYPT_MAX_SALT)];
+
+    let alternate = Sha512::new().chain_update(password).chain_update(salt).chain_update(password).finalize();
+
+    let mut digest = Sha512::new().chain_update(password).chain_update(salt);
+    let mut remaining = password.len();
+    while remaining > 64 {
+        digest.update(alternate);
+        remaining -= 64;
+    }
+    digest.update(&alternate[..remaining]);
+    let mut length = password.len();
+    while length > 0 {
+        if length & 1 == 1 {
+            digest.update(alternate);
+        } else {
+            digest.update(password);
+        }
+        length >>= 1;
+    }
+    let mut result = digest.finalize();
+
+    let mut dp = Sha512::new();
+    for _ in 0..password.len() {
+        dp.update(password);
+    }
+    let p_bytes = repeat_digest(&dp.finalize(), password.len());
+
+    let mut ds = Sha512::new();
+    for _ in 0..16 + result[0] as usize {
+        ds.update(salt);
+    }
+    let s_bytes = repeat_digest(&ds.finalize(), salt.len());
+
+    for round in 0..rounds {
+        let mut c = Sha512::new();
+        if round % 2 == 1 {
+            c.update(&p_bytes);
+        } else {
+            c.update(result);
+        }
+        if round % 3 != 0 {
+            c.update(&s_bytes);
+        }
+        if round % 7 != 0 {
+            c.update(&p_bytes);
+        }
+        if round % 2 == 1 {
+            c.update(result);
+        } else {
+            c.update(&p_bytes);
+        }
+        result = c.finalize();
+    }
+
+    let mut out = String::new();
+    for i in 0..21 {
+        let (a, b, c) = (i, i + 21, i + 42);
+        // 每组三个字节的位置按 (i, i+21, i+42) 轮换
+        let (b2, b1, b0) = match i % 3 {
+            0 => (result[a], result[b], result[c]),
+            1 => (result[b], result[c], result[a]),
+            _ => (result[c], result[a], result[b]),
+        };
+        push_crypt_base64(&mut out, b2, b1, b0, 4);
+    }
+    push_crypt_base64(&mut out, 0, 0, result[63], 2);
+    out
+}
+
+/// 把 64 字节摘要重复拼接到 `len` 字节
+fn repeat_digest(digest: &[u8], len: usize) -> Vec<u8> {
+    digest.iter().copied().cycle().take(len).collect()
+}
+
+fn push_crypt_base64(out: &mut String, b2: u8, b1: u8, b0: u8, chars: usize) {
+    let mut w = ((b2 as u32) << 16) | ((b1 as u32) << 8) | b0 as u32;
+    for _ in 0..chars {
+        out.push(CRYPT_ALPHABET[(w & 0x3f) as usize] as char);
+        w >>= 6;
+    }
+}
+
+impl NativeBinding for CryptoBinding {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn methods(&self) -> Vec<BindingMethod> {
+        vec![
+            BindingMethod::new("hmac", 3),
+            BindingMethod::new("hmacVerify", 4),
+            BindingMethod::new("timingSafeEqual", 2),
+            BindingMethod::new("randomBytes", 1),
+            BindingMethod::new("aesGcmEncrypt", 2),
+            BindingMethod::new("aesGcmDecrypt", 2),
+            BindingMethod::new("ed25519GenerateKeyPair", 0),
+            BindingMethod::new("ed25519Sign", 2),
+            BindingMethod::new("ed25519Verify", 3),
+            BindingMethod::new("hashPassword", 1),
+            BindingMethod::new("verifyPassword", 2),
+        ]
+    }
+
+    fn call(&self, method: &str, args: Vec<BindingValue>) -> BindingValue {
+        let result = match method {
+            "hmac" => self.hmac(&args),
+            "hmacVerify" => self.hmac_verify(&args),
+            "timingSafeEqual" => self.timing_safe_equal(&args),
+            "randomBytes" => self.random_bytes(&args),
+            "aesGcmEncrypt" => self.aes_gcm_encrypt(&args),
+            "aesGcmDecrypt" => self.aes_gcm_decrypt(&args),
+            "ed25519GenerateKeyPair" => Ok(self.ed25519_generate_key_pair()),
+            "ed25519Sign" => self.ed25519_sign(&args),
+            "ed25519Verify" => self.ed25519_verify(&args),
+            "hashPassword" => self.hash_password(&args),
+            "verifyPassword" => self.verify_password(&args),
+            _ => Err(format!("Unknown method: {}", method)),
+        };
+        result.unwrap_or_else(BindingValue::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(value: &[u8]) -> BindingValue {
+        BindingValue::Bytes(value.to_vec())
+    }
+
+    fn string(value: &str) -> BindingValue {
+        BindingValue::String(value.to_string())
+    }
+
+    fn options(fields: &[(&str, BindingValue)]) -> BindingValue {
+        BindingValue::Object(fields.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+    }
+
+    fn to_bytes(value: BindingValue) -> Vec<u8> {
+        match value {
+            BindingValue::Bytes(bytes) => bytes,
+            other => panic!("Expected bytes, got {}", other),
+        }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_hmac() {
+        let crypto = CryptoBinding::new("CRYPTO");
+        // RFC 4231 测试用例 2
+        let args = vec![string("SHA-256"), string("Jefe"), string("what do ya want for nothing?")];
+        let mac = to_bytes(crypto.call("hmac", args.clone()));
+        assert_eq!(hex(&mac), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+
+        let mut verify = args.clone();
+        verify.push(bytes(&mac));
+        assert!(matches!(crypto.call("hmacVerify", verify.clone()), BindingValue::Bool(true)));
+        verify[3] = bytes(&mac[..31]);
+        assert!(matches!(crypto.call("hmacVerify", verify), BindingValue::Bool(false)));
+
+        let mac = to_bytes(crypto.call("hmac", vec![string("sha512"), string("Jefe"), string("what do ya want for nothing?")]));
+        assert_eq!(&hex(&mac)[..32], "164b7a7bfcf819e2e395fbe73b56e0a3");
+        assert!(crypto.call("hmac", vec![string("MD5"), string("k"), string("d")]).is_error());
+    }
+
+    #[test]
+    fn test_timing_safe_equal() {
+        let crypto = CryptoBinding::new("CRYPTO");
+        assert!(matches!(crypto.call("timingSafeEqual", vec![string("abc"), bytes(b"abc")]), BindingValue::Bool(true)));
+        assert!(matches!(crypto.call("timingSafeEqual", vec![string("abc"), string("abd")]), BindingValue::Bool(false)));
+        assert!(matches!(crypto.call("timingSafeEqual", vec![string("abc"), string("ab")]), BindingValue::Bool(false)));
+        assert!(crypto.call("timingSafeEqual", vec![string("abc")]).is_error());
+    }
+
+    #[test]
+    fn test_aes_gcm() {
+        let crypto = CryptoBinding::new("CRYPTO");
+        let key = to_bytes(crypto.call("randomBytes", vec![BindingValue::Int(32)]));
+        let aad = options(&[("additionalData", string("v1"))]);
+
+        let sealed = to_bytes(crypto.call("aesGcmEncrypt", vec![bytes(&key), string("secret"), aad.clone()]));
+        assert_eq!(sealed.len(), AES_GCM_IV_SIZE + 6 + 16);
+        let plain = crypto.call("aesGcmDecrypt", vec![bytes(&key), bytes(&sealed), aad.clone()]);
+        assert_eq!(to_bytes(plain), b"secret");
+
+        // 每次加密使用新的 IV
+        let again = to_bytes(crypto.call("aesGcmEncrypt", vec![bytes(&key), string("secret"), aad.clone()]));
+        assert_ne!(sealed, again);
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(crypto.call("aesGcmDecrypt", vec![bytes(&key), bytes(&tampered), aad]).is_error());
+        assert!(crypto.call("aesGcmDecrypt", vec![bytes(&key), bytes(&sealed)]).is_error());
+        assert!(crypto.call("aesGcmEncrypt", vec![bytes(&key[..16]), string("x")]).is_error());
+
+        // 指定 IV 时结果是确定的
+        let iv = options(&[("iv", bytes(&[7; 12]))]);
+        let a = crypto.call("aesGcmEncrypt", vec![bytes(&key), string("x"), iv.clone()]);
+        let b = crypto.call("aesGcmEncrypt", vec![bytes(&key), string("x"), iv]);
+        assert_eq!(to_bytes(a), to_bytes(b));
+    }
+
+    #[test]
+    fn test_ed25519() {
+        let crypto = CryptoBinding::new("CRYPTO");
+        let BindingValue::Object(pair) = crypto.call("ed25519GenerateKeyPair", vec![]) else {
+            panic!("Expected a key pair");
+        };
+        let public_key = pair["publicKey"].clone();
+        let private_key = pair["privateKey"].clone();
+
+        let signature = crypto.call("ed25519Sign", vec![private_key.clone(), string("hello")]);
+        assert_eq!(to_bytes(signature.clone()).len(), 64);
+        let verify = |message: &str, signature: BindingValue| {
+            crypto.call("ed25519Verify", vec![public_key.clone(), string(message), signature])
+        };
+        assert!(matches!(verify("hello", signature.clone()), BindingValue::Bool(true)));
+        assert!(matches!(verify("hellO", signature), BindingValue::Bool(false)));
+        assert!(matches!(verify("hello", bytes(&[0; 10])), BindingValue::Bool(false)));
+        assert!(crypto.call("ed25519Sign", vec![bytes(&[1; 31]), string("x")]).is_error());
+
+        // RFC 8032 测试向量 1
+        let seed: Vec<u8> = (0..32)
+            .map(|i| u8::from_str_radix(&"9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60"[i * 2..i * 2 + 2], 16).unwrap())
+            .collect();
+        let signature = to_bytes(crypto.call("ed25519Sign", vec![bytes(&seed), bytes(b"")]));
+        assert_eq!(&hex(&signature)[..32], "e5564300c360ac729086e2cc806e828a");
+    }
+
+    #[test]
+    fn test_sha512_crypt_vectors() {
+        // 来自 SHA-crypt 规范
+        assert_eq!(
+            sha512_crypt(b"Hello world!", "saltstring", 5000),
+            "svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+        );
+        assert_eq!(
+            sha512_crypt(b"Hello world!", "saltstringsaltstring", 10000),
+            "OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMCVNSnCM/UrjmM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v."
+        );
+    }
+
+    #[test]
+    fn test_password_hashing() {
+        let crypto = CryptoBinding::new("CRYPTO");
+        let fast = options(&[("memoryCost", BindingValue::Int(1024)), ("timeCost", BindingValue::Int(1))]);
+        let hash = to_bytes(crypto.call("hashPassword", vec![string("hunter2"), fast]));
+        assert!(hash.starts_with(b"$argon2id$v=19$m=1024,t=1,p=1$"));
+
+        let legacy = options(&[("algorithm", string("sha512-crypt")), ("rounds", BindingValue::Int(1000))]);
+        let crypt = to_bytes(crypto.call("hashPassword", vec![string("hunter2"), legacy]));
+        assert!(crypt.starts_with(b"$6$rounds=1000$"));
+
+        for hash in [hash, crypt] {
+            let verify = |password: &str| crypto.call("verifyPassword", vec![string(password), bytes(&hash)]);
+            assert!(matches!(verify("hunter2"), BindingValue::Bool(true)));
+            assert!(matches!(verify("hunter3"), BindingValue::Bool(false)));
+        }
+
+        // 哈希也可以是字符串，显式写出默认轮数的哈希同样能验证
+        for vector in [
+            "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1",
+            "$6$rounds=5000$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1",
+            "$6$rounds=10000$saltstringsaltst$OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMCVNSnCM/UrjmM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v.",
+        ] {
+            let verified = crypto.call("verifyPassword", vec![string("Hello world!"), string(vector)]);
+            assert!(matches!(verified, BindingValue::Bool(true)), "{}", vector);
+        }
+        assert!(crypto.call("verifyPassword", vec![string("x"), string("$1$md5$hash")]).is_error());
+        assert!(crypto.call("hashPassword", vec![string("x"), options(&[("algorithm", string("bcrypt"))])]).is_error());
+        let too_few = options(&[("algorithm", string("sha512-crypt")), ("rounds", BindingValue::Int(10))]);
+        assert!(crypto.call("hashPassword", vec![string("x"), too_few]).is_error());
+
+        // argon2 参数超过上限时拒绝，不会尝试分配内存
+        for (name, value) in [("memoryCost", 4 * 1024 * 1024), ("timeCost", 11), ("parallelism", 17)] {
+            let costly = options(&[(name, BindingValue::Int(value))]);
+            let result = crypto.call("hashPassword", vec![string("x"), costly]);
+            assert!(matches!(&result, BindingValue::Error(e) if e.contains(name)), "{}", name);
+        }
+        let costly = "$argon2id$v=19$m=4194304,t=1,p=1$c2FsdHNhbHQ$aGFzaGhhc2hoYXNoaGFzaA";
+        let result = crypto.call("verifyPassword", vec![string("x"), string(costly)]);
+        assert!(matches!(&result, BindingValue::Error(e) if e.contains("memoryCost")));
+
+        // sha512-crypt 轮数超过上限时拒绝，验证时不会截断后继续计算
+        let costly = options(&[("algorithm", string("sha512-crypt")), ("rounds", BindingValue::Int(1_000_001))]);
+        assert!(crypto.call("hashPassword", vec![string("x"), costly]).is_error());
+        let costly = "$6$rounds=999999999$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1";
+        let result = crypto.call("verifyPassword", vec![string("Hello world!"), string(costly)]);
+        assert!(matches!(&result, BindingValue::Error(e) if e.contains("rounds must be at most")));
+    }
+}
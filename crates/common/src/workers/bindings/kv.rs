@@ -5,58 +5,702 @@
 //! # JS 使用方式
 //!
 //! ```javascript
-//! // 获取值
+//! // 获取值（type: "text"（默认）、"json" 或 "arrayBuffer"）
 //! const value = await env.KV.get("my-key");
+//! const config = await env.KV.get("config", { type: "json" });
 //!
 //! // 存储值
 //! await env.KV.put("my-key", "my-value");
 //!
-//! // 存储带过期时间的值（秒）
+//! // 存储带过期时间的值：expirationTtl 为秒数，expiration 为 Unix 时间戳（秒）
 //! await env.KV.put("temp-key", "temp-value", { expirationTtl: 3600 });
 //!
+//! // 存储带元数据的值，元数据序列化为 JSON，最大 1024 字节
+//! await env.KV.put("user:1", "...", { metadata: { plan: "pro" } });
+//! const { value, metadata } = await env.KV.getWithMetadata("user:1");
+//!
 //! // 删除值
 //! await env.KV.delete("my-key");
 //!
-//! // 列出所有键
-//! const keys = await env.KV.list();
+//! // 批量写入和删除，每批在一次原子提交中生效，最多 10000 个键
+//! await env.KV.putMany([{ key: "a", value: "1" }, { key: "b", value: "2", expirationTtl: 60 }]);
+//! await env.KV.deleteMany(["a", "b"]);
+//!
+//! // 原子操作：compare-and-swap、自增和多键事务
+//! const { value: current, version } = await env.KV.getWithMetadata("config");
+//! const { ok } = await env.KV.compareAndSwap("config", version, "new value");
+//! const visits = await env.KV.increment("visits");
+//! await env.KV.atomic()
+//!     .check("order:1", null)            // 键必须不存在
+//!     .put("order:1", "...")
+//!     .increment("orders")
+//!     .delete("cart:1")
+//!     .commit();                         // { ok, version }
+//!
+//! // 监听前缀下的变更，回调在 Worker 的事件循环中执行
+//! // change: { seq, type: "put" | "delete" | "expire" | "evict" | "lagged", key, value?, metadata?, expiration?, version? }
+//! const watch = env.KV.watch("config:", (change) => { ... });
+//! watch.unsubscribe();
+//!
+//! // 统计信息：{ entries, bytes, hits, misses, evictions, maxEntries?, maxBytes? }
+//! const stats = await env.KV.stats();
+//!
+//! // 分页列出键：{ keys: [{ name, expiration?, metadata? }], list_complete, cursor? }
+//! let page = await env.KV.list({ prefix: "user:", limit: 100 });
+//! page = await env.KV.list({ prefix: "user:", cursor: page.cursor });
 //! ```
 
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 
+use super::kv_watch::{KvChange, KvChangeKind, KvFeed, KvSubscription};
 use crate::runtime::bindings::{BindingMethod, BindingValue, NativeBinding};
 
+/// 元数据序列化后的最大字节数
+pub(super) const MAX_METADATA_SIZE: usize = 1024;
+
+/// 过期时间的上限：9999-12-31T23:59:59Z（Unix 毫秒）
+pub(super) const MAX_EXPIRATION_MILLIS: u64 = 253_402_300_799_000;
+
+/// `list` 每页最多返回的键数
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// `putMany` 和 `deleteMany` 一次最多处理的键数
+const MAX_BULK_SIZE: usize = 10_000;
+
+/// 内存存储的后台线程删除过期键的间隔
+const REAP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 有 `watch` 订阅时清理过期键的间隔，过期事件最多延迟这么久
+const EXPIRE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `watch` 订阅 ID，在进程内唯一，同一运行时中的多个 KV 绑定可以共用一张回调表
+static NEXT_WATCH_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 带元数据和过期时间的值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvValue {
+    pub value: Vec<u8>,
+    /// JSON 编码的元数据
+    pub metadata: Option<String>,
+    /// 过期时间（Unix 毫秒）
+    pub expires_at: Option<u64>,
+    /// 写入时分配的版本，同一存储中单调递增，用于 compare-and-swap
+    pub version: u64,
+}
+
+/// 原子操作的前提条件：键的当前版本必须等于 `version`，`None` 表示键必须不存在
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvCheck {
+    pub key: String,
+    pub version: Option<u64>,
+}
+
+/// 原子操作中的一项修改
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvMutation {
+    Put {
+        key: String,
+        value: Vec<u8>,
+        metadata: Option<String>,
+        expires_at: Option<u64>,
+    },
+    Delete {
+        key: String,
+    },
+    /// 把十进制整数值加上 `delta`，键不存在时视为 0，保留原有的元数据和过期时间
+    Increment {
+        key: String,
+        delta: i64,
+    },
+}
+
+/// 批量写入和备份中的一个键
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvRecord {
+    pub key: String,
+    pub value: Vec<u8>,
+    /// JSON 编码的元数据
+    pub metadata: Option<String>,
+    /// 过期时间（Unix 毫秒）
+    pub expires_at: Option<u64>,
+}
+
+impl KvRecord {
+    pub fn new(key: &str, value: &[u8]) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.to_vec(),
+            metadata: None,
+            expires_at: None,
+        }
+    }
+
+    fn to_mutation(&self) -> KvMutation {
+        KvMutation::Put {
+            key: self.key.clone(),
+            value: self.value.clone(),
+            metadata: self.metadata.clone(),
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+/// 解析后的原子写入：(键, 新值)，新值为 `None` 表示删除
+pub(super) type KvWrites = Vec<(String, Option<KvValue>)>;
+
+/// `list_keys` 返回的键
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvKey {
+    pub name: String,
+    /// JSON 编码的元数据
+    pub metadata: Option<String>,
+    /// 过期时间（Unix 毫秒）
+    pub expires_at: Option<u64>,
+}
+
+/// 存储的统计信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KvStats {
+    /// 键数（可能包括尚未清理的过期键）
+    pub entries: usize,
+    /// 占用的字节数
+    pub bytes: u64,
+    /// 读到值的次数
+    pub hits: u64,
+    /// 键不存在或已过期的次数
+    pub misses: u64,
+    /// 因容量限制被淘汰的键数
+    pub evictions: u64,
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<u64>,
+}
+
 /// KV 存储后端 trait
 ///
-/// 实现此 trait 可以提供不同的存储后端（内存、文件、Redis 等）
+/// 实现此 trait 可以提供不同的存储后端（内存、文件、Redis 等）。
+/// 过期时间都是 Unix 毫秒时间戳，过期的键对所有方法都不可见。
 pub trait KvStore: Send + Sync {
-    /// 获取值
-    fn get(&self, key: &str) -> Option<Vec<u8>>;
+    /// 获取值及其元数据
+    fn get_with_metadata(&self, key: &str) -> Option<KvValue>;
+
+    /// 存储值，`expires_at` 为 Unix 毫秒时间戳
+    fn put_with_metadata(
+        &self,
+        key: &str,
+        value: &[u8],
+        metadata: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> Result<(), String> {
+        let put = KvMutation::Put {
+            key: key.to_string(),
+            value: value.to_vec(),
+            metadata: metadata.map(str::to_string),
+            expires_at,
+        };
+        self.atomic(&[], &[put]).map(|_| ())
+    }
 
-    /// 存储值
-    fn put(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), String>;
+    /// 键的当前版本为 `expected`（`None` 表示不存在）时写入，返回新版本，否则返回 `None`
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<u64>,
+        value: &[u8],
+        metadata: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> Result<Option<u64>, String> {
+        let check = KvCheck {
+            key: key.to_string(),
+            version: expected,
+        };
+        let put = KvMutation::Put {
+            key: key.to_string(),
+            value: value.to_vec(),
+            metadata: metadata.map(str::to_string),
+            expires_at,
+        };
+        self.atomic(&[check], &[put])
+    }
+
+    /// 原子地把整数值加上 `delta`，返回新值
+    fn increment(&self, key: &str, delta: i64) -> Result<i64, String> {
+        loop {
+            let current = self.get_with_metadata(key);
+            let value = parse_integer(current.as_ref().map(|c| c.value.as_slice()))?
+                .checked_add(delta)
+                .ok_or_else(|| format!("Incrementing {} overflows", key))?;
+            let committed = self.compare_and_swap(
+                key,
+                current.as_ref().map(|c| c.version),
+                value.to_string().as_bytes(),
+                current.as_ref().and_then(|c| c.metadata.as_deref()),
+                current.as_ref().and_then(|c| c.expires_at),
+            )?;
+            if committed.is_some() {
+                return Ok(value);
+            }
+        }
+    }
 
     /// 删除值
     fn delete(&self, key: &str) -> Result<bool, String>;
 
+    /// 原子地执行一批修改：所有检查通过时全部生效并返回新版本，任一检查失败时返回 `None`，不做任何修改
+    fn atomic(&self, checks: &[KvCheck], mutations: &[KvMutation]) -> Result<Option<u64>, String>;
+
+    /// 在一次原子提交中写入多个键，任一写入失败时都不生效
+    fn put_many(&self, records: &[KvRecord]) -> Result<(), String> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let puts: Vec<_> = records.iter().map(KvRecord::to_mutation).collect();
+        self.atomic(&[], &puts).map(|_| ())
+    }
+
+    /// 在一次原子提交中删除多个键，不存在的键被忽略
+    fn delete_many(&self, keys: &[String]) -> Result<(), String> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let deletes: Vec<_> = keys.iter().map(|key| KvMutation::Delete { key: key.clone() }).collect();
+        self.atomic(&[], &deletes).map(|_| ())
+    }
+
+    /// 按顺序列出以 `prefix` 开头、在 `start_after` 之后的键，最多 `limit` 个
+    fn list_keys(&self, prefix: Option<&str>, start_after: Option<&str>, limit: Option<usize>) -> Vec<KvKey>;
+
+    /// 获取值
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.get_with_metadata(key).map(|entry| entry.value)
+    }
+
+    /// 存储值，`ttl` 为相对当前时间的有效期
+    fn put(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), String> {
+        self.put_with_metadata(key, value, None, ttl.map(expires_after))
+    }
+
     /// 列出所有键
-    fn list(&self, prefix: Option<&str>, limit: Option<usize>) -> Vec<String>;
+    fn list(&self, prefix: Option<&str>, limit: Option<usize>) -> Vec<String> {
+        self.list_after(prefix, None, limit)
+    }
+
+    /// 按顺序列出 `start_after` 之后的键，用于分页
+    fn list_after(&self, prefix: Option<&str>, start_after: Option<&str>, limit: Option<usize>) -> Vec<String> {
+        self.list_keys(prefix, start_after, limit)
+            .into_iter()
+            .map(|key| key.name)
+            .collect()
+    }
 
     /// 检查键是否存在
     fn exists(&self, key: &str) -> bool {
         self.get(key).is_some()
     }
+
+    /// 把缓冲的写入落盘（内存存储无需实现）
+    fn flush(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// 存储的变更事件源，不支持变更通知的存储返回 `None`
+    fn feed(&self) -> Option<&KvFeed> {
+        None
+    }
+
+    /// 订阅以 `prefix` 开头的键的变更
+    fn watch(&self, prefix: &str) -> Result<KvSubscription, String> {
+        self.feed()
+            .map(|feed| feed.subscribe(prefix))
+            .ok_or_else(|| "This KV store does not support watching".to_string())
+    }
+
+    /// 删除已过期的键并为每个键发出 `Expire` 事件，返回删除的数量
+    fn purge_expired(&self) -> Result<usize, String> {
+        Ok(0)
+    }
+
+    /// 统计信息
+    fn stats(&self) -> KvStats {
+        KvStats::default()
+    }
+}
+
+impl<T: KvStore + ?Sized> KvStore for Arc<T> {
+    fn get_with_metadata(&self, key: &str) -> Option<KvValue> {
+        (**self).get_with_metadata(key)
+    }
+
+    fn put_with_metadata(
+        &self,
+        key: &str,
+        value: &[u8],
+        metadata: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> Result<(), String> {
+        (**self).put_with_metadata(key, value, metadata, expires_at)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<u64>,
+        value: &[u8],
+        metadata: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> Result<Option<u64>, String> {
+        (**self).compare_and_swap(key, expected, value, metadata, expires_at)
+    }
+
+    fn increment(&self, key: &str, delta: i64) -> Result<i64, String> {
+        (**self).increment(key, delta)
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, String> {
+        (**self).delete(key)
+    }
+
+    fn atomic(&self, checks: &[KvCheck], mutations: &[KvMutation]) -> Result<Option<u64>, String> {
+        (**self).atomic(checks, mutations)
+    }
+
+    fn put_many(&self, records: &[KvRecord]) -> Result<(), String> {
+        (**self).put_many(records)
+    }
+
+    fn delete_many(&self, keys: &[String]) -> Result<(), String> {
+        (**self).delete_many(keys)
+    }
+
+    fn list_keys(&self, prefix: Option<&str>, start_after: Option<&str>, limit: Option<usize>) -> Vec<KvKey> {
+        (**self).list_keys(prefix, start_after, limit)
+    }
+
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), String> {
+        (**self).put(key, value, ttl)
+    }
+
+    fn list(&self, prefix: Option<&str>, limit: Option<usize>) -> Vec<String> {
+        (**self).list(prefix, limit)
+    }
+
+    fn list_after(&self, prefix: Option<&str>, start_after: Option<&str>, limit: Option<usize>) -> Vec<String> {
+        (**self).list_after(prefix, start_after, limit)
+    }
+
+    fn exists(&self, key: &str) -> bool {
+        (**self).exists(key)
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        (**self).flush()
+    }
+
+    fn feed(&self) -> Option<&KvFeed> {
+        (**self).feed()
+    }
+
+    fn watch(&self, prefix: &str) -> Result<KvSubscription, String> {
+        (**self).watch(prefix)
+    }
+
+    fn purge_expired(&self) -> Result<usize, String> {
+        (**self).purge_expired()
+    }
+
+    fn stats(&self) -> KvStats {
+        (**self).stats()
+    }
+}
+
+/// 读取的命中和未命中次数
+#[derive(Default)]
+pub(super) struct KvCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl KvCounters {
+    pub(super) fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 只填写了 `hits` 和 `misses` 的统计信息
+    pub(super) fn stats(&self) -> KvStats {
+        KvStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            ..KvStats::default()
+        }
+    }
+}
+
+/// 启动定期删除过期键的后台线程，存储被释放后线程退出
+pub(super) fn spawn_reaper<S: KvStore + 'static>(store: &Arc<S>, interval: Duration) {
+    let store = Arc::downgrade(store);
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let Some(store) = store.upgrade() else {
+            return;
+        };
+        if let Err(e) = store.purge_expired() {
+            eprintln!("Failed to remove expired KV entries: {}", e);
+        }
+    });
+}
+
+/// 当前 Unix 毫秒时间戳
+pub(super) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// `ttl` 之后的 Unix 毫秒时间戳
+pub(super) fn expires_after(ttl: Duration) -> u64 {
+    now_millis().saturating_add(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX))
+}
+
+/// 下一个版本：当前 Unix 微秒时间戳，时钟回拨时在上一个版本上加一，保证重启后仍然递增
+pub(super) fn next_version(last: u64) -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0);
+    now.max(last + 1)
+}
+
+/// 把值解析为十进制整数，不存在时为 0
+fn parse_integer(value: Option<&[u8]>) -> Result<i64, String> {
+    let Some(value) = value else {
+        return Ok(0);
+    };
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .ok_or_else(|| format!("Cannot increment non-integer value {:?}", String::from_utf8_lossy(value)))
+}
+
+/// 在持有存储的写锁时调用：检查版本，把修改解析为按顺序应用的写入
+///
+/// `current` 读取键当前未过期的值，任一检查失败时返回 `None`
+pub(super) fn resolve_atomic(
+    checks: &[KvCheck],
+    mutations: &[KvMutation],
+    version: u64,
+    mut current: impl FnMut(&str) -> Result<Option<KvValue>, String>,
+) -> Result<Option<KvWrites>, String> {
+    for check in checks {
+        if current(&check.key)?.map(|entry| entry.version) != check.version {
+            return Ok(None);
+        }
+    }
+
+    let mut writes: KvWrites = Vec::with_capacity(mutations.len());
+    for mutation in mutations {
+        let write = match mutation {
+            KvMutation::Put {
+                key,
+                value,
+                metadata,
+                expires_at,
+            } => (
+                key.clone(),
+                Some(KvValue {
+                    value: value.clone(),
+                    metadata: metadata.clone(),
+                    expires_at: *expires_at,
+                    version,
+                }),
+            ),
+            KvMutation::Delete { key } => (key.clone(), None),
+            KvMutation::Increment { key, delta } => {
+                // 同一批中前面的修改可见
+                let existing = match writes.iter().rev().find(|(k, _)| k == key) {
+                    Some((_, pending)) => pending.clone(),
+                    None => current(key)?,
+                };
+                let value = parse_integer(existing.as_ref().map(|e| e.value.as_slice()))?
+                    .checked_add(*delta)
+                    .ok_or_else(|| format!("Incrementing {} overflows", key))?;
+                (
+                    key.clone(),
+                    Some(KvValue {
+                        value: value.to_string().into_bytes(),
+                        metadata: existing.as_ref().and_then(|e| e.metadata.clone()),
+                        expires_at: existing.as_ref().and_then(|e| e.expires_at),
+                        version,
+                    }),
+                )
+            }
+        };
+        writes.push(write);
+    }
+    Ok(Some(writes))
+}
+
+/// 内存存储满时的淘汰策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// 淘汰最久未访问的键
+    #[default]
+    Lru,
+    /// 淘汰访问次数最少的键，次数相同时淘汰最久未访问的
+    Lfu,
+}
+
+/// 内存存储的容量限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KvLimits {
+    /// 最多保存的键数
+    pub max_entries: Option<usize>,
+    /// 键、值和元数据的总字节数上限
+    pub max_bytes: Option<u64>,
+    pub eviction: EvictionPolicy,
+}
+
+impl KvLimits {
+    fn is_bounded(&self) -> bool {
+        self.max_entries.is_some() || self.max_bytes.is_some()
+    }
 }
 
 /// 内存 KV 存储实现
+///
+/// 设置了容量限制时，写入后超出限制的部分按淘汰策略删除
 pub struct MemoryKvStore {
-    data: Arc<RwLock<HashMap<String, KvEntry>>>,
+    data: Arc<RwLock<MemoryData>>,
+    feed: KvFeed,
+    limits: KvLimits,
+    counters: KvCounters,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    entries: HashMap<String, KvEntry>,
+    /// 最近一次写入的版本
+    version: u64,
+    /// 键、值和元数据的总字节数
+    bytes: u64,
+    /// 淘汰顺序：(访问次数, 最近一次访问) -> 键，只在有容量限制时维护
+    order: BTreeMap<(u64, u64), String>,
+    /// 每次访问递增的逻辑时钟
+    tick: u64,
+    evictions: u64,
 }
 
 struct KvEntry {
     value: Vec<u8>,
-    expires_at: Option<Instant>,
+    metadata: Option<String>,
+    /// Unix 毫秒时间戳
+    expires_at: Option<u64>,
+    version: u64,
+    /// 访问次数
+    hits: u64,
+    /// 在 `MemoryData::order` 中的位置
+    rank: (u64, u64),
+}
+
+impl KvEntry {
+    fn is_live(&self, now: u64) -> bool {
+        self.expires_at.map(|exp| exp > now).unwrap_or(true)
+    }
+
+    /// 未过期时返回值的副本
+    fn to_value(&self, now: u64) -> Option<KvValue> {
+        self.is_live(now).then(|| KvValue {
+            value: self.value.clone(),
+            metadata: self.metadata.clone(),
+            expires_at: self.expires_at,
+            version: self.version,
+        })
+    }
+}
+
+/// 条目占用的字节数
+fn entry_size(key: &str, value: &[u8], metadata: Option<&str>) -> u64 {
+    (key.len() + value.len() + metadata.map_or(0, str::len)) as u64
+}
+
+impl MemoryData {
+    /// 记录一次访问，更新淘汰顺序
+    fn touch(&mut self, key: &str, limits: &KvLimits) {
+        if !limits.is_bounded() {
+            return;
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        let Some(entry) = self.entries.get_mut(key) else {
+            return;
+        };
+        self.order.remove(&entry.rank);
+        entry.hits += 1;
+        entry.rank = match limits.eviction {
+            EvictionPolicy::Lru => (0, tick),
+            EvictionPolicy::Lfu => (entry.hits, tick),
+        };
+        self.order.insert(entry.rank, key.to_string());
+    }
+
+    /// 写入条目，覆盖时保留访问次数
+    fn insert(&mut self, key: String, value: KvValue, limits: &KvLimits) {
+        let hits = self.remove(&key).map_or(0, |previous| previous.hits);
+        self.bytes += entry_size(&key, &value.value, value.metadata.as_deref());
+        self.entries.insert(
+            key.clone(),
+            KvEntry {
+                value: value.value,
+                metadata: value.metadata,
+                expires_at: value.expires_at,
+                version: value.version,
+                hits,
+                rank: (0, 0),
+            },
+        );
+        self.touch(&key, limits);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<KvEntry> {
+        let entry = self.entries.remove(key)?;
+        self.bytes -= entry_size(key, &entry.value, entry.metadata.as_deref());
+        self.order.remove(&entry.rank);
+        Some(entry)
+    }
+
+    /// 超出容量限制时按淘汰顺序删除键
+    ///
+    /// 优先淘汰 `written` 以外的键，否则 LFU 下刚写入的键访问次数最少，会被立即淘汰
+    fn evict(&mut self, limits: &KvLimits, feed: &KvFeed, written: &[String]) {
+        let over = |data: &MemoryData| {
+            limits.max_entries.is_some_and(|max| data.entries.len() > max)
+                || limits.max_bytes.is_some_and(|max| data.bytes > max)
+        };
+        while over(self) {
+            let victim = self
+                .order
+                .values()
+                .find(|key| !written.contains(key))
+                .or_else(|| self.order.values().next())
+                .cloned();
+            let Some(key) = victim else {
+                break;
+            };
+            self.remove(&key);
+            self.evictions += 1;
+            feed.publish(KvChangeKind::Evict, &key, None);
+        }
+    }
 }
 
 impl Default for MemoryKvStore {
@@ -67,129 +711,590 @@ impl Default for MemoryKvStore {
 
 impl MemoryKvStore {
     pub fn new() -> Self {
+        Self::with_limits(KvLimits::default())
+    }
+
+    /// 创建有容量限制的存储
+    pub fn with_limits(limits: KvLimits) -> Self {
         Self {
-            data: Arc::new(RwLock::new(HashMap::new())),
+            data: Arc::new(RwLock::new(MemoryData::default())),
+            feed: KvFeed::new(),
+            limits,
+            counters: KvCounters::default(),
         }
     }
 
+    /// 创建有容量限制的存储，并启动删除过期键的后台线程
+    pub fn open(limits: KvLimits) -> Arc<Self> {
+        let store = Arc::new(Self::with_limits(limits));
+        spawn_reaper(&store, REAP_INTERVAL);
+        store
+    }
+
     /// 清理过期的键
     pub fn cleanup_expired(&self) {
-        if let Ok(mut data) = self.data.write() {
-            let now = Instant::now();
-            data.retain(|_, entry| {
-                entry.expires_at.map(|exp| exp > now).unwrap_or(true)
-            });
-        }
+        self.purge_expired().ok();
     }
 }
 
 impl KvStore for MemoryKvStore {
-    fn get(&self, key: &str) -> Option<Vec<u8>> {
-        let data = self.data.read().ok()?;
-        let entry = data.get(key)?;
+    fn get_with_metadata(&self, key: &str) -> Option<KvValue> {
+        let now = now_millis();
+        let value = if self.limits.is_bounded() {
+            // 访问会改变淘汰顺序，需要写锁
+            let mut data = self.data.write().ok()?;
+            let value = data.entries.get(key).and_then(|entry| entry.to_value(now));
+            if value.is_some() {
+                data.touch(key, &self.limits);
+            }
+            value
+        } else {
+            let data = self.data.read().ok()?;
+            data.entries.get(key).and_then(|entry| entry.to_value(now))
+        };
+        self.counters.record(value.is_some());
+        value
+    }
 
-        // 检查是否过期
-        if let Some(expires_at) = entry.expires_at {
-            if Instant::now() > expires_at {
-                return None;
+    fn delete(&self, key: &str) -> Result<bool, String> {
+        let mut data = self.data.write().map_err(|e| e.to_string())?;
+        // 已过期的键视为不存在
+        let now = now_millis();
+        match data.remove(key) {
+            Some(entry) if entry.is_live(now) => {
+                self.feed.publish(KvChangeKind::Delete, key, None);
+                Ok(true)
+            }
+            Some(_) => {
+                self.feed.publish(KvChangeKind::Expire, key, None);
+                Ok(false)
             }
+            None => Ok(false),
         }
+    }
+
+    fn atomic(&self, checks: &[KvCheck], mutations: &[KvMutation]) -> Result<Option<u64>, String> {
+        let mut data = self.data.write().map_err(|e| e.to_string())?;
+        let now = now_millis();
+        let version = next_version(data.version);
+
+        let entries = &data.entries;
+        let Some(writes) = resolve_atomic(checks, mutations, version, |key| {
+            Ok(entries.get(key).and_then(|entry| entry.to_value(now)))
+        })?
+        else {
+            return Ok(None);
+        };
 
-        Some(entry.value.clone())
+        if let Some(max) = self.limits.max_bytes {
+            for (key, value) in &writes {
+                if value
+                    .as_ref()
+                    .is_some_and(|value| entry_size(key, &value.value, value.metadata.as_deref()) > max)
+                {
+                    return Err(format!("Value for {} exceeds the namespace limit of {} bytes", key, max));
+                }
+            }
+        }
+
+        self.feed.publish_writes(&writes);
+        let written: Vec<String> = writes.iter().map(|(key, _)| key.clone()).collect();
+        for (key, value) in writes {
+            match value {
+                Some(value) => data.insert(key, value, &self.limits),
+                None => {
+                    data.remove(&key);
+                }
+            }
+        }
+        data.version = version;
+        data.evict(&self.limits, &self.feed, &written);
+        Ok(Some(version))
     }
 
-    fn put(&self, key: &str, value: &[u8], ttl: Option<Duration>) -> Result<(), String> {
+    fn list_keys(&self, prefix: Option<&str>, start_after: Option<&str>, limit: Option<usize>) -> Vec<KvKey> {
+        let data = match self.data.read() {
+            Ok(d) => d,
+            Err(_) => return vec![],
+        };
+
+        let now = now_millis();
+        let mut keys: Vec<KvKey> = data
+            .entries
+            .iter()
+            .filter(|(k, entry)| {
+                // 过滤掉过期的键，按前缀和起始位置过滤
+                entry.is_live(now)
+                    && prefix.map(|p| k.starts_with(p)).unwrap_or(true)
+                    && start_after.map(|after| k.as_str() > after).unwrap_or(true)
+            })
+            .map(|(k, entry)| KvKey {
+                name: k.clone(),
+                metadata: entry.metadata.clone(),
+                expires_at: entry.expires_at,
+            })
+            .collect();
+
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+
+        if let Some(limit) = limit {
+            keys.truncate(limit);
+        }
+
+        keys
+    }
+
+    fn feed(&self) -> Option<&KvFeed> {
+        Some(&self.feed)
+    }
+
+    fn purge_expired(&self) -> Result<usize, String> {
         let mut data = self.data.write().map_err(|e| e.to_string())?;
+        let now = now_millis();
+        let expired: Vec<String> = data
+            .entries
+            .iter()
+            .filter(|(_, entry)| !entry.is_live(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            data.remove(key);
+            self.feed.publish(KvChangeKind::Expire, key, None);
+        }
+        Ok(expired.len())
+    }
 
-        let expires_at = ttl.map(|d| Instant::now() + d);
+    fn stats(&self) -> KvStats {
+        let (entries, bytes, evictions) = match self.data.read() {
+            Ok(data) => (data.entries.len(), data.bytes, data.evictions),
+            Err(_) => (0, 0, 0),
+        };
+        KvStats {
+            entries,
+            bytes,
+            evictions,
+            max_entries: self.limits.max_entries,
+            max_bytes: self.limits.max_bytes,
+            ..self.counters.stats()
+        }
+    }
+}
 
-        data.insert(
-            key.to_string(),
-            KvEntry {
-                value: value.to_vec(),
-                expires_at,
+/// KV 绑定
+///
+/// 将 KV 存储暴露给 JS 环境
+pub struct KvBinding {
+    name: String,
+    store: Box<dyn KvStore>,
+    /// `watch` 的订阅：订阅 ID -> (前缀, 订阅)
+    watches: Mutex<HashMap<u64, (String, KvSubscription)>>,
+    /// 上次清理过期键的时间
+    last_purge: Mutex<Instant>,
+}
+
+impl KvBinding {
+    /// 创建新的 KV 绑定
+    pub fn new(name: &str, store: Box<dyn KvStore>) -> Self {
+        Self {
+            name: name.to_string(),
+            store,
+            watches: Mutex::new(HashMap::new()),
+            last_purge: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// 使用内存存储创建 KV 绑定
+    pub fn memory(name: &str) -> Self {
+        Self::new(name, Box::new(MemoryKvStore::new()))
+    }
+
+    /// 解析写入的值
+    fn parse_value(value: Option<&BindingValue>) -> Result<Vec<u8>, String> {
+        match value {
+            Some(BindingValue::String(v)) => Ok(v.as_bytes().to_vec()),
+            Some(BindingValue::Bytes(v)) => Ok(v.clone()),
+            Some(BindingValue::Int(v)) => Ok(v.to_string().into_bytes()),
+            Some(BindingValue::Float(v)) => Ok(v.to_string().into_bytes()),
+            Some(BindingValue::Json(v)) => Ok(v.as_bytes().to_vec()),
+            _ => Err("put requires a value".to_string()),
+        }
+    }
+
+    /// 解析过期时间选项，返回 Unix 毫秒时间戳
+    ///
+    /// `options` 是 options 对象，`expiration` 为 Unix 时间戳（秒），`expirationTtl` 为秒数；
+    /// 也支持直接传 TTL 秒数
+    fn parse_expiration(options: Option<&BindingValue>) -> Result<Option<u64>, String> {
+        let (expiration, ttl) = match options {
+            Some(BindingValue::Object(opts)) => (opts.get("expiration"), opts.get("expirationTtl")),
+            Some(ttl) => (None, Some(ttl)),
+            None => (None, None),
+        };
+
+        let expires_at = if let Some(ttl) = ttl.and_then(as_seconds).transpose()? {
+            let ttl = Duration::try_from_secs_f64(ttl).map_err(|_| format!("Invalid expiration: {}", ttl))?;
+            expires_after(ttl)
+        } else if let Some(expiration) = expiration.and_then(as_seconds).transpose()? {
+            (expiration * 1000.0) as u64
+        } else {
+            return Ok(None);
+        };
+        // 超出范围的时间会在存储和转换时饱和，直接拒绝
+        if expires_at > MAX_EXPIRATION_MILLIS {
+            return Err("Invalid expiration: must be before the year 10000".to_string());
+        }
+        Ok(Some(expires_at))
+    }
+
+    /// 解析 `metadata` 选项，返回序列化后的 JSON
+    fn parse_metadata(options: Option<&BindingValue>) -> Result<Option<String>, String> {
+        let metadata = match options {
+            Some(BindingValue::Object(opts)) => opts.get("metadata"),
+            _ => None,
+        };
+        let Some(metadata) = metadata.filter(|m| !matches!(m, BindingValue::Null)) else {
+            return Ok(None);
+        };
+
+        let json = metadata
+            .to_json()
+            .map_err(|e| format!("Invalid metadata: {}", e))?
+            .to_string();
+        if json.len() > MAX_METADATA_SIZE {
+            return Err(format!(
+                "Metadata is {} bytes, the limit is {} bytes",
+                json.len(),
+                MAX_METADATA_SIZE
+            ));
+        }
+        Ok(Some(json))
+    }
+
+    /// 按 `type` 选项（第二个参数，字符串或 `{ type }`）转换读到的值
+    fn typed_value(args: &[BindingValue], value: Vec<u8>) -> BindingValue {
+        let kind = match args.get(1) {
+            Some(BindingValue::String(kind)) => kind.as_str(),
+            Some(BindingValue::Object(opts)) => opts.get("type").and_then(|t| t.as_string()).unwrap_or("text"),
+            _ => "text",
+        };
+
+        match kind {
+            "text" => BindingValue::String(String::from_utf8_lossy(&value).into_owned()),
+            "json" => match serde_json::from_slice::<serde_json::Value>(&value) {
+                Ok(json) => BindingValue::Json(json.to_string()),
+                Err(e) => BindingValue::Error(format!("Value is not valid JSON: {}", e)),
             },
-        );
+            "arrayBuffer" => BindingValue::Bytes(value),
+            other => BindingValue::Error(format!("Unsupported type: {}", other)),
+        }
+    }
 
-        Ok(())
+    /// `compareAndSwap(key, version, value, options)`，`version` 为 `null` 表示键必须不存在
+    fn compare_and_swap(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let key = match args.first() {
+            Some(BindingValue::String(k)) => k,
+            _ => return Err("compareAndSwap requires a string key".to_string()),
+        };
+        let expected = parse_version(args.get(1))?;
+        let value = Self::parse_value(args.get(2))?;
+        let expires_at = Self::parse_expiration(args.get(3))?;
+        let metadata = Self::parse_metadata(args.get(3))?;
+
+        let version = self
+            .store
+            .compare_and_swap(key, expected, &value, metadata.as_deref(), expires_at)?;
+        Ok(commit_result(version))
     }
 
-    fn delete(&self, key: &str) -> Result<bool, String> {
-        let mut data = self.data.write().map_err(|e| e.to_string())?;
-        Ok(data.remove(key).is_some())
+    /// `commitAtomic(checks, mutations)`，由 `KV.atomic()` 构建器调用
+    fn commit_atomic(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let (Some(BindingValue::Array(checks)), Some(BindingValue::Array(mutations))) = (args.first(), args.get(1))
+        else {
+            return Err("commitAtomic requires arrays of checks and mutations".to_string());
+        };
+
+        let field = |item: &BindingValue, name: &str| match item {
+            BindingValue::Object(obj) => obj.get(name).cloned(),
+            _ => None,
+        };
+        let key = |item: &BindingValue| match field(item, "key") {
+            Some(BindingValue::String(key)) => Ok(key),
+            _ => Err("Atomic operations require a string key".to_string()),
+        };
+
+        let checks = checks
+            .iter()
+            .map(|check| {
+                Ok(KvCheck {
+                    key: key(check)?,
+                    version: parse_version(field(check, "version").as_ref())?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let mutations = mutations
+            .iter()
+            .map(|mutation| {
+                let key = key(mutation)?;
+                match field(mutation, "type").as_ref().and_then(|t| t.as_string()) {
+                    Some("put") => {
+                        let options = field(mutation, "options");
+                        Ok(KvMutation::Put {
+                            key,
+                            value: Self::parse_value(field(mutation, "value").as_ref())?,
+                            metadata: Self::parse_metadata(options.as_ref())?,
+                            expires_at: Self::parse_expiration(options.as_ref())?,
+                        })
+                    }
+                    Some("delete") => Ok(KvMutation::Delete { key }),
+                    Some("increment") => Ok(KvMutation::Increment {
+                        key,
+                        delta: parse_delta(field(mutation, "delta").as_ref())?,
+                    }),
+                    other => Err(format!("Unknown atomic operation: {:?}", other)),
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(commit_result(self.store.atomic(&checks, &mutations)?))
     }
 
-    fn list(&self, prefix: Option<&str>, limit: Option<usize>) -> Vec<String> {
-        let data = match self.data.read() {
-            Ok(d) => d,
-            Err(_) => return vec![],
+    /// `putMany([{ key, value, metadata?, expiration?, expirationTtl? }])`，所有键在一次原子提交中写入
+    fn put_many(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let Some(BindingValue::Array(entries)) = args.first() else {
+            return Err("putMany requires an array of entries".to_string());
+        };
+        if entries.len() > MAX_BULK_SIZE {
+            return Err(format!("putMany accepts at most {} entries", MAX_BULK_SIZE));
+        }
+
+        let records = entries
+            .iter()
+            .map(|entry| {
+                let BindingValue::Object(fields) = entry else {
+                    return Err(format!("putMany entries must be objects, got {}", entry));
+                };
+                let Some(BindingValue::String(key)) = fields.get("key") else {
+                    return Err("putMany entries require a string key".to_string());
+                };
+                // 条目本身就是 put 的 options
+                Ok(KvRecord {
+                    key: key.clone(),
+                    value: Self::parse_value(fields.get("value"))?,
+                    metadata: Self::parse_metadata(Some(entry))?,
+                    expires_at: Self::parse_expiration(Some(entry))?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        self.store.put_many(&records)?;
+        Ok(BindingValue::Null)
+    }
+
+    /// `deleteMany(keys)`，所有键在一次原子提交中删除
+    fn delete_many(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let Some(BindingValue::Array(keys)) = args.first() else {
+            return Err("deleteMany requires an array of keys".to_string());
+        };
+        if keys.len() > MAX_BULK_SIZE {
+            return Err(format!("deleteMany accepts at most {} keys", MAX_BULK_SIZE));
+        }
+
+        let keys = keys
+            .iter()
+            .map(|key| match key {
+                BindingValue::String(key) => Ok(key.clone()),
+                other => Err(format!("deleteMany keys must be strings, got {}", other)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        self.store.delete_many(&keys)?;
+        Ok(BindingValue::Null)
+    }
+
+    /// `subscribe(prefix)`，返回订阅 ID，由 `KV.watch()` 调用
+    fn subscribe(&self, args: &[BindingValue]) -> Result<BindingValue, String> {
+        let prefix = match args.first() {
+            Some(BindingValue::String(prefix)) => prefix.clone(),
+            None | Some(BindingValue::Null) => String::new(),
+            Some(other) => return Err(format!("watch prefix must be a string, got {}", other)),
+        };
+        let subscription = self.store.watch(&prefix)?;
+        let id = NEXT_WATCH_ID.fetch_add(1, Ordering::Relaxed);
+        self.watches
+            .lock()
+            .map_err(|e| e.to_string())?
+            .insert(id, (prefix, subscription));
+        Ok(BindingValue::Int(id as i64))
+    }
+
+    /// `pollChanges()`，取出所有订阅上已到达的变更：`[{ id, seq, type, key, value?, metadata?, expiration?, version? }]`
+    ///
+    /// 订阅落后时发出 `{ id, type: "lagged" }` 并重新订阅，回调应当重新读取需要的数据
+    fn poll_changes(&self) -> Result<BindingValue, String> {
+        let mut watches = self.watches.lock().map_err(|e| e.to_string())?;
+        if watches.is_empty() {
+            return Ok(BindingValue::Array(Vec::new()));
+        }
+
+        // 过期事件在清理时才发出，有订阅时定期清理
+        if let Ok(mut last_purge) = self.last_purge.lock() {
+            if last_purge.elapsed() >= EXPIRE_CHECK_INTERVAL {
+                *last_purge = Instant::now();
+                self.store.purge_expired()?;
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (id, (prefix, subscription)) in watches.iter_mut() {
+            changes.extend(subscription.drain().into_iter().map(|change| change_to_binding(*id, change)));
+            if subscription.is_lagged() {
+                let mut lagged = HashMap::new();
+                lagged.insert("id".to_string(), BindingValue::Int(*id as i64));
+                lagged.insert("type".to_string(), BindingValue::String("lagged".to_string()));
+                changes.push(BindingValue::Object(lagged));
+                *subscription = self.store.watch(prefix)?;
+            }
+        }
+        Ok(BindingValue::Array(changes))
+    }
+
+    fn list(&self, args: &[BindingValue]) -> BindingValue {
+        let opts = match args.first() {
+            Some(BindingValue::Object(opts)) => Some(opts),
+            _ => None,
+        };
+        let prefix = match args.first() {
+            Some(BindingValue::String(s)) => Some(s.as_str()),
+            _ => opts.and_then(|o| o.get("prefix")).and_then(|p| p.as_string()),
+        };
+
+        let limit = match opts.and_then(|o| o.get("limit")) {
+            Some(BindingValue::Int(l)) if *l > 0 => (*l as usize).min(MAX_LIST_LIMIT),
+            Some(BindingValue::Int(_)) => return BindingValue::Error("limit must be positive".to_string()),
+            _ => MAX_LIST_LIMIT,
+        };
+
+        // 游标是上一页最后一个键的编码
+        let start_after = match opts.and_then(|o| o.get("cursor")).and_then(|c| c.as_string()) {
+            Some(cursor) if !cursor.is_empty() => match decode_cursor(cursor) {
+                Some(key) => Some(key),
+                None => return BindingValue::Error("Invalid cursor".to_string()),
+            },
+            _ => None,
         };
 
-        let now = Instant::now();
-        let mut keys: Vec<String> = data
-            .iter()
-            .filter(|(_, entry)| {
-                // 过滤掉过期的键
-                entry.expires_at.map(|exp| exp > now).unwrap_or(true)
-            })
-            .map(|(k, _)| k.clone())
-            .filter(|k| {
-                // 按前缀过滤
-                prefix.map(|p| k.starts_with(p)).unwrap_or(true)
+        // 多取一个判断是否还有下一页
+        let mut keys = self.store.list_keys(prefix, start_after.as_deref(), Some(limit + 1));
+        let list_complete = keys.len() <= limit;
+        keys.truncate(limit);
+
+        let mut obj = HashMap::new();
+        if !list_complete {
+            if let Some(last) = keys.last() {
+                obj.insert("cursor".to_string(), BindingValue::String(URL_SAFE_NO_PAD.encode(&last.name)));
+            }
+        }
+        let keys = keys
+            .into_iter()
+            .map(|key| {
+                let mut entry = HashMap::new();
+                entry.insert("name".to_string(), BindingValue::String(key.name));
+                if let Some(expires_at) = key.expires_at {
+                    entry.insert("expiration".to_string(), BindingValue::Int((expires_at / 1000) as i64));
+                }
+                if let Some(metadata) = key.metadata {
+                    entry.insert("metadata".to_string(), BindingValue::Json(metadata));
+                }
+                BindingValue::Object(entry)
             })
             .collect();
+        obj.insert("keys".to_string(), BindingValue::Array(keys));
+        obj.insert("list_complete".to_string(), BindingValue::Bool(list_complete));
+        BindingValue::Object(obj)
+    }
+}
 
-        keys.sort();
+/// 把 JS 传入的秒数转换为 `f64`，必须是非负的有限数
+fn as_seconds(value: &BindingValue) -> Option<Result<f64, String>> {
+    let seconds = match value {
+        BindingValue::Null => return None,
+        BindingValue::Int(i) => *i as f64,
+        BindingValue::Float(f) => *f,
+        BindingValue::String(s) => match s.parse::<f64>() {
+            Ok(n) => n,
+            Err(_) => return Some(Err(format!("Invalid expiration: {}", s))),
+        },
+        other => return Some(Err(format!("Invalid expiration: {}", other))),
+    };
+    if seconds.is_finite() && seconds >= 0.0 {
+        Some(Ok(seconds))
+    } else {
+        Some(Err(format!("Invalid expiration: {}", seconds)))
+    }
+}
 
-        if let Some(limit) = limit {
-            keys.truncate(limit);
+/// 把变更转换为 `pollChanges` 返回的对象
+fn change_to_binding(id: u64, change: KvChange) -> BindingValue {
+    let mut obj = HashMap::new();
+    obj.insert("id".to_string(), BindingValue::Int(id as i64));
+    obj.insert("seq".to_string(), BindingValue::Int(change.seq as i64));
+    obj.insert("type".to_string(), BindingValue::String(change.kind.as_str().to_string()));
+    obj.insert("key".to_string(), BindingValue::String(change.key));
+    if let Some(entry) = change.value {
+        // 文本值直接给出字符串，二进制值给出 ArrayBuffer
+        let value = match String::from_utf8(entry.value) {
+            Ok(text) => BindingValue::String(text),
+            Err(e) => BindingValue::Bytes(e.into_bytes()),
+        };
+        obj.insert("value".to_string(), value);
+        obj.insert("version".to_string(), BindingValue::String(format_version(entry.version)));
+        if let Some(metadata) = entry.metadata {
+            obj.insert("metadata".to_string(), BindingValue::Json(metadata));
+        }
+        if let Some(expires_at) = entry.expires_at {
+            obj.insert("expiration".to_string(), BindingValue::Int((expires_at / 1000) as i64));
         }
-
-        keys
     }
+    BindingValue::Object(obj)
 }
 
-/// KV 绑定
-///
-/// 将 KV 存储暴露给 JS 环境
-pub struct KvBinding {
-    name: String,
-    store: Box<dyn KvStore>,
+/// 版本令牌：16 位十六进制数
+fn format_version(version: u64) -> String {
+    format!("{:016x}", version)
 }
 
-impl KvBinding {
-    /// 创建新的 KV 绑定
-    pub fn new(name: &str, store: Box<dyn KvStore>) -> Self {
-        Self {
-            name: name.to_string(),
-            store,
-        }
+fn parse_version(token: Option<&BindingValue>) -> Result<Option<u64>, String> {
+    match token {
+        None | Some(BindingValue::Null) => Ok(None),
+        Some(BindingValue::String(token)) => u64::from_str_radix(token, 16)
+            .map(Some)
+            .map_err(|_| format!("Invalid version: {}", token)),
+        Some(other) => Err(format!("Invalid version: {}", other)),
     }
+}
 
-    /// 使用内存存储创建 KV 绑定
-    pub fn memory(name: &str) -> Self {
-        Self::new(name, Box::new(MemoryKvStore::new()))
+fn parse_delta(delta: Option<&BindingValue>) -> Result<i64, String> {
+    match delta {
+        None | Some(BindingValue::Null) => Ok(1),
+        Some(BindingValue::Int(delta)) => Ok(*delta),
+        Some(other) => Err(format!("Increment must be an integer, got {}", other)),
     }
+}
 
-    /// 解析 TTL 参数
-    fn parse_ttl(args: &[BindingValue]) -> Option<Duration> {
-        // 第三个参数是 options 对象，包含 expirationTtl
-        if args.len() > 2 {
-            if let BindingValue::Object(opts) = &args[2] {
-                if let Some(BindingValue::Int(ttl)) = opts.get("expirationTtl") {
-                    return Some(Duration::from_secs(*ttl as u64));
-                }
-                if let Some(BindingValue::Float(ttl)) = opts.get("expirationTtl") {
-                    return Some(Duration::from_secs(*ttl as u64));
-                }
-            }
-            // 也支持直接传 TTL 秒数作为第三个参数
-            if let BindingValue::Int(ttl) = &args[2] {
-                return Some(Duration::from_secs(*ttl as u64));
-            }
-        }
-        None
-    }
+/// 原子操作的结果：`{ ok, version }`，失败时 `version` 为 `null`
+fn commit_result(version: Option<u64>) -> BindingValue {
+    let mut obj = HashMap::new();
+    obj.insert("ok".to_string(), BindingValue::Bool(version.is_some()));
+    obj.insert(
+        "version".to_string(),
+        version.map(|v| BindingValue::String(format_version(v))).unwrap_or(BindingValue::Null),
+    );
+    BindingValue::Object(obj)
+}
+
+fn decode_cursor(cursor: &str) -> Option<String> {
+    String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()
 }
 
 impl NativeBinding for KvBinding {
@@ -202,11 +1307,24 @@ impl NativeBinding for KvBinding {
             BindingMethod::async_method("get", 1),
             BindingMethod::async_method("put", 2),
             BindingMethod::async_method("delete", 1),
+            BindingMethod::async_method("putMany", 1),
+            BindingMethod::async_method("deleteMany", 1),
             BindingMethod::async_method("list", 0),
-            BindingMethod::new("getWithMetadata", 1),
+            BindingMethod::async_method("getWithMetadata", 1),
+            BindingMethod::async_method("compareAndSwap", 3),
+            BindingMethod::async_method("increment", 1),
+            BindingMethod::async_method("commitAtomic", 2),
+            BindingMethod::new("subscribe", 1),
+            BindingMethod::new("unsubscribe", 1),
+            BindingMethod::new("pollChanges", 0),
+            BindingMethod::async_method("stats", 0),
         ]
     }
 
+    fn js_wrapper(&self) -> Option<&'static str> {
+        Some(KV_JS)
+    }
+
     fn call(&self, method: &str, args: Vec<BindingValue>) -> BindingValue {
         match method {
             "get" => {
@@ -216,35 +1334,61 @@ impl NativeBinding for KvBinding {
                 };
 
                 match self.store.get(key) {
-                    Some(data) => {
-                        // 尝试转换为 UTF-8 字符串
-                        match String::from_utf8(data.clone()) {
-                            Ok(s) => BindingValue::String(s),
-                            Err(_) => BindingValue::Bytes(data),
-                        }
-                    }
+                    Some(data) => Self::typed_value(&args, data),
                     None => BindingValue::Null,
                 }
             }
 
+            "getWithMetadata" => {
+                let key = match args.first() {
+                    Some(BindingValue::String(k)) => k,
+                    _ => return BindingValue::Error("getWithMetadata requires a string key".to_string()),
+                };
+
+                let (value, metadata, version) = match self.store.get_with_metadata(key) {
+                    Some(entry) => {
+                        let value = Self::typed_value(&args, entry.value);
+                        if value.is_error() {
+                            return value;
+                        }
+                        (
+                            value,
+                            entry.metadata.map(BindingValue::Json).unwrap_or(BindingValue::Null),
+                            BindingValue::String(format_version(entry.version)),
+                        )
+                    }
+                    None => (BindingValue::Null, BindingValue::Null, BindingValue::Null),
+                };
+
+                // 返回 { value, metadata, version } 格式，version 用于 compareAndSwap
+                let mut obj = HashMap::new();
+                obj.insert("value".to_string(), value);
+                obj.insert("metadata".to_string(), metadata);
+                obj.insert("version".to_string(), version);
+                BindingValue::Object(obj)
+            }
+
             "put" => {
                 let key = match args.first() {
                     Some(BindingValue::String(k)) => k.clone(),
                     _ => return BindingValue::Error("put requires a string key".to_string()),
                 };
 
-                let value = match args.get(1) {
-                    Some(BindingValue::String(v)) => v.as_bytes().to_vec(),
-                    Some(BindingValue::Bytes(v)) => v.clone(),
-                    Some(BindingValue::Int(v)) => v.to_string().into_bytes(),
-                    Some(BindingValue::Float(v)) => v.to_string().into_bytes(),
-                    Some(BindingValue::Json(v)) => v.as_bytes().to_vec(),
-                    _ => return BindingValue::Error("put requires a value".to_string()),
+                let value = match Self::parse_value(args.get(1)) {
+                    Ok(value) => value,
+                    Err(e) => return BindingValue::Error(e),
                 };
 
-                let ttl = Self::parse_ttl(&args);
+                let expires_at = match Self::parse_expiration(args.get(2)) {
+                    Ok(expires_at) => expires_at,
+                    Err(e) => return BindingValue::Error(e),
+                };
+                let metadata = match Self::parse_metadata(args.get(2)) {
+                    Ok(metadata) => metadata,
+                    Err(e) => return BindingValue::Error(e),
+                };
 
-                match self.store.put(&key, &value, ttl) {
+                match self.store.put_with_metadata(&key, &value, metadata.as_deref(), expires_at) {
                     Ok(_) => BindingValue::Null,
                     Err(e) => BindingValue::Error(e),
                 }
@@ -262,47 +1406,397 @@ impl NativeBinding for KvBinding {
                 }
             }
 
-            "list" => {
-                let prefix = args.first().and_then(|v| {
-                    if let BindingValue::String(s) = v {
-                        Some(s.as_str())
-                    } else if let BindingValue::Object(opts) = v {
-                        opts.get("prefix").and_then(|p| p.as_string())
-                    } else {
-                        None
-                    }
-                });
+            "putMany" => self.put_many(&args).unwrap_or_else(BindingValue::Error),
 
-                let limit = args.first().and_then(|v| {
-                    if let BindingValue::Object(opts) = v {
-                        if let Some(BindingValue::Int(l)) = opts.get("limit") {
-                            return Some(*l as usize);
-                        }
-                    }
-                    None
-                });
+            "deleteMany" => self.delete_many(&args).unwrap_or_else(BindingValue::Error),
+
+            "list" => self.list(&args),
+
+            "compareAndSwap" => self.compare_and_swap(&args).unwrap_or_else(BindingValue::Error),
+
+            "increment" => {
+                let key = match args.first() {
+                    Some(BindingValue::String(k)) => k,
+                    _ => return BindingValue::Error("increment requires a string key".to_string()),
+                };
+
+                match parse_delta(args.get(1)).and_then(|delta| self.store.increment(key, delta)) {
+                    Ok(value) => BindingValue::Int(value),
+                    Err(e) => BindingValue::Error(e),
+                }
+            }
 
-                let keys = self.store.list(prefix, limit);
-                let result: Vec<BindingValue> = keys
-                    .into_iter()
-                    .map(BindingValue::String)
-                    .collect();
+            "commitAtomic" => self.commit_atomic(&args).unwrap_or_else(BindingValue::Error),
 
-                // 返回 { keys: [...] } 格式
+            "subscribe" => self.subscribe(&args).unwrap_or_else(BindingValue::Error),
+
+            "unsubscribe" => {
+                let removed = match args.first() {
+                    Some(BindingValue::Int(id)) => self
+                        .watches
+                        .lock()
+                        .map(|mut watches| watches.remove(&(*id as u64)).is_some())
+                        .unwrap_or(false),
+                    _ => false,
+                };
+                BindingValue::Bool(removed)
+            }
+
+            "pollChanges" => self.poll_changes().unwrap_or_else(BindingValue::Error),
+
+            "stats" => {
+                let stats = self.store.stats();
                 let mut obj = HashMap::new();
-                obj.insert("keys".to_string(), BindingValue::Array(result));
+                obj.insert("entries".to_string(), BindingValue::Int(stats.entries as i64));
+                obj.insert("bytes".to_string(), BindingValue::Int(stats.bytes as i64));
+                obj.insert("hits".to_string(), BindingValue::Int(stats.hits as i64));
+                obj.insert("misses".to_string(), BindingValue::Int(stats.misses as i64));
+                obj.insert("evictions".to_string(), BindingValue::Int(stats.evictions as i64));
+                if let Some(max) = stats.max_entries {
+                    obj.insert("maxEntries".to_string(), BindingValue::Int(max as i64));
+                }
+                if let Some(max) = stats.max_bytes {
+                    obj.insert("maxBytes".to_string(), BindingValue::Int(max as i64));
+                }
                 BindingValue::Object(obj)
             }
 
             _ => BindingValue::Error(format!("Unknown method: {}", method)),
         }
     }
+
+    fn flush(&self) -> Result<(), String> {
+        self.store.flush()
+    }
+}
+
+/// JS 侧的 KV API：`KV.atomic()` 构建器收集检查和修改后一次提交；
+/// `KV.watch()` 把回调登记到 `__raven_kv_watch`，由运行时的事件循环调用 `dispatch()` 分发变更
+const KV_JS: &str = r#"
+(function (kv) {
+    const watch = globalThis.__raven_kv_watch || (globalThis.__raven_kv_watch = {
+        sources: [],
+        callbacks: new Map(),
+        errors: [],
+        dispatch() {
+            const errors = this.errors.splice(0);
+            for (const source of this.sources) {
+                const changes = source.pollChanges();
+                if (!Array.isArray(changes)) {
+                    errors.push(String(changes));
+                    continue;
+                }
+                for (const change of changes) {
+                    const callback = this.callbacks.get(change.id);
+                    if (!callback) continue;
+                    delete change.id;
+                    try {
+                        const result = callback(change);
+                        if (result && typeof result.then === "function") {
+                            result.then(undefined, (e) => this.errors.push(String(e)));
+                        }
+                    } catch (e) {
+                        errors.push(String(e));
+                    }
+                }
+            }
+            return errors;
+        },
+    });
+
+    kv.watch = function (prefix, callback) {
+        if (typeof prefix === "function") {
+            callback = prefix;
+            prefix = "";
+        }
+        if (typeof callback !== "function") {
+            throw new TypeError("KV.watch requires a callback");
+        }
+        const id = kv.subscribe(prefix || "");
+        if (typeof id !== "number") {
+            throw new Error(String(id));
+        }
+        if (!watch.sources.includes(kv)) {
+            watch.sources.push(kv);
+        }
+        watch.callbacks.set(id, callback);
+        return {
+            unsubscribe() {
+                watch.callbacks.delete(id);
+                kv.unsubscribe(id);
+            },
+        };
+    };
+
+    kv.atomic = function () {
+        const checks = [];
+        const mutations = [];
+        const operation = {
+            check(key, version) {
+                checks.push({ key, version: version == null ? null : version });
+                return operation;
+            },
+            put(key, value, options) {
+                mutations.push({ type: "put", key, value, options: options || {} });
+                return operation;
+            },
+            delete(key) {
+                mutations.push({ type: "delete", key });
+                return operation;
+            },
+            increment(key, delta) {
+                mutations.push({ type: "increment", key, delta: delta === undefined ? 1 : delta });
+                return operation;
+            },
+            async commit() {
+                return kv.commitAtomic(checks, mutations);
+            },
+        };
+        return operation;
+    };
+    return kv;
+})
+"#;
+
+/// 所有 `KvStore` 后端都要通过的行为测试
+#[cfg(test)]
+pub(crate) mod conformance {
+    use std::time::Duration;
+
+    use super::{now_millis, KvChangeKind, KvCheck, KvKey, KvMutation, KvStore, KvValue};
+
+    pub(crate) fn check(store: &dyn KvStore) {
+        // 读写和覆盖
+        assert_eq!(store.get("missing"), None);
+        assert!(!store.exists("missing"));
+        store.put("greeting", b"hello", None).unwrap();
+        store.put("greeting", b"hi", None).unwrap();
+        assert_eq!(store.get("greeting"), Some(b"hi".to_vec()));
+        assert!(store.exists("greeting"));
+
+        // 二进制和空值
+        store.put("binary", &[0, 159, 146, 150, 255], None).unwrap();
+        assert_eq!(store.get("binary"), Some(vec![0, 159, 146, 150, 255]));
+        store.put("empty", b"", None).unwrap();
+        assert_eq!(store.get("empty"), Some(Vec::new()));
+
+        // 删除
+        assert!(store.delete("greeting").unwrap());
+        assert!(!store.delete("greeting").unwrap());
+        assert_eq!(store.get("greeting"), None);
+        assert!(store.delete("binary").unwrap());
+        assert!(store.delete("empty").unwrap());
+
+        // 列表按字节序排列，前缀和数量限制
+        for key in ["user:2", "user:10", "user:1", "users", "user:\u{10ffff}", "user;", "app:1", "été"] {
+            store.put(key, key.as_bytes(), None).unwrap();
+        }
+        assert_eq!(
+            store.list(None, None),
+            vec!["app:1", "user:1", "user:10", "user:2", "user:\u{10ffff}", "user;", "users", "été"]
+        );
+        assert_eq!(store.list(Some("user:"), None), vec!["user:1", "user:10", "user:2", "user:\u{10ffff}"]);
+        assert_eq!(store.list(Some("user:"), Some(2)), vec!["user:1", "user:10"]);
+        assert_eq!(store.list(Some("user:\u{10ffff}"), None), vec!["user:\u{10ffff}"]);
+        assert_eq!(store.list(Some("nothing"), None), Vec::<String>::new());
+        assert_eq!(store.list(Some(""), Some(1)), vec!["app:1"]);
+
+        // 分页
+        assert_eq!(store.list_after(Some("user:"), None, Some(3)), vec!["user:1", "user:10", "user:2"]);
+        assert_eq!(store.list_after(Some("user:"), Some("user:2"), Some(3)), vec!["user:\u{10ffff}"]);
+        assert_eq!(store.list_after(None, Some("user;"), None), vec!["users", "été"]);
+        assert_eq!(store.list_after(Some("user:"), Some("user:3"), None), vec!["user:\u{10ffff}"]);
+
+        // 过期的键读不到，也不出现在列表中
+        store.put("ttl:gone", b"x", Some(Duration::ZERO)).unwrap();
+        store.put("ttl:kept", b"y", Some(Duration::from_secs(3600))).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(store.get("ttl:gone"), None);
+        assert!(!store.exists("ttl:gone"));
+        assert!(!store.delete("ttl:gone").unwrap());
+        assert_eq!(store.get("ttl:kept"), Some(b"y".to_vec()));
+        assert_eq!(store.list(Some("ttl:"), None), vec!["ttl:kept"]);
+
+        // 不带 TTL 的写入清除过期时间
+        store.put("ttl:kept", b"z", None).unwrap();
+        assert_eq!(store.get("ttl:kept"), Some(b"z".to_vec()));
+
+        // 过期的键可以重新写入
+        store.put("ttl:gone", b"back", None).unwrap();
+        assert_eq!(store.get("ttl:gone"), Some(b"back".to_vec()));
+
+        // 元数据和绝对过期时间
+        let expires_at = now_millis() + 60_000;
+        store
+            .put_with_metadata("meta", b"v", Some(r#"{"plan":"pro"}"#), Some(expires_at))
+            .unwrap();
+        let entry = store.get_with_metadata("meta").unwrap();
+        assert_eq!(
+            entry,
+            KvValue {
+                value: b"v".to_vec(),
+                metadata: Some(r#"{"plan":"pro"}"#.to_string()),
+                expires_at: Some(expires_at),
+                version: entry.version,
+            }
+        );
+        assert_eq!(
+            store.list_keys(Some("meta"), None, None),
+            vec![KvKey {
+                name: "meta".to_string(),
+                metadata: Some(r#"{"plan":"pro"}"#.to_string()),
+                expires_at: Some(expires_at),
+            }]
+        );
+
+        // 覆盖写入替换元数据
+        store.put("meta", b"w", None).unwrap();
+        let replaced = store.get_with_metadata("meta").unwrap();
+        assert_eq!(
+            replaced,
+            KvValue {
+                value: b"w".to_vec(),
+                metadata: None,
+                expires_at: None,
+                version: replaced.version,
+            }
+        );
+        assert!(replaced.version > entry.version);
+
+        // 过去的时间点立即过期
+        store.put_with_metadata("past", b"x", Some("1"), Some(1)).unwrap();
+        assert_eq!(store.get_with_metadata("past"), None);
+        assert!(store.list_keys(Some("past"), None, None).is_empty());
+
+        // compare-and-swap：None 表示键必须不存在
+        let v1 = store.compare_and_swap("cas", None, b"1", None, None).unwrap().unwrap();
+        assert_eq!(store.compare_and_swap("cas", None, b"x", None, None).unwrap(), None);
+        assert_eq!(store.compare_and_swap("cas", Some(v1 + 1), b"x", None, None).unwrap(), None);
+        let v2 = store.compare_and_swap("cas", Some(v1), b"2", Some("{}"), None).unwrap().unwrap();
+        assert!(v2 > v1);
+        let current = store.get_with_metadata("cas").unwrap();
+        assert_eq!((current.value, current.version), (b"2".to_vec(), v2));
+        assert_eq!(store.compare_and_swap("cas", Some(v1), b"3", None, None).unwrap(), None);
+        assert_eq!(store.get("cas"), Some(b"2".to_vec()));
+
+        // 自增：不存在的键从 0 开始，非整数值报错
+        assert_eq!(store.increment("counter", 1).unwrap(), 1);
+        assert_eq!(store.increment("counter", 41).unwrap(), 42);
+        assert_eq!(store.increment("counter", -2).unwrap(), 40);
+        assert_eq!(store.get("counter"), Some(b"40".to_vec()));
+        assert!(store.increment("meta", 1).is_err());
+        assert!(store.increment("counter", i64::MAX).is_err());
+
+        // 多键事务：任一检查失败则全部不生效
+        let counter = store.get_with_metadata("counter").unwrap().version;
+        let mutations = vec![
+            KvMutation::Put {
+                key: "tx:a".to_string(),
+                value: b"a".to_vec(),
+                metadata: None,
+                expires_at: None,
+            },
+            KvMutation::Delete { key: "cas".to_string() },
+            KvMutation::Increment { key: "counter".to_string(), delta: 2 },
+            KvMutation::Increment { key: "counter".to_string(), delta: 3 },
+        ];
+        let stale = [
+            KvCheck { key: "counter".to_string(), version: Some(counter) },
+            KvCheck { key: "tx:a".to_string(), version: Some(1) },
+        ];
+        assert_eq!(store.atomic(&stale, &mutations).unwrap(), None);
+        assert_eq!(store.get("tx:a"), None);
+        assert_eq!(store.get("counter"), Some(b"40".to_vec()));
+
+        let checks = [
+            KvCheck { key: "counter".to_string(), version: Some(counter) },
+            KvCheck { key: "tx:a".to_string(), version: None },
+        ];
+        let version = store.atomic(&checks, &mutations).unwrap().unwrap();
+        assert_eq!(store.get("tx:a"), Some(b"a".to_vec()));
+        assert_eq!(store.get("cas"), None);
+        assert_eq!(store.get("counter"), Some(b"45".to_vec()));
+        assert_eq!(store.get_with_metadata("tx:a").unwrap().version, version);
+        assert_eq!(store.get_with_metadata("counter").unwrap().version, version);
+        assert_eq!(store.atomic(&checks, &mutations).unwrap(), None);
+
+        // 并发自增不丢失更新
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..25 {
+                        store.increment("concurrent", 1).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(store.get("concurrent"), Some(b"100".to_vec()));
+
+        // 变更通知：按前缀过滤，序号递增
+        let watch = store.watch("watch:").unwrap();
+        store.put("watch:a", b"1", None).unwrap();
+        store.put("other", b"x", None).unwrap();
+        store
+            .atomic(
+                &[],
+                &[
+                    KvMutation::Increment { key: "watch:a".to_string(), delta: 1 },
+                    KvMutation::Delete { key: "watch:b".to_string() },
+                ],
+            )
+            .unwrap();
+        assert!(store.delete("watch:a").unwrap());
+        store.put("watch:ttl", b"t", Some(Duration::ZERO)).unwrap();
+        store.put("watch:gone", b"g", Some(Duration::ZERO)).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        // 删除已过期的键返回 false，发出 Expire 事件
+        assert!(!store.delete("watch:gone").unwrap());
+        assert!(store.purge_expired().unwrap() >= 1);
+        assert_eq!(store.purge_expired().unwrap(), 0);
+
+        let changes = watch.drain();
+        let summary: Vec<_> = changes.iter().map(|c| (c.kind, c.key.as_str())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (KvChangeKind::Put, "watch:a"),
+                (KvChangeKind::Put, "watch:a"),
+                (KvChangeKind::Delete, "watch:b"),
+                (KvChangeKind::Delete, "watch:a"),
+                (KvChangeKind::Put, "watch:ttl"),
+                (KvChangeKind::Put, "watch:gone"),
+                (KvChangeKind::Expire, "watch:gone"),
+                (KvChangeKind::Expire, "watch:ttl"),
+            ]
+        );
+        assert!(changes.windows(2).all(|w| w[0].seq < w[1].seq));
+        assert_eq!(changes[1].value.as_ref().map(|v| v.value.as_slice()), Some(&b"2"[..]));
+        assert!(changes[3].value.is_none());
+        drop(watch);
+
+        // 统计信息
+        let stats = store.stats();
+        assert!(stats.entries > 0 && stats.bytes > 0);
+        assert!(stats.hits > 0 && stats.misses > 0);
+        let before = store.stats();
+        store.get("missing");
+        store.get("counter");
+        assert_eq!(store.stats().misses, before.misses + 1);
+        assert_eq!(store.stats().hits, before.hits + 1);
+
+        store.flush().unwrap();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_memory_kv_store_conformance() {
+        conformance::check(&MemoryKvStore::new());
+    }
+
     #[test]
     fn test_memory_kv_store() {
         let store = MemoryKvStore::new();
@@ -352,6 +1846,8 @@ mod tests {
         let result = binding.call("list", vec![]);
         if let BindingValue::Object(obj) = result {
             assert!(obj.contains_key("keys"));
+            assert!(matches!(obj.get("list_complete"), Some(BindingValue::Bool(true))));
+            assert!(!obj.contains_key("cursor"));
         } else {
             panic!("Expected object result");
         }
@@ -365,6 +1861,381 @@ mod tests {
         }
     }
 
+    fn string(s: &str) -> BindingValue {
+        BindingValue::String(s.to_string())
+    }
+
+    fn object(fields: &[(&str, BindingValue)]) -> BindingValue {
+        BindingValue::Object(fields.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+    }
+
+    fn field(value: &BindingValue, name: &str) -> BindingValue {
+        match value {
+            BindingValue::Object(obj) => obj.get(name).cloned().unwrap_or(BindingValue::Null),
+            other => panic!("Expected object, got {}", other),
+        }
+    }
+
+    #[test]
+    fn test_kv_binding_typed_get_and_metadata() {
+        let binding = KvBinding::memory("KV");
+        let metadata = object(&[("plan", string("pro")), ("seats", BindingValue::Int(3))]);
+        let result = binding.call(
+            "put",
+            vec![
+                string("user:1"),
+                string(r#"{"name":"ada"}"#),
+                object(&[("metadata", metadata), ("expirationTtl", BindingValue::Int(600))]),
+            ],
+        );
+        assert!(!result.is_error(), "{}", result);
+
+        let text = binding.call("get", vec![string("user:1")]);
+        assert_eq!(text.as_string(), Some(r#"{"name":"ada"}"#));
+        let json = binding.call("get", vec![string("user:1"), string("json")]);
+        assert!(matches!(json, BindingValue::Json(j) if j == r#"{"name":"ada"}"#));
+        let bytes = binding.call("get", vec![string("user:1"), object(&[("type", string("arrayBuffer"))])]);
+        assert!(matches!(bytes, BindingValue::Bytes(b) if b == br#"{"name":"ada"}"#));
+        assert!(binding.call("get", vec![string("user:1"), string("stream")]).is_error());
+
+        let result = binding.call("getWithMetadata", vec![string("user:1"), string("json")]);
+        assert!(matches!(field(&result, "value"), BindingValue::Json(_)));
+        assert!(matches!(field(&result, "metadata"), BindingValue::Json(j) if j == r#"{"plan":"pro","seats":3}"#));
+        let result = binding.call("getWithMetadata", vec![string("missing")]);
+        assert!(matches!(field(&result, "value"), BindingValue::Null));
+        assert!(matches!(field(&result, "metadata"), BindingValue::Null));
+
+        // 不是 JSON 的值按 json 读取时报错
+        binding.call("put", vec![string("plain"), string("hello")]);
+        assert!(binding.call("get", vec![string("plain"), string("json")]).is_error());
+
+        // 元数据大小限制
+        let big = object(&[("metadata", string(&"x".repeat(MAX_METADATA_SIZE)))]);
+        assert!(binding.call("put", vec![string("big"), string("v"), big]).is_error());
+        assert!(binding
+            .call("put", vec![string("bad"), string("v"), object(&[("expirationTtl", BindingValue::Int(-1))])])
+            .is_error());
+    }
+
+    #[test]
+    fn test_kv_binding_absolute_expiration() {
+        let binding = KvBinding::memory("KV");
+        let expiration = (now_millis() / 1000 + 3600) as i64;
+        binding.call(
+            "put",
+            vec![string("later"), string("v"), object(&[("expiration", BindingValue::Int(expiration))])],
+        );
+        binding.call(
+            "put",
+            vec![string("past"), string("v"), object(&[("expiration", BindingValue::Int(1))])],
+        );
+        assert_eq!(binding.call("get", vec![string("later")]).as_string(), Some("v"));
+        assert!(matches!(binding.call("get", vec![string("past")]), BindingValue::Null));
+
+        let result = binding.call("list", vec![]);
+        let BindingValue::Array(keys) = field(&result, "keys") else {
+            panic!("Expected keys array");
+        };
+        assert_eq!(keys.len(), 1);
+        assert_eq!(field(&keys[0], "name").as_string(), Some("later"));
+        assert!(matches!(field(&keys[0], "expiration"), BindingValue::Int(e) if e == expiration));
+    }
+
+    #[test]
+    fn test_kv_binding_huge_expiration() {
+        let binding = KvBinding::memory("KV");
+        for options in [
+            object(&[("expirationTtl", BindingValue::Float(1e30))]),
+            object(&[("expirationTtl", BindingValue::Float(1.8e19))]),
+            object(&[("expirationTtl", BindingValue::Int(i64::MAX))]),
+            object(&[("expiration", BindingValue::Float(1e30))]),
+            object(&[("expiration", BindingValue::Int(253_402_300_800))]),
+            BindingValue::Float(1e300),
+        ] {
+            let result = binding.call("put", vec![string("k"), string("v"), options.clone()]);
+            assert!(matches!(&result, BindingValue::Error(e) if e.starts_with("Invalid expiration")), "{}", options);
+            let cas = binding.call("compareAndSwap", vec![string("k"), BindingValue::Null, string("v"), options]);
+            assert!(cas.is_error());
+        }
+        assert!(matches!(binding.call("get", vec![string("k")]), BindingValue::Null));
+
+        // 上限内的时间仍然可用
+        let options = object(&[("expiration", BindingValue::Int(253_402_300_799))]);
+        assert!(matches!(binding.call("put", vec![string("k"), string("v"), options]), BindingValue::Null));
+        assert_eq!(binding.store.get_with_metadata("k").unwrap().expires_at, Some(MAX_EXPIRATION_MILLIS));
+    }
+
+    #[test]
+    fn test_kv_binding_list_cursor() {
+        let binding = KvBinding::memory("KV");
+        for i in 0..5 {
+            binding.call("put", vec![string(&format!("item:{}", i)), string("v")]);
+        }
+        binding.call("put", vec![string("other"), string("v")]);
+
+        let mut names = Vec::new();
+        let mut cursor = BindingValue::Null;
+        loop {
+            let result = binding.call(
+                "list",
+                vec![object(&[("prefix", string("item:")), ("limit", BindingValue::Int(2)), ("cursor", cursor)])],
+            );
+            let BindingValue::Array(keys) = field(&result, "keys") else {
+                panic!("Expected keys array");
+            };
+            names.extend(keys.iter().map(|k| field(k, "name").into_string().unwrap()));
+            match field(&result, "list_complete") {
+                BindingValue::Bool(true) => {
+                    assert!(matches!(field(&result, "cursor"), BindingValue::Null));
+                    break;
+                }
+                _ => cursor = field(&result, "cursor"),
+            }
+        }
+        assert_eq!(names, vec!["item:0", "item:1", "item:2", "item:3", "item:4"]);
+
+        let result = binding.call("list", vec![object(&[("cursor", string("!!"))])]);
+        assert!(result.is_error());
+    }
+
+    #[test]
+    fn test_kv_binding_atomic() {
+        let binding = KvBinding::memory("KV");
+
+        // compareAndSwap：null 表示键必须不存在
+        let created = binding.call("compareAndSwap", vec![string("doc"), BindingValue::Null, string("v1")]);
+        assert!(matches!(field(&created, "ok"), BindingValue::Bool(true)));
+        let version = field(&created, "version");
+        assert_eq!(
+            field(&binding.call("getWithMetadata", vec![string("doc")]), "version").into_string(),
+            version.clone().into_string()
+        );
+
+        let conflict = binding.call("compareAndSwap", vec![string("doc"), BindingValue::Null, string("x")]);
+        assert!(matches!(field(&conflict, "ok"), BindingValue::Bool(false)));
+        assert!(matches!(field(&conflict, "version"), BindingValue::Null));
+
+        let updated = binding.call(
+            "compareAndSwap",
+            vec![string("doc"), version.clone(), string("v2"), object(&[("metadata", string("m"))])],
+        );
+        assert!(matches!(field(&updated, "ok"), BindingValue::Bool(true)));
+        let stale = binding.call("compareAndSwap", vec![string("doc"), version, string("v3")]);
+        assert!(matches!(field(&stale, "ok"), BindingValue::Bool(false)));
+        assert!(binding.call("compareAndSwap", vec![string("doc"), string("zz"), string("v")]).is_error());
+
+        // increment 默认加 1
+        assert!(matches!(binding.call("increment", vec![string("n")]), BindingValue::Int(1)));
+        assert!(matches!(binding.call("increment", vec![string("n"), BindingValue::Int(9)]), BindingValue::Int(10)));
+        assert!(binding.call("increment", vec![string("doc")]).is_error());
+
+        // commitAtomic
+        let checks = BindingValue::Array(vec![object(&[("key", string("order")), ("version", BindingValue::Null)])]);
+        let mutations = BindingValue::Array(vec![
+            object(&[("type", string("put")), ("key", string("order")), ("value", string("o")), ("options", object(&[]))]),
+            object(&[("type", string("increment")), ("key", string("n")), ("delta", BindingValue::Int(5))]),
+            object(&[("type", string("delete")), ("key", string("doc"))]),
+        ]);
+        let committed = binding.call("commitAtomic", vec![checks.clone(), mutations.clone()]);
+        assert!(matches!(field(&committed, "ok"), BindingValue::Bool(true)));
+        assert_eq!(binding.call("get", vec![string("n")]).into_string().as_deref(), Some("15"));
+        assert!(matches!(binding.call("get", vec![string("doc")]), BindingValue::Null));
+
+        let rejected = binding.call("commitAtomic", vec![checks, mutations]);
+        assert!(matches!(field(&rejected, "ok"), BindingValue::Bool(false)));
+        assert_eq!(binding.call("get", vec![string("n")]).into_string().as_deref(), Some("15"));
+
+        let unknown = BindingValue::Array(vec![object(&[("type", string("swap")), ("key", string("n"))])]);
+        assert!(binding.call("commitAtomic", vec![BindingValue::Array(vec![]), unknown]).is_error());
+    }
+
+    #[test]
+    fn test_kv_binding_bulk() {
+        let binding = KvBinding::memory("KV");
+
+        let entries = BindingValue::Array(vec![
+            object(&[("key", string("a")), ("value", string("1"))]),
+            object(&[
+                ("key", string("b")),
+                ("value", BindingValue::Bytes(vec![0, 1])),
+                ("metadata", object(&[("tag", string("x"))])),
+                ("expirationTtl", BindingValue::Int(60)),
+            ]),
+            object(&[("key", string("c")), ("value", string("3"))]),
+        ]);
+        assert!(matches!(binding.call("putMany", vec![entries]), BindingValue::Null));
+        assert_eq!(binding.store.list(None, None), vec!["a", "b", "c"]);
+        let b = binding.store.get_with_metadata("b").unwrap();
+        assert_eq!(b.value, vec![0, 1]);
+        assert_eq!(b.metadata.as_deref(), Some(r#"{"tag":"x"}"#));
+        assert!(b.expires_at.is_some());
+
+        // 任一条目无效时整批都不写入
+        let invalid = BindingValue::Array(vec![
+            object(&[("key", string("d")), ("value", string("4"))]),
+            object(&[("value", string("5"))]),
+        ]);
+        assert!(binding.call("putMany", vec![invalid]).is_error());
+        assert!(binding.call("putMany", vec![string("a")]).is_error());
+        assert!(!binding.store.exists("d"));
+
+        let keys = BindingValue::Array(vec![string("a"), string("c"), string("missing")]);
+        assert!(matches!(binding.call("deleteMany", vec![keys]), BindingValue::Null));
+        assert_eq!(binding.store.list(None, None), vec!["b"]);
+        assert!(binding.call("deleteMany", vec![BindingValue::Array(vec![BindingValue::Int(1)])]).is_error());
+
+        let too_many = BindingValue::Array(vec![string("k"); MAX_BULK_SIZE + 1]);
+        assert!(binding.call("deleteMany", vec![too_many]).is_error());
+    }
+
+    #[test]
+    fn test_kv_binding_watch() {
+        let binding = KvBinding::memory("KV");
+        let BindingValue::Int(id) = binding.call("subscribe", vec![string("config:")]) else {
+            panic!("Expected a subscription id");
+        };
+        assert!(matches!(binding.call("pollChanges", vec![]), BindingValue::Array(c) if c.is_empty()));
+
+        binding.call("put", vec![string("config:theme"), string("dark"), object(&[("metadata", string("m"))])]);
+        binding.call("put", vec![string("user:1"), string("x")]);
+        binding.call("delete", vec![string("config:theme")]);
+
+        let BindingValue::Array(changes) = binding.call("pollChanges", vec![]) else {
+            panic!("Expected an array of changes");
+        };
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(field(&changes[0], "id"), BindingValue::Int(i) if i == id));
+        assert_eq!(field(&changes[0], "type").into_string().as_deref(), Some("put"));
+        assert_eq!(field(&changes[0], "value").into_string().as_deref(), Some("dark"));
+        assert!(field(&changes[0], "version").into_string().is_some());
+        assert_eq!(field(&changes[1], "type").into_string().as_deref(), Some("delete"));
+        assert!(matches!(field(&changes[1], "value"), BindingValue::Null));
+
+        // 落后的订阅发出 lagged 并重新订阅
+        for i in 0..=1024 {
+            binding.call("put", vec![string(&format!("config:{}", i)), string("v")]);
+        }
+        let BindingValue::Array(changes) = binding.call("pollChanges", vec![]) else {
+            panic!("Expected an array of changes");
+        };
+        assert_eq!(field(changes.last().unwrap(), "type").into_string().as_deref(), Some("lagged"));
+        binding.call("put", vec![string("config:after"), string("v")]);
+        assert!(matches!(binding.call("pollChanges", vec![]), BindingValue::Array(c) if c.len() == 1));
+
+        assert!(matches!(binding.call("unsubscribe", vec![BindingValue::Int(id)]), BindingValue::Bool(true)));
+        binding.call("put", vec![string("config:gone"), string("v")]);
+        assert!(matches!(binding.call("pollChanges", vec![]), BindingValue::Array(c) if c.is_empty()));
+    }
+
+    #[test]
+    fn test_memory_kv_store_lru_eviction() {
+        let store = MemoryKvStore::with_limits(KvLimits {
+            max_entries: Some(3),
+            ..KvLimits::default()
+        });
+        let watch = store.watch("").unwrap();
+        for key in ["a", "b", "c"] {
+            store.put(key, b"v", None).unwrap();
+        }
+        // 读取 a 后 b 成为最久未访问的键
+        store.get("a");
+        store.put("d", b"v", None).unwrap();
+        assert_eq!(store.list(None, None), vec!["a", "c", "d"]);
+
+        // 覆盖写入也算访问
+        store.put("c", b"w", None).unwrap();
+        store.put("e", b"v", None).unwrap();
+        assert_eq!(store.list(None, None), vec!["c", "d", "e"]);
+
+        let stats = store.stats();
+        assert_eq!((stats.entries, stats.evictions, stats.max_entries), (3, 2, Some(3)));
+        let evicted: Vec<_> = watch
+            .drain()
+            .into_iter()
+            .filter(|c| c.kind == KvChangeKind::Evict)
+            .map(|c| c.key)
+            .collect();
+        assert_eq!(evicted, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_memory_kv_store_lfu_eviction() {
+        let store = MemoryKvStore::with_limits(KvLimits {
+            max_entries: Some(2),
+            eviction: EvictionPolicy::Lfu,
+            ..KvLimits::default()
+        });
+        store.put("hot", b"v", None).unwrap();
+        store.put("cold", b"v", None).unwrap();
+        for _ in 0..3 {
+            store.get("hot");
+        }
+        // cold 的访问次数最少，即使 hot 更早写入；刚写入的键不会被立即淘汰
+        store.put("new", b"v", None).unwrap();
+        assert_eq!(store.list(None, None), vec!["hot", "new"]);
+        store.put("newer", b"v", None).unwrap();
+        assert_eq!(store.list(None, None), vec!["hot", "newer"]);
+    }
+
+    #[test]
+    fn test_memory_kv_store_max_bytes() {
+        let store = MemoryKvStore::with_limits(KvLimits {
+            max_bytes: Some(10),
+            ..KvLimits::default()
+        });
+        store.put("a", b"1234", None).unwrap();
+        store.put("b", b"1234", None).unwrap();
+        assert_eq!(store.stats().bytes, 10);
+        store.put("c", b"12", None).unwrap();
+        assert_eq!(store.list(None, None), vec!["b", "c"]);
+        assert_eq!(store.stats().bytes, 8);
+
+        // 删除和覆盖写入释放空间
+        store.delete("b").unwrap();
+        store.put("c", b"1", None).unwrap();
+        assert_eq!(store.stats().bytes, 2);
+
+        // 单个值超过限制时拒绝写入
+        assert!(store.put("big", b"0123456789", None).is_err());
+        assert_eq!(store.get("c"), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn test_memory_kv_store_reaper() {
+        let store = Arc::new(MemoryKvStore::new());
+        let watch = store.watch("").unwrap();
+        store.put("gone", b"x", Some(Duration::from_millis(1))).unwrap();
+        store.put("kept", b"y", None).unwrap();
+        spawn_reaper(&store, Duration::from_millis(10));
+
+        let expired = std::iter::from_fn(|| watch.recv_timeout(Duration::from_secs(5)))
+            .find(|c| c.kind == KvChangeKind::Expire)
+            .unwrap();
+        assert_eq!(expired.key, "gone");
+        assert_eq!(store.stats().entries, 1);
+    }
+
+    #[test]
+    fn test_kv_binding_stats() {
+        let binding = KvBinding::new(
+            "KV",
+            Box::new(MemoryKvStore::with_limits(KvLimits {
+                max_entries: Some(1),
+                ..KvLimits::default()
+            })),
+        );
+        binding.call("put", vec![string("a"), string("1")]);
+        binding.call("put", vec![string("b"), string("2")]);
+        binding.call("get", vec![string("a")]);
+        binding.call("get", vec![string("b")]);
+
+        let stats = binding.call("stats", vec![]);
+        for (name, expected) in [("entries", 1), ("bytes", 2), ("hits", 1), ("misses", 1), ("evictions", 1), ("maxEntries", 1)] {
+            assert!(matches!(field(&stats, name), BindingValue::Int(v) if v == expected), "{}", name);
+        }
+        assert!(matches!(field(&stats, "maxBytes"), BindingValue::Null));
+    }
+
     #[test]
     fn test_kv_with_ttl() {
         let store = MemoryKvStore::new();
@@ -0,0 +1,334 @@
+//! KV 导入导出
+//!
+//! 命名空间导出为按键排序的 NDJSON，每行一个键，值用 base64 编码：
+//!
+//! ```text
+//! {"key":"config:theme","value":"ZGFyaw==","metadata":{"by":"admin"},"expires_at":1767225600000}
+//! ```
+//!
+//! `metadata` 和 `expires_at`（Unix 毫秒）只在有值时出现。导出逐页读取，不是快照，
+//! 导出期间的写入可能只有一部分出现在结果中。导入先校验整个文件，再把所有写入和
+//! `Replace` 模式的删除放在一次原子提交中，任一行有误或写入失败时不做任何修改；
+//! 后端无关，可以用来在内存、文件和 SQLite 后端之间迁移命名空间。
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+
+use super::kv::{now_millis, KvMutation, KvRecord, KvStore, MAX_EXPIRATION_MILLIS, MAX_METADATA_SIZE};
+
+/// 导出和 `Replace` 模式导入时每次列出的键数
+const BATCH_SIZE: usize = 1000;
+
+/// 导入方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportMode {
+    /// 写入备份中的键，保留命名空间中的其他键
+    #[default]
+    Merge,
+    /// 先删除备份中没有的键，导入后命名空间与备份一致
+    Replace,
+}
+
+impl ImportMode {
+    /// 解析 `merge` 或 `replace`
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "merge" => Ok(ImportMode::Merge),
+            "replace" => Ok(ImportMode::Replace),
+            other => Err(format!("Unknown import mode '{}', expected merge or replace", other)),
+        }
+    }
+}
+
+/// 导入结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// 写入的键数
+    pub imported: usize,
+    /// 备份中已经过期而跳过的键数
+    pub expired: usize,
+    /// `Replace` 模式下删除的键数
+    pub deleted: usize,
+}
+
+/// 备份文件中的一行
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BackupLine {
+    key: String,
+    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metadata: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+}
+
+impl BackupLine {
+    fn into_record(self) -> Result<KvRecord, String> {
+        if self.key.is_empty() {
+            return Err("key must not be empty".to_string());
+        }
+        let value = STANDARD
+            .decode(&self.value)
+            .map_err(|e| format!("invalid base64 value for key {}: {}", self.key, e))?;
+        let metadata = self.metadata.filter(|m| !m.is_null()).map(|m| m.to_string());
+        if let Some(metadata) = &metadata {
+            if metadata.len() > MAX_METADATA_SIZE {
+                return Err(format!(
+                    "metadata of key {} is {} bytes, the limit is {} bytes",
+                    self.key,
+                    metadata.len(),
+                    MAX_METADATA_SIZE
+                ));
+            }
+        }
+        if self.expires_at.is_some_and(|at| at > MAX_EXPIRATION_MILLIS) {
+            return Err(format!("expires_at of key {} must be before the year 10000", self.key));
+        }
+        Ok(KvRecord {
+            key: self.key,
+            value,
+            metadata,
+            expires_at: self.expires_at,
+        })
+    }
+}
+
+/// 把以 `prefix` 开头的键导出为 NDJSON，返回导出的键数
+pub fn export_ndjson(store: &dyn KvStore, prefix: Option<&str>, mut writer: impl Write) -> Result<usize, String> {
+    let mut count = 0;
+    let mut start_after: Option<String> = None;
+    loop {
+        let keys = store.list_keys(prefix, start_after.as_deref(), Some(BATCH_SIZE));
+        let complete = keys.len() < BATCH_SIZE;
+        for key in keys {
+            // 列出之后被删除或过期的键不导出
+            if let Some(entry) = store.get_with_metadata(&key.name) {
+                let metadata = entry
+                    .metadata
+                    .map(|m| serde_json::from_str(&m).map_err(|e| format!("Invalid metadata of key {}: {}", key.name, e)))
+                    .transpose()?;
+                let line = BackupLine {
+                    key: key.name.clone(),
+                    value: STANDARD.encode(&entry.value),
+                    metadata,
+                    expires_at: entry.expires_at,
+                };
+                serde_json::to_writer(&mut writer, &line).map_err(|e| e.to_string())?;
+                writer.write_all(b"\n").map_err(|e| e.to_string())?;
+                count += 1;
+            }
+            start_after = Some(key.name);
+        }
+        if complete {
+            break;
+        }
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/// 从 NDJSON 导入键，空行被忽略
+pub fn import_ndjson(store: &dyn KvStore, reader: impl BufRead, mode: ImportMode) -> Result<ImportSummary, String> {
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read line {}: {}", index + 1, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str::<BackupLine>(&line)
+            .map_err(|e| e.to_string())
+            .and_then(BackupLine::into_record)
+            .map_err(|e| format!("Line {}: {}", index + 1, e))?;
+        records.push(record);
+    }
+
+    let now = now_millis();
+    let mut summary = ImportSummary::default();
+    records.retain(|record| {
+        let live = record.expires_at.is_none_or(|expires_at| expires_at > now);
+        if !live {
+            summary.expired += 1;
+        }
+        live
+    });
+    // 同一个键出现多次时以最后一行为准
+    records.reverse();
+    let mut seen = HashSet::new();
+    records.retain(|record| seen.insert(record.key.clone()));
+    records.reverse();
+
+    let mut mutations = Vec::new();
+    if mode == ImportMode::Replace {
+        let mut start_after: Option<String> = None;
+        loop {
+            let keys = store.list_after(None, start_after.as_deref(), Some(BATCH_SIZE));
+            let complete = keys.len() < BATCH_SIZE;
+            start_after = keys.last().cloned();
+            for key in keys.into_iter().filter(|key| !seen.contains(key)) {
+                mutations.push(KvMutation::Delete { key });
+            }
+            if complete {
+                break;
+            }
+        }
+    }
+    summary.deleted = mutations.len();
+    summary.imported = records.len();
+    mutations.extend(records.into_iter().map(|record| KvMutation::Put {
+        key: record.key,
+        value: record.value,
+        metadata: record.metadata,
+        expires_at: record.expires_at,
+    }));
+
+    // 删除和写入一起提交，写入失败时命名空间保持原样
+    if !mutations.is_empty() {
+        store.atomic(&[], &mutations)?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workers::bindings::kv::{KvCheck, KvKey, KvValue};
+    use crate::workers::bindings::{FileKvStore, FsyncPolicy, MemoryKvStore, SqliteKvStore};
+
+    fn seed(store: &dyn KvStore) {
+        let mut theme = KvRecord::new("config:theme", b"dark");
+        theme.metadata = Some(r#"{"by":"admin"}"#.to_string());
+        let mut session = KvRecord::new("session:1", &[0, 159, 146, 150]);
+        session.expires_at = Some(now_millis() + 3_600_000);
+        store.put_many(&[theme, session, KvRecord::new("visits", b"42")]).unwrap();
+    }
+
+    fn export(store: &dyn KvStore, prefix: Option<&str>) -> String {
+        let mut out = Vec::new();
+        export_ndjson(store, prefix, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_export_format() {
+        let store = MemoryKvStore::new();
+        seed(&store);
+
+        let lines: Vec<serde_json::Value> = export(&store, None)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["key"], "config:theme");
+        assert_eq!(lines[0]["value"], "ZGFyaw==");
+        assert_eq!(lines[0]["metadata"]["by"], "admin");
+        assert!(lines[0].get("expires_at").is_none());
+        assert_eq!(lines[1]["value"], "AJ+Slg==");
+        assert!(lines[1]["expires_at"].is_u64());
+
+        assert_eq!(export(&store, Some("config:")).lines().count(), 1);
+    }
+
+    #[test]
+    fn test_round_trip_between_backends() {
+        let dir = std::env::temp_dir().join(format!("raven-kv-backup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let memory = MemoryKvStore::new();
+        let file = FileKvStore::open(dir.join("kv.log").to_str().unwrap(), FsyncPolicy::Never).unwrap();
+        let sqlite = SqliteKvStore::open(dir.join("kv.sqlite").to_str().unwrap()).unwrap();
+        seed(&memory);
+        let backup = export(&memory, None);
+
+        for store in [&*file as &dyn KvStore, &*sqlite] {
+            let summary = import_ndjson(store, backup.as_bytes(), ImportMode::Merge).unwrap();
+            assert_eq!(summary.imported, 3);
+            assert_eq!(export(store, None), backup);
+            let session = store.get_with_metadata("session:1").unwrap();
+            assert_eq!(session.value, vec![0, 159, 146, 150]);
+            assert_eq!(session.expires_at, memory.get_with_metadata("session:1").unwrap().expires_at);
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_merge_and_replace() {
+        let store = MemoryKvStore::new();
+        seed(&store);
+        store.put("local", b"kept", None).unwrap();
+
+        let backup = concat!(
+            r#"{"key":"visits","value":"MQ=="}"#,
+            "\n\n",
+            r#"{"key":"old","value":"eA==","expires_at":1000}"#,
+            "\n",
+            r#"{"key":"visits","value":"Mg=="}"#,
+            "\n",
+        );
+        let summary = import_ndjson(&store, backup.as_bytes(), ImportMode::Merge).unwrap();
+        assert_eq!(summary, ImportSummary { imported: 1, expired: 1, deleted: 0 });
+        assert_eq!(store.get("visits"), Some(b"2".to_vec()));
+        assert_eq!(store.get("local"), Some(b"kept".to_vec()));
+
+        let summary = import_ndjson(&store, backup.as_bytes(), ImportMode::Replace).unwrap();
+        assert_eq!(summary.deleted, 3);
+        assert_eq!(store.list(None, None), vec!["visits".to_string()]);
+    }
+
+    /// 删除成功、写入总是失败的存储
+    struct FailingPuts(MemoryKvStore);
+
+    impl KvStore for FailingPuts {
+        fn get_with_metadata(&self, key: &str) -> Option<KvValue> {
+            self.0.get_with_metadata(key)
+        }
+
+        fn delete(&self, key: &str) -> Result<bool, String> {
+            self.0.delete(key)
+        }
+
+        fn atomic(&self, checks: &[KvCheck], mutations: &[KvMutation]) -> Result<Option<u64>, String> {
+            if mutations.iter().any(|m| matches!(m, KvMutation::Put { .. })) {
+                return Err("disk full".to_string());
+            }
+            self.0.atomic(checks, mutations)
+        }
+
+        fn list_keys(&self, prefix: Option<&str>, start_after: Option<&str>, limit: Option<usize>) -> Vec<KvKey> {
+            self.0.list_keys(prefix, start_after, limit)
+        }
+    }
+
+    #[test]
+    fn test_failed_replace_keeps_namespace() {
+        let store = FailingPuts(MemoryKvStore::new());
+        seed(&store.0);
+        let backup = "{\"key\":\"visits\",\"value\":\"MQ==\"}\n";
+        let err = import_ndjson(&store, backup.as_bytes(), ImportMode::Replace).unwrap_err();
+        assert_eq!(err, "disk full");
+        assert_eq!(store.list(None, None), vec!["config:theme", "session:1", "visits"]);
+    }
+
+    #[test]
+    fn test_invalid_backup_is_rejected() {
+        let store = MemoryKvStore::new();
+        store.put("local", b"kept", None).unwrap();
+
+        for (backup, error) in [
+            ("{\"key\":\"a\",\"value\":\"YQ==\"}\nnot json\n", "Line 2"),
+            ("{\"key\":\"a\",\"value\":\"***\"}\n", "invalid base64"),
+            ("{\"key\":\"a\",\"value\":\"YQ==\",\"ttl\":1}\n", "unknown field"),
+            ("{\"key\":\"\",\"value\":\"YQ==\"}\n", "must not be empty"),
+            ("{\"key\":\"a\",\"value\":\"YQ==\",\"expires_at\":253402300800000}\n", "before the year 10000"),
+        ] {
+            let err = import_ndjson(&store, backup.as_bytes(), ImportMode::Replace).unwrap_err();
+            assert!(err.contains(error), "{}", err);
+        }
+        assert_eq!(store.list(None, None), vec!["local".to_string()]);
+
+        assert_eq!(ImportMode::parse("replace"), Ok(ImportMode::Replace));
+        assert!(ImportMode::parse("overwrite").is_err());
+    }
+}
@@ -0,0 +1,857 @@
+//! 文件 KV 存储
+//!
+//! 所有写入追加到一个日志文件，启动时重放日志重建内存索引。
+//! 每条记录带长度和 CRC32，重放遇到不完整或校验失败的记录（写到一半时崩溃）时
+//! 截断到最后一条完整记录。过期时间以 Unix 毫秒时间戳保存，重启后仍然有效。
+//! 失效的记录超过日志的一半时重写日志（先写临时文件再重命名）。
+//! 原子操作的所有写入放在一条批量记录中，崩溃后要么全部重放，要么全部丢弃。
+//! 打开期间持有日志旁 `.lock` 文件的排他锁，其他进程（如另一个服务器或 `raven-kv`）
+//! 不能同时打开同一日志。
+//!
+//! 日志格式：
+//!
+//! ```text
+//! "RAVENKV\x01"
+//! 记录: len u32 | crc32 u32 | payload（len 字节）
+//! payload: op u8 | expires_at u64（Unix 毫秒，0 表示不过期）| key_len u32 | key | 其余部分
+//!
+//! op 1 = put:               其余部分为 value
+//! op 2 = delete:            其余部分为空
+//! op 3 = 带元数据的 put:    其余部分为 metadata_len u32 | metadata（JSON）| value
+//! op 4 = 带版本的 put:      其余部分为 version u64 | metadata_len u32（0xFFFFFFFF 表示没有元数据）| metadata | value
+//! op 5 = 批量写入:          key 为空，其余部分为若干个 sub_len u32 | op 2 或 op 4 的 payload
+//! ```
+//!
+//! 当前版本只写入 op 2、4、5，op 1 和 op 3 的记录重放时版本为 0。整数均为小端序。
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::kv::{
+    next_version, now_millis, resolve_atomic, KvCheck, KvCounters, KvKey, KvMutation, KvStats, KvStore, KvValue, KvWrites,
+};
+use super::kv_watch::{KvChangeKind, KvFeed};
+
+const MAGIC: &[u8; 8] = b"RAVENKV\x01";
+
+/// 记录头：长度和 CRC32
+const RECORD_HEADER: usize = 8;
+
+/// payload 中 key 之前的固定部分
+const PAYLOAD_HEADER: usize = 1 + 8 + 4;
+
+const OP_PUT: u8 = 1;
+const OP_DELETE: u8 = 2;
+const OP_PUT_WITH_METADATA: u8 = 3;
+const OP_PUT_VERSIONED: u8 = 4;
+const OP_BATCH: u8 = 5;
+
+/// op 4 中表示没有元数据的长度
+const NO_METADATA: u32 = u32::MAX;
+
+/// 日志小于该大小时不压缩
+const COMPACTION_MIN_SIZE: u64 = 1024 * 1024;
+
+/// 已打开的存储，同一文件在进程内只打开一次（如重新加载时新旧运行时同时存在）
+static OPEN_STORES: LazyLock<Mutex<HashMap<PathBuf, Weak<FileKvStore>>>> = LazyLock::new(Mutex::default);
+
+/// 何时把日志 fsync 到磁盘
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// 每次写入后 fsync，确认的写入不会因崩溃丢失
+    #[default]
+    Always,
+    /// 写入时距上次 fsync 超过间隔才 fsync，后台线程每隔一个间隔 fsync 尚未落盘的写入
+    Interval(Duration),
+    /// 只在 `flush()`（服务器关闭时）、压缩和存储释放时 fsync
+    Never,
+}
+
+/// 追加日志实现的持久化 KV 存储
+pub struct FileKvStore {
+    path: PathBuf,
+    /// 持有排他锁的 `.lock` 文件，存储释放时解锁
+    _lock: File,
+    fsync: FsyncPolicy,
+    state: Mutex<LogState>,
+    feed: KvFeed,
+    counters: KvCounters,
+}
+
+struct LogState {
+    file: File,
+    index: BTreeMap<String, Entry>,
+    /// 日志文件的长度
+    len: u64,
+    /// 索引中的条目对应的记录大小之和
+    live_bytes: u64,
+    last_sync: Instant,
+    /// 是否有上次 fsync 之后追加的写入
+    dirty: bool,
+    /// 最近一次写入的版本
+    version: u64,
+}
+
+struct Entry {
+    value: KvValue,
+    /// 在日志中的记录大小
+    size: u64,
+}
+
+impl Entry {
+    fn is_expired(&self, now: u64) -> bool {
+        self.value.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+impl FileKvStore {
+    /// 打开（或创建）日志文件，重放其中的记录
+    ///
+    /// 同一路径已经打开时返回已有的存储，此时 `fsync` 必须与已有存储相同；
+    /// 修改 fsync 策略需要等已有存储全部释放（如重启服务器）后再打开
+    pub fn open(path: &str, fsync: FsyncPolicy) -> Result<Arc<Self>, String> {
+        let path = Path::new(path);
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let key = match path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            Some(dir) => fs::canonicalize(dir).map(|d| d.join(path.file_name().unwrap_or_default())),
+            None => std::env::current_dir().map(|d| d.join(path)),
+        }
+        .map_err(|e| format!("Failed to resolve {}: {}", path.display(), e))?;
+
+        let mut open = OPEN_STORES.lock().map_err(|e| e.to_string())?;
+        if let Some(store) = open.get(&key).and_then(Weak::upgrade) {
+            if store.fsync != fsync {
+                return Err(format!(
+                    "{} is already open with fsync policy {:?}, cannot reopen it with {:?}",
+                    key.display(),
+                    store.fsync,
+                    fsync
+                ));
+            }
+            return Ok(store);
+        }
+        let lock = lock_log(&key)?;
+        let store = Arc::new(Self {
+            state: Mutex::new(load(&key)?),
+            _lock: lock,
+            path: key.clone(),
+            fsync,
+            feed: KvFeed::new(),
+            counters: KvCounters::default(),
+        });
+        open.retain(|_, store| store.strong_count() > 0);
+        open.insert(key, Arc::downgrade(&store));
+        if let FsyncPolicy::Interval(interval) = fsync {
+            spawn_syncer(&store, interval);
+        }
+        Ok(store)
+    }
+
+    /// 日志文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 重写日志，只保留未过期的条目
+    pub fn compact(&self) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        self.compact_locked(&mut state)
+    }
+
+    fn compact_locked(&self, state: &mut LogState) -> Result<(), String> {
+        let now = now_millis();
+        state.index.retain(|key, entry| {
+            let expired = entry.is_expired(now);
+            if expired {
+                self.feed.publish(KvChangeKind::Expire, key, None);
+            }
+            !expired
+        });
+
+        let temp = compaction_path(&self.path);
+        let mut data = MAGIC.to_vec();
+        for (key, entry) in state.index.iter_mut() {
+            let record = frame(&encode_payload(key, Some(&entry.value)));
+            entry.size = record.len() as u64;
+            data.extend_from_slice(&record);
+        }
+        let mut file = File::create(&temp).map_err(|e| format!("Failed to create {}: {}", temp.display(), e))?;
+        file.write_all(&data)
+            .and_then(|_| file.sync_all())
+            .and_then(|_| fs::rename(&temp, &self.path))
+            .map_err(|e| format!("Failed to compact {}: {}", self.path.display(), e))?;
+        sync_dir(&self.path);
+
+        state.file = open_append(&self.path)?;
+        state.len = data.len() as u64;
+        state.live_bytes = state.len - MAGIC.len() as u64;
+        state.last_sync = Instant::now();
+        state.dirty = false;
+        Ok(())
+    }
+
+    fn sync_locked(&self, state: &mut LogState) -> Result<(), String> {
+        state
+            .file
+            .sync_data()
+            .map_err(|e| format!("Failed to sync {}: {}", self.path.display(), e))?;
+        state.last_sync = Instant::now();
+        state.dirty = false;
+        Ok(())
+    }
+
+    /// fsync 距上次 fsync 超过 `interval` 的写入，供后台线程调用
+    fn sync_due(&self, interval: Duration) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        if state.dirty && state.last_sync.elapsed() >= interval {
+            self.sync_locked(&mut state)?;
+        }
+        Ok(())
+    }
+
+    /// 把一组写入作为一条记录追加到日志并更新索引
+    fn append(&self, state: &mut LogState, writes: KvWrites) -> Result<(), String> {
+        let (record, sizes) = encode(&writes);
+
+        if let Err(e) = state.file.write_all(&record) {
+            // 去掉写了一半的记录，否则之后追加的记录在重放时会被当作损坏而丢弃
+            let len = state.len;
+            state.file.set_len(len).ok();
+            return Err(format!("Failed to write {}: {}", self.path.display(), e));
+        }
+        state.len += record.len() as u64;
+        state.dirty = true;
+        self.feed.publish_writes(&writes);
+
+        for ((key, value), size) in writes.into_iter().zip(sizes) {
+            let previous = match value {
+                Some(value) => {
+                    state.version = state.version.max(value.version);
+                    state.live_bytes += size;
+                    state.index.insert(key, Entry { value, size })
+                }
+                None => state.index.remove(&key),
+            };
+            if let Some(previous) = previous {
+                state.live_bytes -= previous.size;
+            }
+        }
+
+        let sync = match self.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Interval(interval) => state.last_sync.elapsed() >= interval,
+            FsyncPolicy::Never => false,
+        };
+        if sync {
+            self.sync_locked(state)?;
+        }
+
+        // 失效记录超过一半时压缩
+        let data_len = state.len - MAGIC.len() as u64;
+        if state.len >= COMPACTION_MIN_SIZE && state.live_bytes * 2 < data_len {
+            self.compact_locked(state)?;
+        }
+        Ok(())
+    }
+}
+
+impl KvStore for FileKvStore {
+    fn get_with_metadata(&self, key: &str) -> Option<KvValue> {
+        let state = self.state.lock().ok()?;
+        let now = now_millis();
+        let value = state.index.get(key).filter(|entry| !entry.is_expired(now)).map(|entry| entry.value.clone());
+        self.counters.record(value.is_some());
+        value
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        match state.index.get(key) {
+            None => Ok(false),
+            // 已过期的键视为不存在，日志中的记录重放时同样会被跳过，只需从索引中移除
+            Some(entry) if entry.is_expired(now_millis()) => {
+                let size = entry.size;
+                state.index.remove(key);
+                state.live_bytes -= size;
+                self.feed.publish(KvChangeKind::Expire, key, None);
+                Ok(false)
+            }
+            Some(_) => {
+                self.append(&mut state, vec![(key.to_string(), None)])?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn atomic(&self, checks: &[KvCheck], mutations: &[KvMutation]) -> Result<Option<u64>, String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        let now = now_millis();
+        let version = next_version(state.version);
+
+        let index = &state.index;
+        let Some(writes) = resolve_atomic(checks, mutations, version, |key| {
+            Ok(index.get(key).filter(|entry| !entry.is_expired(now)).map(|entry| entry.value.clone()))
+        })?
+        else {
+            return Ok(None);
+        };
+        if !writes.is_empty() {
+            self.append(&mut state, writes)?;
+        }
+        Ok(Some(version))
+    }
+
+    fn list_keys(&self, prefix: Option<&str>, start_after: Option<&str>, limit: Option<usize>) -> Vec<KvKey> {
+        let Ok(state) = self.state.lock() else {
+            return vec![];
+        };
+        let now = now_millis();
+        let prefix = prefix.unwrap_or("");
+        let start = match start_after {
+            Some(after) if after >= prefix => Bound::Excluded(after.to_string()),
+            _ => Bound::Included(prefix.to_string()),
+        };
+        state
+            .index
+            .range((start, Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, entry)| KvKey {
+                name: key.clone(),
+                metadata: entry.value.metadata.clone(),
+                expires_at: entry.value.expires_at,
+            })
+            .take(limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        self.sync_locked(&mut state)
+    }
+
+    fn feed(&self) -> Option<&KvFeed> {
+        Some(&self.feed)
+    }
+
+    /// `bytes` 是日志中有效记录的大小
+    fn stats(&self) -> KvStats {
+        let (entries, bytes) = match self.state.lock() {
+            Ok(state) => (state.index.len(), state.live_bytes),
+            Err(_) => (0, 0),
+        };
+        KvStats {
+            entries,
+            bytes,
+            ..self.counters.stats()
+        }
+    }
+
+    /// 只从索引中移除，日志中的记录带有过期时间，重放时同样会被跳过
+    fn purge_expired(&self) -> Result<usize, String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        let now = now_millis();
+        let expired: Vec<String> = state
+            .index
+            .iter()
+            .filter(|(_, entry)| entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            if let Some(entry) = state.index.remove(key) {
+                state.live_bytes -= entry.size;
+            }
+            self.feed.publish(KvChangeKind::Expire, key, None);
+        }
+        Ok(expired.len())
+    }
+}
+
+/// 读取日志并重建索引，截断末尾不完整或损坏的记录
+fn load(path: &Path) -> Result<LogState, String> {
+    // 上次压缩中断时留下的临时文件
+    fs::remove_file(compaction_path(path)).ok();
+
+    let mut data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    if data.len() < MAGIC.len() && MAGIC.starts_with(&data) {
+        // 新文件，或写文件头时崩溃
+        fs::write(path, MAGIC)
+            .and_then(|_| File::open(path)?.sync_all())
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        sync_dir(path);
+        data = MAGIC.to_vec();
+    }
+    if !data.starts_with(MAGIC) {
+        return Err(format!("{} is not a KV log", path.display()));
+    }
+
+    let now = now_millis();
+    let mut index: BTreeMap<String, Entry> = BTreeMap::new();
+    let mut live_bytes = 0u64;
+    let mut version = 0u64;
+    let mut offset = MAGIC.len();
+    while let Some((records, size)) = decode(&data[offset..]) {
+        for record in records {
+            if let Some(previous) = index.remove(&record.key) {
+                live_bytes -= previous.size;
+            }
+            if let Some(value) = record.value {
+                version = version.max(value.version);
+                let entry = Entry {
+                    value,
+                    size: record.size,
+                };
+                if !entry.is_expired(now) {
+                    live_bytes += entry.size;
+                    index.insert(record.key, entry);
+                }
+            }
+        }
+        offset += size;
+    }
+
+    let file = open_append(path)?;
+    if offset < data.len() {
+        eprintln!(
+            "⚠️  Truncating {} incomplete byte(s) at the end of {}",
+            data.len() - offset,
+            path.display()
+        );
+        file.set_len(offset as u64)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("Failed to truncate {}: {}", path.display(), e))?;
+    }
+
+    Ok(LogState {
+        file,
+        index,
+        len: offset as u64,
+        live_bytes,
+        last_sync: Instant::now(),
+        dirty: false,
+        version,
+    })
+}
+
+/// 编码一组写入，多于一个时放在一条批量记录中，返回记录和每个写入占用的大小
+fn encode(writes: &KvWrites) -> (Vec<u8>, Vec<u64>) {
+    let payloads: Vec<Vec<u8>> = writes.iter().map(|(key, value)| encode_payload(key, value.as_ref())).collect();
+    if let [payload] = payloads.as_slice() {
+        let record = frame(payload);
+        let size = record.len() as u64;
+        return (record, vec![size]);
+    }
+
+    let mut batch = vec![OP_BATCH];
+    batch.extend_from_slice(&0u64.to_le_bytes());
+    batch.extend_from_slice(&0u32.to_le_bytes());
+    for payload in &payloads {
+        batch.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        batch.extend_from_slice(payload);
+    }
+    let sizes = payloads.iter().map(|p| (p.len() + 4) as u64).collect();
+    (frame(&batch), sizes)
+}
+
+/// 编码一个 put（op 4）或 delete（op 2）的 payload
+fn encode_payload(key: &str, value: Option<&KvValue>) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(PAYLOAD_HEADER + key.len() + value.map_or(0, |v| v.value.len() + 12));
+    payload.push(if value.is_some() { OP_PUT_VERSIONED } else { OP_DELETE });
+    payload.extend_from_slice(&value.and_then(|v| v.expires_at).unwrap_or(0).to_le_bytes());
+    payload.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    payload.extend_from_slice(key.as_bytes());
+    if let Some(value) = value {
+        payload.extend_from_slice(&value.version.to_le_bytes());
+        match &value.metadata {
+            Some(metadata) => {
+                payload.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+                payload.extend_from_slice(metadata.as_bytes());
+            }
+            None => payload.extend_from_slice(&NO_METADATA.to_le_bytes()),
+        }
+        payload.extend_from_slice(&value.value);
+    }
+    payload
+}
+
+/// 加上长度和校验和
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+/// 日志中的一个写入
+struct Record {
+    key: String,
+    /// `None` 表示删除
+    value: Option<KvValue>,
+    /// 占用的日志大小
+    size: u64,
+}
+
+/// 解码一条记录，返回其中的写入和包括记录头的大小，不完整或损坏时返回 `None`
+fn decode(data: &[u8]) -> Option<(Vec<Record>, usize)> {
+    let len = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(data.get(4..8)?.try_into().ok()?);
+    let payload = data.get(RECORD_HEADER..RECORD_HEADER.checked_add(len)?)?;
+    if payload.len() < PAYLOAD_HEADER || crc32fast::hash(payload) != crc {
+        return None;
+    }
+    let size = RECORD_HEADER + len;
+
+    if payload[0] != OP_BATCH {
+        let mut record = decode_payload(payload)?;
+        record.size = size as u64;
+        return Some((vec![record], size));
+    }
+
+    let mut records = Vec::new();
+    let mut rest = &payload[PAYLOAD_HEADER..];
+    while !rest.is_empty() {
+        let sub_len = u32::from_le_bytes(rest.get(0..4)?.try_into().ok()?) as usize;
+        let sub = rest.get(4..4usize.checked_add(sub_len)?)?;
+        if sub.first() == Some(&OP_BATCH) {
+            return None;
+        }
+        let mut record = decode_payload(sub)?;
+        record.size = (sub_len + 4) as u64;
+        records.push(record);
+        rest = &rest[4 + sub_len..];
+    }
+    Some((records, size))
+}
+
+/// 解码单个 put 或 delete 的 payload
+fn decode_payload(payload: &[u8]) -> Option<Record> {
+    let op = *payload.first()?;
+    let expires_at = u64::from_le_bytes(payload.get(1..9)?.try_into().ok()?);
+    let key_len = u32::from_le_bytes(payload.get(9..13)?.try_into().ok()?) as usize;
+    let key = payload.get(PAYLOAD_HEADER..PAYLOAD_HEADER.checked_add(key_len)?)?;
+    let key = String::from_utf8(key.to_vec()).ok()?;
+    let mut rest = &payload[PAYLOAD_HEADER + key_len..];
+
+    let mut version = 0;
+    let mut metadata = None;
+    match op {
+        OP_DELETE => {
+            return Some(Record {
+                key,
+                value: None,
+                size: 0,
+            })
+        }
+        OP_PUT => {}
+        OP_PUT_WITH_METADATA | OP_PUT_VERSIONED => {
+            if op == OP_PUT_VERSIONED {
+                version = u64::from_le_bytes(rest.get(0..8)?.try_into().ok()?);
+                rest = &rest[8..];
+            }
+            let metadata_len = u32::from_le_bytes(rest.get(0..4)?.try_into().ok()?);
+            rest = &rest[4..];
+            if op == OP_PUT_WITH_METADATA || metadata_len != NO_METADATA {
+                let metadata_len = metadata_len as usize;
+                metadata = Some(String::from_utf8(rest.get(..metadata_len)?.to_vec()).ok()?);
+                rest = &rest[metadata_len..];
+            }
+        }
+        _ => return None,
+    }
+
+    Some(Record {
+        key,
+        value: Some(KvValue {
+            value: rest.to_vec(),
+            metadata,
+            expires_at: (expires_at != 0).then_some(expires_at),
+            version,
+        }),
+        size: 0,
+    })
+}
+
+fn open_append(path: &Path) -> Result<File, String> {
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
+
+impl Drop for FileKvStore {
+    fn drop(&mut self) {
+        if let Ok(state) = self.state.get_mut() {
+            if state.dirty {
+                state.file.sync_data().ok();
+            }
+        }
+    }
+}
+
+/// 启动定期 fsync 的后台线程，写入停止后最后一批写入也会在一个间隔内落盘；存储被释放后线程退出
+fn spawn_syncer(store: &Arc<FileKvStore>, interval: Duration) {
+    let store = Arc::downgrade(store);
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let Some(store) = store.upgrade() else {
+            return;
+        };
+        if let Err(e) = store.sync_due(interval) {
+            eprintln!("Failed to sync KV log: {}", e);
+        }
+    });
+}
+
+/// 锁定日志旁的 `.lock` 文件，其他进程已经打开该日志时报错
+///
+/// 不直接锁日志文件，因为压缩会用新文件替换它
+fn lock_log(path: &Path) -> Result<File, String> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    let lock_path = PathBuf::from(name);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|e| format!("Failed to open {}: {}", lock_path.display(), e))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(format!(
+            "{} is in use by another process; stop the server using it first",
+            path.display()
+        )),
+        Err(TryLockError::Error(e)) => Err(format!("Failed to lock {}: {}", lock_path.display(), e)),
+    }
+}
+
+fn compaction_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".compact");
+    PathBuf::from(name)
+}
+
+/// fsync 文件所在目录，让新建和重命名在崩溃后仍然可见
+fn sync_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        if let Ok(dir) = File::open(dir) {
+            dir.sync_all().ok();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workers::bindings::kv::conformance;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("raven-kv-{}-{}", name, rand::random::<u64>()))
+            .join("data.kvlog")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn put_record(key: &str, value: &[u8]) -> Vec<u8> {
+        let value = KvValue {
+            value: value.to_vec(),
+            metadata: Some("{}".to_string()),
+            expires_at: None,
+            version: next_version(0),
+        };
+        encode(&vec![(key.to_string(), Some(value))]).0
+    }
+
+    #[test]
+    fn test_file_kv_store_conformance() {
+        let path = temp_path("conformance");
+        conformance::check(&*FileKvStore::open(&path, FsyncPolicy::Always).unwrap());
+        fs::remove_dir_all(Path::new(&path).parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_file_kv_store_persists_across_reopen() {
+        let path = temp_path("reopen");
+        {
+            let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+            store.put("a", b"1", None).unwrap();
+            store.put("b", b"2", Some(Duration::from_secs(3600))).unwrap();
+            store.put("c", b"3", Some(Duration::ZERO)).unwrap();
+            store.put("a", b"one", None).unwrap();
+            store.put("d", b"4", None).unwrap();
+            assert!(store.delete("d").unwrap());
+            store.put_with_metadata("e", b"5", Some(r#"{"v":1}"#), None).unwrap();
+            assert!(!store.delete("missing").unwrap());
+
+            // 同一路径共享已打开的存储，fsync 策略不同时拒绝
+            let again = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+            assert!(Arc::ptr_eq(&store, &again));
+            let err = FileKvStore::open(&path, FsyncPolicy::Never).err().unwrap();
+            assert!(err.contains("already open with fsync policy Always"), "{}", err);
+        }
+
+        let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(store.get("a"), Some(b"one".to_vec()));
+        assert_eq!(store.get("b"), Some(b"2".to_vec()));
+        assert_eq!(store.get("c"), None);
+        assert_eq!(store.get("d"), None);
+        assert_eq!(store.list(None, None), vec!["a", "b", "e"]);
+        assert_eq!(store.get_with_metadata("e").unwrap().metadata.as_deref(), Some(r#"{"v":1}"#));
+
+        // 压缩后元数据仍然保留
+        store.compact().unwrap();
+        drop(store);
+        let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(store.get_with_metadata("e").unwrap().metadata.as_deref(), Some(r#"{"v":1}"#));
+        assert_eq!(store.get_with_metadata("a").unwrap().metadata, None);
+
+        // 过期时间是绝对时间，重启后不会重新计时
+        let expires_at = store.state.lock().unwrap().index["b"].value.expires_at.unwrap();
+        assert!(expires_at <= now_millis() + 3_600_000);
+        assert!(expires_at > now_millis() + 3_500_000);
+
+        fs::remove_dir_all(Path::new(&path).parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_interval_policy_syncs_idle_store() {
+        let path = temp_path("interval");
+        let store = FileKvStore::open(&path, FsyncPolicy::Interval(Duration::from_millis(200))).unwrap();
+        store.put("a", b"1", None).unwrap();
+        store.put("b", b"2", None).unwrap();
+        assert!(store.state.lock().unwrap().dirty);
+
+        // 之后没有写入，后台线程也会在一个间隔后 fsync
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while store.state.lock().unwrap().dirty {
+            assert!(Instant::now() < deadline, "idle store was never synced");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        fs::remove_dir_all(Path::new(&path).parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_file_kv_store_locks_log() {
+        let path = temp_path("lock");
+        let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+        store.put("a", b"1", None).unwrap();
+
+        // 另一个进程打开同一日志时拿不到锁（flock 对每个打开的文件单独生效，这里用新的文件模拟）
+        let err = lock_log(&store.path).unwrap_err();
+        assert!(err.contains("in use by another process"), "{}", err);
+        // 压缩替换日志文件后锁仍然有效
+        store.compact().unwrap();
+        assert!(lock_log(&store.path).is_err());
+
+        drop(store);
+        drop(lock_log(Path::new(&fs::canonicalize(&path).unwrap())).unwrap());
+        let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+
+        fs::remove_dir_all(Path::new(&path).parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_file_kv_store_recovers_from_torn_write() {
+        let path = temp_path("torn");
+        {
+            let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+            store.put("kept", b"value", None).unwrap();
+        }
+        let good_len = fs::metadata(&path).unwrap().len();
+
+        // 最后一条记录只写了一半
+        let record = put_record("torn", b"lost value");
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&record[..record.len() / 2]).unwrap();
+        drop(file);
+
+        let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(store.get("kept"), Some(b"value".to_vec()));
+        assert_eq!(store.get("torn"), None);
+        assert_eq!(fs::metadata(&path).unwrap().len(), good_len);
+
+        // 截断后继续追加的记录在下次打开时可以读到
+        store.put("after", b"ok", None).unwrap();
+        drop(store);
+
+        // 校验和不匹配的记录同样被丢弃
+        let mut record = put_record("corrupt", b"x");
+        let last = record.len() - 1;
+        record[last] ^= 0xff;
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&record).unwrap();
+        drop(file);
+
+        let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(store.list(None, None), vec!["after", "kept"]);
+        drop(store);
+
+        // 批量记录中长度为 0 的子记录（校验和正确）视为损坏，不会 panic
+        let mut batch = vec![OP_BATCH];
+        batch.extend_from_slice(&[0; 12]);
+        batch.extend_from_slice(&0u32.to_le_bytes());
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&frame(&batch)).unwrap();
+        drop(file);
+        assert!(decode(&frame(&batch)).is_none());
+
+        let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(store.list(None, None), vec!["after", "kept"]);
+
+        // 不是 KV 日志的文件不会被覆盖
+        let other = temp_path("foreign");
+        fs::create_dir_all(Path::new(&other).parent().unwrap()).unwrap();
+        fs::write(&other, b"hello world").unwrap();
+        assert!(FileKvStore::open(&other, FsyncPolicy::Always).is_err());
+
+        fs::remove_dir_all(Path::new(&path).parent().unwrap()).ok();
+        fs::remove_dir_all(Path::new(&other).parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_file_kv_store_compaction() {
+        let path = temp_path("compact");
+        let value = vec![b'x'; 4096];
+        {
+            let store = FileKvStore::open(&path, FsyncPolicy::Never).unwrap();
+            store.put("expired", b"gone", Some(Duration::ZERO)).unwrap();
+            for i in 0..600 {
+                store.put("hot", &value, None).unwrap();
+                store.put(&format!("key{}", i % 3), format!("{}", i).as_bytes(), None).unwrap();
+            }
+            // 600 次覆盖写入约 2.4 MB，压缩后只剩最新的几条
+            assert!(fs::metadata(&path).unwrap().len() < COMPACTION_MIN_SIZE);
+
+            store.put("tail", b"t", None).unwrap();
+            store.compact().unwrap();
+            store.flush().unwrap();
+        }
+
+        let store = FileKvStore::open(&path, FsyncPolicy::Always).unwrap();
+        assert_eq!(store.list(None, None), vec!["hot", "key0", "key1", "key2", "tail"]);
+        assert_eq!(store.get("hot"), Some(value));
+        assert_eq!(store.get("key2"), Some(b"599".to_vec()));
+        assert!(!compaction_path(Path::new(&path)).exists());
+
+        fs::remove_dir_all(Path::new(&path).parent().unwrap()).ok();
+    }
+}
@@ -0,0 +1,143 @@
+//! 按命名空间 ID 共享的 KV 存储
+//!
+//! 同一进程中 ID 相同的 KV 绑定使用同一个存储，多个 Worker、热重载前后的运行时
+//! 都能看到相同的数据。命名空间第一次使用时按配置打开后端，之后一直保持打开。
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use super::kv::{KvStore, MemoryKvStore};
+use super::kv_file::FileKvStore;
+use super::kv_sqlite::SqliteKvStore;
+use super::kv_watch::KvSubscription;
+use crate::workers::config::KvNamespaceConfig;
+
+/// 已打开的命名空间
+struct Namespace {
+    backend: String,
+    path: Option<String>,
+    store: Arc<dyn KvStore>,
+}
+
+/// KV 命名空间注册表
+#[derive(Clone, Default)]
+pub struct KvRegistry {
+    namespaces: Arc<Mutex<HashMap<String, Namespace>>>,
+}
+
+impl KvRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 进程级的注册表，`WorkersRuntime::configure` 通过它打开 KV 命名空间
+    pub fn global() -> &'static KvRegistry {
+        static GLOBAL: OnceLock<KvRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(KvRegistry::new)
+    }
+
+    /// 获取命名空间的存储，第一次使用时按配置打开后端
+    ///
+    /// 同一个 ID 再次打开时必须使用相同的后端和路径
+    pub fn open(&self, config: &KvNamespaceConfig) -> Result<Arc<dyn KvStore>, String> {
+        let mut namespaces = self.namespaces.lock().map_err(|e| e.to_string())?;
+        if let Some(ns) = namespaces.get(&config.id) {
+            if ns.backend != config.backend || ns.path != config.path {
+                return Err(format!(
+                    "KV namespace {} is already open with the {} backend{}",
+                    config.id,
+                    ns.backend,
+                    ns.path.as_deref().map(|p| format!(" at {}", p)).unwrap_or_default()
+                ));
+            }
+            return Ok(Arc::clone(&ns.store));
+        }
+
+        let store: Arc<dyn KvStore> = match config.backend.as_str() {
+            "memory" => MemoryKvStore::open(config.limits),
+            backend @ ("file" | "sqlite") => {
+                let path = config.path.as_deref().ok_or_else(|| {
+                    format!("KV namespace {} uses the {} backend but has no path", config.id, backend)
+                })?;
+                if backend == "file" {
+                    Arc::new(FileKvStore::open(path, config.fsync).map_err(|e| format!("KV namespace {}: {}", config.id, e))?)
+                } else {
+                    SqliteKvStore::open(path).map_err(|e| format!("KV namespace {}: {}", config.id, e))?
+                }
+            }
+            other => {
+                return Err(format!(
+                    "Unsupported KV backend '{}' for namespace {}",
+                    other, config.id
+                ))
+            }
+        };
+
+        namespaces.insert(
+            config.id.clone(),
+            Namespace {
+                backend: config.backend.clone(),
+                path: config.path.clone(),
+                store: Arc::clone(&store),
+            },
+        );
+        Ok(store)
+    }
+
+    /// 获取已打开的命名空间
+    pub fn get(&self, id: &str) -> Option<Arc<dyn KvStore>> {
+        let namespaces = self.namespaces.lock().ok()?;
+        namespaces.get(id).map(|ns| Arc::clone(&ns.store))
+    }
+
+    /// 订阅已打开的命名空间中以 `prefix` 开头的键的变更，供服务器转发给 WebSocket 等外部订阅者
+    pub fn watch(&self, id: &str, prefix: &str) -> Result<KvSubscription, String> {
+        self.get(id)
+            .ok_or_else(|| format!("KV namespace {} is not open", id))?
+            .watch(prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaces_are_shared_by_id() {
+        let registry = KvRegistry::new();
+        let a = registry.open(&KvNamespaceConfig::memory("CACHE", "shared")).unwrap();
+        let b = registry.open(&KvNamespaceConfig::memory("OTHER_NAME", "shared")).unwrap();
+        let c = registry.open(&KvNamespaceConfig::memory("CACHE", "private")).unwrap();
+
+        a.put("key", b"value", None).unwrap();
+        assert_eq!(b.get("key"), Some(b"value".to_vec()));
+        assert_eq!(c.get("key"), None);
+        assert!(Arc::ptr_eq(&a, &registry.get("shared").unwrap()));
+        assert!(registry.get("missing").is_none());
+
+        let watch = registry.watch("shared", "").unwrap();
+        b.delete("key").unwrap();
+        assert_eq!(watch.try_recv().map(|change| change.key), Some("key".to_string()));
+        assert!(registry.watch("missing", "").is_err());
+
+        // 不同的注册表互不影响
+        assert_eq!(KvRegistry::new().open(&KvNamespaceConfig::memory("CACHE", "shared")).unwrap().get("key"), None);
+    }
+
+    #[test]
+    fn test_conflicting_backend() {
+        let registry = KvRegistry::new();
+        registry.open(&KvNamespaceConfig::memory("CACHE", "cache")).unwrap();
+
+        let err = match registry.open(&KvNamespaceConfig::sqlite("CACHE", "cache", "/tmp/raven-unused.sqlite")) {
+            Err(err) => err,
+            Ok(_) => panic!("Expected a conflict"),
+        };
+        assert!(err.contains("already open with the memory backend"), "{}", err);
+
+        let mut redis = KvNamespaceConfig::memory("CACHE", "redis");
+        redis.backend = "redis".to_string();
+        assert!(registry.open(&redis).is_err());
+        assert!(registry.get("redis").is_none());
+    }
+}
@@ -0,0 +1,492 @@
+//! SQLite KV 存储
+//!
+//! 适合键很多的命名空间：键是主键，前缀列表走索引范围扫描，分页从上一页最后一个键继续，
+//! 不需要像内存存储那样复制并排序全部键。过期时间以 Unix 毫秒时间戳保存在带索引的列上，
+//! 查询时过滤，后台线程定期删除。
+//!
+//! 数据库使用 WAL 模式：写入通过一个连接串行执行，读取使用连接池中的只读连接，
+//! 读写互不阻塞。
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, ToSql, Transaction, TransactionBehavior};
+
+use super::kv::{self, next_version, resolve_atomic, spawn_reaper, KvCheck, KvCounters, KvKey, KvMutation, KvStats, KvStore, KvValue};
+use super::kv_watch::{KvChangeKind, KvFeed};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS kv (
+        key TEXT PRIMARY KEY NOT NULL,
+        value BLOB NOT NULL,
+        expires_at INTEGER,
+        metadata TEXT,
+        version INTEGER NOT NULL DEFAULT 0
+    ) WITHOUT ROWID;
+    CREATE INDEX IF NOT EXISTS kv_expires_at ON kv (expires_at) WHERE expires_at IS NOT NULL;
+";
+
+/// 后台删除过期键的间隔
+const REAP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 连接池中保留的空闲只读连接数
+const MAX_IDLE_READERS: usize = 4;
+
+/// 等待其他连接释放锁的时间
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SQLite 实现的持久化 KV 存储
+pub struct SqliteKvStore {
+    path: PathBuf,
+    writer: Mutex<Writer>,
+    readers: Mutex<Vec<Connection>>,
+    feed: KvFeed,
+    counters: KvCounters,
+}
+
+struct Writer {
+    conn: Connection,
+    /// 最近一次写入的版本
+    version: u64,
+}
+
+impl SqliteKvStore {
+    /// 打开（或创建）数据库，并启动删除过期键的后台线程
+    pub fn open(path: &str) -> Result<Arc<Self>, String> {
+        let store = Arc::new(Self::open_without_reaper(path)?);
+        spawn_reaper(&store, REAP_INTERVAL);
+        Ok(store)
+    }
+
+    fn open_without_reaper(path: &str) -> Result<Self, String> {
+        let path = PathBuf::from(path);
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+
+        let writer = Connection::open(&path).map_err(|e| sqlite_error(&path, e))?;
+        writer
+            .busy_timeout(BUSY_TIMEOUT)
+            .and_then(|_| writer.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0)))
+            .and_then(|_| writer.execute_batch("PRAGMA synchronous = NORMAL;"))
+            .and_then(|_| writer.execute_batch(SCHEMA))
+            .and_then(|_| migrate(&writer))
+            .map_err(|e| sqlite_error(&path, e))?;
+        let version: i64 = writer
+            .query_row("SELECT COALESCE(MAX(version), 0) FROM kv", [], |row| row.get(0))
+            .map_err(|e| sqlite_error(&path, e))?;
+
+        Ok(Self {
+            path,
+            writer: Mutex::new(Writer {
+                conn: writer,
+                version: version as u64,
+            }),
+            readers: Mutex::new(Vec::new()),
+            feed: KvFeed::new(),
+            counters: KvCounters::default(),
+        })
+    }
+
+    /// 数据库文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 删除已过期的键，返回删除的数量
+    pub fn reap_expired(&self) -> Result<usize, String> {
+        let writer = self.writer.lock().map_err(|e| e.to_string())?;
+        let keys = writer
+            .conn
+            .prepare_cached("DELETE FROM kv WHERE expires_at <= ?1 RETURNING key")
+            .and_then(|mut stmt| stmt.query_map([now_millis()], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| sqlite_error(&self.path, e))?;
+        for key in &keys {
+            self.feed.publish(KvChangeKind::Expire, key, None);
+        }
+        Ok(keys.len())
+    }
+
+    /// 用连接池中的只读连接执行查询
+    fn read<T>(&self, query: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T, String> {
+        let pooled = self.readers.lock().map_err(|e| e.to_string())?.pop();
+        let conn = match pooled {
+            Some(conn) => conn,
+            None => {
+                let conn = Connection::open_with_flags(
+                    &self.path,
+                    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                )
+                .map_err(|e| sqlite_error(&self.path, e))?;
+                conn.busy_timeout(BUSY_TIMEOUT).map_err(|e| sqlite_error(&self.path, e))?;
+                conn
+            }
+        };
+
+        let result = query(&conn).map_err(|e| sqlite_error(&self.path, e));
+        if let Ok(mut readers) = self.readers.lock() {
+            if readers.len() < MAX_IDLE_READERS {
+                readers.push(conn);
+            }
+        }
+        result
+    }
+
+    fn write<T>(&self, update: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T, String> {
+        let writer = self.writer.lock().map_err(|e| e.to_string())?;
+        update(&writer.conn).map_err(|e| sqlite_error(&self.path, e))
+    }
+}
+
+impl KvStore for SqliteKvStore {
+    fn get_with_metadata(&self, key: &str) -> Option<KvValue> {
+        let value = self
+            .read(|conn| select_value(conn, key))
+            .unwrap_or_else(|e| {
+                eprintln!("KV get failed: {}", e);
+                None
+            });
+        self.counters.record(value.is_some());
+        value
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, String> {
+        // 已过期的键视为不存在，同时删除并发出 Expire 事件
+        let writer = self.writer.lock().map_err(|e| e.to_string())?;
+        let now = now_millis();
+        let deleted = writer
+            .conn
+            .prepare_cached("DELETE FROM kv WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)")
+            .and_then(|mut stmt| stmt.execute(params![key, now]))
+            .map_err(|e| sqlite_error(&self.path, e))?;
+        if deleted > 0 {
+            self.feed.publish(KvChangeKind::Delete, key, None);
+            return Ok(true);
+        }
+        let expired = writer
+            .conn
+            .prepare_cached("DELETE FROM kv WHERE key = ?1 AND expires_at <= ?2")
+            .and_then(|mut stmt| stmt.execute(params![key, now]))
+            .map_err(|e| sqlite_error(&self.path, e))?;
+        if expired > 0 {
+            self.feed.publish(KvChangeKind::Expire, key, None);
+        }
+        Ok(false)
+    }
+
+    fn atomic(&self, checks: &[KvCheck], mutations: &[KvMutation]) -> Result<Option<u64>, String> {
+        let mut writer = self.writer.lock().map_err(|e| e.to_string())?;
+        let version = next_version(writer.version);
+
+        let tx = Transaction::new_unchecked(&writer.conn, TransactionBehavior::Immediate)
+            .map_err(|e| sqlite_error(&self.path, e))?;
+        let resolved = resolve_atomic(checks, mutations, version, |key| {
+            select_value(&tx, key).map_err(|e| sqlite_error(&self.path, e))
+        })?;
+        let Some(writes) = resolved else {
+            return Ok(None);
+        };
+
+        for (key, value) in &writes {
+            let result = match value {
+                Some(value) => tx
+                    .prepare_cached(
+                        "INSERT OR REPLACE INTO kv (key, value, expires_at, metadata, version) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    )
+                    .and_then(|mut stmt| {
+                        stmt.execute(params![
+                            key,
+                            value.value,
+                            value.expires_at.map(|at| at.min(i64::MAX as u64) as i64),
+                            value.metadata,
+                            value.version as i64,
+                        ])
+                    }),
+                None => tx
+                    .prepare_cached("DELETE FROM kv WHERE key = ?1")
+                    .and_then(|mut stmt| stmt.execute([key])),
+            };
+            result.map_err(|e| sqlite_error(&self.path, e))?;
+        }
+        tx.commit().map_err(|e| sqlite_error(&self.path, e))?;
+        writer.version = version;
+        self.feed.publish_writes(&writes);
+        Ok(Some(version))
+    }
+
+    fn list_keys(&self, prefix: Option<&str>, start_after: Option<&str>, limit: Option<usize>) -> Vec<KvKey> {
+        let prefix = prefix.unwrap_or("");
+        // 键按字节序比较（BINARY 排序规则），前缀范围是 [prefix, prefix 的后继)
+        let (start, inclusive) = match start_after {
+            Some(after) if after >= prefix => (after, false),
+            _ => (prefix, true),
+        };
+        let end = prefix_end(prefix);
+        let limit = limit.map(|l| l.min(i64::MAX as usize) as i64).unwrap_or(-1);
+
+        self.read(|conn| {
+            let mut stmt = conn.prepare_cached(&list_sql(inclusive, end.is_some()))?;
+            let now = now_millis();
+            let mut args: Vec<&dyn ToSql> = vec![&start, &now, &limit];
+            if let Some(end) = &end {
+                args.push(end);
+            }
+            let keys = stmt.query_map(args.as_slice(), |row| {
+                Ok(KvKey {
+                    name: row.get(0)?,
+                    metadata: row.get(1)?,
+                    expires_at: row.get::<_, Option<i64>>(2)?.map(|at| at as u64),
+                })
+            })?;
+            keys.collect()
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("KV list failed: {}", e);
+            Vec::new()
+        })
+    }
+
+    fn flush(&self) -> Result<(), String> {
+        // 把 WAL 写回数据库文件并 fsync
+        self.write(|conn| conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())))
+    }
+
+    fn feed(&self) -> Option<&KvFeed> {
+        Some(&self.feed)
+    }
+
+    fn purge_expired(&self) -> Result<usize, String> {
+        self.reap_expired()
+    }
+
+    fn stats(&self) -> KvStats {
+        let totals = self.read(|conn| {
+            conn.query_row(
+                "SELECT COUNT(*), COALESCE(SUM(LENGTH(CAST(key AS BLOB)) + LENGTH(value) + COALESCE(LENGTH(CAST(metadata AS BLOB)), 0)), 0) FROM kv",
+                [],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )
+        });
+        let (entries, bytes) = totals.unwrap_or_else(|e| {
+            eprintln!("KV stats failed: {}", e);
+            (0, 0)
+        });
+        KvStats {
+            entries: entries as usize,
+            bytes: bytes as u64,
+            ..self.counters.stats()
+        }
+    }
+}
+
+/// 列出键的查询，每种边界组合一条语句，保证走主键的范围扫描
+///
+/// 参数：?1 起始键，?2 当前时间，?3 数量限制（-1 不限），?4 上界（`bounded` 时）
+fn list_sql(inclusive: bool, bounded: bool) -> String {
+    format!(
+        "SELECT key, metadata, expires_at FROM kv WHERE key {} ?1{} AND (expires_at IS NULL OR expires_at > ?2) ORDER BY key LIMIT ?3",
+        if inclusive { ">=" } else { ">" },
+        if bounded { " AND key < ?4" } else { "" },
+    )
+}
+
+/// 升级旧版本创建的数据库
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    for (column, definition) in [("metadata", "TEXT"), ("version", "INTEGER NOT NULL DEFAULT 0")] {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('kv') WHERE name = ?1",
+            [column],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            conn.execute_batch(&format!("ALTER TABLE kv ADD COLUMN {} {};", column, definition))?;
+        }
+    }
+    Ok(())
+}
+
+/// 读取键当前未过期的值
+fn select_value(conn: &Connection, key: &str) -> rusqlite::Result<Option<KvValue>> {
+    conn.prepare_cached(
+        "SELECT value, metadata, expires_at, version FROM kv WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+    )?
+    .query_row(params![key, now_millis()], |row| {
+        Ok(KvValue {
+            value: row.get(0)?,
+            metadata: row.get(1)?,
+            expires_at: row.get::<_, Option<i64>>(2)?.map(|at| at as u64),
+            version: row.get::<_, i64>(3)? as u64,
+        })
+    })
+    .optional()
+}
+
+/// 以 `prefix` 开头的键的上界（不含），`None` 表示没有上界
+fn prefix_end(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        // 跳过代理区，U+10FFFF 没有后继，去掉它继续进位
+        let next = match last {
+            '\u{d7ff}' => Some('\u{e000}'),
+            c => char::from_u32(c as u32 + 1),
+        };
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+fn sqlite_error(path: &Path, e: rusqlite::Error) -> String {
+    format!("SQLite error in {}: {}", path.display(), e)
+}
+
+fn now_millis() -> i64 {
+    kv::now_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workers::bindings::kv::conformance;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("raven-sqlite-{}-{}", name, rand::random::<u64>()))
+            .join("kv.sqlite")
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn cleanup(path: &str) {
+        std::fs::remove_dir_all(Path::new(path).parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_sqlite_kv_store_conformance() {
+        let path = temp_path("conformance");
+        conformance::check(&*SqliteKvStore::open(&path).unwrap());
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_sqlite_kv_store_persists_and_reaps() {
+        let path = temp_path("reopen");
+        {
+            let store = SqliteKvStore::open_without_reaper(&path).unwrap();
+            store.put("a", b"1", None).unwrap();
+            store.put("b", b"2", Some(Duration::from_secs(3600))).unwrap();
+            store.put("c", b"3", Some(Duration::ZERO)).unwrap();
+            store.flush().unwrap();
+        }
+
+        let store = SqliteKvStore::open_without_reaper(&path).unwrap();
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+        assert_eq!(store.get("b"), Some(b"2".to_vec()));
+        assert_eq!(store.list(None, None), vec!["a", "b"]);
+
+        // 过期的行在删除前仍在表中，只是被查询过滤
+        assert_eq!(store.reap_expired().unwrap(), 1);
+        assert_eq!(store.reap_expired().unwrap(), 0);
+        let rows: i64 = store.read(|conn| conn.query_row("SELECT COUNT(*) FROM kv", [], |row| row.get(0))).unwrap();
+        assert_eq!(rows, 2);
+
+        let mode: String = store
+            .read(|conn| conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)))
+            .unwrap();
+        assert_eq!(mode, "wal");
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_sqlite_kv_store_migrates_old_schema() {
+        let path = temp_path("migrate");
+        std::fs::create_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE kv (key TEXT PRIMARY KEY NOT NULL, value BLOB NOT NULL, expires_at INTEGER) WITHOUT ROWID;
+             INSERT INTO kv VALUES ('old', x'6f6c64', NULL);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let store = SqliteKvStore::open_without_reaper(&path).unwrap();
+        assert_eq!(
+            store.get_with_metadata("old"),
+            Some(KvValue {
+                value: b"old".to_vec(),
+                metadata: None,
+                expires_at: None,
+                version: 0,
+            })
+        );
+        store.put_with_metadata("new", b"v", Some("[1]"), None).unwrap();
+        assert_eq!(store.get_with_metadata("new").unwrap().metadata.as_deref(), Some("[1]"));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_sqlite_kv_store_uses_indexes() {
+        let path = temp_path("plan");
+        let store = SqliteKvStore::open_without_reaper(&path).unwrap();
+        let plan = |sql: &str, params: &[&dyn ToSql]| -> String {
+            store
+                .read(|conn| {
+                    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+                    let rows = stmt.query_map(params, |row| row.get::<_, String>(3))?;
+                    rows.collect::<rusqlite::Result<Vec<_>>>()
+                })
+                .unwrap()
+                .join("; ")
+        };
+
+        for inclusive in [true, false] {
+            let list = plan(&list_sql(inclusive, true), &[&"user:", &0, &10, &"user;"]);
+            assert!(list.contains("SEARCH kv USING PRIMARY KEY (key>? AND key<?)"), "{}", list);
+            assert!(!list.contains("TEMP B-TREE"), "{}", list);
+
+            let list = plan(&list_sql(inclusive, false), &[&"user:", &0, &10]);
+            assert!(list.contains("SEARCH kv USING PRIMARY KEY (key>?)"), "{}", list);
+        }
+
+        let reap = plan("DELETE FROM kv WHERE expires_at <= ?1", &[&0]);
+        assert!(reap.contains("kv_expires_at"), "{}", reap);
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_sqlite_kv_store_paginates_large_namespace() {
+        let path = temp_path("paginate");
+        let store = SqliteKvStore::open_without_reaper(&path).unwrap();
+        for i in 0..2500 {
+            store.put(&format!("item:{:05}", i), b"v", None).unwrap();
+            store.put(&format!("other:{:05}", i), b"v", None).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let page = store.list_after(Some("item:"), cursor.as_deref(), Some(1000));
+            if page.is_empty() {
+                break;
+            }
+            cursor = page.last().cloned();
+            seen.extend(page);
+        }
+        assert_eq!(seen.len(), 2500);
+        assert_eq!(seen.first().map(String::as_str), Some("item:00000"));
+        assert_eq!(seen.last().map(String::as_str), Some("item:02499"));
+        cleanup(&path);
+    }
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end(""), None);
+        assert_eq!(prefix_end("user:"), Some("user;".to_string()));
+        assert_eq!(prefix_end("a\u{10ffff}"), Some("b".to_string()));
+        assert_eq!(prefix_end("\u{d7ff}"), Some("\u{e000}".to_string()));
+        assert_eq!(prefix_end("\u{10ffff}"), None);
+    }
+}
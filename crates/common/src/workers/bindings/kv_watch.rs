@@ -0,0 +1,196 @@
+//! KV 变更通知
+//!
+//! 每个存储有一个 `KvFeed`，写入、删除、清理过期键和淘汰键时按提交顺序发出带序号的事件，
+//! 订阅者按前缀过滤。订阅通过有界通道接收事件，处理不过来的订阅会被标记为落后并断开。
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::kv::{KvValue, KvWrites};
+
+/// 每个订阅最多缓存的未读事件数
+const MAX_PENDING_CHANGES: usize = 1024;
+
+/// 变更类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvChangeKind {
+    Put,
+    Delete,
+    /// 过期的键被清理
+    Expire,
+    /// 因容量限制被淘汰
+    Evict,
+}
+
+impl KvChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KvChangeKind::Put => "put",
+            KvChangeKind::Delete => "delete",
+            KvChangeKind::Expire => "expire",
+            KvChangeKind::Evict => "evict",
+        }
+    }
+}
+
+/// 一次变更
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvChange {
+    /// 存储打开后从 1 开始递增的序号
+    pub seq: u64,
+    pub kind: KvChangeKind,
+    pub key: String,
+    /// `Put` 写入的值
+    pub value: Option<KvValue>,
+}
+
+struct Subscriber {
+    prefix: String,
+    sender: SyncSender<KvChange>,
+    lagged: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct FeedState {
+    seq: u64,
+    subscribers: Vec<Subscriber>,
+}
+
+/// 存储的变更事件源
+///
+/// 存储在持有写锁时调用 `publish`，保证事件顺序与提交顺序一致
+#[derive(Default)]
+pub struct KvFeed {
+    state: Mutex<FeedState>,
+}
+
+impl KvFeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 订阅以 `prefix` 开头的键的变更
+    pub fn subscribe(&self, prefix: &str) -> KvSubscription {
+        let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_CHANGES);
+        let lagged = Arc::new(AtomicBool::new(false));
+        if let Ok(mut state) = self.state.lock() {
+            state.subscribers.push(Subscriber {
+                prefix: prefix.to_string(),
+                sender,
+                lagged: Arc::clone(&lagged),
+            });
+        }
+        KvSubscription { receiver, lagged }
+    }
+
+    /// 最近一次事件的序号
+    pub fn last_seq(&self) -> u64 {
+        self.state.lock().map(|state| state.seq).unwrap_or(0)
+    }
+
+    /// 发出一个事件，返回它的序号
+    pub fn publish(&self, kind: KvChangeKind, key: &str, value: Option<&KvValue>) -> u64 {
+        let Ok(mut state) = self.state.lock() else {
+            return 0;
+        };
+        state.seq += 1;
+        let seq = state.seq;
+        state.subscribers.retain(|subscriber| {
+            if !key.starts_with(&subscriber.prefix) {
+                return true;
+            }
+            let change = KvChange {
+                seq,
+                kind,
+                key: key.to_string(),
+                value: value.cloned(),
+            };
+            match subscriber.sender.try_send(change) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    subscriber.lagged.store(true, Ordering::SeqCst);
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+        seq
+    }
+
+    /// 为一批已提交的写入发出 `Put` 或 `Delete` 事件
+    pub(super) fn publish_writes(&self, writes: &KvWrites) {
+        for (key, value) in writes {
+            let kind = if value.is_some() { KvChangeKind::Put } else { KvChangeKind::Delete };
+            self.publish(kind, key, value.as_ref());
+        }
+    }
+}
+
+/// 变更订阅，丢弃时自动退订
+pub struct KvSubscription {
+    receiver: Receiver<KvChange>,
+    lagged: Arc<AtomicBool>,
+}
+
+impl KvSubscription {
+    /// 取出一个已到达的事件
+    pub fn try_recv(&self) -> Option<KvChange> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// 等待下一个事件，超时或订阅已断开时返回 `None`
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<KvChange> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// 取出所有已到达的事件
+    pub fn drain(&self) -> Vec<KvChange> {
+        self.receiver.try_iter().collect()
+    }
+
+    /// 订阅是否因为未读事件过多而被断开，断开后不会再收到事件，需要重新订阅
+    pub fn is_lagged(&self) -> bool {
+        self.lagged.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_filters_by_prefix() {
+        let feed = KvFeed::new();
+        let config = feed.subscribe("config:");
+        let all = feed.subscribe("");
+
+        feed.publish(KvChangeKind::Put, "config:theme", None);
+        feed.publish(KvChangeKind::Delete, "user:1", None);
+        feed.publish(KvChangeKind::Expire, "config:theme", None);
+
+        let changes: Vec<_> = config.drain().into_iter().map(|c| (c.seq, c.kind)).collect();
+        assert_eq!(changes, vec![(1, KvChangeKind::Put), (3, KvChangeKind::Expire)]);
+        assert_eq!(all.drain().len(), 3);
+        assert_eq!(feed.last_seq(), 3);
+        assert!(config.try_recv().is_none());
+    }
+
+    #[test]
+    fn test_dropped_and_lagging_subscribers() {
+        let feed = KvFeed::new();
+        drop(feed.subscribe(""));
+        let slow = feed.subscribe("");
+
+        for i in 0..=MAX_PENDING_CHANGES {
+            feed.publish(KvChangeKind::Put, &i.to_string(), None);
+        }
+        assert!(slow.is_lagged());
+        assert_eq!(slow.drain().len(), MAX_PENDING_CHANGES);
+        assert!(feed.state.lock().unwrap().subscribers.is_empty());
+
+        feed.publish(KvChangeKind::Put, "after", None);
+        assert!(slow.recv_timeout(Duration::from_millis(1)).is_none());
+    }
+}
@@ -1,6 +1,6 @@
 //! Workers 绑定实现
 //!
-//! 提供具体的绑定实现（KV, UTILS 等）
+//! 提供具体的绑定实现（KV, QUEUE, UTILS, CRYPTO 等）
 //!
 //! # 注意
 //!
@@ -17,12 +17,28 @@
 //! registry.register("KV", Box::new(KvBinding::memory("KV")));
 //! ```
 
+mod crypto;
 mod kv;
+mod kv_backup;
+mod kv_file;
+mod kv_registry;
+mod kv_sqlite;
+mod kv_watch;
+mod queue;
 mod utils;
 
 // 重新导出核心绑定系统（为了向后兼容）
 pub use crate::runtime::bindings::{BindingRegistry, NativeBinding, BindingMethod, BindingValue};
 
 // 导出具体的绑定实现
-pub use kv::{KvBinding, KvStore, MemoryKvStore};
+pub use crypto::CryptoBinding;
+pub use kv::{
+    EvictionPolicy, KvBinding, KvCheck, KvKey, KvLimits, KvMutation, KvRecord, KvStats, KvStore, KvValue, MemoryKvStore,
+};
+pub use kv_backup::{export_ndjson, import_ndjson, ImportMode, ImportSummary};
+pub use kv_file::{FileKvStore, FsyncPolicy};
+pub use kv_registry::KvRegistry;
+pub use kv_sqlite::SqliteKvStore;
+pub use kv_watch::{KvChange, KvChangeKind, KvFeed, KvSubscription};
+pub use queue::{parse_delay_seconds, Queue, QueueBinding, QueueMessage, QueueRegistry};
 pub use utils::UtilsBinding;
\ No newline at end of file
@@ -0,0 +1,523 @@
+//! Queue 绑定
+//!
+//! 提供类似 Cloudflare Queues 的消息队列：Producer Worker 投递消息，
+//! Consumer Worker 的 `queue(batch, env, ctx)` 入口按批消费。
+//!
+//! # JS 使用方式
+//!
+//! ```javascript
+//! import { JOBS } from 'raven/queue'
+//!
+//! // 投递一条消息（消息体需要能表示为 JSON）
+//! await env.JOBS.send({ type: "resize", id: 42 });
+//!
+//! // 延迟 60 秒投递
+//! await env.JOBS.send({ type: "cleanup" }, { delaySeconds: 60 });
+//!
+//! // 批量投递
+//! await env.JOBS.sendBatch([{ body: "a" }, { body: "b", delaySeconds: 5 }]);
+//! ```
+//!
+//! `delaySeconds` 必须在 0 到 43200（12 小时）之间。
+//!
+//! 配置了 `queue_dir` 时每个队列保存为目录下的一个 JSON 文件，进程重启后未确认的消息会重新投递。
+//! 每次投递、取出和结算（一批消息的确认和重试合并为一次）都会重写整个文件并在重命名前 fsync，
+//! 开销随队列长度增长，适合积压不多的队列。写入失败时内存中的队列保持不变。
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::bindings::{BindingMethod, BindingValue, NativeBinding};
+
+/// `delaySeconds` 的最大值（12 小时）
+pub const MAX_DELAY_SECONDS: f64 = 43_200.0;
+
+/// 校验 `delaySeconds` 并转换为延迟时间
+pub fn parse_delay_seconds(seconds: f64) -> Result<Duration, String> {
+    if !(0.0..=MAX_DELAY_SECONDS).contains(&seconds) {
+        return Err(format!(
+            "Invalid delaySeconds: {}, must be between 0 and {}",
+            seconds, MAX_DELAY_SECONDS
+        ));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// 队列中的一条消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueMessage {
+    pub id: String,
+    pub body: serde_json::Value,
+    /// 投递时间（Unix 毫秒）
+    pub timestamp: u64,
+    /// 已经交给 Consumer 的次数
+    pub attempts: u32,
+    /// 在该时间（Unix 毫秒）之前不会交给 Consumer
+    pub visible_at: u64,
+    /// 已交给 Consumer、等待确认
+    #[serde(default)]
+    pub leased: bool,
+}
+
+/// 消息队列，可在多个 Worker 之间共享
+pub struct Queue {
+    name: String,
+    messages: Mutex<Vec<QueueMessage>>,
+    /// 持久化文件，`None` 时只保存在内存中
+    path: Option<PathBuf>,
+}
+
+impl Queue {
+    /// 内存队列
+    pub fn memory(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            messages: Mutex::new(Vec::new()),
+            path: None,
+        }
+    }
+
+    /// 打开 `dir` 下的持久化队列
+    ///
+    /// 上次进程退出时已交给 Consumer 但没有确认的消息会重新变为可见
+    pub fn open(name: &str, dir: &Path) -> Result<Self, String> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid queue name '{}'", name));
+        }
+
+        let path = dir.join(format!("{}.json", name));
+        let mut messages: Vec<QueueMessage> = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        let now = unix_millis();
+        for message in &mut messages {
+            if message.leased {
+                message.leased = false;
+                message.visible_at = now;
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            messages: Mutex::new(messages),
+            path: Some(path),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 投递一批消息：(消息体, 延迟)，返回消息 ID
+    pub fn send_batch(&self, bodies: Vec<(serde_json::Value, Duration)>) -> Result<Vec<String>, String> {
+        let now = unix_millis();
+        self.update(|messages| {
+            let mut ids = Vec::with_capacity(bodies.len());
+            for (body, delay) in bodies {
+                let id = format!("{:016x}{:016x}", now, rand::random::<u64>());
+                messages.push(QueueMessage {
+                    id: id.clone(),
+                    body,
+                    timestamp: now,
+                    attempts: 0,
+                    visible_at: now.saturating_add(delay.as_millis() as u64),
+                    leased: false,
+                });
+                ids.push(id);
+            }
+            ids
+        })
+    }
+
+    /// 投递一条消息，返回消息 ID
+    pub fn send(&self, body: serde_json::Value, delay: Duration) -> Result<String, String> {
+        Ok(self.send_batch(vec![(body, delay)])?.remove(0))
+    }
+
+    /// 取出最多 `max` 条可见的消息交给 Consumer
+    ///
+    /// 取出的消息在 `lease` 时间内不会再次交出，期间需要 `ack` 或 `retry`
+    pub fn receive(&self, max: usize, lease: Duration) -> Result<Vec<QueueMessage>, String> {
+        let now = unix_millis();
+        self.update(|messages| {
+            let mut batch = Vec::new();
+            for message in messages.iter_mut() {
+                if batch.len() >= max {
+                    break;
+                }
+                // 租期已过的消息视为 Consumer 没有处理完，重新投递
+                if message.visible_at > now {
+                    continue;
+                }
+                message.attempts += 1;
+                message.leased = true;
+                message.visible_at = now.saturating_add(lease.as_millis() as u64);
+                batch.push(message.clone());
+            }
+            batch
+        })
+    }
+
+    /// 确认消息已处理，从队列中删除
+    pub fn ack(&self, ids: &[String]) -> Result<(), String> {
+        self.settle(ids, &[])
+    }
+
+    /// 在 `delay` 之后重新投递消息
+    pub fn retry(&self, id: &str, delay: Duration) -> Result<(), String> {
+        self.settle(&[], &[(id.to_string(), delay)])
+    }
+
+    /// 一次性确认 `acks` 中的消息，并让 `retries` 中的消息在各自的延迟之后重新投递
+    pub fn settle(&self, acks: &[String], retries: &[(String, Duration)]) -> Result<(), String> {
+        if acks.is_empty() && retries.is_empty() {
+            return Ok(());
+        }
+        let now = unix_millis();
+        self.update(|messages| {
+            messages.retain(|m| !acks.contains(&m.id));
+            for (id, delay) in retries {
+                if let Some(message) = messages.iter_mut().find(|m| &m.id == id) {
+                    message.leased = false;
+                    message.visible_at = now.saturating_add(delay.as_millis() as u64);
+                }
+            }
+        })
+    }
+
+    /// 队列中的消息数（包括延迟和等待确认的消息）
+    pub fn len(&self) -> usize {
+        self.messages.lock().map(|m| m.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 修改消息列表
+    ///
+    /// 持久化队列先修改副本并写回文件，写入成功后才替换内存中的列表
+    fn update<T>(&self, f: impl FnOnce(&mut Vec<QueueMessage>) -> T) -> Result<T, String> {
+        let mut messages = self.messages.lock().map_err(|e| e.to_string())?;
+        let Some(path) = &self.path else {
+            return Ok(f(&mut messages));
+        };
+        let mut updated = messages.clone();
+        let result = f(&mut updated);
+        {
+            let data = serde_json::to_vec(&updated).map_err(|e| e.to_string())?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            }
+            // 先写临时文件并落盘再重命名，避免写到一半时进程退出或断电损坏队列
+            let tmp = path.with_extension("json.tmp");
+            let mut file = fs::File::create(&tmp)
+                .map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+            file.write_all(&data)
+                .and_then(|()| file.sync_all())
+                .map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+            fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        *messages = updated;
+        Ok(result)
+    }
+}
+
+/// 按名称共享的队列，`dir` 为 `None` 时队列保存在内存中
+#[derive(Clone, Default)]
+pub struct QueueRegistry {
+    dir: Option<PathBuf>,
+    queues: Arc<Mutex<HashMap<String, Arc<Queue>>>>,
+}
+
+impl QueueRegistry {
+    pub fn new(dir: Option<&str>) -> Self {
+        Self {
+            dir: dir.map(PathBuf::from),
+            queues: Arc::default(),
+        }
+    }
+
+    /// 获取队列，第一次使用时创建或从磁盘加载
+    pub fn get(&self, name: &str) -> Result<Arc<Queue>, String> {
+        let mut queues = self.queues.lock().map_err(|e| e.to_string())?;
+        if let Some(queue) = queues.get(name) {
+            return Ok(Arc::clone(queue));
+        }
+        let queue = Arc::new(match &self.dir {
+            Some(dir) => Queue::open(name, dir)?,
+            None => Queue::memory(name),
+        });
+        queues.insert(name.to_string(), Arc::clone(&queue));
+        Ok(queue)
+    }
+}
+
+/// Queue 绑定
+///
+/// 将队列的投递接口暴露给 JS 环境
+pub struct QueueBinding {
+    name: String,
+    queue: Arc<Queue>,
+}
+
+impl QueueBinding {
+    /// 创建新的 Queue 绑定
+    pub fn new(name: &str, queue: Arc<Queue>) -> Self {
+        Self {
+            name: name.to_string(),
+            queue,
+        }
+    }
+
+    /// 使用与绑定同名的内存队列创建 Queue 绑定
+    pub fn memory(name: &str) -> Self {
+        Self::new(name, Arc::new(Queue::memory(name)))
+    }
+
+    /// 解析 `{ delaySeconds }` 选项
+    fn parse_delay(options: Option<&BindingValue>) -> Result<Duration, String> {
+        let seconds = match options {
+            Some(BindingValue::Object(opts)) => match opts.get("delaySeconds") {
+                Some(BindingValue::Int(s)) => *s as f64,
+                Some(BindingValue::Float(s)) => *s,
+                _ => 0.0,
+            },
+            _ => 0.0,
+        };
+        parse_delay_seconds(seconds)
+    }
+}
+
+impl NativeBinding for QueueBinding {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn methods(&self) -> Vec<BindingMethod> {
+        vec![
+            BindingMethod::async_method("send", 1),
+            BindingMethod::async_method("sendBatch", 1),
+        ]
+    }
+
+    fn call(&self, method: &str, args: Vec<BindingValue>) -> BindingValue {
+        let result = match method {
+            "send" => {
+                let body = match args.first() {
+                    Some(body) => binding_to_json(body),
+                    None => Err("send requires a message body".to_string()),
+                };
+                body.and_then(|body| {
+                    let delay = Self::parse_delay(args.get(1))?;
+                    self.queue.send(body, delay).map(|_| ())
+                })
+            }
+
+            "sendBatch" => {
+                let Some(BindingValue::Array(entries)) = args.first() else {
+                    return BindingValue::Error("sendBatch requires an array of messages".to_string());
+                };
+                let default_delay = match Self::parse_delay(args.get(1)) {
+                    Ok(delay) => delay,
+                    Err(e) => return BindingValue::Error(e),
+                };
+                entries
+                    .iter()
+                    .map(|entry| match entry {
+                        BindingValue::Object(fields) => {
+                            let body = fields
+                                .get("body")
+                                .ok_or("sendBatch messages require a body")?;
+                            let delay = if fields.contains_key("delaySeconds") {
+                                Self::parse_delay(Some(entry))?
+                            } else {
+                                default_delay
+                            };
+                            Ok((binding_to_json(body)?, delay))
+                        }
+                        _ => Err("sendBatch messages must be objects with a body".to_string()),
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+                    .and_then(|bodies| self.queue.send_batch(bodies).map(|_| ()))
+            }
+
+            _ => return BindingValue::Error(format!("Unknown method: {}", method)),
+        };
+
+        match result {
+            Ok(()) => BindingValue::Null,
+            Err(e) => BindingValue::Error(e),
+        }
+    }
+}
+
+/// 把绑定参数转换为 JSON 消息体
+fn binding_to_json(value: &BindingValue) -> Result<serde_json::Value, String> {
+    if let BindingValue::Bytes(_) = value {
+        return Err("Binary message bodies are not supported".to_string());
+    }
+    value.to_json()
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_queue_receive_ack_retry() {
+        let queue = Queue::memory("jobs");
+        queue.send(json!({ "n": 1 }), Duration::ZERO).unwrap();
+        queue.send(json!("later"), Duration::from_secs(60)).unwrap();
+        queue.send(json!(3), Duration::ZERO).unwrap();
+
+        let batch = queue.receive(10, Duration::from_secs(30)).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].body, json!({ "n": 1 }));
+        assert_eq!(batch[0].attempts, 1);
+
+        // 等待确认期间不会再次交出
+        assert!(queue.receive(10, Duration::from_secs(30)).unwrap().is_empty());
+
+        queue.ack(&[batch[0].id.clone()]).unwrap();
+        queue.retry(&batch[1].id, Duration::ZERO).unwrap();
+        assert_eq!(queue.len(), 2);
+
+        let batch = queue.receive(10, Duration::from_secs(30)).unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].body, json!(3));
+        assert_eq!(batch[0].attempts, 2);
+    }
+
+    #[test]
+    fn test_persistent_queue() {
+        let dir = std::env::temp_dir().join(format!("raven-queue-{}", rand::random::<u64>()));
+
+        let queue = Queue::open("jobs", &dir).unwrap();
+        queue.send(json!("a"), Duration::ZERO).unwrap();
+        queue.send(json!("b"), Duration::ZERO).unwrap();
+        let batch = queue.receive(1, Duration::from_secs(300)).unwrap();
+        assert_eq!(batch[0].body, json!("a"));
+        drop(queue);
+
+        // 重启后没有确认的消息重新投递
+        let queue = Queue::open("jobs", &dir).unwrap();
+        let batch = queue.receive(10, Duration::from_secs(300)).unwrap();
+        let bodies: Vec<_> = batch.iter().map(|m| m.body.clone()).collect();
+        assert_eq!(bodies, vec![json!("a"), json!("b")]);
+        assert_eq!(batch[0].attempts, 2);
+
+        assert!(Queue::open("../jobs", &dir).is_err());
+
+        // 写入失败时内存中的队列不变
+        fs::create_dir_all(dir.join("jobs.json.tmp")).unwrap();
+        assert!(queue.send(json!("c"), Duration::ZERO).is_err());
+        assert!(queue.settle(&[batch[0].id.clone()], &[(batch[1].id.clone(), Duration::ZERO)]).is_err());
+        assert_eq!(queue.len(), 2);
+        assert!(queue.receive(10, Duration::from_secs(300)).unwrap_err().contains("jobs.json.tmp"));
+        fs::remove_dir_all(dir.join("jobs.json.tmp")).unwrap();
+        assert!(queue.receive(10, Duration::from_secs(300)).unwrap().is_empty());
+
+        // 确认和重试一起写回
+        queue.settle(&[batch[0].id.clone()], &[(batch[1].id.clone(), Duration::ZERO)]).unwrap();
+        let batch = Queue::open("jobs", &dir).unwrap().receive(10, Duration::from_secs(300)).unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].body, json!("b"));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_queue_binding() {
+        let queue = Arc::new(Queue::memory("jobs"));
+        let binding = QueueBinding::new("JOBS", Arc::clone(&queue));
+
+        let mut body = HashMap::new();
+        body.insert("id".to_string(), BindingValue::Int(7));
+        let result = binding.call("send", vec![BindingValue::Object(body)]);
+        assert!(!result.is_error());
+
+        let mut message = HashMap::new();
+        message.insert("body".to_string(), BindingValue::String("x".to_string()));
+        let mut delayed = message.clone();
+        delayed.insert("delaySeconds".to_string(), BindingValue::Int(60));
+        let result = binding.call(
+            "sendBatch",
+            vec![BindingValue::Array(vec![
+                BindingValue::Object(message),
+                BindingValue::Object(delayed),
+            ])],
+        );
+        assert!(!result.is_error());
+        assert_eq!(queue.len(), 3);
+
+        let batch = queue.receive(10, Duration::from_secs(30)).unwrap();
+        let bodies: Vec<_> = batch.iter().map(|m| m.body.clone()).collect();
+        assert_eq!(bodies, vec![json!({ "id": 7 }), json!("x")]);
+
+        assert!(binding.call("sendBatch", vec![BindingValue::String("x".to_string())]).is_error());
+    }
+
+    #[test]
+    fn test_queue_binding_invalid_delay() {
+        let queue = Arc::new(Queue::memory("jobs"));
+        let binding = QueueBinding::new("JOBS", Arc::clone(&queue));
+
+        for delay in [
+            BindingValue::Float(1e30),
+            BindingValue::Float(f64::INFINITY),
+            BindingValue::Float(f64::NAN),
+            BindingValue::Int(-1),
+            BindingValue::Int(43_201),
+        ] {
+            let mut options = HashMap::new();
+            options.insert("delaySeconds".to_string(), delay);
+            let result = binding.call(
+                "send",
+                vec![BindingValue::String("x".to_string()), BindingValue::Object(options.clone())],
+            );
+            assert!(matches!(&result, BindingValue::Error(e) if e.contains("Invalid delaySeconds")));
+
+            let mut message = options.clone();
+            message.insert("body".to_string(), BindingValue::String("x".to_string()));
+            let result = binding.call("sendBatch", vec![BindingValue::Array(vec![BindingValue::Object(message)])]);
+            assert!(result.is_error());
+
+            let message = HashMap::from([("body".to_string(), BindingValue::String("x".to_string()))]);
+            let result = binding.call(
+                "sendBatch",
+                vec![BindingValue::Array(vec![BindingValue::Object(message)]), BindingValue::Object(options)],
+            );
+            assert!(result.is_error());
+        }
+        assert!(queue.is_empty());
+
+        let mut options = HashMap::new();
+        options.insert("delaySeconds".to_string(), BindingValue::Int(43_200));
+        let result = binding.call("send", vec![BindingValue::String("x".to_string()), BindingValue::Object(options)]);
+        assert!(!result.is_error());
+        assert_eq!(queue.len(), 1);
+    }
+}
@@ -0,0 +1,991 @@
+//! Cache API
+//!
+//! 提供 Cloudflare Workers 风格的 `caches.default` 和 `caches.open(name)`，
+//! 以请求 URL 为键缓存响应，遵循 HTTP 缓存语义：
+//! `Cache-Control`（`max-age`、`s-maxage`、`no-store`、`private`、`no-cache`、
+//! `stale-while-revalidate`）、`Expires`、`Vary` 和 `ETag` 条件请求。
+//!
+//! 缓存数据保存在 `CacheBackend` 中，`WorkerServer` 的所有 Worker 共享同一个后端，
+//! 可以是内存或磁盘目录。内存后端最多占用 `MemoryCacheBackend::DEFAULT_MAX_BYTES`，
+//! 超出时淘汰最久未使用的 URL。
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use boa_engine::{
+    js_string,
+    object::{
+        builtins::{AlignedVec, JsArrayBuffer},
+        ObjectInitializer,
+    },
+    Context, JsArgs, JsNativeError, JsResult, JsString, JsValue, NativeFunction, Source,
+};
+use boa_gc::{Finalize, Trace};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::html_rewriter;
+use super::http::{status_text, HttpResponse};
+use super::workers_runtime::js_to_bytes;
+
+/// 缓存中的一个响应（某个 URL 的一个 `Vary` 变体）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub status: u16,
+    /// 响应头（名称为小写）
+    pub headers: Vec<(String, String)>,
+    #[serde(with = "body_base64")]
+    pub body: Vec<u8>,
+    /// 存入时间（Unix 秒）
+    pub stored_at: u64,
+    /// `Vary` 列出的请求头在存入时的值
+    pub vary: Vec<(String, Option<String>)>,
+}
+
+impl CachedResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 请求头是否与存入时 `Vary` 列出的值一致
+    fn matches_vary(&self, headers: &HashMap<String, String>) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| headers.get(name).map(|v| v.trim()) == value.as_deref())
+    }
+}
+
+mod body_base64 {
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        base64::engine::general_purpose::STANDARD
+            .decode(text)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// 缓存存储后端
+///
+/// 以 (缓存名, URL) 为键保存该 URL 的所有 `Vary` 变体
+pub trait CacheBackend: Send + Sync {
+    /// 读取某个 URL 的所有变体
+    fn load(&self, cache: &str, url: &str) -> Result<Vec<CachedResponse>, String>;
+
+    /// 覆盖某个 URL 的所有变体，为空时删除
+    fn store(&self, cache: &str, url: &str, entries: Vec<CachedResponse>) -> Result<(), String>;
+}
+
+/// (缓存名, URL)
+type CacheKey = (String, String);
+
+/// 内存缓存后端，总大小超过 `max_bytes` 时淘汰最久未使用的 URL
+pub struct MemoryCacheBackend {
+    entries: Mutex<MemoryEntries>,
+    max_bytes: usize,
+}
+
+#[derive(Default)]
+struct MemoryEntries {
+    /// 键 -> (变体, 大小, 最近一次访问的序号)
+    map: HashMap<CacheKey, (Vec<CachedResponse>, usize, u64)>,
+    /// 最近一次访问的序号 -> 键，从小到大即从最久未使用到最近使用
+    order: BTreeMap<u64, CacheKey>,
+    bytes: usize,
+    clock: u64,
+}
+
+impl MemoryEntries {
+    /// 标记为最近使用
+    fn touch(&mut self, key: &CacheKey) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some((_, _, used)) = self.map.get_mut(key) {
+            self.order.remove(used);
+            *used = clock;
+            self.order.insert(clock, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some((_, size, used)) = self.map.remove(key) {
+            self.order.remove(&used);
+            self.bytes -= size;
+        }
+    }
+}
+
+impl Default for MemoryCacheBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryCacheBackend {
+    /// 默认的大小上限：64 MiB
+    pub const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+    pub fn new() -> Self {
+        Self::with_max_bytes(Self::DEFAULT_MAX_BYTES)
+    }
+
+    /// 最多保存 `max_bytes` 字节（按 body、响应头和 `Vary` 值的长度估算）
+    pub fn with_max_bytes(max_bytes: usize) -> Self {
+        Self {
+            entries: Mutex::default(),
+            max_bytes,
+        }
+    }
+}
+
+/// 估算一个 URL 的所有变体占用的字节数
+fn entries_size(key: &CacheKey, entries: &[CachedResponse]) -> usize {
+    let strings = |pairs: &[(String, String)]| pairs.iter().map(|(k, v)| k.len() + v.len()).sum::<usize>();
+    key.0.len()
+        + key.1.len()
+        + entries
+            .iter()
+            .map(|entry| {
+                entry.body.len()
+                    + strings(&entry.headers)
+                    + entry
+                        .vary
+                        .iter()
+                        .map(|(k, v)| k.len() + v.as_ref().map_or(0, String::len))
+                        .sum::<usize>()
+            })
+            .sum::<usize>()
+}
+
+impl CacheBackend for MemoryCacheBackend {
+    fn load(&self, cache: &str, url: &str) -> Result<Vec<CachedResponse>, String> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+        let key = (cache.to_string(), url.to_string());
+        entries.touch(&key);
+        Ok(entries
+            .map
+            .get(&key)
+            .map(|(responses, _, _)| responses.clone())
+            .unwrap_or_default())
+    }
+
+    fn store(&self, cache: &str, url: &str, responses: Vec<CachedResponse>) -> Result<(), String> {
+        let key = (cache.to_string(), url.to_string());
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+        entries.remove(&key);
+        let size = entries_size(&key, &responses);
+        // 单个 URL 超过上限时不缓存
+        if responses.is_empty() || size > self.max_bytes {
+            return Ok(());
+        }
+
+        while entries.bytes + size > self.max_bytes {
+            let Some((_, oldest)) = entries.order.pop_first() else {
+                break;
+            };
+            if let Some((_, size, _)) = entries.map.remove(&oldest) {
+                entries.bytes -= size;
+            }
+        }
+        entries.bytes += size;
+        entries.map.insert(key.clone(), (responses, size, 0));
+        entries.touch(&key);
+        Ok(())
+    }
+}
+
+/// 磁盘缓存后端，每个 URL 一个 JSON 文件，文件名为缓存名和 URL 的 SHA-256
+pub struct DiskCacheBackend {
+    directory: PathBuf,
+}
+
+impl DiskCacheBackend {
+    pub fn new(directory: &str) -> Self {
+        Self {
+            directory: PathBuf::from(directory),
+        }
+    }
+
+    fn path(&self, cache: &str, url: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(cache.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(url.as_bytes());
+        self.directory.join(format!("{:x}.json", hasher.finalize()))
+    }
+}
+
+impl CacheBackend for DiskCacheBackend {
+    fn load(&self, cache: &str, url: &str) -> Result<Vec<CachedResponse>, String> {
+        let path = self.path(cache, url);
+        match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| format!("Corrupt cache file {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(format!("Failed to read cache file {}: {}", path.display(), e)),
+        }
+    }
+
+    fn store(&self, cache: &str, url: &str, entries: Vec<CachedResponse>) -> Result<(), String> {
+        let path = self.path(cache, url);
+        if entries.is_empty() {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(format!("Failed to remove cache file {}: {}", path.display(), e))
+                }
+                _ => Ok(()),
+            };
+        }
+
+        fs::create_dir_all(&self.directory).map_err(|e| {
+            format!("Failed to create cache directory {}: {}", self.directory.display(), e)
+        })?;
+        let text = serde_json::to_string(&entries).map_err(|e| e.to_string())?;
+        // 先写临时文件再重命名，避免读到写了一半的文件；临时文件名唯一，同一 URL 的并发写入互不干扰
+        let temp = path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+        fs::write(&temp, text)
+            .and_then(|_| fs::rename(&temp, &path))
+            .map_err(|e| format!("Failed to write cache file {}: {}", path.display(), e))
+    }
+}
+
+/// 缓存查询使用的请求
+#[derive(Debug, Clone, Default)]
+pub struct CacheRequest {
+    pub url: String,
+    pub method: String,
+    /// 请求头（名称为小写）
+    pub headers: HashMap<String, String>,
+}
+
+impl CacheRequest {
+    pub fn get(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            method: "GET".to_string(),
+            headers: HashMap::new(),
+        }
+    }
+
+    /// 缓存键：去掉片段（`#...`）的 URL
+    fn key(&self) -> &str {
+        self.url.split('#').next().unwrap_or_default()
+    }
+
+    fn is_get(&self) -> bool {
+        self.method.eq_ignore_ascii_case("GET")
+    }
+}
+
+/// 遵循 HTTP 缓存语义的缓存，可在多个运行时之间共享
+#[derive(Clone)]
+pub struct HttpCache {
+    backend: Arc<dyn CacheBackend>,
+}
+
+impl HttpCache {
+    pub fn new(backend: Arc<dyn CacheBackend>) -> Self {
+        Self { backend }
+    }
+
+    /// 内存缓存
+    pub fn memory() -> Self {
+        Self::new(Arc::new(MemoryCacheBackend::new()))
+    }
+
+    /// 存入响应
+    ///
+    /// 只接受 GET 请求；`no-store`、`private` 以及没有过期时间的响应不会被缓存
+    pub fn put(&self, cache: &str, request: &CacheRequest, response: &HttpResponse) -> Result<(), String> {
+        self.put_at(cache, request, response, unix_now())
+    }
+
+    fn put_at(
+        &self,
+        cache: &str,
+        request: &CacheRequest,
+        response: &HttpResponse,
+        now: u64,
+    ) -> Result<(), String> {
+        if !request.is_get() {
+            return Err("Cache API only supports GET requests".to_string());
+        }
+        if response.status == 206 {
+            return Err("Partial responses cannot be cached".to_string());
+        }
+        let vary = response.headers.get("vary").map(|v| v.as_str()).unwrap_or_default();
+        if vary.split(',').any(|v| v.trim() == "*") {
+            return Err("Responses with 'Vary: *' cannot be cached".to_string());
+        }
+
+        let mut headers: Vec<(String, String)> = response
+            .headers
+            .iter()
+            .map(|(k, v)| (k.to_lowercase(), v.clone()))
+            .collect();
+        headers.sort();
+        let entry = CachedResponse {
+            status: response.status,
+            headers,
+            body: response.body.clone(),
+            stored_at: now,
+            vary: vary
+                .split(',')
+                .map(|v| v.trim().to_lowercase())
+                .filter(|v| !v.is_empty())
+                .map(|name| {
+                    let value = request.headers.get(&name).map(|v| v.trim().to_string());
+                    (name, value)
+                })
+                .collect(),
+        };
+
+        let directives = CacheControl::parse(entry.header("cache-control"));
+        if directives.has("no-store")
+            || directives.has("private")
+            || entry.header("set-cookie").is_some()
+            || freshness_lifetime(&entry).is_none()
+        {
+            return Ok(());
+        }
+
+        let key = request.key();
+        let mut entries = self.backend.load(cache, key)?;
+        entries.retain(|e| e.vary != entry.vary);
+        entries.push(entry);
+        self.backend.store(cache, key, entries)
+    }
+
+    /// 查找缓存的响应
+    ///
+    /// 新鲜的响应带 `x-raven-cache: HIT`，在 `stale-while-revalidate` 窗口内的过期响应
+    /// 带 `x-raven-cache: STALE`；请求的 `If-None-Match` 与 `ETag` 匹配时返回 304
+    pub fn lookup(
+        &self,
+        cache: &str,
+        request: &CacheRequest,
+        ignore_method: bool,
+    ) -> Result<Option<HttpResponse>, String> {
+        self.lookup_at(cache, request, ignore_method, unix_now())
+    }
+
+    fn lookup_at(
+        &self,
+        cache: &str,
+        request: &CacheRequest,
+        ignore_method: bool,
+        now: u64,
+    ) -> Result<Option<HttpResponse>, String> {
+        if !ignore_method && !request.is_get() {
+            return Ok(None);
+        }
+        let key = request.key();
+        let mut entries = self.backend.load(cache, key)?;
+        let Some(index) = entries.iter().position(|e| e.matches_vary(&request.headers)) else {
+            return Ok(None);
+        };
+
+        let entry = &entries[index];
+        let initial_age = entry
+            .header("age")
+            .and_then(|v| delta_seconds(v.trim()))
+            .unwrap_or(0);
+        let age = now.saturating_sub(entry.stored_at).saturating_add(initial_age);
+        let directives = CacheControl::parse(entry.header("cache-control"));
+        let lifetime = if directives.has("no-cache") {
+            0
+        } else {
+            freshness_lifetime(entry).unwrap_or(0)
+        };
+        let stale_window = directives.seconds("stale-while-revalidate").unwrap_or(0);
+
+        let status = if age < lifetime {
+            "HIT"
+        } else if age < lifetime.saturating_add(stale_window) {
+            "STALE"
+        } else {
+            // 已过期，顺便清理
+            entries.remove(index);
+            self.backend.store(cache, key, entries)?;
+            return Ok(None);
+        };
+
+        let not_modified = match (request.headers.get("if-none-match"), entry.header("etag")) {
+            (Some(condition), Some(etag)) => etag_matches(condition, etag),
+            _ => false,
+        };
+        let mut response = HttpResponse::new(entry.status, status_text(entry.status));
+        response.headers.clear();
+        for (name, value) in &entry.headers {
+            response.headers.insert(name.clone(), value.clone());
+        }
+        if not_modified {
+            response.status = 304;
+            response.status_text = status_text(304).to_string();
+            response.headers.remove("content-length");
+            response.headers.remove("content-type");
+        } else {
+            response.body = entry.body.clone();
+            response
+                .headers
+                .insert("content-length".to_string(), response.body.len().to_string());
+        }
+        response.headers.insert("age".to_string(), age.to_string());
+        response
+            .headers
+            .insert("x-raven-cache".to_string(), status.to_string());
+        Ok(Some(response))
+    }
+
+    /// 删除 URL 的所有缓存变体，返回是否存在
+    pub fn delete(&self, cache: &str, request: &CacheRequest, ignore_method: bool) -> Result<bool, String> {
+        if !ignore_method && !request.is_get() {
+            return Ok(false);
+        }
+        let key = request.key();
+        if self.backend.load(cache, key)?.is_empty() {
+            return Ok(false);
+        }
+        self.backend.store(cache, key, Vec::new())?;
+        Ok(true)
+    }
+}
+
+/// 解析后的 `Cache-Control` 指令
+struct CacheControl {
+    directives: Vec<(String, Option<String>)>,
+}
+
+impl CacheControl {
+    fn parse(value: Option<&str>) -> Self {
+        let directives = value
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|d| {
+                let d = d.trim();
+                if d.is_empty() {
+                    return None;
+                }
+                Some(match d.split_once('=') {
+                    Some((name, value)) => (
+                        name.trim().to_lowercase(),
+                        Some(value.trim().trim_matches('"').to_string()),
+                    ),
+                    None => (d.to_lowercase(), None),
+                })
+            })
+            .collect();
+        Self { directives }
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.directives.iter().any(|(n, _)| n == name)
+    }
+
+    fn seconds(&self, name: &str) -> Option<u64> {
+        self.directives
+            .iter()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| delta_seconds(v.as_deref()?))
+    }
+}
+
+/// delta-seconds 的上限，超过时按此值处理（RFC 9111 §1.2.2）
+const MAX_DELTA_SECONDS: u64 = 1 << 31;
+
+/// 解析 delta-seconds（非负整数秒），过大的值截断为 [`MAX_DELTA_SECONDS`]
+fn delta_seconds(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(value.parse::<u64>().unwrap_or(u64::MAX).min(MAX_DELTA_SECONDS))
+}
+
+/// 响应的新鲜期（秒）：`s-maxage` > `max-age` > `Expires - Date`，都没有时为 `None`
+fn freshness_lifetime(entry: &CachedResponse) -> Option<u64> {
+    let directives = CacheControl::parse(entry.header("cache-control"));
+    if let Some(seconds) = directives
+        .seconds("s-maxage")
+        .or_else(|| directives.seconds("max-age"))
+    {
+        return Some(seconds);
+    }
+
+    let expires = chrono::DateTime::parse_from_rfc2822(entry.header("expires")?).ok()?;
+    let date = entry
+        .header("date")
+        .and_then(|d| chrono::DateTime::parse_from_rfc2822(d).ok())
+        .map(|d| d.timestamp())
+        .unwrap_or(entry.stored_at as i64);
+    Some((expires.timestamp() - date).max(0) as u64)
+}
+
+/// `If-None-Match` 是否匹配 `ETag`（弱比较）
+fn etag_matches(condition: &str, etag: &str) -> bool {
+    let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    condition.trim() == "*" || condition.split(',').any(|c| weak(c) == weak(etag))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+const CACHE_JS: &str = r#"
+(function() {
+    function toRequest(request) {
+        if (typeof request === "string") {
+            return { url: request, method: "GET", headers: {} };
+        }
+        var headers = request.headers || {};
+        return {
+            url: String(request.url),
+            method: request.method || "GET",
+            headers: headers._data || headers
+        };
+    }
+
+    class Cache {
+        constructor(name) {
+            this._name = name;
+        }
+
+        match(request, options) {
+            try {
+                var r = toRequest(request);
+                var ignoreMethod = !!(options && options.ignoreMethod);
+                var cached = __raven_cache_native.match(this._name, r.url, r.method, r.headers, ignoreMethod);
+                if (!cached) {
+                    return Promise.resolve(undefined);
+                }
+                return Promise.resolve(new Response(cached.status === 304 ? null : cached.body, {
+                    status: cached.status,
+                    headers: cached.headers
+                }));
+            } catch (e) {
+                return Promise.reject(e);
+            }
+        }
+
+        put(request, response) {
+            try {
+                var r = toRequest(request);
+                __raven_cache_native.put(
+                    this._name, r.url, r.method, r.headers,
+                    response.status, response.headers || {}, response.body
+                );
+                return Promise.resolve(undefined);
+            } catch (e) {
+                return Promise.reject(e);
+            }
+        }
+
+        delete(request, options) {
+            try {
+                var r = toRequest(request);
+                var ignoreMethod = !!(options && options.ignoreMethod);
+                return Promise.resolve(__raven_cache_native.delete(this._name, r.url, r.method, r.headers, ignoreMethod));
+            } catch (e) {
+                return Promise.reject(e);
+            }
+        }
+    }
+
+    class CacheStorage {
+        constructor() {
+            this.default = new Cache("default");
+            this._caches = {};
+        }
+
+        open(name) {
+            name = String(name);
+            if (!this._caches[name]) {
+                this._caches[name] = new Cache(name);
+            }
+            return Promise.resolve(this._caches[name]);
+        }
+    }
+
+    globalThis.Cache = Cache;
+    globalThis.caches = new CacheStorage();
+})();
+"#;
+
+/// 原生函数捕获的缓存句柄
+#[derive(Clone, Trace, Finalize)]
+struct CacheHandle {
+    #[unsafe_ignore_trace]
+    cache: HttpCache,
+}
+
+/// 注册 `caches` 全局对象
+pub fn register_globals(context: &mut Context, cache: HttpCache) -> Result<(), String> {
+    set_backend(context, cache)?;
+    context
+        .eval(Source::from_bytes(CACHE_JS))
+        .map_err(|e| format!("Failed to install caches: {}", e))?;
+    Ok(())
+}
+
+/// 切换 `caches` 使用的缓存（如改为 `WorkerServer` 共享的缓存）
+pub fn set_backend(context: &mut Context, cache: HttpCache) -> Result<(), String> {
+    let handle = CacheHandle { cache };
+
+    let match_fn = NativeFunction::from_copy_closure_with_captures(
+        |_, args, handle: &CacheHandle, context| {
+            let cache = args.get_or_undefined(0).to_string(context)?.to_std_string_escaped();
+            let request = js_cache_request(args, context)?;
+            let ignore_method = args.get_or_undefined(4).to_boolean();
+            let response = handle
+                .cache
+                .lookup(&cache, &request, ignore_method)
+                .map_err(|e| JsNativeError::error().with_message(e))?;
+            match response {
+                Some(response) => http_response_to_js(&response, context),
+                None => Ok(JsValue::null()),
+            }
+        },
+        handle.clone(),
+    );
+
+    let put_fn = NativeFunction::from_copy_closure_with_captures(
+        |_, args, handle: &CacheHandle, context| {
+            let cache = args.get_or_undefined(0).to_string(context)?.to_std_string_escaped();
+            let request = js_cache_request(args, context)?;
+            let status = args.get_or_undefined(4).to_number(context)? as u16;
+            let mut response = HttpResponse::new(status, status_text(status));
+            response.headers = js_headers(args.get_or_undefined(5), context)?;
+            let body = args.get_or_undefined(6);
+            response.body = if body.is_null_or_undefined() {
+                Vec::new()
+            } else if let Some(stream) = html_rewriter::body_stream(body) {
+                html_rewriter::read_all(&stream, context)?
+            } else if let Some(bytes) = js_to_bytes(body, context)? {
+                bytes
+            } else {
+                body.to_string(context)?.to_std_string_escaped().into_bytes()
+            };
+            handle
+                .cache
+                .put(&cache, &request, &response)
+                .map_err(|e| JsNativeError::typ().with_message(e))?;
+            Ok(JsValue::undefined())
+        },
+        handle.clone(),
+    );
+
+    let delete_fn = NativeFunction::from_copy_closure_with_captures(
+        |_, args, handle: &CacheHandle, context| {
+            let cache = args.get_or_undefined(0).to_string(context)?.to_std_string_escaped();
+            let request = js_cache_request(args, context)?;
+            let ignore_method = args.get_or_undefined(4).to_boolean();
+            let deleted = handle
+                .cache
+                .delete(&cache, &request, ignore_method)
+                .map_err(|e| JsNativeError::error().with_message(e))?;
+            Ok(JsValue::from(deleted))
+        },
+        handle,
+    );
+
+    let natives = ObjectInitializer::new(context)
+        .function(match_fn, js_string!("match"), 5)
+        .function(put_fn, js_string!("put"), 7)
+        .function(delete_fn, js_string!("delete"), 5)
+        .build();
+    context
+        .global_object()
+        .set(js_string!("__raven_cache_native"), natives, false, context)
+        .map_err(|e| format!("Failed to install caches: {}", e))?;
+    Ok(())
+}
+
+/// 从参数 (缓存名, url, method, headers) 构造缓存请求
+fn js_cache_request(args: &[JsValue], context: &mut Context) -> JsResult<CacheRequest> {
+    Ok(CacheRequest {
+        url: args.get_or_undefined(1).to_string(context)?.to_std_string_escaped(),
+        method: args.get_or_undefined(2).to_string(context)?.to_std_string_escaped(),
+        headers: js_headers(args.get_or_undefined(3), context)?,
+    })
+}
+
+/// 读取普通对象形式的头部，名称转为小写，忽略函数和以 `_` 开头的属性
+fn js_headers(value: &JsValue, context: &mut Context) -> JsResult<HashMap<String, String>> {
+    let mut headers = HashMap::new();
+    let Some(obj) = value.as_object() else {
+        return Ok(headers);
+    };
+    for key in obj.own_property_keys(context)? {
+        let name = key.to_string();
+        if name.starts_with('_') {
+            continue;
+        }
+        let value = obj.get(key, context)?;
+        if value.is_callable() || value.is_undefined() {
+            continue;
+        }
+        headers.insert(
+            name.to_lowercase(),
+            value.to_string(context)?.to_std_string_escaped(),
+        );
+    }
+    Ok(headers)
+}
+
+fn http_response_to_js(response: &HttpResponse, context: &mut Context) -> JsResult<JsValue> {
+    let headers = ObjectInitializer::new(context).build();
+    for (name, value) in &response.headers {
+        headers.set(
+            JsString::from(name.as_str()),
+            JsValue::from(js_string!(value.as_str())),
+            false,
+            context,
+        )?;
+    }
+    let body = JsArrayBuffer::from_byte_block(AlignedVec::from_slice(0, &response.body), context)?;
+
+    let object = ObjectInitializer::new(context).build();
+    object.set(js_string!("status"), JsValue::from(response.status as i32), false, context)?;
+    object.set(js_string!("headers"), headers, false, context)?;
+    object.set(js_string!("body"), body, false, context)?;
+    Ok(object.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(cache_control: &str, body: &str) -> HttpResponse {
+        HttpResponse::ok(body).with_header("Cache-Control", cache_control)
+    }
+
+    #[test]
+    fn test_cache_freshness() {
+        let cache = HttpCache::memory();
+        let request = CacheRequest::get("https://example.com/a#frag");
+
+        cache
+            .put_at("default", &request, &response("max-age=60, stale-while-revalidate=30", "v1"), 1000)
+            .unwrap();
+
+        let hit = cache.lookup_at("default", &CacheRequest::get("https://example.com/a"), false, 1010).unwrap().unwrap();
+        assert_eq!(hit.body, b"v1");
+        assert_eq!(hit.headers["age"], "10");
+        assert_eq!(hit.headers["x-raven-cache"], "HIT");
+
+        let stale = cache.lookup_at("default", &request, false, 1075).unwrap().unwrap();
+        assert_eq!(stale.headers["x-raven-cache"], "STALE");
+
+        assert!(cache.lookup_at("default", &request, false, 1100).unwrap().is_none());
+        // 过期条目已被清理
+        assert!(!cache.delete("default", &request, false).unwrap());
+
+        // 不同缓存名互不影响
+        cache.put_at("other", &request, &response("max-age=60", "v2"), 1000).unwrap();
+        assert!(cache.lookup_at("default", &request, false, 1000).unwrap().is_none());
+        assert!(cache.delete("other", &request, false).unwrap());
+    }
+
+    #[test]
+    fn test_cache_huge_delta_seconds() {
+        let cache = HttpCache::memory();
+        let request = CacheRequest::get("https://example.com/huge");
+
+        // 超大的 Age 不会回绕成很小的值，响应已过期
+        let aged = response("max-age=60", "old").with_header("Age", "18446744073709551615");
+        cache.put_at("default", &request, &aged, 1000).unwrap();
+        assert!(cache.lookup_at("default", &request, false, 1000).unwrap().is_none());
+
+        let aged = response("max-age=60", "old").with_header("Age", "99999999999999999999999");
+        cache.put_at("default", &request, &aged, 1000).unwrap();
+        assert!(cache.lookup_at("default", &request, false, 1000).unwrap().is_none());
+
+        // 超大的 max-age 截断为 2^31 秒，加上 stale-while-revalidate 不会溢出
+        let forever = response("max-age=18446744073709551615, stale-while-revalidate=1", "v");
+        cache.put_at("default", &request, &forever, 1000).unwrap();
+        let hit = cache.lookup_at("default", &request, false, 1000 + (1 << 31) - 1).unwrap().unwrap();
+        assert_eq!(hit.headers["x-raven-cache"], "HIT");
+        let stale = cache.lookup_at("default", &request, false, 1000 + (1 << 31)).unwrap().unwrap();
+        assert_eq!(stale.headers["x-raven-cache"], "STALE");
+        assert!(cache.lookup_at("default", &request, false, 1001 + (1 << 31)).unwrap().is_none());
+
+        assert_eq!(delta_seconds("42"), Some(42));
+        assert_eq!(delta_seconds("-1"), None);
+        assert_eq!(delta_seconds("+5"), None);
+        assert_eq!(delta_seconds(""), None);
+    }
+
+    #[test]
+    fn test_cache_storability() {
+        let cache = HttpCache::memory();
+        let request = CacheRequest::get("https://example.com/");
+
+        for cache_control in ["no-store", "private, max-age=60", ""] {
+            cache.put_at("default", &request, &response(cache_control, "x"), 0).unwrap();
+            assert!(cache.lookup_at("default", &request, false, 0).unwrap().is_none(), "{}", cache_control);
+        }
+
+        let expires = response("", "x")
+            .with_header("Date", "Sun, 06 Nov 1994 08:49:37 GMT")
+            .with_header("Expires", "Sun, 06 Nov 1994 08:50:37 GMT");
+        cache.put_at("default", &request, &expires, 0).unwrap();
+        assert!(cache.lookup_at("default", &request, false, 59).unwrap().is_some());
+        assert!(cache.lookup_at("default", &request, false, 61).unwrap().is_none());
+
+        let post = CacheRequest {
+            method: "POST".to_string(),
+            ..request.clone()
+        };
+        assert!(cache.put_at("default", &post, &response("max-age=60", "x"), 0).is_err());
+        let partial = HttpResponse {
+            status: 206,
+            ..response("max-age=60", "x")
+        };
+        assert!(cache.put_at("default", &request, &partial, 0).is_err());
+        let vary_all = response("max-age=60", "x").with_header("Vary", "*");
+        assert!(cache.put_at("default", &request, &vary_all, 0).is_err());
+
+        // POST 查询默认不命中，ignoreMethod 时命中
+        cache.put_at("default", &request, &response("max-age=60", "x"), 0).unwrap();
+        assert!(cache.lookup_at("default", &post, false, 0).unwrap().is_none());
+        assert!(cache.lookup_at("default", &post, true, 0).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_cache_vary_and_etag() {
+        let cache = HttpCache::memory();
+        let mut en = CacheRequest::get("https://example.com/page");
+        en.headers.insert("accept-language".to_string(), "en".to_string());
+        let mut fr = en.clone();
+        fr.headers.insert("accept-language".to_string(), "fr".to_string());
+
+        let vary = |body: &str| {
+            response("max-age=60", body)
+                .with_header("Vary", "Accept-Language")
+                .with_header("ETag", "\"v1\"")
+        };
+        cache.put_at("default", &en, &vary("hello"), 0).unwrap();
+        cache.put_at("default", &fr, &vary("bonjour"), 0).unwrap();
+
+        assert_eq!(cache.lookup_at("default", &en, false, 0).unwrap().unwrap().body, b"hello");
+        assert_eq!(cache.lookup_at("default", &fr, false, 0).unwrap().unwrap().body, b"bonjour");
+        assert!(cache
+            .lookup_at("default", &CacheRequest::get("https://example.com/page"), false, 0)
+            .unwrap()
+            .is_none());
+
+        en.headers.insert("if-none-match".to_string(), "W/\"v1\", \"v0\"".to_string());
+        let not_modified = cache.lookup_at("default", &en, false, 0).unwrap().unwrap();
+        assert_eq!(not_modified.status, 304);
+        assert!(not_modified.body.is_empty());
+        assert_eq!(not_modified.headers["etag"], "\"v1\"");
+    }
+
+    #[test]
+    fn test_disk_cache_backend() {
+        let dir = std::env::temp_dir().join(format!("raven-cache-test-{}", std::process::id()));
+        let cache = HttpCache::new(Arc::new(DiskCacheBackend::new(dir.to_str().unwrap())));
+        let request = CacheRequest::get("https://example.com/bin");
+
+        let mut binary = response("max-age=60", "");
+        binary.body = vec![0, 159, 146, 150];
+        cache.put("default", &request, &binary).unwrap();
+
+        // 新的后端实例读取同一目录
+        let reopened = HttpCache::new(Arc::new(DiskCacheBackend::new(dir.to_str().unwrap())));
+        let hit = reopened.lookup("default", &request, false).unwrap().unwrap();
+        assert_eq!(hit.body, vec![0, 159, 146, 150]);
+        assert!(reopened.delete("default", &request, false).unwrap());
+        assert!(cache.lookup("default", &request, false).unwrap().is_none());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_memory_backend_evicts_least_recently_used() {
+        let entry = |body: &str| CachedResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: body.as_bytes().to_vec(),
+            stored_at: 0,
+            vary: Vec::new(),
+        };
+        // 每个键占 "c" + "uN" + 10 字节
+        let backend = MemoryCacheBackend::with_max_bytes(40);
+        backend.store("c", "u1", vec![entry("aaaaaaaaaa")]).unwrap();
+        backend.store("c", "u2", vec![entry("bbbbbbbbbb")]).unwrap();
+        backend.store("c", "u3", vec![entry("cccccccccc")]).unwrap();
+        // 读取 u1 后，u2 成为最久未使用
+        assert_eq!(backend.load("c", "u1").unwrap().len(), 1);
+        backend.store("c", "u4", vec![entry("dddddddddd")]).unwrap();
+        assert!(backend.load("c", "u2").unwrap().is_empty());
+        assert_eq!(backend.load("c", "u1").unwrap().len(), 1);
+        assert_eq!(backend.load("c", "u4").unwrap().len(), 1);
+        assert!(backend.entries.lock().unwrap().bytes <= 40);
+
+        // 超过上限的单个 URL 不缓存，也不会挤掉其他 URL
+        backend.store("c", "big", vec![entry(&"x".repeat(100))]).unwrap();
+        assert!(backend.load("c", "big").unwrap().is_empty());
+        assert_eq!(backend.load("c", "u1").unwrap().len(), 1);
+
+        backend.store("c", "u1", Vec::new()).unwrap();
+        assert!(backend.load("c", "u1").unwrap().is_empty());
+
+        // 锁被污染时返回错误而不是 panic
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _entries = backend.entries.lock().unwrap();
+                panic!("poison");
+            })
+            .join()
+            .ok();
+        });
+        assert!(backend.load("c", "u4").is_err());
+        assert!(backend.store("c", "u4", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_disk_backend_concurrent_stores() {
+        let dir = std::env::temp_dir().join(format!("raven-cache-concurrent-{}", rand::random::<u64>()));
+        let backend = DiskCacheBackend::new(dir.to_str().unwrap());
+        let request = CacheRequest::get("https://example.com/race");
+        std::thread::scope(|s| {
+            for n in 0..8 {
+                let (backend, request) = (&backend, &request);
+                s.spawn(move || {
+                    for _ in 0..20 {
+                        let entry = CachedResponse {
+                            status: 200,
+                            headers: Vec::new(),
+                            body: format!("body-{}", n).into_bytes(),
+                            stored_at: 0,
+                            vary: Vec::new(),
+                        };
+                        backend.store("default", request.key(), vec![entry]).unwrap();
+                    }
+                });
+            }
+        });
+        let entries = backend.load("default", request.key()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(String::from_utf8_lossy(&entries[0].body).starts_with("body-"));
+        // 没有留下临时文件
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
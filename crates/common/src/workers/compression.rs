@@ -0,0 +1,345 @@
+//! 响应压缩
+//!
+//! 根据请求的 `Accept-Encoding` 和响应的 `Content-Type` 选择 gzip、brotli 或 zstd
+//! 压缩响应 body，并维护 `Content-Encoding`、`Content-Length` 和 `Vary` 头。
+//! Worker 可以在 `Response` 上设置 `encodeBody: "manual"` 或自行设置
+//! `Content-Encoding` 来跳过自动压缩。
+//!
+//! 压缩需要完整的 body，流式响应（如 `HTMLRewriter.transform()` 的结果）按原样分块发送，
+//! 不压缩，也不添加 `Vary`。
+
+use std::io::Write;
+
+use super::http::{HttpRequest, HttpResponse};
+
+/// 响应内容编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Zstd,
+    Brotli,
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// 从 `Content-Encoding` 名称解析（`zstd` / `br` / `gzip`）
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "zstd" => Ok(Self::Zstd),
+            "br" | "brotli" => Ok(Self::Brotli),
+            "gzip" => Ok(Self::Gzip),
+            other => Err(format!("Unknown content encoding: '{}'", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    /// 压缩数据
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Zstd => zstd::encode_all(data, 3).map_err(|e| e.to_string()),
+            Self::Brotli => {
+                let mut out = Vec::new();
+                {
+                    let mut writer = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+                    writer.write_all(data).map_err(|e| e.to_string())?;
+                }
+                Ok(out)
+            }
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data).map_err(|e| e.to_string())?;
+                encoder.finish().map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// 响应压缩配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// body 小于该字节数时不压缩
+    pub min_size: usize,
+    /// 服务器支持的编码，客户端权重相同时按此顺序优先
+    pub encodings: Vec<ContentEncoding>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_size: 1024,
+            encodings: vec![ContentEncoding::Zstd, ContentEncoding::Brotli, ContentEncoding::Gzip],
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// 关闭压缩
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    /// 按配置压缩响应
+    ///
+    /// 已编码、部分内容、流式 body、`Cache-Control: no-transform` 以及 Worker 选择手动编码的响应保持不变。
+    /// 可压缩类型的响应总会带上 `Vary: Accept-Encoding`，即使本次未压缩。
+    pub fn apply(&self, request: &HttpRequest, response: &mut HttpResponse) {
+        if !self.enabled || !self.is_eligible(response) {
+            return;
+        }
+        add_vary(response, "Accept-Encoding");
+
+        if response.body.len() < self.min_size || request.method.eq_ignore_ascii_case("HEAD") {
+            return;
+        }
+        let accept_encoding = request
+            .headers
+            .get("accept-encoding")
+            .map(|v| v.as_str())
+            .unwrap_or_default();
+        let Some(encoding) = negotiate(accept_encoding, &self.encodings) else {
+            return;
+        };
+
+        match encoding.encode(&response.body) {
+            Ok(body) if body.len() < response.body.len() => {
+                response.body = body;
+                response
+                    .headers
+                    .insert("content-encoding".to_string(), encoding.as_str().to_string());
+                // 响应总是完整缓冲后发送，不使用分块传输
+                response.headers.remove("transfer-encoding");
+                response
+                    .headers
+                    .insert("content-length".to_string(), response.body.len().to_string());
+                // 压缩后原有的强 ETag 不再对应实际内容
+                if let Some(etag) = response.headers.get_mut("etag") {
+                    if !etag.starts_with("W/") {
+                        *etag = format!("W/{}", etag);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to compress response: {}", e),
+        }
+    }
+
+    /// 响应是否可以自动压缩（不考虑大小和客户端）
+    fn is_eligible(&self, response: &HttpResponse) -> bool {
+        if !response.auto_encode
+            || response.websocket.is_some()
+            || response.stream.is_some()
+            || matches!(response.status, 100..=199 | 204 | 206 | 304)
+            || response.headers.contains_key("content-encoding")
+            || response.headers.contains_key("content-range")
+        {
+            return false;
+        }
+        let no_transform = response
+            .headers
+            .get("cache-control")
+            .is_some_and(|v| v.to_lowercase().contains("no-transform"));
+        !no_transform
+            && response
+                .headers
+                .get("content-type")
+                .is_some_and(|v| is_compressible(v))
+    }
+}
+
+/// 按 `Accept-Encoding` 的权重选择编码，权重相同时按 `supported` 的顺序
+pub fn negotiate(accept_encoding: &str, supported: &[ContentEncoding]) -> Option<ContentEncoding> {
+    let mut wildcard = None;
+    let mut weights: Vec<(ContentEncoding, f32)> = Vec::new();
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or_default().trim().to_lowercase();
+        let q = params
+            .find_map(|p| p.trim().strip_prefix("q=").map(|q| q.trim().parse::<f32>().unwrap_or(0.0)))
+            .unwrap_or(1.0);
+        if name == "*" {
+            wildcard = Some(q);
+        } else if let Ok(encoding) = ContentEncoding::parse(&name) {
+            if name != "brotli" {
+                weights.push((encoding, q));
+            }
+        }
+    }
+
+    let mut best: Option<(ContentEncoding, f32)> = None;
+    for encoding in supported {
+        let q = weights
+            .iter()
+            .find(|(e, _)| e == encoding)
+            .map(|(_, q)| *q)
+            .or(wildcard)
+            .unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((*encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// 文本类内容才值得压缩，图片、视频和压缩包通常已经压缩过
+fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "application/x-javascript"
+                | "application/manifest+json"
+                | "image/svg+xml"
+                | "image/x-icon"
+                | "font/ttf"
+                | "font/otf"
+        )
+}
+
+/// 向 `Vary` 头追加一项（已存在时不重复）
+fn add_vary(response: &mut HttpResponse, value: &str) {
+    match response.headers.get_mut("vary") {
+        Some(vary) => {
+            let present = vary
+                .split(',')
+                .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case(value));
+            if !present {
+                vary.push_str(", ");
+                vary.push_str(value);
+            }
+        }
+        None => {
+            response.headers.insert("vary".to_string(), value.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workers::http::BodyStream;
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    fn request(accept_encoding: &str) -> HttpRequest {
+        let mut headers = HashMap::new();
+        headers.insert("accept-encoding".to_string(), accept_encoding.to_string());
+        HttpRequest {
+            method: "GET".to_string(),
+            path: "/".to_string(),
+            headers,
+            ..Default::default()
+        }
+    }
+
+    fn text_response(len: usize) -> HttpResponse {
+        HttpResponse::ok(&"hello raven ".repeat(len / 12 + 1)[..len])
+            .with_header("Content-Type", "text/html; charset=utf-8")
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let all = CompressionConfig::default().encodings;
+        assert_eq!(negotiate("gzip, deflate, br", &all), Some(ContentEncoding::Brotli));
+        assert_eq!(negotiate("gzip, br, zstd", &all), Some(ContentEncoding::Zstd));
+        assert_eq!(negotiate("br;q=0.5, gzip", &all), Some(ContentEncoding::Gzip));
+        assert_eq!(negotiate("*;q=0.1, zstd;q=0", &all), Some(ContentEncoding::Brotli));
+        assert_eq!(negotiate("identity", &all), None);
+        assert_eq!(negotiate("", &all), None);
+        assert_eq!(negotiate("br", &[ContentEncoding::Gzip]), None);
+    }
+
+    #[test]
+    fn test_compress_response() {
+        let config = CompressionConfig::default();
+
+        let mut response = text_response(4096).with_header("ETag", "\"abc\"");
+        config.apply(&request("gzip"), &mut response);
+        assert_eq!(response.headers["content-encoding"], "gzip");
+        assert_eq!(response.headers["content-length"], response.body.len().to_string());
+        assert_eq!(response.headers["vary"], "Accept-Encoding");
+        assert_eq!(response.headers["etag"], "W/\"abc\"");
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&response.body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded.len(), 4096);
+
+        let mut response = text_response(4096);
+        config.apply(&request("zstd"), &mut response);
+        assert_eq!(zstd::decode_all(&response.body[..]).unwrap().len(), 4096);
+
+        let mut response = text_response(4096);
+        config.apply(&request("br"), &mut response);
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(&response.body[..], 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded.len(), 4096);
+    }
+
+    #[test]
+    fn test_skip_compression() {
+        let config = CompressionConfig::default();
+
+        // 小于阈值：不压缩但仍声明 Vary
+        let mut response = text_response(100);
+        config.apply(&request("gzip"), &mut response);
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert_eq!(response.headers["vary"], "Accept-Encoding");
+
+        // 不可压缩的类型
+        let mut response = text_response(4096).with_header("Content-Type", "image/png");
+        config.apply(&request("gzip"), &mut response);
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert!(!response.headers.contains_key("vary"));
+
+        // Worker 手动编码
+        let mut response = text_response(4096);
+        response.auto_encode = false;
+        config.apply(&request("gzip"), &mut response);
+        assert!(!response.headers.contains_key("content-encoding"));
+
+        // 已有 Vary 时追加
+        let mut response = text_response(4096).with_header("Vary", "Origin");
+        config.apply(&request("gzip"), &mut response);
+        assert_eq!(response.headers["vary"], "Origin, Accept-Encoding");
+
+        // 流式 body 原样发送
+        let (sender, stream) = BodyStream::channel(1);
+        let mut response = text_response(4096);
+        response.stream = Some(stream);
+        config.apply(&request("gzip"), &mut response);
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert!(!response.headers.contains_key("vary"));
+        sender.send(Ok(b"chunk".to_vec())).unwrap();
+        assert_eq!(response.stream.unwrap().next_chunk().unwrap().unwrap(), b"chunk");
+
+        let mut response = text_response(4096);
+        CompressionConfig::disabled().apply(&request("gzip"), &mut response);
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert_eq!(response.body.len(), 4096);
+    }
+}
@@ -0,0 +1,447 @@
+//! Worker 服务器配置
+//!
+//! 一个 `WorkerServer` 可以挂载多个 Worker，每个 Worker 拥有独立的
+//! 运行时、绑定和资源限制，通过路由规则分发请求。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use super::access_log::AccessLogFormat;
+use super::bindings::{FsyncPolicy, KvLimits};
+use super::compression::CompressionConfig;
+use super::proxy::TrustedProxies;
+use super::scheduler::MissedRunPolicy;
+use super::tls::TlsConfig;
+
+/// 密钥值，`Debug` 输出时隐藏内容
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: &str) -> Self {
+        Self(value.to_string())
+    }
+
+    /// 密钥明文
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+/// KV 命名空间配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvNamespaceConfig {
+    /// 在 `env` 上的绑定名称，如 `CACHE`
+    pub binding: String,
+    /// 命名空间 ID，同一进程中 ID 相同的绑定共享一个存储
+    pub id: String,
+    /// 存储后端：`memory`、`file` 或 `sqlite`
+    pub backend: String,
+    /// `file` 后端的日志文件或 `sqlite` 后端的数据库路径
+    pub path: Option<String>,
+    /// `file` 后端何时 fsync，热重载时不能修改（需要重启服务器）
+    pub fsync: FsyncPolicy,
+    /// `memory` 后端的容量限制和淘汰策略
+    pub limits: KvLimits,
+}
+
+impl KvNamespaceConfig {
+    pub fn memory(binding: &str, id: &str) -> Self {
+        Self {
+            binding: binding.to_string(),
+            id: id.to_string(),
+            backend: "memory".to_string(),
+            path: None,
+            fsync: FsyncPolicy::default(),
+            limits: KvLimits::default(),
+        }
+    }
+
+    /// 保存在日志文件中的命名空间，重启后数据仍然存在
+    pub fn file(binding: &str, id: &str, path: &str) -> Self {
+        Self {
+            backend: "file".to_string(),
+            path: Some(path.to_string()),
+            ..Self::memory(binding, id)
+        }
+    }
+
+    /// 保存在 SQLite 数据库中的命名空间，适合键很多的场景
+    pub fn sqlite(binding: &str, id: &str, path: &str) -> Self {
+        Self {
+            backend: "sqlite".to_string(),
+            path: Some(path.to_string()),
+            ..Self::memory(binding, id)
+        }
+    }
+
+    /// 设置内存命名空间的容量限制，超出时按淘汰策略删除键
+    pub fn with_limits(mut self, limits: KvLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+/// Service 绑定配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceBindingConfig {
+    /// 在 `env` 上的绑定名称，如 `AUTH`
+    pub binding: String,
+    /// 目标 Worker 名称
+    pub service: String,
+    /// 单次调用的超时时间，与上游传来的截止时间取较早者
+    pub timeout: Option<Duration>,
+}
+
+impl ServiceBindingConfig {
+    pub fn new(binding: &str, service: &str) -> Self {
+        Self {
+            binding: binding.to_string(),
+            service: service.to_string(),
+            timeout: None,
+        }
+    }
+}
+
+/// 队列 Producer 配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueProducerConfig {
+    /// 在 `env` 上的绑定名称，如 `JOBS`
+    pub binding: String,
+    /// 队列名称
+    pub queue: String,
+}
+
+impl QueueProducerConfig {
+    pub fn new(binding: &str, queue: &str) -> Self {
+        Self {
+            binding: binding.to_string(),
+            queue: queue.to_string(),
+        }
+    }
+}
+
+/// 队列 Consumer 配置，消息交给 Worker 的 `queue(batch, env, ctx)` 入口
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueConsumerConfig {
+    /// 队列名称
+    pub queue: String,
+    /// 每批最多的消息数
+    pub max_batch_size: usize,
+    /// 最多重试次数，超过后转入死信队列（未配置时丢弃）
+    pub max_retries: u32,
+    /// 死信队列名称
+    pub dead_letter_queue: Option<String>,
+    /// 未指定 `delaySeconds` 时重试前的等待时间
+    pub retry_delay: Duration,
+}
+
+impl QueueConsumerConfig {
+    pub fn new(queue: &str) -> Self {
+        Self {
+            queue: queue.to_string(),
+            max_batch_size: 10,
+            max_retries: 3,
+            dead_letter_queue: None,
+            retry_delay: Duration::ZERO,
+        }
+    }
+
+    /// 设置死信队列
+    pub fn with_dead_letter_queue(mut self, queue: &str) -> Self {
+        self.dead_letter_queue = Some(queue.to_string());
+        self
+    }
+}
+
+/// Durable Object 配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurableObjectConfig {
+    /// 在 `env` 上的绑定名称，如 `COUNTER`
+    pub binding: String,
+    /// Worker 导出的类名
+    pub class_name: String,
+}
+
+impl DurableObjectConfig {
+    pub fn new(binding: &str, class_name: &str) -> Self {
+        Self {
+            binding: binding.to_string(),
+            class_name: class_name.to_string(),
+        }
+    }
+}
+
+/// 静态资源配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetsConfig {
+    /// 资源目录
+    pub directory: String,
+    /// 在 `env` 上的绑定名称
+    pub binding: String,
+    /// 为 `true` 时总是先调用 `fetch`，由 Worker 通过 `env.ASSETS.fetch()` 回退到资源
+    pub run_worker_first: bool,
+}
+
+impl AssetsConfig {
+    pub fn new(directory: &str) -> Self {
+        Self {
+            directory: directory.to_string(),
+            binding: "ASSETS".to_string(),
+            run_worker_first: false,
+        }
+    }
+}
+
+/// Worker 资源限制
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerLimits {
+    /// 请求 body 的最大字节数，超出返回 413
+    pub max_body_size: usize,
+    /// `request.formData()` 中单个字段或文件的最大字节数
+    pub max_form_part_size: usize,
+    /// 单个循环的最大迭代次数（防止死循环）
+    pub loop_iteration_limit: Option<u64>,
+    /// 最大递归深度
+    pub recursion_limit: Option<usize>,
+    /// 虚拟机栈的最大大小
+    pub stack_size_limit: Option<usize>,
+}
+
+impl Default for WorkerLimits {
+    fn default() -> Self {
+        Self {
+            max_body_size: 10 * 1024 * 1024,
+            max_form_part_size: 5 * 1024 * 1024,
+            loop_iteration_limit: None,
+            recursion_limit: None,
+            stack_size_limit: None,
+        }
+    }
+}
+
+/// 单个 Worker 的配置
+#[derive(Debug, Clone, Default)]
+pub struct WorkerConfig {
+    /// Worker 名称（用于日志和手动触发）
+    pub name: String,
+    /// Worker 脚本路径
+    pub script_path: String,
+    /// 路由规则，如 `/api/*`、`api.example.com/*`、`*.example.com/v1/*`
+    pub routes: Vec<String>,
+    /// Cron 触发器（触发 Worker 的 scheduled 入口）
+    pub crons: Vec<String>,
+    /// 资源限制
+    pub limits: WorkerLimits,
+    /// 普通环境变量（`env.NAME`）
+    pub vars: HashMap<String, String>,
+    /// 密钥（`env.NAME`）
+    pub secrets: HashMap<String, Secret>,
+    /// KV 命名空间（`env.BINDING`）
+    pub kv_namespaces: Vec<KvNamespaceConfig>,
+    /// 是否要求请求携带已校验的客户端证书（需开启 TLS 客户端认证）
+    pub require_client_cert: bool,
+    /// 静态资源目录
+    pub assets: Option<AssetsConfig>,
+    /// Service 绑定（`env.BINDING.fetch()` 调用同一服务器中的其他 Worker）
+    pub services: Vec<ServiceBindingConfig>,
+    /// 队列 Producer（`env.BINDING.send()`）
+    pub queue_producers: Vec<QueueProducerConfig>,
+    /// 队列 Consumer
+    pub queue_consumers: Vec<QueueConsumerConfig>,
+    /// Durable Object 命名空间（`env.BINDING.getByName()`）
+    pub durable_objects: Vec<DurableObjectConfig>,
+}
+
+impl WorkerConfig {
+    pub fn new(name: &str, script_path: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            script_path: script_path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// 添加路由规则
+    pub fn with_route(mut self, route: &str) -> Self {
+        self.routes.push(route.to_string());
+        self
+    }
+
+    /// 添加 Cron 触发器
+    pub fn with_cron(mut self, cron: &str) -> Self {
+        self.crons.push(cron.to_string());
+        self
+    }
+
+    /// 设置资源限制
+    pub fn with_limits(mut self, limits: WorkerLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// 添加环境变量
+    pub fn with_var(mut self, name: &str, value: &str) -> Self {
+        self.vars.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// 添加密钥
+    pub fn with_secret(mut self, name: &str, value: &str) -> Self {
+        self.secrets.insert(name.to_string(), Secret::new(value));
+        self
+    }
+
+    /// 添加 KV 命名空间
+    pub fn with_kv_namespace(mut self, namespace: KvNamespaceConfig) -> Self {
+        self.kv_namespaces.push(namespace);
+        self
+    }
+
+    /// 设置静态资源目录
+    pub fn with_assets(mut self, assets: AssetsConfig) -> Self {
+        self.assets = Some(assets);
+        self
+    }
+
+    /// 添加 Service 绑定
+    pub fn with_service(mut self, service: ServiceBindingConfig) -> Self {
+        self.services.push(service);
+        self
+    }
+
+    /// 添加队列 Producer
+    pub fn with_queue_producer(mut self, producer: QueueProducerConfig) -> Self {
+        self.queue_producers.push(producer);
+        self
+    }
+
+    /// 添加队列 Consumer
+    pub fn with_queue_consumer(mut self, consumer: QueueConsumerConfig) -> Self {
+        self.queue_consumers.push(consumer);
+        self
+    }
+
+    /// 添加 Durable Object 命名空间
+    pub fn with_durable_object(mut self, durable_object: DurableObjectConfig) -> Self {
+        self.durable_objects.push(durable_object);
+        self
+    }
+}
+
+/// Worker 服务器配置
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// 监听地址
+    pub host: String,
+    /// 监听端口
+    pub port: u16,
+    /// Worker 脚本路径（单 Worker 模式）
+    pub script_path: String,
+    /// Cron 触发器（单 Worker 模式）
+    pub crons: Vec<String>,
+    /// 挂载的 Worker 列表，非空时忽略 `script_path` 和 `crons`
+    pub workers: Vec<WorkerConfig>,
+    /// 错过执行时间时的处理策略
+    pub missed_run_policy: MissedRunPolicy,
+    /// 是否开启手动触发入口 `/__raven/scheduled?cron=...`（用于测试）
+    pub test_scheduled: bool,
+    /// TLS 配置，`None` 时使用明文 HTTP
+    pub tls: Option<TlsConfig>,
+    /// 访问日志格式
+    pub access_log: AccessLogFormat,
+    /// 是否开启 Prometheus 指标入口 `/__raven/metrics`
+    pub metrics: bool,
+    /// 关闭时等待进行中的请求和 `waitUntil` 任务完成的最长时间
+    pub shutdown_timeout: Duration,
+    /// 受信任的反向代理，来自这些地址的请求采用 `X-Forwarded-*` 头
+    pub trusted_proxies: TrustedProxies,
+    /// 响应压缩
+    pub compression: CompressionConfig,
+    /// Cache API 的磁盘缓存目录，`None` 时缓存在内存中
+    pub cache_dir: Option<String>,
+    /// 队列的持久化目录，`None` 时队列保存在内存中
+    pub queue_dir: Option<String>,
+    /// Durable Object 存储目录，`None` 时保存在内存中
+    pub durable_objects_dir: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8787,
+            script_path: "worker.js".to_string(),
+            crons: Vec::new(),
+            workers: Vec::new(),
+            missed_run_policy: MissedRunPolicy::default(),
+            test_scheduled: false,
+            tls: None,
+            access_log: AccessLogFormat::default(),
+            metrics: false,
+            shutdown_timeout: Duration::from_secs(30),
+            trusted_proxies: TrustedProxies::none(),
+            compression: CompressionConfig::default(),
+            cache_dir: None,
+            queue_dir: None,
+            durable_objects_dir: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn new(host: &str, port: u16, script_path: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            script_path: script_path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// 添加 Cron 触发器（单 Worker 模式）
+    pub fn with_cron(mut self, cron: &str) -> Self {
+        self.crons.push(cron.to_string());
+        self
+    }
+
+    /// 开启 TLS
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// 挂载一个 Worker
+    pub fn with_worker(mut self, worker: WorkerConfig) -> Self {
+        self.workers.push(worker);
+        self
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// 实际生效的 Worker 列表
+    ///
+    /// 未配置 `workers` 时，根据 `script_path` 生成一个匹配所有请求的默认 Worker
+    pub fn worker_configs(&self) -> Vec<WorkerConfig> {
+        if !self.workers.is_empty() {
+            return self.workers.clone();
+        }
+
+        vec![WorkerConfig {
+            name: "default".to_string(),
+            script_path: self.script_path.clone(),
+            routes: vec!["/*".to_string()],
+            crons: self.crons.clone(),
+            ..Default::default()
+        }]
+    }
+}
@@ -0,0 +1,66 @@
+//! 客户端连接
+//!
+//! 统一明文 TCP 和 TLS 连接的读写接口。
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+
+use super::tls::{TlsInfo, TlsStream};
+
+/// 客户端连接
+pub enum Connection {
+    Plain(TcpStream),
+    Tls(Box<TlsStream>),
+}
+
+impl Connection {
+    /// TLS 连接信息，明文连接返回 `None`
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        match self {
+            Connection::Plain(_) => None,
+            Connection::Tls(stream) => Some(TlsInfo::from_connection(&stream.conn)),
+        }
+    }
+
+    /// 底层 TCP 连接
+    pub fn tcp(&self) -> &TcpStream {
+        match self {
+            Connection::Plain(stream) => stream,
+            Connection::Tls(stream) => stream.get_ref(),
+        }
+    }
+
+    /// 关闭连接（TLS 连接会先发送 close_notify）
+    pub fn close(&mut self) {
+        if let Connection::Tls(stream) = self {
+            stream.conn.send_close_notify();
+            stream.flush().ok();
+        }
+        self.tcp().shutdown(Shutdown::Both).ok();
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.read(buf),
+            Connection::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Plain(stream) => stream.write(buf),
+            Connection::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Plain(stream) => stream.flush(),
+            Connection::Tls(stream) => stream.flush(),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Cron 表达式解析
+//!
+//! 支持标准的 5 段 cron 表达式（分 时 日 月 周），与 Cloudflare Workers 的
+//! Cron Triggers 一致，所有时间均按 UTC 计算。
+//!
+//! # 支持的语法
+//!
+//! - `*`、`*/15`、`1-5`、`1-30/5`、`1,15,30`
+//! - 月份和星期的英文缩写（`JAN`-`DEC`、`SUN`-`SAT`），星期中 `7` 也表示周日
+//! - 预定义宏：`@yearly`、`@monthly`、`@weekly`、`@daily`、`@hourly`
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+
+const MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// 查找下一次执行时间时最多向后搜索的年数
+const MAX_SEARCH_YEARS: i32 = 5;
+
+/// 已解析的 cron 表达式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// 日字段是否被限制（不是 `*`）
+    dom_restricted: bool,
+    /// 周字段是否被限制（不是 `*`）
+    dow_restricted: bool,
+}
+
+impl CronSchedule {
+    /// 解析 cron 表达式
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let trimmed = expr.trim();
+        let expanded = match trimmed {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "Invalid cron expression '{}': expected 5 fields, got {}",
+                expr,
+                fields.len()
+            ));
+        }
+
+        let minutes = parse_field(fields[0], 0, 59, None)
+            .map_err(|e| format!("Invalid minute field in '{}': {}", expr, e))?;
+        let hours = parse_field(fields[1], 0, 23, None)
+            .map_err(|e| format!("Invalid hour field in '{}': {}", expr, e))?;
+        let days_of_month = parse_field(fields[2], 1, 31, None)
+            .map_err(|e| format!("Invalid day-of-month field in '{}': {}", expr, e))?;
+        let months = parse_field(fields[3], 1, 12, Some((&MONTH_NAMES, 1)))
+            .map_err(|e| format!("Invalid month field in '{}': {}", expr, e))?;
+        let mut days_of_week = parse_field(fields[4], 0, 7, Some((&WEEKDAY_NAMES, 0)))
+            .map_err(|e| format!("Invalid day-of-week field in '{}': {}", expr, e))?;
+
+        // 7 和 0 都表示周日
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            expr: trimmed.to_string(),
+            minutes,
+            hours,
+            days_of_month,
+            months,
+            days_of_week,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+        })
+    }
+
+    /// 原始表达式（作为 `event.cron` 传给 Worker）
+    pub fn expr(&self) -> &str {
+        &self.expr
+    }
+
+    /// 判断给定时间（精确到分钟）是否匹配
+    pub fn matches(&self, time: &DateTime<Utc>) -> bool {
+        self.minutes & (1 << time.minute()) != 0
+            && self.hours & (1 << time.hour()) != 0
+            && self.months & (1 << time.month()) != 0
+            && self.day_matches(time)
+    }
+
+    /// 计算严格晚于 `after` 的下一次执行时间
+    pub fn next_after(&self, after: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit_year = start.year() + MAX_SEARCH_YEARS;
+        let mut t = start;
+
+        while t.year() <= limit_year {
+            if self.months & (1 << t.month()) == 0 {
+                // 跳到下个月的第一天
+                let (year, month) = if t.month() == 12 {
+                    (t.year() + 1, 1)
+                } else {
+                    (t.year(), t.month() + 1)
+                };
+                t = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+                continue;
+            }
+
+            if !self.day_matches(&t) {
+                t = (t + Duration::days(1)).with_hour(0)?.with_minute(0)?;
+                continue;
+            }
+
+            if self.hours & (1 << t.hour()) == 0 {
+                t = (t + Duration::hours(1)).with_minute(0)?;
+                continue;
+            }
+
+            if self.minutes & (1 << t.minute()) == 0 {
+                t += Duration::minutes(1);
+                continue;
+            }
+
+            return Some(t);
+        }
+
+        None
+    }
+
+    fn day_matches(&self, time: &DateTime<Utc>) -> bool {
+        let dom = self.days_of_month & (1 << time.day()) != 0;
+        let dow = self.days_of_week & (1 << time.weekday().num_days_from_sunday()) != 0;
+
+        // 与 Vixie cron 一致：日和周同时被限制时，满足任意一个即可
+        match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        }
+    }
+}
+
+/// 解析单个字段为位掩码
+fn parse_field(field: &str, min: u32, max: u32, names: Option<(&[&str], u32)>) -> Result<u64, String> {
+    let mut mask = 0u64;
+
+    for part in field.split(',') {
+        if part.is_empty() {
+            return Err("empty list item".to_string());
+        }
+
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("invalid step '{}'", step))?;
+                if step == 0 {
+                    return Err("step must be greater than 0".to_string());
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (parse_value(a, names)?, parse_value(b, names)?)
+        } else {
+            let value = parse_value(range, names)?;
+            // `5/10` 表示从 5 开始到最大值，每 10 个单位
+            if part.contains('/') {
+                (value, max)
+            } else {
+                (value, value)
+            }
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("value out of range {}-{}: '{}'", min, max, part));
+        }
+
+        let mut v = start;
+        while v <= end {
+            mask |= 1 << v;
+            v += step;
+        }
+    }
+
+    Ok(mask)
+}
+
+/// 解析数字或名称
+fn parse_value(value: &str, names: Option<(&[&str], u32)>) -> Result<u32, String> {
+    if let Ok(n) = value.parse::<u32>() {
+        return Ok(n);
+    }
+
+    if let Some((names, offset)) = names {
+        let upper = value.to_uppercase();
+        if let Some(index) = names.iter().position(|n| *n == upper) {
+            return Ok(index as u32 + offset);
+        }
+    }
+
+    Err(format!("invalid value '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn test_every_five_minutes() {
+        let schedule = CronSchedule::parse("*/5 * * * *").unwrap();
+        assert_eq!(
+            schedule.next_after(&at(2024, 1, 1, 10, 2)),
+            Some(at(2024, 1, 1, 10, 5))
+        );
+        assert_eq!(
+            schedule.next_after(&at(2024, 1, 1, 10, 55)),
+            Some(at(2024, 1, 1, 11, 0))
+        );
+    }
+
+    #[test]
+    fn test_names_and_ranges() {
+        // 工作日早上 9 点
+        let schedule = CronSchedule::parse("0 9 * * MON-FRI").unwrap();
+        // 2024-01-06 是周六
+        assert_eq!(
+            schedule.next_after(&at(2024, 1, 6, 12, 0)),
+            Some(at(2024, 1, 8, 9, 0))
+        );
+
+        let schedule = CronSchedule::parse("30 2 1 jan,jul *").unwrap();
+        assert_eq!(
+            schedule.next_after(&at(2024, 2, 1, 0, 0)),
+            Some(at(2024, 7, 1, 2, 30))
+        );
+    }
+
+    #[test]
+    fn test_day_of_month_or_week() {
+        // 每月 13 号或每个周五
+        let schedule = CronSchedule::parse("0 0 13 * 5").unwrap();
+        assert!(schedule.matches(&at(2024, 9, 13, 0, 0)));
+        assert!(schedule.matches(&at(2024, 9, 6, 0, 0)));
+        assert!(!schedule.matches(&at(2024, 9, 7, 0, 0)));
+
+        // 7 表示周日
+        let schedule = CronSchedule::parse("0 0 * * 7").unwrap();
+        assert!(schedule.matches(&at(2024, 9, 8, 0, 0)));
+    }
+
+    #[test]
+    fn test_macros() {
+        let schedule = CronSchedule::parse("@daily").unwrap();
+        assert_eq!(schedule.expr(), "@daily");
+        assert_eq!(
+            schedule.next_after(&at(2024, 2, 28, 23, 59)),
+            Some(at(2024, 2, 29, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("* * * FOO *").is_err());
+        // 2 月 30 日永远不会到来
+        let schedule = CronSchedule::parse("0 0 30 2 *").unwrap();
+        assert_eq!(schedule.next_after(&at(2024, 1, 1, 0, 0)), None);
+    }
+}
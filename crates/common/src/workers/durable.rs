@@ -0,0 +1,770 @@
+//! Durable Objects
+//!
+//! Worker 导出的类（`export class Counter { fetch(request) {} }`）按名称寻址实例，
+//! 通过 `env` 上的命名空间访问：
+//!
+//! ```javascript
+//! const stub = env.COUNTER.getByName("room-1");   // 或 env.COUNTER.get(env.COUNTER.idFromName("room-1"))
+//! const response = await stub.fetch(request);
+//! ```
+//!
+//! 同一实例的请求依次执行，前一个请求（包括其中的 `await`）结束后才开始下一个；
+//! 构造函数中的 `state.blockConcurrencyWhile()` 完成前不会处理请求。
+//! 每个实例有独立的 `state.storage`，值需要能表示为 JSON，
+//! `storage.transaction()` 中的写入在回调成功后一次性提交，抛出异常或调用 `txn.rollback()` 时丢弃。
+//! 存储保存在 `DurableStore` 中，`WorkerServer` 的所有 Worker 共享同一个，可以是内存或磁盘目录。
+//!
+//! 每个 Worker 最多保留 1000 个实例对象，超出时丢弃最久未使用且没有进行中请求的实例，
+//! 再次访问时重新构造（存储中的数据不受影响）。磁盘存储最多在内存中缓存
+//! `MAX_CACHED_OBJECTS` 个实例的数据；内存存储保存所有实例的数据，没有上限。
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use boa_engine::{
+    js_string, object::ObjectInitializer, Context, JsArgs, JsNativeError, JsObject, JsResult,
+    JsValue, NativeFunction, Source,
+};
+use boa_gc::{Finalize, Trace};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const DURABLE_JS: &str = r#"
+(function() {
+    var native = function() { return globalThis.__raven_durable_native; };
+    // 按最近使用的顺序排列，最多保留 MAX_INSTANCES 个
+    var instances = new Map();
+    var MAX_INSTANCES = 1000;
+
+    // 丢弃最久未使用的空闲实例，直到数量不超过上限
+    function evictInstances() {
+        for (var [key, instance] of instances) {
+            if (instances.size <= MAX_INSTANCES) {
+                break;
+            }
+            if (instance.active === 0) {
+                instances.delete(key);
+            }
+        }
+    }
+
+    function toRequest(input, init) {
+        if (typeof input !== "string") {
+            return input;
+        }
+        init = init || {};
+        var headers = {};
+        var source = init.headers || {};
+        Object.keys(source).forEach(function(name) {
+            headers[name.toLowerCase()] = String(source[name]);
+        });
+        return {
+            url: input,
+            method: init.method || "GET",
+            headers: {
+                _data: headers,
+                get: function(name) {
+                    var value = headers[String(name).toLowerCase()];
+                    return value === undefined ? null : value;
+                }
+            },
+            body: init.body === undefined ? null : init.body
+        };
+    }
+
+    function parse(value) {
+        return value === undefined ? undefined : JSON.parse(value);
+    }
+
+    function encode(value) {
+        var text = JSON.stringify(value);
+        if (text === undefined) {
+            throw new TypeError("Durable Object storage values must be JSON-serializable");
+        }
+        return text;
+    }
+
+    class Transaction {
+        constructor(ns, id) {
+            this._ns = ns;
+            this._id = id;
+            this._writes = new Map();
+            this._deleteAll = false;
+            this._rolledBack = false;
+        }
+
+        _read(key) {
+            key = String(key);
+            if (this._writes.has(key)) {
+                return this._writes.get(key);
+            }
+            return this._deleteAll ? undefined : parse(native().get(this._ns, this._id, key));
+        }
+
+        async get(key) {
+            if (Array.isArray(key)) {
+                var result = new Map();
+                for (var k of key) {
+                    var value = this._read(k);
+                    if (value !== undefined) {
+                        result.set(String(k), value);
+                    }
+                }
+                return result;
+            }
+            return this._read(key);
+        }
+
+        async put(key, value) {
+            if (typeof key === "object" && key !== null) {
+                for (var k of Object.keys(key)) {
+                    encode(key[k]);
+                    this._writes.set(k, key[k]);
+                }
+                return;
+            }
+            encode(value);
+            this._writes.set(String(key), value);
+        }
+
+        async delete(key) {
+            if (Array.isArray(key)) {
+                var count = 0;
+                for (var k of key) {
+                    if (await this.delete(k)) {
+                        count++;
+                    }
+                }
+                return count;
+            }
+            var existed = this._read(key) !== undefined;
+            this._writes.set(String(key), undefined);
+            return existed;
+        }
+
+        async deleteAll() {
+            this._writes = new Map();
+            this._deleteAll = true;
+        }
+
+        async list(options) {
+            var entries = new Map();
+            if (!this._deleteAll) {
+                var stored = JSON.parse(native().list(this._ns, this._id, JSON.stringify(options || {})));
+                for (var entry of stored) {
+                    entries.set(entry[0], JSON.parse(entry[1]));
+                }
+            }
+            // 未提交的写入只在没有分页参数时合并进结果
+            if (!options || options.limit === undefined) {
+                var prefix = options && options.prefix;
+                this._writes.forEach(function(value, key) {
+                    if (prefix && key.indexOf(prefix) !== 0) {
+                        return;
+                    }
+                    if (value === undefined) {
+                        entries.delete(key);
+                    } else {
+                        entries.set(key, value);
+                    }
+                });
+            }
+            return entries;
+        }
+
+        rollback() {
+            this._rolledBack = true;
+        }
+
+        _commit() {
+            if (this._rolledBack || (this._writes.size === 0 && !this._deleteAll)) {
+                return;
+            }
+            var puts = [];
+            var deletes = [];
+            this._writes.forEach(function(value, key) {
+                if (value === undefined) {
+                    deletes.push(key);
+                } else {
+                    puts.push([key, encode(value)]);
+                }
+            });
+            native().commit(this._ns, this._id, JSON.stringify({
+                puts: puts,
+                deletes: deletes,
+                deleteAll: this._deleteAll
+            }));
+        }
+    }
+
+    class DurableObjectStorage {
+        constructor(ns, id) {
+            this._ns = ns;
+            this._id = id;
+        }
+
+        async _write(fn) {
+            var txn = new Transaction(this._ns, this._id);
+            var result = await fn(txn);
+            txn._commit();
+            return result;
+        }
+
+        get(key) {
+            return new Transaction(this._ns, this._id).get(key);
+        }
+
+        list(options) {
+            return new Transaction(this._ns, this._id).list(options);
+        }
+
+        put(key, value) {
+            return this._write(function(txn) { return txn.put(key, value); });
+        }
+
+        delete(key) {
+            return this._write(function(txn) { return txn.delete(key); });
+        }
+
+        deleteAll() {
+            return this._write(function(txn) { return txn.deleteAll(); });
+        }
+
+        transaction(fn) {
+            return this._write(fn);
+        }
+    }
+
+    class DurableObjectId {
+        constructor(name) {
+            this.name = name;
+        }
+
+        toString() {
+            return this.name;
+        }
+
+        equals(other) {
+            return other instanceof DurableObjectId && other.name === this.name;
+        }
+    }
+
+    class DurableObjectState {
+        constructor(ns, id) {
+            this.id = id;
+            this.storage = new DurableObjectStorage(ns, id.name);
+            this._gate = Promise.resolve();
+        }
+
+        // 排在之前的请求之后执行 fn，fn 完成前不处理后续请求
+        blockConcurrencyWhile(fn) {
+            var result = this._gate.then(function() { return fn(); });
+            this._gate = result.then(function() {}, function() {});
+            return result;
+        }
+    }
+
+    class DurableObjectStub {
+        constructor(ns, cls, env, id) {
+            this._ns = ns;
+            this._cls = cls;
+            this._env = env;
+            this.id = id;
+        }
+
+        fetch(input, init) {
+            var key = this._ns + "\n" + this.id.name;
+            var instance = instances.get(key);
+            if (instance) {
+                instances.delete(key);
+            } else {
+                var state = new DurableObjectState(this._ns, this.id);
+                instance = { state: state, object: new this._cls(state, this._env), active: 0 };
+            }
+            instances.set(key, instance);
+            instance.active++;
+            evictInstances();
+            var request = toRequest(input, init);
+            var done = function() { instance.active--; };
+            var result = instance.state.blockConcurrencyWhile(function() {
+                if (typeof instance.object.fetch !== "function") {
+                    throw new TypeError("Durable Object does not implement fetch()");
+                }
+                return instance.object.fetch(request);
+            });
+            result.then(done, done);
+            return result;
+        }
+    }
+
+    globalThis.__raven_durable_namespace = function(ns, cls, env) {
+        return {
+            idFromName: function(name) {
+                return new DurableObjectId(String(name));
+            },
+            idFromString: function(id) {
+                return new DurableObjectId(String(id));
+            },
+            newUniqueId: function() {
+                return new DurableObjectId(native().uniqueId());
+            },
+            get: function(id) {
+                if (!(id instanceof DurableObjectId)) {
+                    throw new TypeError("get() expects a DurableObjectId");
+                }
+                return new DurableObjectStub(ns, cls, env, id);
+            },
+            getByName: function(name) {
+                return this.get(this.idFromName(name));
+            }
+        };
+    };
+})();
+"#;
+
+/// 一个实例的存储：键 -> JSON 文本
+type Storage = BTreeMap<String, String>;
+
+/// 磁盘存储在内存中缓存的实例数，超出时丢弃最久未使用的实例，下次访问时重新读取
+pub const MAX_CACHED_OBJECTS: usize = 1024;
+
+/// 已加载的实例：(命名空间, 实例 ID) -> (存储, 最近一次访问的序号)
+type Objects = HashMap<(String, String), (Storage, u64)>;
+
+/// `storage.list()` 的选项
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ListOptions {
+    pub prefix: Option<String>,
+    /// 包含该键
+    pub start: Option<String>,
+    /// 不包含该键
+    pub end: Option<String>,
+    pub limit: Option<usize>,
+    pub reverse: bool,
+}
+
+/// 一次提交的写入
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Commit {
+    pub puts: Vec<(String, String)>,
+    pub deletes: Vec<String>,
+    /// 先清空实例的所有数据
+    pub delete_all: bool,
+}
+
+/// Durable Object 实例的存储，`directory` 为 `None` 时保存在内存中
+///
+/// 磁盘存储中每个实例对应一个 JSON 文件，提交时整体写入临时文件、落盘再重命名，
+/// 保证一次提交要么全部生效要么都不生效。
+#[derive(Clone)]
+pub struct DurableStore {
+    directory: Option<PathBuf>,
+    objects: Arc<Mutex<Objects>>,
+    /// 访问计数，用于找出最久未使用的实例
+    clock: Arc<AtomicU64>,
+    /// 磁盘存储最多缓存的实例数
+    max_cached: usize,
+}
+
+impl Default for DurableStore {
+    fn default() -> Self {
+        Self::memory()
+    }
+}
+
+impl DurableStore {
+    pub fn new(directory: Option<&str>) -> Self {
+        Self {
+            directory: directory.map(PathBuf::from),
+            objects: Arc::default(),
+            clock: Arc::default(),
+            max_cached: MAX_CACHED_OBJECTS,
+        }
+    }
+
+    pub fn memory() -> Self {
+        Self::new(None)
+    }
+
+    pub fn get(&self, ns: &str, id: &str, key: &str) -> Result<Option<String>, String> {
+        self.with_storage(ns, id, |storage| Ok(storage.get(key).cloned()))
+    }
+
+    pub fn list(&self, ns: &str, id: &str, options: &ListOptions) -> Result<Vec<(String, String)>, String> {
+        self.with_storage(ns, id, |storage| {
+            let entries = storage.iter().filter(|(key, _)| {
+                options.prefix.as_ref().is_none_or(|p| key.starts_with(p.as_str()))
+                    && options.start.as_ref().is_none_or(|s| *key >= s)
+                    && options.end.as_ref().is_none_or(|e| *key < e)
+            });
+            let limit = options.limit.unwrap_or(usize::MAX);
+            let entries: Vec<(String, String)> = if options.reverse {
+                entries.rev().take(limit).map(|(k, v)| (k.clone(), v.clone())).collect()
+            } else {
+                entries.take(limit).map(|(k, v)| (k.clone(), v.clone())).collect()
+            };
+            Ok(entries)
+        })
+    }
+
+    /// 原子地应用一次提交
+    pub fn commit(&self, ns: &str, id: &str, commit: Commit) -> Result<(), String> {
+        self.with_storage(ns, id, |storage| {
+            let mut updated = if commit.delete_all { Storage::new() } else { storage.clone() };
+            for key in commit.deletes {
+                updated.remove(&key);
+            }
+            updated.extend(commit.puts);
+            self.persist(ns, id, &updated)?;
+            *storage = updated;
+            Ok(())
+        })
+    }
+
+    fn with_storage<T>(
+        &self,
+        ns: &str,
+        id: &str,
+        f: impl FnOnce(&mut Storage) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut objects = self.objects.lock().map_err(|e| e.to_string())?;
+        let key = (ns.to_string(), id.to_string());
+        if !objects.contains_key(&key) {
+            let storage = self.load(ns, id)?;
+            // 磁盘上的数据可以重新读取，内存存储不能丢弃
+            if self.directory.is_some() && objects.len() >= self.max_cached {
+                let oldest = objects
+                    .iter()
+                    .min_by_key(|(_, (_, used))| *used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    objects.remove(&oldest);
+                }
+            }
+            objects.insert(key.clone(), (storage, 0));
+        }
+        let (storage, used) = objects.get_mut(&key).expect("inserted above");
+        *used = self.clock.fetch_add(1, Ordering::Relaxed);
+        f(storage)
+    }
+
+    fn path(&self, ns: &str, id: &str) -> Option<PathBuf> {
+        let directory = self.directory.as_ref()?;
+        let mut hasher = Sha256::new();
+        hasher.update(id.as_bytes());
+        let mut path = directory.clone();
+        for part in ns.split('/') {
+            path.push(sanitize(part));
+        }
+        path.push(format!("{:x}.json", hasher.finalize()));
+        Some(path)
+    }
+
+    fn load(&self, ns: &str, id: &str) -> Result<Storage, String> {
+        let Some(path) = self.path(ns, id) else {
+            return Ok(Storage::new());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| format!("Corrupt Durable Object storage {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Storage::new()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    fn persist(&self, ns: &str, id: &str, storage: &Storage) -> Result<(), String> {
+        let Some(path) = self.path(ns, id) else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let text = serde_json::to_string(storage).map_err(|e| e.to_string())?;
+        // 先写临时文件并落盘再重命名，避免提交写到一半时进程退出或断电
+        let temp = path.with_extension("tmp");
+        let mut file =
+            File::create(&temp).map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+        file.write_all(text.as_bytes())
+            .and_then(|()| file.sync_all())
+            .map_err(|e| format!("Failed to write {}: {}", temp.display(), e))?;
+        fs::rename(&temp, &path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        sync_dir(&path);
+        Ok(())
+    }
+}
+
+/// 落盘 `path` 所在的目录，使重命名在断电后仍然有效
+fn sync_dir(path: &Path) {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        if let Ok(dir) = File::open(dir) {
+            dir.sync_all().ok();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// 把命名空间的一段转换为安全的目录名
+///
+/// 字母、数字、`-` 和 `_` 保持不变，其他字节编码为 `%XX`，不同的名称不会对应到同一个目录
+fn sanitize(part: &str) -> String {
+    let mut name = String::with_capacity(part.len());
+    for byte in part.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+/// 原生函数捕获的存储句柄
+#[derive(Clone, Trace, Finalize)]
+struct StoreHandle {
+    #[unsafe_ignore_trace]
+    store: DurableStore,
+}
+
+/// 注册 Durable Object 的 JS 实现
+pub fn register_globals(context: &mut Context, store: DurableStore) -> Result<(), String> {
+    set_backend(context, store)?;
+    context
+        .eval(Source::from_bytes(DURABLE_JS))
+        .map_err(|e| format!("Failed to install Durable Objects: {}", e))?;
+    Ok(())
+}
+
+/// 切换实例存储（如改为 `WorkerServer` 共享的存储）
+pub fn set_backend(context: &mut Context, store: DurableStore) -> Result<(), String> {
+    let handle = StoreHandle { store };
+
+    let get_fn = NativeFunction::from_copy_closure_with_captures(
+        |_, args, handle: &StoreHandle, context| {
+            let (ns, id) = instance_args(args, context)?;
+            let key = args.get_or_undefined(2).to_string(context)?.to_std_string_escaped();
+            match handle.store.get(&ns, &id, &key).map_err(js_error)? {
+                Some(value) => Ok(JsValue::from(js_string!(value))),
+                None => Ok(JsValue::undefined()),
+            }
+        },
+        handle.clone(),
+    );
+
+    let list_fn = NativeFunction::from_copy_closure_with_captures(
+        |_, args, handle: &StoreHandle, context| {
+            let (ns, id) = instance_args(args, context)?;
+            let options = args.get_or_undefined(2).to_string(context)?.to_std_string_escaped();
+            let options: ListOptions = serde_json::from_str(&options)
+                .map_err(|e| JsNativeError::typ().with_message(format!("Invalid list options: {}", e)))?;
+            let entries = handle.store.list(&ns, &id, &options).map_err(js_error)?;
+            let json = serde_json::to_string(&entries).map_err(|e| js_error(e.to_string()))?;
+            Ok(JsValue::from(js_string!(json)))
+        },
+        handle.clone(),
+    );
+
+    let commit_fn = NativeFunction::from_copy_closure_with_captures(
+        |_, args, handle: &StoreHandle, context| {
+            let (ns, id) = instance_args(args, context)?;
+            let commit = args.get_or_undefined(2).to_string(context)?.to_std_string_escaped();
+            let commit: Commit =
+                serde_json::from_str(&commit).map_err(|e| js_error(e.to_string()))?;
+            handle.store.commit(&ns, &id, commit).map_err(js_error)?;
+            Ok(JsValue::undefined())
+        },
+        handle,
+    );
+
+    let unique_id_fn = NativeFunction::from_fn_ptr(|_, _, _| {
+        let id = format!("{:016x}{:016x}", rand::random::<u64>(), rand::random::<u64>());
+        Ok(JsValue::from(js_string!(id)))
+    });
+
+    let natives = ObjectInitializer::new(context)
+        .function(get_fn, js_string!("get"), 3)
+        .function(list_fn, js_string!("list"), 3)
+        .function(commit_fn, js_string!("commit"), 3)
+        .function(unique_id_fn, js_string!("uniqueId"), 0)
+        .build();
+    context
+        .global_object()
+        .set(js_string!("__raven_durable_native"), natives, false, context)
+        .map_err(|e| format!("Failed to install Durable Objects: {}", e))?;
+    Ok(())
+}
+
+/// 创建 `env` 上的命名空间对象，`ns` 用于区分不同 Worker 和类的实例
+pub fn create_namespace(ns: &str, class: &JsObject, env: &JsObject, context: &mut Context) -> JsResult<JsValue> {
+    let function = context
+        .global_object()
+        .get(js_string!("__raven_durable_namespace"), context)?;
+    let function = function
+        .as_callable()
+        .ok_or_else(|| JsNativeError::typ().with_message("Durable Objects are not installed"))?
+        .clone();
+    function.call(
+        &JsValue::undefined(),
+        &[JsValue::from(js_string!(ns)), class.clone().into(), env.clone().into()],
+        context,
+    )
+}
+
+fn instance_args(args: &[JsValue], context: &mut Context) -> JsResult<(String, String)> {
+    Ok((
+        args.get_or_undefined(0).to_string(context)?.to_std_string_escaped(),
+        args.get_or_undefined(1).to_string(context)?.to_std_string_escaped(),
+    ))
+}
+
+fn js_error(message: String) -> boa_engine::JsError {
+    JsNativeError::error().with_message(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_durable_store_commit_and_list() {
+        let store = DurableStore::memory();
+        store
+            .commit("w/Counter", "a", Commit {
+                puts: vec![put("k1", "1"), put("k2", "2"), put("x", "3")],
+                ..Default::default()
+            })
+            .unwrap();
+        // 其他实例互不影响
+        assert_eq!(store.get("w/Counter", "b", "k1").unwrap(), None);
+        assert_eq!(store.get("w/Counter", "a", "k1").unwrap().as_deref(), Some("1"));
+
+        let options = ListOptions {
+            prefix: Some("k".to_string()),
+            reverse: true,
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(store.list("w/Counter", "a", &options).unwrap(), vec![put("k2", "2")]);
+
+        store
+            .commit("w/Counter", "a", Commit {
+                puts: vec![put("k3", "4")],
+                deletes: vec!["k1".to_string()],
+                delete_all: false,
+            })
+            .unwrap();
+        let keys: Vec<String> = store
+            .list("w/Counter", "a", &ListOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec!["k2", "k3", "x"]);
+    }
+
+    #[test]
+    fn test_durable_store_on_disk() {
+        let dir = std::env::temp_dir().join(format!("raven-durable-{}", rand::random::<u64>()));
+        let dir_str = dir.to_string_lossy().into_owned();
+
+        let store = DurableStore::new(Some(&dir_str));
+        store
+            .commit("api/Room", "lobby", Commit {
+                puts: vec![put("count", "7")],
+                ..Default::default()
+            })
+            .unwrap();
+
+        let reopened = DurableStore::new(Some(&dir_str));
+        assert_eq!(reopened.get("api/Room", "lobby", "count").unwrap().as_deref(), Some("7"));
+
+        reopened
+            .commit("api/Room", "lobby", Commit {
+                delete_all: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(DurableStore::new(Some(&dir_str)).get("api/Room", "lobby", "count").unwrap(), None);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_namespace_directories_do_not_collide() {
+        assert_eq!(sanitize("api_v2-Room"), "api_v2-Room");
+        assert_eq!(sanitize("a.b"), "a%2Eb");
+        assert_eq!(sanitize(".."), "%2E%2E");
+        assert_ne!(sanitize("a.b"), sanitize("a_b"));
+        assert_ne!(sanitize("a%2Eb"), sanitize("a.b"));
+
+        let dir = std::env::temp_dir().join(format!("raven-durable-{}", rand::random::<u64>()));
+        let dir_str = dir.to_string_lossy().into_owned();
+        let store = DurableStore::new(Some(&dir_str));
+        for ns in ["w/a.b", "w/a_b"] {
+            store
+                .commit(ns, "x", Commit {
+                    puts: vec![put("ns", ns)],
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        let reopened = DurableStore::new(Some(&dir_str));
+        assert_eq!(reopened.get("w/a.b", "x", "ns").unwrap().as_deref(), Some("w/a.b"));
+        assert_eq!(reopened.get("w/a_b", "x", "ns").unwrap().as_deref(), Some("w/a_b"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_disk_store_evicts_cached_objects() {
+        let dir = std::env::temp_dir().join(format!("raven-durable-{}", rand::random::<u64>()));
+        let store = DurableStore {
+            max_cached: 2,
+            ..DurableStore::new(Some(&dir.to_string_lossy()))
+        };
+        for id in ["a", "b", "c"] {
+            store
+                .commit("w/Room", id, Commit {
+                    puts: vec![put("id", id)],
+                    ..Default::default()
+                })
+                .unwrap();
+            assert!(store.objects.lock().unwrap().len() <= 2);
+        }
+        // 被丢弃的实例从磁盘重新读取
+        assert_eq!(store.get("w/Room", "a", "id").unwrap().as_deref(), Some("a"));
+        let objects = store.objects.lock().unwrap();
+        assert!(objects.contains_key(&("w/Room".to_string(), "a".to_string())));
+        assert!(!objects.contains_key(&("w/Room".to_string(), "b".to_string())));
+        drop(objects);
+
+        // 内存存储不丢弃数据
+        let memory = DurableStore {
+            max_cached: 2,
+            ..DurableStore::memory()
+        };
+        for id in ["a", "b", "c"] {
+            memory
+                .commit("w/Room", id, Commit {
+                    puts: vec![put("id", id)],
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+        assert_eq!(memory.get("w/Room", "a", "id").unwrap().as_deref(), Some("a"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
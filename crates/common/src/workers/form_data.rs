@@ -0,0 +1,253 @@
+//! FormData、Blob 和 File
+//!
+//! 提供 Web 标准的 `FormData`、`Blob` 和 `File` 全局对象，
+//! `request.formData()` 把 `HttpRequest::form_data` 解析出的字段转换为 `FormData`。
+
+use boa_engine::{
+    js_string,
+    object::builtins::{AlignedVec, JsArray, JsArrayBuffer},
+    Context, JsArgs, JsNativeError, JsResult, JsValue, NativeFunction, Source,
+};
+
+use super::http::FormPart;
+use super::workers_runtime::js_to_bytes;
+
+const FORM_DATA_JS: &str = r#"
+(function() {
+    class Blob {
+        constructor(parts, options) {
+            this._buffer = __raven_blob_bytes(parts || []);
+            this.size = this._buffer.byteLength;
+            this.type = options && options.type ? String(options.type).toLowerCase() : "";
+        }
+
+        arrayBuffer() {
+            return Promise.resolve(this._buffer.slice(0));
+        }
+
+        bytes() {
+            return Promise.resolve(new Uint8Array(this._buffer.slice(0)));
+        }
+
+        text() {
+            return Promise.resolve(__raven_utf8_decode(this._buffer));
+        }
+
+        slice(start, end, type) {
+            return new Blob([this._buffer.slice(start, end)], { type: type || "" });
+        }
+    }
+
+    class File extends Blob {
+        constructor(parts, name, options) {
+            super(parts, options);
+            this.name = String(name);
+            this.lastModified = options && options.lastModified !== undefined
+                ? Number(options.lastModified)
+                : Date.now();
+        }
+    }
+
+    function toEntry(name, value, filename) {
+        if (value instanceof Blob) {
+            if (!(value instanceof File) || filename !== undefined) {
+                var fileName = filename !== undefined
+                    ? String(filename)
+                    : (value instanceof File ? value.name : "blob");
+                value = new File([value], fileName, { type: value.type });
+            }
+        } else {
+            value = String(value);
+        }
+        return [String(name), value];
+    }
+
+    class FormData {
+        constructor() {
+            this._entries = [];
+        }
+
+        append(name, value, filename) {
+            this._entries.push(toEntry(name, value, filename));
+        }
+
+        set(name, value, filename) {
+            var entry = toEntry(name, value, filename);
+            var replaced = false;
+            var entries = [];
+            for (var i = 0; i < this._entries.length; i++) {
+                if (this._entries[i][0] !== entry[0]) {
+                    entries.push(this._entries[i]);
+                } else if (!replaced) {
+                    entries.push(entry);
+                    replaced = true;
+                }
+            }
+            if (!replaced) {
+                entries.push(entry);
+            }
+            this._entries = entries;
+        }
+
+        get(name) {
+            name = String(name);
+            for (var i = 0; i < this._entries.length; i++) {
+                if (this._entries[i][0] === name) {
+                    return this._entries[i][1];
+                }
+            }
+            return null;
+        }
+
+        getAll(name) {
+            name = String(name);
+            return this._entries
+                .filter(function(entry) { return entry[0] === name; })
+                .map(function(entry) { return entry[1]; });
+        }
+
+        has(name) {
+            return this.get(name) !== null;
+        }
+
+        delete(name) {
+            name = String(name);
+            this._entries = this._entries.filter(function(entry) { return entry[0] !== name; });
+        }
+
+        entries() {
+            return this._entries.map(function(entry) { return [entry[0], entry[1]]; })[Symbol.iterator]();
+        }
+
+        keys() {
+            return this._entries.map(function(entry) { return entry[0]; })[Symbol.iterator]();
+        }
+
+        values() {
+            return this._entries.map(function(entry) { return entry[1]; })[Symbol.iterator]();
+        }
+
+        forEach(callback, thisArg) {
+            var entries = this._entries.slice();
+            for (var i = 0; i < entries.length; i++) {
+                callback.call(thisArg, entries[i][1], entries[i][0], this);
+            }
+        }
+
+        [Symbol.iterator]() {
+            return this.entries();
+        }
+    }
+
+    globalThis.Blob = Blob;
+    globalThis.File = File;
+    globalThis.FormData = FormData;
+
+    globalThis.__raven_form_data = function(entries) {
+        var form = new FormData();
+        for (var i = 0; i < entries.length; i++) {
+            var entry = entries[i];
+            if (entry[2] === undefined) {
+                form.append(entry[0], entry[1]);
+            } else {
+                form.append(entry[0], new File([entry[1]], entry[2], { type: entry[3] }));
+            }
+        }
+        return form;
+    };
+})();
+"#;
+
+/// 注册 `FormData`、`Blob` 和 `File` 全局对象
+pub fn register_globals(context: &mut Context) -> Result<(), String> {
+    let natives: [(&str, usize, NativeFunction); 2] = [
+        (
+            "__raven_blob_bytes",
+            1,
+            NativeFunction::from_fn_ptr(|_, args, context| {
+                let parts = args
+                    .get_or_undefined(0)
+                    .as_object()
+                    .and_then(|obj| JsArray::from_object(obj.clone()).ok())
+                    .ok_or_else(|| JsNativeError::typ().with_message("Blob parts must be an array"))?;
+
+                let mut bytes = Vec::new();
+                for i in 0..parts.length(context)? {
+                    let part = parts.get(i, context)?;
+                    // Blob / File 取其内部的 ArrayBuffer
+                    let part = match part.as_object() {
+                        Some(obj) if obj.has_property(js_string!("_buffer"), context)? => {
+                            obj.get(js_string!("_buffer"), context)?
+                        }
+                        _ => part,
+                    };
+                    match js_to_bytes(&part, context)? {
+                        Some(data) => bytes.extend_from_slice(&data),
+                        None => bytes.extend_from_slice(
+                            part.to_string(context)?.to_std_string_escaped().as_bytes(),
+                        ),
+                    }
+                }
+                Ok(array_buffer(&bytes, context)?.into())
+            }),
+        ),
+        (
+            "__raven_utf8_decode",
+            1,
+            NativeFunction::from_fn_ptr(|_, args, context| {
+                let bytes = js_to_bytes(args.get_or_undefined(0), context)?.unwrap_or_default();
+                Ok(JsValue::from(js_string!(String::from_utf8_lossy(&bytes).as_ref())))
+            }),
+        ),
+    ];
+
+    for (name, length, function) in natives {
+        context
+            .register_global_builtin_callable(js_string!(name), length, function)
+            .map_err(|e| format!("Failed to register {}: {}", name, e))?;
+    }
+
+    context
+        .eval(Source::from_bytes(FORM_DATA_JS))
+        .map_err(|e| format!("Failed to install FormData: {}", e))?;
+    Ok(())
+}
+
+/// 把解析出的表单字段转换为 JS `FormData`，文件字段转换为 `File`
+pub fn to_js(parts: &[FormPart], context: &mut Context) -> JsResult<JsValue> {
+    let entries = JsArray::new(context);
+    for part in parts {
+        let entry = match &part.filename {
+            Some(filename) => JsArray::from_iter(
+                [
+                    JsValue::from(js_string!(part.name.as_str())),
+                    array_buffer(&part.data, context)?.into(),
+                    JsValue::from(js_string!(filename.as_str())),
+                    JsValue::from(js_string!(part.content_type.as_deref().unwrap_or_default())),
+                ],
+                context,
+            ),
+            None => JsArray::from_iter(
+                [
+                    JsValue::from(js_string!(part.name.as_str())),
+                    JsValue::from(js_string!(part.text().as_str())),
+                ],
+                context,
+            ),
+        };
+        entries.push(entry, context)?;
+    }
+
+    let form_data_fn = context
+        .global_object()
+        .get(js_string!("__raven_form_data"), context)?;
+    let form_data_fn = form_data_fn
+        .as_callable()
+        .ok_or_else(|| JsNativeError::typ().with_message("FormData is not installed"))?
+        .clone();
+    form_data_fn.call(&JsValue::undefined(), &[entries.into()], context)
+}
+
+fn array_buffer(bytes: &[u8], context: &mut Context) -> JsResult<JsArrayBuffer> {
+    JsArrayBuffer::from_byte_block(AlignedVec::from_slice(0, bytes), context)
+}
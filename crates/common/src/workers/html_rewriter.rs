@@ -0,0 +1,1807 @@
+//! HTMLRewriter
+//!
+//! Cloudflare 兼容的 `HTMLRewriter`，按 CSS 选择器匹配元素并改写响应中的 HTML：
+//!
+//! ```javascript
+//! return new HTMLRewriter()
+//!     .on("a[href^='http://']", { element(el) { el.setAttribute("href", el.getAttribute("href").replace("http:", "https:")); } })
+//!     .on("script", { element(el) { el.remove(); } })
+//!     .on("h1", { text(chunk) { chunk.replace(chunk.text.toUpperCase()); } })
+//!     .onDocument({ end(end) { end.append("<footer>internal</footer>", { html: true }); } })
+//!     .transform(response);
+//! ```
+//!
+//! 选择器支持 `*`、标签名、`#id`、`.class`、属性（`[a]`、`=`、`~=`、`|=`、`^=`、`$=`、`*=`，可加 ` i`）、
+//! `:first-child`、`:not()`，以及后代和子元素组合器，多个选择器用逗号分隔。
+//! `HtmlRewriter` 按块接收 HTML，只缓存尚未结束的标签或注释，每块处理完即可输出改写后的部分。
+//! 处理函数需要是同步的；文本和属性值保持 HTML 原文，不解码实体。
+//!
+//! `transform()` 返回的 Response 的 body 是一个流：服务器写响应时每次取一块输入交给
+//! `HtmlRewriter`，改写出的部分立即按 chunked 编码发给客户端，整个输出不会在内存中累积，
+//! 处理函数也是在这时才被调用。输入是 Worker 传入的 Response 的 body，它本身已经在内存中；
+//! 对另一个 `transform()` 的结果再次 `transform()` 时逐块读取上游的输出。
+//! 需要完整 body 的地方（`cache.put()`、Service 绑定的调用方）会一次读完整个流。
+//! body 必须是 UTF-8，其他编码的页面会报错而不是被改写坏。
+
+use std::cell::RefCell;
+
+use boa_engine::{
+    js_string, object::ObjectInitializer, Context, Finalize, JsArgs, JsData, JsNativeError, JsObject, JsResult, JsValue,
+    NativeFunction, Source, Trace,
+};
+use serde::Deserialize;
+
+use crate::runtime::js_to_bytes;
+use serde_json::{json, Value};
+
+/// `transform()` 每次交给 `HtmlRewriter` 的字节数
+const CHUNK_SIZE: usize = 16 * 1024;
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// 内容不解析标签的元素
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "title"];
+
+/// 开始标签会隐式结束同组的当前元素，如 `<li>a<li>b`
+const IMPLIED_END_GROUPS: &[&[&str]] = &[&["li"], &["p"], &["dt", "dd"], &["td", "th"], &["tr"], &["option"]];
+
+const HTML_REWRITER_JS: &str = r#"
+(function() {
+    var native = function() { return globalThis.__raven_html_native; };
+
+    function content(value, options) {
+        return [String(value), !!(options && options.html)];
+    }
+
+    function mutations() {
+        return {
+            before: [], after: [], prepend: [], append: [],
+            replace: null, inner: null, removed: false, keepContent: false,
+            tagName: null, attributes: null, text: null, endTag: false
+        };
+    }
+
+    // before/append 按调用顺序插入，after/prepend 每次插到最前面
+    class Mutable {
+        constructor(m) {
+            this._m = m;
+        }
+
+        get removed() {
+            return this._m.removed;
+        }
+
+        before(value, options) {
+            this._m.before.push(content(value, options));
+            return this;
+        }
+
+        after(value, options) {
+            this._m.after.unshift(content(value, options));
+            return this;
+        }
+
+        replace(value, options) {
+            this._m.replace = content(value, options);
+            this._m.removed = true;
+            return this;
+        }
+
+        remove() {
+            this._m.replace = null;
+            this._m.removed = true;
+            return this;
+        }
+    }
+
+    class Element extends Mutable {
+        constructor(data, m, endTags) {
+            super(m);
+            this._id = data.id;
+            this._tagName = data.tagName;
+            this._attributes = data.attributes;
+            this._endTags = endTags;
+            this.selfClosing = data.selfClosing;
+            this.canHaveContent = data.canHaveContent;
+            this.namespaceURI = "http://www.w3.org/1999/xhtml";
+        }
+
+        get tagName() {
+            return this._tagName;
+        }
+
+        set tagName(name) {
+            this._tagName = String(name);
+            this._m.tagName = this._tagName;
+        }
+
+        get attributes() {
+            return this._attributes.map(function(a) {
+                return [a[0].toLowerCase(), a[1]];
+            })[Symbol.iterator]();
+        }
+
+        _find(name) {
+            name = String(name).toLowerCase();
+            return this._attributes.findIndex(function(a) {
+                return a[0].toLowerCase() === name;
+            });
+        }
+
+        getAttribute(name) {
+            var index = this._find(name);
+            return index < 0 ? null : this._attributes[index][1];
+        }
+
+        hasAttribute(name) {
+            return this._find(name) >= 0;
+        }
+
+        setAttribute(name, value) {
+            var index = this._find(name);
+            if (index < 0) {
+                this._attributes.push([String(name), String(value)]);
+            } else {
+                this._attributes[index][1] = String(value);
+            }
+            this._m.attributes = this._attributes;
+            return this;
+        }
+
+        removeAttribute(name) {
+            var index = this._find(name);
+            if (index >= 0) {
+                this._attributes.splice(index, 1);
+                this._m.attributes = this._attributes;
+            }
+            return this;
+        }
+
+        prepend(value, options) {
+            this._m.prepend.unshift(content(value, options));
+            return this;
+        }
+
+        append(value, options) {
+            this._m.append.push(content(value, options));
+            return this;
+        }
+
+        setInnerContent(value, options) {
+            this._m.inner = content(value, options);
+            return this;
+        }
+
+        removeAndKeepContent() {
+            this._m.keepContent = true;
+            return this;
+        }
+
+        onEndTag(handler) {
+            if (!this.canHaveContent) {
+                throw new TypeError("<" + this._tagName + "> has no end tag");
+            }
+            var handlers = this._endTags.get(this._id) || [];
+            handlers.push(handler);
+            this._endTags.set(this._id, handlers);
+            this._m.endTag = true;
+        }
+    }
+
+    class EndTag extends Mutable {
+        constructor(data, m) {
+            super(m);
+            this._name = data.name;
+        }
+
+        get name() {
+            return this._name;
+        }
+
+        set name(value) {
+            this._name = String(value);
+            this._m.tagName = this._name;
+        }
+    }
+
+    class TextChunk extends Mutable {
+        constructor(data, m) {
+            super(m);
+            this.text = data.text;
+            this.lastInTextNode = data.lastInTextNode;
+        }
+    }
+
+    class Comment extends Mutable {
+        constructor(data, m) {
+            super(m);
+            this._text = data.text;
+        }
+
+        get text() {
+            return this._text;
+        }
+
+        set text(value) {
+            this._text = String(value);
+            this._m.text = this._text;
+        }
+    }
+
+    class Doctype {
+        constructor(data) {
+            this.name = data.name;
+            this.publicId = data.publicId;
+            this.systemId = data.systemId;
+        }
+    }
+
+    class DocumentEnd {
+        constructor(m) {
+            this._m = m;
+        }
+
+        append(value, options) {
+            this._m.append.push(content(value, options));
+            return this;
+        }
+    }
+
+    // 原生代码对每个匹配的节点调用一次，返回处理函数对节点的修改
+    function dispatcher(specs) {
+        var endTags = new Map();
+        return function(kind, json) {
+            var data = JSON.parse(json);
+            var m = mutations();
+            var node;
+            var calls;
+            if (kind === "endTag") {
+                node = new EndTag(data, m);
+                calls = endTags.get(data.id) || [];
+                endTags.delete(data.id);
+            } else {
+                node = kind === "element" ? new Element(data, m, endTags)
+                    : kind === "text" ? new TextChunk(data, m)
+                    : kind === "comments" ? new Comment(data, m)
+                    : kind === "doctype" ? new Doctype(data)
+                    : new DocumentEnd(m);
+                calls = data.handlers.map(function(index) {
+                    var handlers = specs[index].handlers;
+                    return function(node) { return handlers[kind].call(handlers, node); };
+                });
+            }
+            calls.forEach(function(call) {
+                var result = call(node);
+                if (result && typeof result.then === "function") {
+                    throw new TypeError("HTMLRewriter handlers must be synchronous");
+                }
+            });
+            return JSON.stringify(m);
+        };
+    }
+
+    class HTMLRewriter {
+        constructor() {
+            this._specs = [];
+        }
+
+        on(selector, handlers) {
+            selector = String(selector);
+            native().validate(selector);
+            this._specs.push({ selector: selector, handlers: handlers || {} });
+            return this;
+        }
+
+        onDocument(handlers) {
+            this._specs.push({ selector: null, handlers: handlers || {} });
+            return this;
+        }
+
+        transform(response) {
+            if (!(response instanceof Response)) {
+                throw new TypeError("HTMLRewriter.transform() expects a Response");
+            }
+            var specs = this._specs;
+            var flags = specs.map(function(spec) {
+                var h = spec.handlers;
+                return {
+                    selector: spec.selector,
+                    element: typeof h.element === "function",
+                    text: typeof h.text === "function",
+                    comments: typeof h.comments === "function",
+                    doctype: typeof h.doctype === "function",
+                    end: typeof h.end === "function"
+                };
+            });
+            var body = native().stream(response.body == null ? "" : response.body, JSON.stringify(flags), dispatcher(specs));
+            var headers = {};
+            Object.keys(response.headers || {}).forEach(function(name) {
+                if (name !== "content-length") {
+                    headers[name] = response.headers[name];
+                }
+            });
+            return new Response(body, { status: response.status, headers: headers });
+        }
+    }
+
+    globalThis.HTMLRewriter = HTMLRewriter;
+})();
+"#;
+
+/// 一组处理函数：`selector` 为 `None` 时是文档级处理函数（`onDocument`）
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HandlerSpec {
+    pub selector: Option<String>,
+    pub element: bool,
+    pub text: bool,
+    pub comments: bool,
+    pub doctype: bool,
+    pub end: bool,
+}
+
+/// 插入的内容：(内容, 是否为 HTML)，不是 HTML 时输出前转义
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Content(pub String, pub bool);
+
+impl Content {
+    fn write_to(&self, output: &mut String) {
+        if self.1 {
+            output.push_str(&self.0);
+        } else {
+            for c in self.0.chars() {
+                match c {
+                    '&' => output.push_str("&amp;"),
+                    '<' => output.push_str("&lt;"),
+                    '>' => output.push_str("&gt;"),
+                    c => output.push(c),
+                }
+            }
+        }
+    }
+}
+
+/// 处理函数对一个节点的修改
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Mutations {
+    pub before: Vec<Content>,
+    pub after: Vec<Content>,
+    pub prepend: Vec<Content>,
+    pub append: Vec<Content>,
+    /// 替换整个节点，此时 `removed` 也为 `true`
+    pub replace: Option<Content>,
+    /// 替换元素的内容
+    pub inner: Option<Content>,
+    pub removed: bool,
+    /// 只移除开始和结束标签
+    pub keep_content: bool,
+    pub tag_name: Option<String>,
+    pub attributes: Option<Vec<(String, String)>>,
+    /// 注释的新内容
+    pub text: Option<String>,
+    /// 元素登记了 `onEndTag` 处理函数
+    pub end_tag: bool,
+}
+
+/// 流式 HTML 改写器
+///
+/// 处理函数以 `handle(kind, event)` 的形式调用，`kind` 为 `element`、`endTag`、`text`、
+/// `comments`、`doctype` 或 `end`，`event.handlers` 为匹配的 `HandlerSpec` 序号。
+pub struct HtmlRewriter {
+    specs: Vec<(HandlerSpec, Option<Selector>)>,
+    /// 尚未处理的输入（未结束的标签、注释等）
+    buffer: String,
+    output: String,
+    stack: Vec<Frame>,
+    /// 根节点下已出现的元素数
+    root_children: usize,
+    /// 当前文本节点已有块交给处理函数
+    text_started: bool,
+    /// 当前所在的原始文本元素（`script`、`style` 等）
+    raw_text: Option<String>,
+    next_id: u64,
+}
+
+/// 一个打开的元素
+struct Frame {
+    element: ElementInfo,
+    /// 选择器匹配该元素的 `HandlerSpec` 序号
+    matched: Vec<usize>,
+    children: usize,
+    /// 输出的标签名（处理函数可以修改）
+    tag_name: String,
+    removed: bool,
+    /// 内容已被 `setInnerContent` 替换
+    inner: bool,
+    skip_end_tag: bool,
+    append: Vec<Content>,
+    after: Vec<Content>,
+    /// 登记了 `onEndTag` 时的元素 ID
+    end_tag_id: Option<u64>,
+}
+
+impl Frame {
+    fn skips_content(&self) -> bool {
+        self.removed || self.inner
+    }
+}
+
+impl HtmlRewriter {
+    pub fn new(specs: Vec<HandlerSpec>) -> Result<Self, String> {
+        let specs = specs
+            .into_iter()
+            .map(|spec| {
+                let selector = spec.selector.as_deref().map(Selector::parse).transpose()?;
+                Ok((spec, selector))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            specs,
+            buffer: String::new(),
+            output: String::new(),
+            stack: Vec::new(),
+            root_children: 0,
+            text_started: false,
+            raw_text: None,
+            next_id: 0,
+        })
+    }
+
+    /// 处理一块输入，返回到目前为止可以输出的内容
+    pub fn write<E>(
+        &mut self,
+        chunk: &str,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<String, E> {
+        self.buffer.push_str(chunk);
+        self.process(false, handle)?;
+        Ok(std::mem::take(&mut self.output))
+    }
+
+    /// 结束输入，返回剩余的输出
+    pub fn end<E>(mut self, handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>) -> Result<String, E> {
+        self.process(true, handle)?;
+        self.end_text("", handle)?;
+        while let Some(frame) = self.stack.pop() {
+            self.close(frame, None, handle)?;
+        }
+        let handlers = self.document_handlers(|spec| spec.end);
+        if !handlers.is_empty() {
+            let m = handle("end", &json!({ "handlers": handlers }))?;
+            self.emit(&m.append);
+        }
+        Ok(self.output)
+    }
+
+    fn process<E>(
+        &mut self,
+        at_end: bool,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        loop {
+            if let Some(name) = self.raw_text.clone() {
+                match find_raw_text_end(&self.buffer, &name) {
+                    Some(pos) => {
+                        let text: String = self.buffer.drain(..pos).collect();
+                        self.raw_text = None;
+                        self.end_text(&text, handle)?;
+                    }
+                    None => {
+                        // 保留可能是结束标签开头的部分
+                        let keep = if at_end { 0 } else { name.len() + 2 };
+                        let mut split = self.buffer.len().saturating_sub(keep);
+                        while !self.buffer.is_char_boundary(split) {
+                            split -= 1;
+                        }
+                        let text: String = self.buffer.drain(..split).collect();
+                        return self.text_chunk(&text, handle);
+                    }
+                }
+            }
+
+            let Some(lt) = self.buffer.find('<') else {
+                let text = std::mem::take(&mut self.buffer);
+                return self.text_chunk(&text, handle);
+            };
+            match parse_markup(&self.buffer[lt..], at_end) {
+                Markup::Incomplete => {
+                    let text: String = self.buffer.drain(..lt).collect();
+                    return self.text_chunk(&text, handle);
+                }
+                Markup::Text => {
+                    let text: String = self.buffer.drain(..=lt).collect();
+                    self.text_chunk(&text, handle)?;
+                }
+                markup => {
+                    let text: String = self.buffer.drain(..lt).collect();
+                    self.end_text(&text, handle)?;
+                    let raw: String = self.buffer.drain(..markup.len()).collect();
+                    match markup {
+                        Markup::Comment { text, .. } => self.comment(&raw, &text, handle)?,
+                        Markup::Doctype { .. } => self.doctype(&raw, handle)?,
+                        Markup::StartTag(tag) => self.start_tag(tag, &raw, handle)?,
+                        Markup::EndTag { name, .. } => self.end_tag(&name, &raw, handle)?,
+                        _ => {
+                            if !self.suppressed() {
+                                self.output.push_str(&raw);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 文本节点中间的一块
+    fn text_chunk<E>(
+        &mut self,
+        text: &str,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.text_started = true;
+        self.text(text, false, handle)
+    }
+
+    /// 文本节点的最后一块，之前有块交给处理函数时即使为空也要通知
+    fn end_text<E>(
+        &mut self,
+        text: &str,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        if text.is_empty() && !self.text_started {
+            return Ok(());
+        }
+        self.text_started = false;
+        self.text(text, true, handle)
+    }
+
+    fn text<E>(
+        &mut self,
+        text: &str,
+        last: bool,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        if self.suppressed() {
+            return Ok(());
+        }
+        let handlers = self.active_handlers(|spec| spec.text);
+        if handlers.is_empty() {
+            self.output.push_str(text);
+            return Ok(());
+        }
+        let m = handle("text", &json!({ "handlers": handlers, "text": text, "lastInTextNode": last }))?;
+        self.emit_node(text, &m);
+        Ok(())
+    }
+
+    fn comment<E>(
+        &mut self,
+        raw: &str,
+        text: &str,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        if self.suppressed() {
+            return Ok(());
+        }
+        let handlers = self.active_handlers(|spec| spec.comments);
+        if handlers.is_empty() {
+            self.output.push_str(raw);
+            return Ok(());
+        }
+        let m = handle("comments", &json!({ "handlers": handlers, "text": text }))?;
+        match &m.text {
+            Some(text) => self.emit_node(&format!("<!--{}-->", text), &m),
+            None => self.emit_node(raw, &m),
+        }
+        Ok(())
+    }
+
+    fn doctype<E>(
+        &mut self,
+        raw: &str,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        let handlers = self.document_handlers(|spec| spec.doctype);
+        if !handlers.is_empty() {
+            let (name, public_id, system_id) = parse_doctype(raw);
+            handle(
+                "doctype",
+                &json!({ "handlers": handlers, "name": name, "publicId": public_id, "systemId": system_id }),
+            )?;
+        }
+        if !self.suppressed() {
+            self.output.push_str(raw);
+        }
+        Ok(())
+    }
+
+    fn start_tag<E>(
+        &mut self,
+        tag: StartTag,
+        raw: &str,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        if self.stack.last().is_some_and(|top| implies_end(&tag.name, &top.element.name)) {
+            let frame = self.stack.pop().expect("checked above");
+            self.close(frame, None, handle)?;
+        }
+
+        let siblings = match self.stack.last_mut() {
+            Some(parent) => &mut parent.children,
+            None => &mut self.root_children,
+        };
+        *siblings += 1;
+        let element = ElementInfo {
+            name: tag.name,
+            attributes: tag.attributes,
+            index: *siblings,
+        };
+        let void = VOID_ELEMENTS.contains(&element.name.as_str())
+            || (tag.self_closing && self.stack.iter().any(|f| f.element.name == "svg" || f.element.name == "math"));
+
+        let suppressed = self.suppressed();
+        let matched: Vec<usize> = if suppressed {
+            Vec::new()
+        } else {
+            self.specs
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, selector))| selector.as_ref().is_some_and(|s| s.matches(&element, &self.stack)))
+                .map(|(index, _)| index)
+                .collect()
+        };
+        let handlers: Vec<usize> = matched.iter().copied().filter(|&i| self.specs[i].0.element).collect();
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let m = if handlers.is_empty() {
+            Mutations::default()
+        } else {
+            handle(
+                "element",
+                &json!({
+                    "handlers": handlers,
+                    "id": id,
+                    "tagName": element.name,
+                    "attributes": element.attributes,
+                    "selfClosing": tag.self_closing,
+                    "canHaveContent": !void,
+                }),
+            )?
+        };
+        let tag_name = m.tag_name.clone().unwrap_or_else(|| element.name.clone());
+
+        if !suppressed {
+            self.emit(&m.before);
+            if m.removed {
+                if let Some(content) = &m.replace {
+                    content.write_to(&mut self.output);
+                }
+            } else {
+                if !m.keep_content {
+                    match &m.attributes {
+                        None if m.tag_name.is_none() => self.output.push_str(raw),
+                        attributes => {
+                            let attributes = attributes.as_ref().unwrap_or(&element.attributes);
+                            self.output.push_str(&serialize_start_tag(&tag_name, attributes, tag.self_closing));
+                        }
+                    }
+                }
+                if !void {
+                    match &m.inner {
+                        Some(content) => content.write_to(&mut self.output),
+                        None => self.emit(&m.prepend),
+                    }
+                }
+            }
+        }
+
+        if void {
+            if !suppressed {
+                self.emit(&m.after);
+            }
+            return Ok(());
+        }
+
+        if RAW_TEXT_ELEMENTS.contains(&element.name.as_str()) {
+            self.raw_text = Some(element.name.clone());
+        }
+        self.stack.push(Frame {
+            element,
+            matched,
+            children: 0,
+            tag_name,
+            removed: m.removed,
+            inner: m.inner.is_some(),
+            skip_end_tag: m.removed || m.keep_content,
+            append: m.append,
+            after: m.after,
+            end_tag_id: m.end_tag.then_some(id),
+        });
+        Ok(())
+    }
+
+    fn end_tag<E>(
+        &mut self,
+        name: &str,
+        raw: &str,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        let Some(index) = self.stack.iter().rposition(|f| f.element.name == name) else {
+            // 没有对应开始标签的结束标签原样输出
+            if !self.suppressed() {
+                self.output.push_str(raw);
+            }
+            return Ok(());
+        };
+        while self.stack.len() > index + 1 {
+            let frame = self.stack.pop().expect("checked above");
+            self.close(frame, None, handle)?;
+        }
+        let frame = self.stack.pop().expect("checked above");
+        self.close(frame, Some(raw), handle)
+    }
+
+    /// 结束一个已出栈的元素，`raw` 为 `None` 表示元素被隐式结束
+    fn close<E>(
+        &mut self,
+        frame: Frame,
+        raw: Option<&str>,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, E>,
+    ) -> Result<(), E> {
+        if self.raw_text.as_deref() == Some(frame.element.name.as_str()) {
+            self.raw_text = None;
+        }
+        if self.suppressed() {
+            return Ok(());
+        }
+        if !frame.skips_content() {
+            self.emit(&frame.append);
+        }
+
+        let m = match frame.end_tag_id {
+            Some(id) if !frame.removed => handle("endTag", &json!({ "id": id, "name": frame.tag_name }))?,
+            _ => Mutations::default(),
+        };
+        self.emit(&m.before);
+        if !frame.skip_end_tag && !m.removed {
+            match (&m.tag_name, raw) {
+                (Some(name), _) => self.output.push_str(&format!("</{}>", name)),
+                (None, Some(raw)) if frame.tag_name == frame.element.name => self.output.push_str(raw),
+                (None, Some(_)) => self.output.push_str(&format!("</{}>", frame.tag_name)),
+                (None, None) => {}
+            }
+        }
+        self.emit(&m.after);
+        self.emit(&frame.after);
+        Ok(())
+    }
+
+    /// 输出被修改的文本或注释节点
+    fn emit_node(&mut self, original: &str, m: &Mutations) {
+        self.emit(&m.before);
+        if m.removed {
+            if let Some(content) = &m.replace {
+                content.write_to(&mut self.output);
+            }
+        } else {
+            self.output.push_str(original);
+        }
+        self.emit(&m.after);
+    }
+
+    fn emit(&mut self, contents: &[Content]) {
+        for content in contents {
+            content.write_to(&mut self.output);
+        }
+    }
+
+    /// 在被移除或替换的元素内部
+    fn suppressed(&self) -> bool {
+        self.stack.iter().any(Frame::skips_content)
+    }
+
+    /// 文档级处理函数，以及选择器匹配了某个外层元素的处理函数
+    fn active_handlers(&self, filter: impl Fn(&HandlerSpec) -> bool) -> Vec<usize> {
+        self.specs
+            .iter()
+            .enumerate()
+            .filter(|(index, (spec, selector))| {
+                filter(spec) && (selector.is_none() || self.stack.iter().any(|f| f.matched.contains(index)))
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn document_handlers(&self, filter: impl Fn(&HandlerSpec) -> bool) -> Vec<usize> {
+        self.specs
+            .iter()
+            .enumerate()
+            .filter(|(_, (spec, selector))| selector.is_none() && filter(spec))
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+fn implies_end(start: &str, open: &str) -> bool {
+    IMPLIED_END_GROUPS
+        .iter()
+        .any(|group| group.contains(&start) && group.contains(&open))
+}
+
+fn serialize_start_tag(name: &str, attributes: &[(String, String)], self_closing: bool) -> String {
+    let mut tag = format!("<{}", name);
+    for (name, value) in attributes {
+        if value.is_empty() {
+            tag.push_str(&format!(" {}", name));
+        } else {
+            tag.push_str(&format!(" {}=\"{}\"", name, value.replace('"', "&quot;")));
+        }
+    }
+    tag.push_str(if self_closing { " />" } else { ">" });
+    tag
+}
+
+/// 原始文本元素的结束标签位置
+fn find_raw_text_end(buffer: &str, name: &str) -> Option<usize> {
+    let lower = buffer.to_ascii_lowercase();
+    let pattern = format!("</{}", name);
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(&pattern) {
+        let pos = from + pos;
+        match lower.as_bytes().get(pos + pattern.len()) {
+            Some(c) if is_tag_delimiter(*c) => return Some(pos),
+            None => return None,
+            Some(_) => from = pos + 1,
+        }
+    }
+    None
+}
+
+fn is_tag_delimiter(c: u8) -> bool {
+    c.is_ascii_whitespace() || c == b'/' || c == b'>'
+}
+
+fn tag_name(s: &str) -> String {
+    let end = s.bytes().position(is_tag_delimiter).unwrap_or(s.len());
+    s[..end].to_ascii_lowercase()
+}
+
+/// 解析 `<!DOCTYPE name PUBLIC "public" "system">`
+fn parse_doctype(raw: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let inner = raw["<!doctype".len()..].trim_end_matches('>');
+    let mut words = inner.split_whitespace();
+    let name = words.next().map(str::to_ascii_lowercase);
+    let keyword = words.next().map(str::to_ascii_uppercase);
+    let quoted: Vec<String> = inner
+        .split(['"', '\''])
+        .skip(1)
+        .step_by(2)
+        .map(str::to_string)
+        .collect();
+    match keyword.as_deref() {
+        Some("PUBLIC") => (name, quoted.first().cloned(), quoted.get(1).cloned()),
+        Some("SYSTEM") => (name, None, quoted.first().cloned()),
+        _ => (name, None, None),
+    }
+}
+
+struct StartTag {
+    len: usize,
+    name: String,
+    attributes: Vec<(String, String)>,
+    self_closing: bool,
+}
+
+enum Markup {
+    /// 需要更多输入
+    Incomplete,
+    /// `<` 不是标签的开头
+    Text,
+    Comment { len: usize, text: String },
+    Doctype { len: usize },
+    /// 其他 `<!...>` 和 `<?...>`，原样输出
+    Declaration { len: usize },
+    StartTag(StartTag),
+    EndTag { len: usize, name: String },
+}
+
+impl Markup {
+    fn len(&self) -> usize {
+        match self {
+            Markup::Incomplete | Markup::Text => 0,
+            Markup::Comment { len, .. }
+            | Markup::Doctype { len }
+            | Markup::Declaration { len }
+            | Markup::EndTag { len, .. } => *len,
+            Markup::StartTag(tag) => tag.len,
+        }
+    }
+}
+
+/// 解析以 `<` 开头的输入，`at_end` 时不完整的标签按文本处理
+fn parse_markup(s: &str, at_end: bool) -> Markup {
+    let incomplete = if at_end { Markup::Text } else { Markup::Incomplete };
+    let rest = &s[1..];
+    let Some(first) = rest.bytes().next() else {
+        return incomplete;
+    };
+    match first {
+        b'!' => {
+            if rest.starts_with("!--") {
+                return match s[4..].find("-->") {
+                    Some(p) => Markup::Comment {
+                        len: 4 + p + 3,
+                        text: s[4..4 + p].to_string(),
+                    },
+                    None => incomplete,
+                };
+            }
+            if "!--".starts_with(rest) {
+                return incomplete;
+            }
+            let doctype = rest.len() >= 8 && rest.as_bytes()[..8].eq_ignore_ascii_case(b"!doctype");
+            if !doctype && "!doctype".starts_with(&rest.to_ascii_lowercase()) {
+                return incomplete;
+            }
+            match s.find('>') {
+                Some(p) if doctype => Markup::Doctype { len: p + 1 },
+                Some(p) => Markup::Declaration { len: p + 1 },
+                None => incomplete,
+            }
+        }
+        b'?' => match s.find('>') {
+            Some(p) => Markup::Declaration { len: p + 1 },
+            None => incomplete,
+        },
+        b'/' => match rest.as_bytes().get(1) {
+            None => incomplete,
+            Some(c) if c.is_ascii_alphabetic() => match s.find('>') {
+                Some(p) => Markup::EndTag {
+                    len: p + 1,
+                    name: tag_name(&s[2..]),
+                },
+                None => incomplete,
+            },
+            Some(_) => Markup::Text,
+        },
+        c if c.is_ascii_alphabetic() => match parse_start_tag(s) {
+            Some(tag) => Markup::StartTag(tag),
+            None => incomplete,
+        },
+        _ => Markup::Text,
+    }
+}
+
+/// 解析开始标签，标签尚未结束时返回 `None`
+///
+/// 分隔符都是 ASCII，按字节扫描不会切开多字节字符
+fn parse_start_tag(s: &str) -> Option<StartTag> {
+    let bytes = s.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() && !is_tag_delimiter(bytes[i]) {
+        i += 1;
+    }
+    let name = s[1..i].to_ascii_lowercase();
+
+    let mut attributes = Vec::new();
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        match bytes.get(i) {
+            None => return None,
+            Some(b'>') => break,
+            _ => {}
+        }
+
+        let start = i;
+        while i < bytes.len() && !is_tag_delimiter(bytes[i]) && (bytes[i] != b'=' || i == start) {
+            i += 1;
+        }
+        let attr_name = s[start..i].to_string();
+
+        let mut j = i;
+        while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+            j += 1;
+        }
+        let mut value = String::new();
+        match bytes.get(j) {
+            None => return None,
+            Some(b'=') => {
+                j += 1;
+                while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                    j += 1;
+                }
+                match bytes.get(j) {
+                    None => return None,
+                    Some(&quote) if quote == b'"' || quote == b'\'' => {
+                        let end = j + 1 + s[j + 1..].find(quote as char)?;
+                        value = s[j + 1..end].to_string();
+                        j = end + 1;
+                    }
+                    Some(_) => {
+                        let start = j;
+                        while j < bytes.len() && !bytes[j].is_ascii_whitespace() && bytes[j] != b'>' {
+                            j += 1;
+                        }
+                        value = s[start..j].to_string();
+                    }
+                }
+                i = j;
+            }
+            Some(_) => {}
+        }
+        attributes.push((attr_name, value));
+    }
+
+    Some(StartTag {
+        len: i + 1,
+        name,
+        attributes,
+        self_closing: bytes[i - 1] == b'/',
+    })
+}
+
+/// 选择器匹配时使用的元素信息
+struct ElementInfo {
+    /// 小写的标签名
+    name: String,
+    attributes: Vec<(String, String)>,
+    /// 在兄弟元素中的位置，从 1 开始
+    index: usize,
+}
+
+impl ElementInfo {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// 逗号分隔的 CSS 选择器
+#[derive(Debug, Clone, PartialEq)]
+struct Selector(Vec<ComplexSelector>);
+
+/// `compounds[i]` 和 `compounds[i + 1]` 之间是 `combinators[i]`
+#[derive(Debug, Clone, PartialEq)]
+struct ComplexSelector {
+    compounds: Vec<Compound>,
+    combinators: Vec<Combinator>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Compound {
+    tag: Option<String>,
+    conditions: Vec<Condition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Id(String),
+    Class(String),
+    Attribute {
+        name: String,
+        op: Option<(AttributeOp, String)>,
+        case_insensitive: bool,
+    },
+    FirstChild,
+    Not(Box<Compound>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AttributeOp {
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+impl Selector {
+    fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = SelectorParser {
+            chars: input.chars().collect(),
+            pos: 0,
+        };
+        let mut alternatives = Vec::new();
+        loop {
+            alternatives.push(parser.complex().map_err(|e| format!("Invalid selector '{}': {}", input, e))?);
+            match parser.peek() {
+                Some(',') => parser.pos += 1,
+                None => break,
+                Some(c) => return Err(format!("Invalid selector '{}': unexpected '{}'", input, c)),
+            }
+        }
+        Ok(Self(alternatives))
+    }
+
+    fn matches(&self, element: &ElementInfo, ancestors: &[Frame]) -> bool {
+        self.0
+            .iter()
+            .any(|complex| complex.matches_from(complex.compounds.len() - 1, element, ancestors))
+    }
+}
+
+impl ComplexSelector {
+    fn matches_from(&self, index: usize, element: &ElementInfo, ancestors: &[Frame]) -> bool {
+        if !self.compounds[index].matches(element) {
+            return false;
+        }
+        if index == 0 {
+            return true;
+        }
+        match self.combinators[index - 1] {
+            Combinator::Child => ancestors
+                .split_last()
+                .is_some_and(|(parent, rest)| self.matches_from(index - 1, &parent.element, rest)),
+            Combinator::Descendant => (0..ancestors.len())
+                .rev()
+                .any(|i| self.matches_from(index - 1, &ancestors[i].element, &ancestors[..i])),
+        }
+    }
+}
+
+impl Compound {
+    fn matches(&self, element: &ElementInfo) -> bool {
+        self.tag.as_ref().is_none_or(|tag| *tag == element.name)
+            && self.conditions.iter().all(|condition| condition.matches(element))
+    }
+}
+
+impl Condition {
+    fn matches(&self, element: &ElementInfo) -> bool {
+        match self {
+            Condition::Id(id) => element.attribute("id") == Some(id.as_str()),
+            Condition::Class(class) => element
+                .attribute("class")
+                .is_some_and(|classes| classes.split_ascii_whitespace().any(|c| c == class)),
+            Condition::Attribute {
+                name,
+                op,
+                case_insensitive,
+            } => {
+                let Some(value) = element.attribute(name) else {
+                    return false;
+                };
+                let Some((op, expected)) = op else {
+                    return true;
+                };
+                let (value, expected) = if *case_insensitive {
+                    (value.to_lowercase(), expected.to_lowercase())
+                } else {
+                    (value.to_string(), expected.clone())
+                };
+                match op {
+                    AttributeOp::Equals => value == expected,
+                    AttributeOp::Includes => value.split_ascii_whitespace().any(|v| v == expected),
+                    AttributeOp::DashMatch => value == expected || value.starts_with(&format!("{}-", expected)),
+                    AttributeOp::Prefix => !expected.is_empty() && value.starts_with(&expected),
+                    AttributeOp::Suffix => !expected.is_empty() && value.ends_with(&expected),
+                    AttributeOp::Substring => !expected.is_empty() && value.contains(&expected),
+                }
+            }
+            Condition::FirstChild => element.index == 1,
+            Condition::Not(compound) => !compound.matches(element),
+        }
+    }
+}
+
+struct SelectorParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl SelectorParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}'", c))
+        }
+    }
+
+    fn complex(&mut self) -> Result<ComplexSelector, String> {
+        self.skip_whitespace();
+        let mut compounds = vec![self.compound()?];
+        let mut combinators = Vec::new();
+        loop {
+            let whitespace = self.skip_whitespace();
+            let combinator = match self.peek() {
+                Some('>') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    Combinator::Child
+                }
+                None | Some(',') => break,
+                Some(_) if whitespace => Combinator::Descendant,
+                Some(c) => return Err(format!("unexpected '{}'", c)),
+            };
+            combinators.push(combinator);
+            compounds.push(self.compound()?);
+        }
+        Ok(ComplexSelector { compounds, combinators })
+    }
+
+    fn compound(&mut self) -> Result<Compound, String> {
+        let mut compound = Compound::default();
+        if self.peek() == Some('*') {
+            self.pos += 1;
+        } else if self.peek().is_some_and(is_ident_char) {
+            compound.tag = Some(self.ident()?.to_ascii_lowercase());
+        } else if !matches!(self.peek(), Some('#' | '.' | '[' | ':')) {
+            return Err("expected a selector".to_string());
+        }
+
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.pos += 1;
+                    compound.conditions.push(Condition::Id(self.ident()?));
+                }
+                Some('.') => {
+                    self.pos += 1;
+                    compound.conditions.push(Condition::Class(self.ident()?));
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    compound.conditions.push(self.attribute()?);
+                }
+                Some(':') => {
+                    self.pos += 1;
+                    let name = self.ident()?.to_ascii_lowercase();
+                    let condition = match name.as_str() {
+                        "first-child" => Condition::FirstChild,
+                        "not" => {
+                            self.expect('(')?;
+                            self.skip_whitespace();
+                            let inner = self.compound()?;
+                            self.skip_whitespace();
+                            self.expect(')')?;
+                            Condition::Not(Box::new(inner))
+                        }
+                        _ => return Err(format!("unsupported pseudo-class ':{}'", name)),
+                    };
+                    compound.conditions.push(condition);
+                }
+                _ => return Ok(compound),
+            }
+        }
+    }
+
+    fn attribute(&mut self) -> Result<Condition, String> {
+        self.skip_whitespace();
+        let name = self.ident()?;
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Condition::Attribute {
+                name,
+                op: None,
+                case_insensitive: false,
+            });
+        }
+
+        let op = match self.peek() {
+            Some('=') => AttributeOp::Equals,
+            Some('~') => AttributeOp::Includes,
+            Some('|') => AttributeOp::DashMatch,
+            Some('^') => AttributeOp::Prefix,
+            Some('$') => AttributeOp::Suffix,
+            Some('*') => AttributeOp::Substring,
+            _ => return Err("expected an attribute operator".to_string()),
+        };
+        self.pos += 1;
+        if op != AttributeOp::Equals {
+            self.expect('=')?;
+        }
+        self.skip_whitespace();
+
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.pos += 1;
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != quote) {
+                    self.pos += 1;
+                }
+                let value: String = self.chars[start..self.pos].iter().collect();
+                self.expect(quote)?;
+                value
+            }
+            _ => self.ident()?,
+        };
+        self.skip_whitespace();
+        let case_insensitive = match self.peek() {
+            Some('i' | 'I') => {
+                self.pos += 1;
+                true
+            }
+            Some('s' | 'S') => {
+                self.pos += 1;
+                false
+            }
+            _ => false,
+        };
+        self.skip_whitespace();
+        self.expect(']')?;
+        Ok(Condition::Attribute {
+            name,
+            op: Some((op, value)),
+            case_insensitive,
+        })
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(is_ident_char) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err("expected an identifier".to_string());
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()
+}
+
+/// 把按字节分块的 UTF-8 输入解码为字符串，跨块的字符留到下一块
+#[derive(Default)]
+struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    fn push(&mut self, chunk: &[u8]) -> Result<String, String> {
+        self.pending.extend_from_slice(chunk);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // 末尾是不完整的字符，等下一块
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err(NOT_UTF8.to_string()),
+        };
+        let rest = self.pending.split_off(valid);
+        let text = std::mem::replace(&mut self.pending, rest);
+        Ok(String::from_utf8(text).unwrap_or_default())
+    }
+
+    fn finish(self) -> Result<(), String> {
+        if self.pending.is_empty() {
+            Ok(())
+        } else {
+            Err(NOT_UTF8.to_string())
+        }
+    }
+}
+
+const NOT_UTF8: &str = "HTMLRewriter only supports UTF-8 bodies";
+
+/// `transform()` 返回的 Response 的 body，每次读取时改写一块输入
+#[derive(Trace, Finalize, JsData)]
+struct TransformStream {
+    /// 把事件交给 JS 处理函数的 `dispatcher(specs)`
+    dispatch: JsObject,
+    /// 输入是另一个 `transform()` 的结果时从它逐块读取
+    upstream: Option<JsObject>,
+    /// 正在读取时为 `None`
+    #[unsafe_ignore_trace]
+    state: RefCell<Option<TransformState>>,
+}
+
+struct TransformState {
+    /// 没有上游流时的输入和已经交给改写器的字节数
+    input: Vec<u8>,
+    offset: usize,
+    decoder: Utf8Decoder,
+    /// 输出全部产生后为 `None`
+    rewriter: Option<HtmlRewriter>,
+}
+
+impl TransformState {
+    /// 改写下一块输入，输入读完时结束改写；全部输出后返回 `None`
+    fn advance(&mut self, dispatch: &JsObject, upstream: Option<&JsObject>, context: &mut Context) -> JsResult<Option<Vec<u8>>> {
+        if self.rewriter.is_none() {
+            return Ok(None);
+        }
+        let input = match upstream {
+            Some(upstream) => read_chunk(upstream, context)?,
+            None => {
+                let end = self.input.len().min(self.offset + CHUNK_SIZE);
+                let chunk = (end > self.offset).then(|| self.input[self.offset..end].to_vec());
+                self.offset = end;
+                chunk
+            }
+        };
+        let mut handle = |kind: &str, event: &Value| -> JsResult<Mutations> {
+            let result = dispatch.call(
+                &JsValue::undefined(),
+                &[JsValue::from(js_string!(kind)), JsValue::from(js_string!(event.to_string()))],
+                context,
+            )?;
+            let json = result.to_string(context)?.to_std_string_escaped();
+            serde_json::from_str(&json).map_err(|e| JsNativeError::error().with_message(e.to_string()).into())
+        };
+
+        let output = match input {
+            Some(input) => {
+                let text = self.decoder.push(&input).map_err(|e| JsNativeError::typ().with_message(e))?;
+                match self.rewriter.as_mut() {
+                    Some(rewriter) => rewriter.write(&text, &mut handle)?,
+                    None => String::new(),
+                }
+            }
+            None => {
+                self.input = Vec::new();
+                std::mem::take(&mut self.decoder)
+                    .finish()
+                    .map_err(|e| JsNativeError::typ().with_message(e))?;
+                match self.rewriter.take() {
+                    Some(rewriter) => rewriter.end(&mut handle)?,
+                    None => String::new(),
+                }
+            }
+        };
+        Ok(Some(output.into_bytes()))
+    }
+}
+
+/// `value` 是 `transform()` 产生的流式 body 时返回它
+pub fn body_stream(value: &JsValue) -> Option<JsObject> {
+    value.as_object().filter(|obj| obj.is::<TransformStream>())
+}
+
+/// 从流式 body 读取下一块输出，全部读完后返回 `None`
+///
+/// 一块输入可能只产生空的输出（如标签还没有结束），此时返回空的 `Vec`
+pub fn read_chunk(stream: &JsObject, context: &mut Context) -> JsResult<Option<Vec<u8>>> {
+    let (dispatch, upstream, mut state) = {
+        let Some(data) = stream.downcast_ref::<TransformStream>() else {
+            return Err(JsNativeError::typ().with_message("not a HTMLRewriter body").into());
+        };
+        let state = data.state.borrow_mut().take();
+        let state = state.ok_or_else(|| JsNativeError::typ().with_message("HTMLRewriter body is already being read"))?;
+        (data.dispatch.clone(), data.upstream.clone(), state)
+    };
+    // 处理函数可能访问 body 所在的对象，调用期间不持有借用
+    let result = state.advance(&dispatch, upstream.as_ref(), context);
+    if let Some(data) = stream.downcast_ref::<TransformStream>() {
+        *data.state.borrow_mut() = Some(state);
+    }
+    result
+}
+
+/// 读完流式 body 的剩余部分
+pub fn read_all(stream: &JsObject, context: &mut Context) -> JsResult<Vec<u8>> {
+    let mut body = Vec::new();
+    while let Some(chunk) = read_chunk(stream, context)? {
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// 注册全局的 `HTMLRewriter`
+pub fn register_globals(context: &mut Context) -> Result<(), String> {
+    let validate_fn = NativeFunction::from_fn_ptr(|_, args, context| {
+        let selector = args.get_or_undefined(0).to_string(context)?.to_std_string_escaped();
+        Selector::parse(&selector).map_err(|e| JsNativeError::typ().with_message(e))?;
+        Ok(JsValue::undefined())
+    });
+
+    let stream_fn = NativeFunction::from_fn_ptr(|_, args, context| {
+        let body = args.get_or_undefined(0);
+        let (input, upstream) = match body_stream(body) {
+            Some(upstream) => (Vec::new(), Some(upstream)),
+            None => match js_to_bytes(body, context)? {
+                Some(bytes) => (bytes, None),
+                None => (body.to_string(context)?.to_std_string_escaped().into_bytes(), None),
+            },
+        };
+        let specs = args.get_or_undefined(1).to_string(context)?.to_std_string_escaped();
+        let specs: Vec<HandlerSpec> =
+            serde_json::from_str(&specs).map_err(|e| JsNativeError::typ().with_message(e.to_string()))?;
+        let dispatch = args
+            .get_or_undefined(2)
+            .as_callable()
+            .ok_or_else(|| JsNativeError::typ().with_message("dispatch is not a function"))?
+            .clone();
+        let rewriter = HtmlRewriter::new(specs).map_err(|e| JsNativeError::typ().with_message(e))?;
+
+        let stream = TransformStream {
+            dispatch,
+            upstream,
+            state: RefCell::new(Some(TransformState {
+                input,
+                offset: 0,
+                decoder: Utf8Decoder::default(),
+                rewriter: Some(rewriter),
+            })),
+        };
+        Ok(ObjectInitializer::with_native_data(stream, context).build().into())
+    });
+
+    let natives = ObjectInitializer::new(context)
+        .function(validate_fn, js_string!("validate"), 1)
+        .function(stream_fn, js_string!("stream"), 3)
+        .build();
+    context
+        .global_object()
+        .set(js_string!("__raven_html_native"), natives, false, context)
+        .map_err(|e| format!("Failed to install HTMLRewriter: {}", e))?;
+    context
+        .eval(Source::from_bytes(HTML_REWRITER_JS))
+        .map_err(|e| format!("Failed to install HTMLRewriter: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str, attributes: &[(&str, &str)], index: usize) -> ElementInfo {
+        ElementInfo {
+            name: name.to_string(),
+            attributes: attributes.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect(),
+            index,
+        }
+    }
+
+    fn frame(element: ElementInfo) -> Frame {
+        Frame {
+            element,
+            matched: Vec::new(),
+            children: 0,
+            tag_name: String::new(),
+            removed: false,
+            inner: false,
+            skip_end_tag: false,
+            append: Vec::new(),
+            after: Vec::new(),
+            end_tag_id: None,
+        }
+    }
+
+    fn spec(selector: Option<&str>) -> HandlerSpec {
+        HandlerSpec {
+            selector: selector.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    /// 按 `chunk_size` 分块改写
+    fn rewrite(
+        html: &str,
+        specs: Vec<HandlerSpec>,
+        chunk_size: usize,
+        handle: &mut impl FnMut(&str, &Value) -> Result<Mutations, String>,
+    ) -> String {
+        let mut rewriter = HtmlRewriter::new(specs).unwrap();
+        let mut output = String::new();
+        let chars: Vec<char> = html.chars().collect();
+        for chunk in chars.chunks(chunk_size) {
+            output.push_str(&rewriter.write(&chunk.iter().collect::<String>(), handle).unwrap());
+        }
+        output.push_str(&rewriter.end(handle).unwrap());
+        output
+    }
+
+    #[test]
+    fn test_selector_matching() {
+        let ancestors = vec![
+            frame(element("div", &[("id", "main"), ("class", "page wide")], 1)),
+            frame(element("ul", &[], 2)),
+        ];
+        let li = element("li", &[("data-lang", "en-US"), ("HREF", "https://example.com/a.PDF")], 1);
+        let matches = |selector: &str| Selector::parse(selector).unwrap().matches(&li, &ancestors);
+
+        assert!(matches("li"));
+        assert!(matches("*"));
+        assert!(matches("#main li"));
+        assert!(matches("div.wide > ul > li:first-child"));
+        assert!(matches("[data-lang|=en]"));
+        assert!(matches("[href^='https://'][href$='.pdf' i]"));
+        assert!(matches("li:not(.active)"));
+        assert!(matches("p, li"));
+        assert!(!matches("div > li"));
+        assert!(!matches(".page"));
+        assert!(!matches("[href$='.pdf']"));
+
+        assert!(Selector::parse("li:hover").is_err());
+        assert!(Selector::parse("div >").is_err());
+        assert!(Selector::parse("[a=b").is_err());
+    }
+
+    #[test]
+    fn test_rewrite_elements_in_chunks() {
+        let html = "<!DOCTYPE html><html><body><p class=note>Hi <b>there</b></p>\
+                    <script>if (a</b) { x('<p>') }</script><img src=\"/a.png\">\
+                    <a href='http://x.test/'>link</a><ul><li>one<li>two</ul></body></html>";
+        let specs = vec![
+            HandlerSpec {
+                element: true,
+                ..spec(Some("p.note"))
+            },
+            HandlerSpec {
+                element: true,
+                ..spec(Some("script"))
+            },
+            HandlerSpec {
+                element: true,
+                ..spec(Some("a[href^=http]"))
+            },
+            HandlerSpec {
+                element: true,
+                ..spec(Some("ul > li"))
+            },
+            HandlerSpec {
+                element: true,
+                ..spec(Some("img"))
+            },
+        ];
+        let expected = "<!DOCTYPE html><html><body><div>[Hi <b>there</b>]</div>\
+                        <img src=\"/a.png\" alt=\"&quot;\">&lt;!&gt;\
+                        <a href=\"https://x.test/\">link</a><ul><li>*one</li><li>*two</li></ul></body></html>";
+
+        for chunk_size in [1, 2, 3, 7, 1000] {
+            let mut handle = |kind: &str, event: &Value| -> Result<Mutations, String> {
+                assert_eq!(kind, "element");
+                let mut m = Mutations::default();
+                match event["handlers"][0].as_u64().unwrap() {
+                    0 => {
+                        m.tag_name = Some("div".to_string());
+                        m.attributes = Some(Vec::new());
+                        m.prepend = vec![Content("[".to_string(), false)];
+                        m.append = vec![Content("]".to_string(), false)];
+                    }
+                    1 => m.removed = true,
+                    2 => m.attributes = Some(vec![("href".to_string(), "https://x.test/".to_string())]),
+                    3 => {
+                        m.prepend = vec![Content("*".to_string(), true)];
+                        m.append = vec![Content("</li>".to_string(), true)];
+                    }
+                    _ => {
+                        let mut attributes: Vec<(String, String)> =
+                            serde_json::from_value(event["attributes"].clone()).unwrap();
+                        attributes.push(("alt".to_string(), "\"".to_string()));
+                        m.attributes = Some(attributes);
+                        m.after = vec![Content("<!>".to_string(), false)];
+                    }
+                }
+                Ok(m)
+            };
+            // 隐式结束的 li 没有结束标签，由 append 补上
+            let output = rewrite(html, specs.clone(), chunk_size, &mut handle);
+            assert_eq!(output, expected, "chunk size {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn test_rewrite_text_comments_and_document() {
+        let html = "<!doctype html><h1>Hello <em>wörld</em></h1><!-- secret --><p>keep</p>";
+        let specs = vec![
+            HandlerSpec {
+                text: true,
+                comments: true,
+                element: true,
+                ..spec(Some("h1"))
+            },
+            HandlerSpec {
+                comments: true,
+                doctype: true,
+                end: true,
+                ..spec(None)
+            },
+        ];
+
+        for chunk_size in [1, 4, 1000] {
+            let mut doctype = None;
+            let mut text = String::new();
+            let mut handle = |kind: &str, event: &Value| -> Result<Mutations, String> {
+                let mut m = Mutations::default();
+                match kind {
+                    "element" => m.end_tag = true,
+                    "endTag" => m.after = vec![Content("<hr>".to_string(), true)],
+                    "text" => {
+                        text.push_str(event["text"].as_str().unwrap());
+                        m.replace = Some(Content(event["text"].as_str().unwrap().to_uppercase(), false));
+                        m.removed = true;
+                    }
+                    "comments" => m.removed = true,
+                    "doctype" => doctype = event["name"].as_str().map(str::to_string),
+                    "end" => m.append = vec![Content("<!-- end -->".to_string(), true)],
+                    other => panic!("unexpected {}", other),
+                }
+                Ok(m)
+            };
+            let output = rewrite(html, specs.clone(), chunk_size, &mut handle);
+            assert_eq!(
+                output,
+                "<!doctype html><h1>HELLO <em>WÖRLD</em></h1><hr><p>keep</p><!-- end -->",
+                "chunk size {}",
+                chunk_size
+            );
+            assert_eq!(text, "Hello wörld");
+            assert_eq!(doctype.as_deref(), Some("html"));
+        }
+    }
+
+    #[test]
+    fn test_output_before_input_ends() {
+        let mut rewriter = HtmlRewriter::new(vec![HandlerSpec {
+            element: true,
+            ..spec(Some("a"))
+        }])
+        .unwrap();
+        let mut handle = |_: &str, _: &Value| -> Result<Mutations, String> {
+            Ok(Mutations {
+                attributes: Some(vec![("rel".to_string(), "nofollow".to_string())]),
+                ..Default::default()
+            })
+        };
+
+        // 第一个元素改写后立即输出，不等后面的输入；未结束的标签留在缓冲中
+        let first = rewriter.write("<p>intro <a href=x>one</a> <a hr", &mut handle).unwrap();
+        assert_eq!(first, "<p>intro <a rel=\"nofollow\">one</a> ");
+        let second = rewriter.write("ef=y>two</a>", &mut handle).unwrap();
+        assert_eq!(second, "<a rel=\"nofollow\">two</a>");
+        assert_eq!(rewriter.end(&mut handle).unwrap(), "");
+    }
+
+    #[test]
+    fn test_utf8_decoder() {
+        let bytes = "aé€😀".as_bytes();
+        for size in 1..=bytes.len() {
+            let mut decoder = Utf8Decoder::default();
+            let text: String = bytes.chunks(size).map(|chunk| decoder.push(chunk).unwrap()).collect();
+            assert_eq!(text, "aé€😀");
+            decoder.finish().unwrap();
+        }
+
+        assert!(Utf8Decoder::default().push(&[b'a', 0xff, b'b']).is_err());
+        let mut truncated = Utf8Decoder::default();
+        assert_eq!(truncated.push(&bytes[..2]).unwrap(), "a");
+        assert!(truncated.finish().is_err());
+    }
+
+    #[test]
+    fn test_handler_error_stops_rewrite() {
+        let mut rewriter = HtmlRewriter::new(vec![HandlerSpec {
+            element: true,
+            ..spec(Some("b"))
+        }])
+        .unwrap();
+        let mut handle = |_: &str, _: &Value| -> Result<Mutations, String> { Err("boom".to_string()) };
+        assert_eq!(rewriter.write("<a>x</a>", &mut handle).unwrap(), "<a>x</a>");
+        assert_eq!(rewriter.write("<b>", &mut handle).unwrap_err(), "boom");
+        assert!(HtmlRewriter::new(vec![spec(Some("b:hover"))]).is_err());
+    }
+}
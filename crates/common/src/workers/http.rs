@@ -3,31 +3,91 @@
 //! 使用标准库实现，无外部依赖。
 
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::net::TcpStream;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+
+use super::proxy::TrustedProxies;
+use super::tls::TlsInfo;
+
+/// 客户端实际请求的 URL
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RequestUrl {
+    /// `http` 或 `https`
+    pub scheme: String,
+    /// 主机名（可能带端口），来自请求行或 `Host` 头
+    pub host: String,
+    /// 百分号解码后的路径
+    pub path: String,
+    /// 客户端发送的原始路径（未解码）
+    pub raw_path: String,
+    /// 原始查询字符串（不含 `?`）
+    pub query: Option<String>,
+    /// 解码后的查询参数，保持原有顺序
+    pub query_params: Vec<(String, String)>,
+}
+
+impl RequestUrl {
+    /// 第一个同名查询参数
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query_params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl fmt::Display for RequestUrl {
+    /// 完整 URL，路径和查询保持客户端发送的编码
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}{}", self.scheme, self.host, self.raw_path)?;
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+        Ok(())
+    }
+}
 
 /// HTTP 请求
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct HttpRequest {
     pub method: String,
     pub path: String,
     pub version: String,
     pub headers: HashMap<String, String>,
     pub body: Vec<u8>,
+    /// TLS 连接信息，明文请求为 `None`
+    pub tls: Option<TlsInfo>,
+    /// 客户端地址
+    pub remote_addr: Option<SocketAddr>,
 }
 
 impl HttpRequest {
+    /// body 超出限制时 `from_stream_limited` 返回的错误
+    pub const PAYLOAD_TOO_LARGE: &'static str = "Payload too large";
+
     /// 从 TcpStream 解析 HTTP 请求
     pub fn from_stream(stream: &mut TcpStream) -> Result<Self, String> {
+        Self::from_stream_limited(stream, usize::MAX)
+    }
+
+    /// 从 TcpStream 解析 HTTP 请求，body 超过 `max_body_size` 时不读取并返回错误
+    pub fn from_stream_limited(stream: &mut TcpStream, max_body_size: usize) -> Result<Self, String> {
         let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+        Self::from_reader(&mut reader, max_body_size)
+    }
 
+    /// 从任意输入流（如 TLS 连接）解析 HTTP 请求
+    pub fn from_reader<R: BufRead>(reader: &mut R, max_body_size: usize) -> Result<Self, String> {
         // 解析请求行
         let mut request_line = String::new();
         reader
             .read_line(&mut request_line)
             .map_err(|e| e.to_string())?;
 
-        let parts: Vec<&str> = request_line.trim().split_whitespace().collect();
+        let parts: Vec<&str> = request_line.split_whitespace().collect();
         if parts.len() < 3 {
             return Err("Invalid request line".to_string());
         }
@@ -56,6 +116,9 @@ impl HttpRequest {
         let mut body = Vec::new();
         if let Some(content_length) = headers.get("content-length") {
             if let Ok(len) = content_length.parse::<usize>() {
+                if len > max_body_size {
+                    return Err(Self::PAYLOAD_TOO_LARGE.to_string());
+                }
                 body.resize(len, 0);
                 reader.read_exact(&mut body).map_err(|e| e.to_string())?;
             }
@@ -67,18 +130,554 @@ impl HttpRequest {
             version,
             headers,
             body,
+            tls: None,
+            remote_addr: None,
         })
     }
 
-    /// 构建完整 URL
-    pub fn url(&self, host: &str) -> String {
-        format!("http://{}{}", host, self.path)
+    /// 解析客户端请求的 URL
+    ///
+    /// 主机名依次取自绝对形式的请求行（`GET http://host/path`）、`Host` 头和 `default_host`；
+    /// 协议由连接是否为 TLS 决定。来自 `proxies` 中受信任代理的请求
+    /// 会改用 `X-Forwarded-Proto` 和 `X-Forwarded-Host`。
+    pub fn url(&self, default_host: &str, proxies: &TrustedProxies) -> RequestUrl {
+        let (authority, raw_path, query) = self.split_target();
+
+        let mut scheme = if self.tls.is_some() { "https" } else { "http" }.to_string();
+        let mut host = authority
+            .or_else(|| self.headers.get("host").map(|h| h.as_str()))
+            .filter(|h| is_valid_host(h))
+            .unwrap_or(default_host)
+            .to_string();
+
+        let hops = self.trusted_hops(proxies);
+        if hops > 0 {
+            if let Some(proto) = self.forwarded_value("x-forwarded-proto", hops) {
+                let proto = proto.to_lowercase();
+                if proto == "http" || proto == "https" {
+                    scheme = proto;
+                }
+            }
+            if let Some(forwarded) = self.forwarded_value("x-forwarded-host", hops) {
+                if is_valid_host(forwarded) {
+                    host = forwarded.to_string();
+                }
+            }
+        }
+
+        RequestUrl {
+            scheme,
+            host,
+            path: percent_decode(raw_path),
+            raw_path: raw_path.to_string(),
+            query: query.map(|q| q.to_string()),
+            query_params: query.map(parse_query).unwrap_or_default(),
+        }
+    }
+
+    /// 客户端 IP
+    ///
+    /// 来自受信任代理的请求按 `X-Forwarded-For` 从右向左跳过受信任的代理，
+    /// 取第一个不受信任的地址
+    pub fn client_ip(&self, proxies: &TrustedProxies) -> Option<IpAddr> {
+        let remote = self.remote_addr?.ip();
+        if !proxies.contains(remote) {
+            return Some(remote);
+        }
+
+        let forwarded_for = self
+            .headers
+            .get("x-forwarded-for")
+            .map(|v| v.as_str())
+            .unwrap_or_default();
+        let mut client = remote;
+        for hop in forwarded_for.rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => {
+                    client = ip;
+                    if !proxies.contains(ip) {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        Some(client)
+    }
+
+    /// 百分号解码后的路径（不含查询字符串）
+    pub fn decoded_path(&self) -> String {
+        percent_decode(self.split_target().1)
+    }
+
+    /// 获取查询参数（`+` 和 `%XX` 会被解码）
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        let query = self.split_target().2?;
+        parse_query(query)
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// 拆分请求目标：(绝对形式中的主机, 路径, 查询字符串)
+    fn split_target(&self) -> (Option<&str>, &str, Option<&str>) {
+        let mut authority = None;
+        let mut target = self.path.as_str();
+        if let Some(rest) = target
+            .strip_prefix("http://")
+            .or_else(|| target.strip_prefix("https://"))
+        {
+            let end = rest.find(['/', '?']).unwrap_or(rest.len());
+            authority = Some(&rest[..end]);
+            target = &rest[end..];
+        }
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+        let path = if path.is_empty() { "/" } else { path };
+        (authority, path, query)
+    }
+
+    /// 链路上受信任代理的跳数
+    ///
+    /// 直接连接不是受信任代理时为 0；否则为 1 加上 `X-Forwarded-For`
+    /// 从右向左连续受信任的地址个数（与 `client_ip` 的遍历一致）
+    fn trusted_hops(&self, proxies: &TrustedProxies) -> usize {
+        let Some(remote) = self.remote_addr.map(|addr| addr.ip()) else {
+            return 0;
+        };
+        if !proxies.contains(remote) {
+            return 0;
+        }
+
+        let forwarded_for = self
+            .headers
+            .get("x-forwarded-for")
+            .map(|v| v.as_str())
+            .unwrap_or_default();
+        1 + forwarded_for
+            .rsplit(',')
+            .map_while(|hop| hop.trim().parse::<IpAddr>().ok())
+            .take_while(|ip| proxies.contains(*ip))
+            .count()
+    }
+
+    /// 转发头中由最外层受信任代理追加的值
+    ///
+    /// 每个受信任代理在末尾追加一项，因此从右数第 `hops` 项来自面向客户端的代理，
+    /// 更靠左的项可能由客户端伪造。项数不足时取最右一项（最近的代理写入的值）
+    fn forwarded_value(&self, name: &str, hops: usize) -> Option<&str> {
+        let values: Vec<&str> = self.headers.get(name)?.split(',').map(str::trim).collect();
+        let value = values
+            .len()
+            .checked_sub(hops)
+            .map_or(values.last().copied(), |index| values.get(index).copied())?;
+        (!value.is_empty()).then_some(value)
     }
 
     /// 获取 body 文本
     pub fn body_text(&self) -> Result<String, std::string::FromUtf8Error> {
         String::from_utf8(self.body.clone())
     }
+
+    /// 解析表单 body（`multipart/form-data` 或 `application/x-www-form-urlencoded`）
+    ///
+    /// 单个字段或文件超过 `max_part_size` 字节时返回错误
+    pub fn form_data(&self, max_part_size: usize) -> Result<Vec<FormPart>, String> {
+        let content_type = self
+            .headers
+            .get("content-type")
+            .map(|v| v.as_str())
+            .unwrap_or_default();
+        parse_form(content_type, &self.body, max_part_size)
+    }
+}
+
+/// 解码 URL 查询参数中的 `+` 和 `%XX`
+fn decode_query_component(value: &str) -> String {
+    percent_decode(&value.replace('+', " "))
+}
+
+/// 解析查询字符串为 (键, 值) 列表
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_query_component(key), decode_query_component(value))
+        })
+        .collect()
+}
+
+/// 主机名只能包含域名、IP（含 IPv6 方括号）和端口允许的字符
+fn is_valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':' | '[' | ']'))
+}
+
+/// 解码 `%XX`，无效的转义保持原样
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        decoded.push(b);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// 按 `Content-Type` 解析表单 body，单个字段或文件超过 `max_part_size` 字节时返回错误
+pub fn parse_form(content_type: &str, body: &[u8], max_part_size: usize) -> Result<Vec<FormPart>, String> {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    match mime.as_str() {
+        "multipart/form-data" => {
+            let boundary = header_param(content_type, "boundary")
+                .ok_or("Missing multipart boundary")?;
+            let mut parser = MultipartParser::new(&boundary, max_part_size)?;
+            parser.push(body)?;
+            parser.finish()
+        }
+        "application/x-www-form-urlencoded" => {
+            parse_query(&String::from_utf8_lossy(body))
+                .into_iter()
+                .map(|(name, value)| {
+                    if value.len() > max_part_size {
+                        return Err(format!("Form field '{}' is too large", name));
+                    }
+                    Ok(FormPart {
+                        name,
+                        data: value.into_bytes(),
+                        ..Default::default()
+                    })
+                })
+                .collect()
+        }
+        _ => Err(format!("Unsupported form content type: '{}'", mime)),
+    }
+}
+
+/// 头部值中的参数，如 `multipart/form-data; boundary="abc"` 中的 `boundary`
+///
+/// 引号内的 `;` 不作为分隔符
+fn header_param(value: &str, name: &str) -> Option<String> {
+    let mut params = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                params.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&value[start..]);
+
+    params.into_iter().skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// 表单中的一个字段或文件
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FormPart {
+    pub name: String,
+    /// 文件名，普通字段为 `None`
+    pub filename: Option<String>,
+    /// 文件的 `Content-Type`
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl FormPart {
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// 字段文本（无效的 UTF-8 会被替换）
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+}
+
+/// 单个 part 头部的最大字节数
+const MAX_PART_HEADER_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MultipartState {
+    /// 第一个分隔线之前的内容（忽略）
+    Preamble,
+    /// 分隔线之后，等待 `\r\n` 或结束标记 `--`
+    Boundary,
+    Headers,
+    Body,
+    /// 结束标记之后的内容（忽略）
+    Done,
+}
+
+/// 流式 `multipart/form-data` 解析器
+///
+/// body 可以分块传入，除当前 part 的数据外只缓冲不超过分隔线长度的内容，
+/// 单个 part 超过 `max_part_size` 时立即返回错误。
+pub struct MultipartParser {
+    /// `\r\n--boundary`
+    delimiter: Vec<u8>,
+    max_part_size: usize,
+    state: MultipartState,
+    buffer: Vec<u8>,
+    current: Option<FormPart>,
+    parts: Vec<FormPart>,
+}
+
+impl MultipartParser {
+    pub fn new(boundary: &str, max_part_size: usize) -> Result<Self, String> {
+        if boundary.is_empty() || boundary.len() > 70 {
+            return Err(format!("Invalid multipart boundary: '{}'", boundary));
+        }
+        Ok(Self {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            max_part_size,
+            state: MultipartState::Preamble,
+            // 第一个分隔线前没有换行，补上后与其他分隔线统一处理
+            buffer: b"\r\n".to_vec(),
+            current: None,
+            parts: Vec::new(),
+        })
+    }
+
+    /// 传入下一块 body 数据
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), String> {
+        if self.state == MultipartState::Done {
+            return Ok(());
+        }
+        self.buffer.extend_from_slice(chunk);
+
+        loop {
+            match self.state {
+                MultipartState::Preamble => match find(&self.buffer, &self.delimiter) {
+                    Some(pos) => {
+                        self.buffer.drain(..pos + self.delimiter.len());
+                        self.state = MultipartState::Boundary;
+                    }
+                    None => {
+                        let keep = self.delimiter.len() - 1;
+                        let drop = self.buffer.len().saturating_sub(keep);
+                        self.buffer.drain(..drop);
+                        return Ok(());
+                    }
+                },
+                MultipartState::Boundary => {
+                    if self.buffer.len() < 2 {
+                        return Ok(());
+                    }
+                    if self.buffer.starts_with(b"--") {
+                        self.buffer.clear();
+                        self.state = MultipartState::Done;
+                        return Ok(());
+                    }
+                    if !self.buffer.starts_with(b"\r\n") {
+                        return Err("Malformed multipart boundary".to_string());
+                    }
+                    self.buffer.drain(..2);
+                    self.state = MultipartState::Headers;
+                }
+                MultipartState::Headers => {
+                    let (headers, consumed) = if self.buffer.starts_with(b"\r\n") {
+                        (String::new(), 2)
+                    } else {
+                        match find(&self.buffer, b"\r\n\r\n") {
+                            Some(pos) => (String::from_utf8_lossy(&self.buffer[..pos]).into_owned(), pos + 4),
+                            None if self.buffer.len() > MAX_PART_HEADER_SIZE => {
+                                return Err("Multipart headers are too large".to_string());
+                            }
+                            None => return Ok(()),
+                        }
+                    };
+                    self.buffer.drain(..consumed);
+                    self.current = Some(parse_part_headers(&headers)?);
+                    self.state = MultipartState::Body;
+                }
+                MultipartState::Body => {
+                    let found = find(&self.buffer, &self.delimiter);
+                    let end = found.unwrap_or_else(|| {
+                        self.buffer.len().saturating_sub(self.delimiter.len() - 1)
+                    });
+                    let part = self.current.as_mut().expect("part headers parsed");
+                    if part.data.len() + end > self.max_part_size {
+                        return Err(format!("Form field '{}' is too large", part.name));
+                    }
+                    part.data.extend_from_slice(&self.buffer[..end]);
+
+                    match found {
+                        Some(pos) => {
+                            self.buffer.drain(..pos + self.delimiter.len());
+                            self.parts.extend(self.current.take());
+                            self.state = MultipartState::Boundary;
+                        }
+                        None => {
+                            self.buffer.drain(..end);
+                            return Ok(());
+                        }
+                    }
+                }
+                MultipartState::Done => return Ok(()),
+            }
+        }
+    }
+
+    /// 结束解析，返回所有 part；body 未以结束标记结尾时返回错误
+    pub fn finish(self) -> Result<Vec<FormPart>, String> {
+        if self.state != MultipartState::Done {
+            return Err("Unexpected end of multipart body".to_string());
+        }
+        Ok(self.parts)
+    }
+}
+
+/// 解析 part 的 `Content-Disposition` 和 `Content-Type` 头
+fn parse_part_headers(headers: &str) -> Result<FormPart, String> {
+    let mut part = FormPart::default();
+    let mut name = None;
+    for line in headers.split("\r\n") {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim().to_lowercase().as_str() {
+            "content-disposition" => {
+                name = header_param(value, "name");
+                part.filename = header_param(value, "filename");
+            }
+            "content-type" => part.content_type = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    part.name = name.ok_or("Multipart part is missing a field name")?;
+    Ok(part)
+}
+
+/// 在 `haystack` 中查找 `needle` 第一次出现的位置
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// HTTP 状态码对应的原因短语
+pub fn status_text(status: u16) -> &'static str {
+    match status {
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        416 => "Range Not Satisfiable",
+        426 => "Upgrade Required",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+/// 流式 body 中的一块：空块表示正常结束，`Err` 表示生产方出错
+pub type StreamChunk = Result<Vec<u8>, String>;
+
+/// 逐块产生的响应 body
+///
+/// 生产方（执行 JS 的主线程）每产生一块就发送，连接线程收到后立即按
+/// `Transfer-Encoding: chunked` 写给客户端。通道有容量上限，客户端读得慢时生产方暂停。
+#[derive(Clone)]
+pub struct BodyStream {
+    receiver: Arc<Mutex<Receiver<StreamChunk>>>,
+}
+
+impl BodyStream {
+    /// 创建最多缓冲 `capacity` 块的流，返回发送端和流
+    pub fn channel(capacity: usize) -> (SyncSender<StreamChunk>, Self) {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let stream = Self {
+            receiver: Arc::new(Mutex::new(receiver)),
+        };
+        (sender, stream)
+    }
+
+    /// 读取下一块，流已结束时返回 `None`
+    ///
+    /// 生产方没有发送结束标记就退出（如 Worker 被重新加载）时返回错误
+    pub fn next_chunk(&self) -> Option<StreamChunk> {
+        let receiver = match self.receiver.lock() {
+            Ok(receiver) => receiver,
+            Err(e) => return Some(Err(e.to_string())),
+        };
+        match receiver.recv() {
+            Ok(Ok(chunk)) if chunk.is_empty() => None,
+            Ok(chunk) => Some(chunk),
+            Err(_) => Some(Err("Response stream ended early".to_string())),
+        }
+    }
+
+    /// 读取剩余的所有块
+    pub fn collect(&self) -> Result<Vec<u8>, String> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.next_chunk() {
+            body.extend_from_slice(&chunk?);
+        }
+        Ok(body)
+    }
+
+    /// 按 chunked 编码写出，每块写完立即 flush
+    fn write_chunked<W: Write>(&self, stream: &mut W) -> Result<(), io::Error> {
+        while let Some(chunk) = self.next_chunk() {
+            let chunk = chunk.map_err(io::Error::other)?;
+            stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())?;
+            stream.write_all(&chunk)?;
+            stream.write_all(b"\r\n")?;
+            stream.flush()?;
+        }
+        stream.write_all(b"0\r\n\r\n")
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BodyStream")
+    }
 }
 
 /// HTTP 响应
@@ -88,6 +687,12 @@ pub struct HttpResponse {
     pub status_text: String,
     pub headers: HashMap<String, String>,
     pub body: Vec<u8>,
+    /// 逐块产生的 body，设置时忽略 `body` 并按 chunked 编码写出
+    pub stream: Option<BodyStream>,
+    /// Worker 接受的 WebSocket 连接（101 响应）
+    pub websocket: Option<u64>,
+    /// 是否允许服务器按 `Accept-Encoding` 自动压缩 body
+    pub auto_encode: bool,
 }
 
 impl Default for HttpResponse {
@@ -106,6 +711,9 @@ impl HttpResponse {
             status_text: status_text.to_string(),
             headers,
             body: Vec::new(),
+            stream: None,
+            websocket: None,
+            auto_encode: true,
         }
     }
 
@@ -120,14 +728,7 @@ impl HttpResponse {
     }
 
     pub fn error(status: u16, message: &str) -> Self {
-        let status_text = match status {
-            400 => "Bad Request",
-            404 => "Not Found",
-            500 => "Internal Server Error",
-            _ => "Error",
-        };
-
-        let mut resp = Self::new(status, status_text);
+        let mut resp = Self::new(status, status_text(status));
         resp.body = message.as_bytes().to_vec();
         resp.headers.insert(
             "content-length".to_string(),
@@ -148,23 +749,34 @@ impl HttpResponse {
         self
     }
 
-    /// 将响应写入 TcpStream
-    pub fn write_to(&self, stream: &mut TcpStream) -> Result<(), std::io::Error> {
+    /// 将响应写入 TcpStream（或 TLS 连接）
+    pub fn write_to<W: Write>(&self, stream: &mut W) -> Result<(), std::io::Error> {
         // 状态行
         let status_line = format!("HTTP/1.1 {} {}\r\n", self.status, self.status_text);
         stream.write_all(status_line.as_bytes())?;
 
-        // Headers
+        // Headers，流式 body 的长度未知，改用 chunked 编码
         for (key, value) in &self.headers {
+            let framing = key.eq_ignore_ascii_case("content-length")
+                || key.eq_ignore_ascii_case("transfer-encoding");
+            if self.stream.is_some() && framing {
+                continue;
+            }
             let header_line = format!("{}: {}\r\n", key, value);
             stream.write_all(header_line.as_bytes())?;
         }
+        if self.stream.is_some() {
+            stream.write_all(b"transfer-encoding: chunked\r\n")?;
+        }
 
         // 空行
         stream.write_all(b"\r\n")?;
 
         // Body
-        stream.write_all(&self.body)?;
+        match &self.stream {
+            Some(body) => body.write_chunked(stream)?,
+            None => stream.write_all(&self.body)?,
+        }
         stream.flush()?;
 
         Ok(())
@@ -175,6 +787,172 @@ impl HttpResponse {
 mod tests {
     use super::*;
 
+    fn request(path: &str, headers: &[(&str, &str)], remote: &str) -> HttpRequest {
+        HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            remote_addr: Some(remote.parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_request_url() {
+        let req = request(
+            "/files/a%20b.txt?q=hello+world&tag=%E4%B8%AD&empty",
+            &[("host", "example.com:8443")],
+            "203.0.113.9:50000",
+        );
+        let url = req.url("127.0.0.1:8787", &TrustedProxies::none());
+        assert_eq!(url.scheme, "http");
+        assert_eq!(url.host, "example.com:8443");
+        assert_eq!(url.path, "/files/a b.txt");
+        assert_eq!(url.query_param("q"), Some("hello world"));
+        assert_eq!(url.query_param("tag"), Some("中"));
+        assert_eq!(url.query_param("empty"), Some(""));
+        assert_eq!(
+            url.to_string(),
+            "http://example.com:8443/files/a%20b.txt?q=hello+world&tag=%E4%B8%AD&empty"
+        );
+
+        // 缺少或非法的 Host 头使用默认主机
+        let req = request("/", &[("host", "evil.com/x")], "203.0.113.9:50000");
+        assert_eq!(req.url("127.0.0.1:8787", &TrustedProxies::none()).to_string(), "http://127.0.0.1:8787/");
+
+        // 绝对形式的请求行
+        let req = request("http://api.example.com?x=1", &[("host", "other")], "203.0.113.9:50000");
+        let url = req.url("127.0.0.1:8787", &TrustedProxies::none());
+        assert_eq!(url.host, "api.example.com");
+        assert_eq!(url.raw_path, "/");
+        assert_eq!(url.query.as_deref(), Some("x=1"));
+    }
+
+    #[test]
+    fn test_forwarded_headers() {
+        let headers = [
+            ("host", "internal:8787"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "www.example.com"),
+            ("x-forwarded-for", "198.51.100.7, 10.0.0.2"),
+        ];
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8"]).unwrap();
+
+        // 来自受信任代理
+        let req = request("/", &headers, "10.0.0.1:40000");
+        assert_eq!(req.url("x", &proxies).to_string(), "https://www.example.com/");
+        assert_eq!(req.client_ip(&proxies), Some("198.51.100.7".parse().unwrap()));
+
+        // 直接来自客户端时忽略转发头
+        let req = request("/", &headers, "198.51.100.7:40000");
+        assert_eq!(req.url("x", &proxies).to_string(), "http://internal:8787/");
+        assert_eq!(req.client_ip(&proxies), Some("198.51.100.7".parse().unwrap()));
+        let req = request("/", &headers, "10.0.0.1:40000");
+        assert_eq!(req.client_ip(&TrustedProxies::none()), Some("10.0.0.1".parse().unwrap()));
+
+        // 客户端伪造的值位于受信任代理追加的值左侧，会被跳过
+        let spoofed = [
+            ("host", "internal:8787"),
+            ("x-forwarded-proto", "http, https"),
+            ("x-forwarded-host", "evil.com, www.example.com"),
+            ("x-forwarded-for", "192.0.2.1, 198.51.100.7"),
+        ];
+        let req = request("/", &spoofed, "10.0.0.1:40000");
+        assert_eq!(req.url("x", &proxies).to_string(), "https://www.example.com/");
+        assert_eq!(req.client_ip(&proxies), Some("198.51.100.7".parse().unwrap()));
+
+        // 多级受信任代理各自追加一项时，取面向客户端的代理追加的值
+        let chained = [
+            ("host", "internal:8787"),
+            ("x-forwarded-proto", "http, https, http"),
+            ("x-forwarded-host", "evil.com, www.example.com, edge.internal"),
+            ("x-forwarded-for", "198.51.100.7, 10.0.0.2"),
+        ];
+        let req = request("/", &chained, "10.0.0.1:40000");
+        assert_eq!(req.url("x", &proxies).to_string(), "https://www.example.com/");
+    }
+
+    const MULTIPART_BODY: &str = "preamble\r\n--XyZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nhello world\r\n--XyZ\r\nContent-Disposition: form-data; name=\"upload\"; filename=\"a;b.txt\"\r\nContent-Type: text/plain\r\n\r\nline 1\r\nline 2\r\n--XyZ--\r\n";
+
+    #[test]
+    fn test_multipart_form_data() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            "content-type".to_string(),
+            "multipart/form-data; boundary=\"XyZ\"".to_string(),
+        );
+        let req = HttpRequest {
+            method: "POST".to_string(),
+            headers,
+            body: MULTIPART_BODY.as_bytes().to_vec(),
+            ..Default::default()
+        };
+
+        let parts = req.form_data(1024).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "title");
+        assert_eq!(parts[0].text(), "hello world");
+        assert!(!parts[0].is_file());
+        assert_eq!(parts[1].filename.as_deref(), Some("a;b.txt"));
+        assert_eq!(parts[1].content_type.as_deref(), Some("text/plain"));
+        assert_eq!(parts[1].data, b"line 1\r\nline 2");
+
+        let err = req.form_data(12).unwrap_err();
+        assert!(err.contains("upload"), "{}", err);
+
+        let mut truncated = req.clone();
+        truncated.body.truncate(MULTIPART_BODY.len() - 10);
+        assert!(truncated.form_data(1024).is_err());
+    }
+
+    #[test]
+    fn test_multipart_parser_chunks() {
+        // 按任意大小分块传入，结果与一次性传入相同
+        for size in [1, 2, 3, 7, 64] {
+            let mut parser = MultipartParser::new("XyZ", 1024).unwrap();
+            for chunk in MULTIPART_BODY.as_bytes().chunks(size) {
+                parser.push(chunk).unwrap();
+            }
+            let parts = parser.finish().unwrap();
+            assert_eq!(parts.len(), 2, "chunk size {}", size);
+            assert_eq!(parts[1].data, b"line 1\r\nline 2");
+        }
+    }
+
+    #[test]
+    fn test_urlencoded_form_data() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            "content-type".to_string(),
+            "application/x-www-form-urlencoded; charset=UTF-8".to_string(),
+        );
+        let req = HttpRequest {
+            method: "POST".to_string(),
+            headers,
+            body: b"name=J%C3%BCrgen+M&tag=a&tag=b".to_vec(),
+            ..Default::default()
+        };
+
+        let parts = req.form_data(1024).unwrap();
+        let fields: Vec<(&str, String)> = parts.iter().map(|p| (p.name.as_str(), p.text())).collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("name", "Jürgen M".to_string()),
+                ("tag", "a".to_string()),
+                ("tag", "b".to_string())
+            ]
+        );
+
+        let mut json = req.clone();
+        json.headers.insert("content-type".to_string(), "application/json".to_string());
+        assert!(json.form_data(1024).is_err());
+    }
+
     #[test]
     fn test_http_response() {
         let resp = HttpResponse::ok("Hello World")
@@ -184,4 +962,31 @@ mod tests {
         assert_eq!(resp.body, b"Hello World");
         assert_eq!(resp.headers.get("x-custom"), Some(&"value".to_string()));
     }
+
+    #[test]
+    fn test_write_streamed_response() {
+        let (sender, stream) = BodyStream::channel(4);
+        let mut resp = HttpResponse::new(200, "OK").with_header("Content-Length", "99");
+        resp.stream = Some(stream);
+        sender.send(Ok(b"hello ".to_vec())).unwrap();
+        sender.send(Ok(b"world".to_vec())).unwrap();
+        sender.send(Ok(Vec::new())).unwrap();
+
+        let mut wire = Vec::new();
+        resp.write_to(&mut wire).unwrap();
+        let wire = String::from_utf8(wire).unwrap();
+        assert!(!wire.to_lowercase().contains("content-length"));
+        assert!(wire.contains("transfer-encoding: chunked\r\n"));
+        assert!(wire.ends_with("\r\n\r\n6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n"));
+
+        // 生产方出错或提前退出时不写结束块
+        let (sender, stream) = BodyStream::channel(4);
+        let mut resp = HttpResponse::new(200, "OK");
+        resp.stream = Some(stream);
+        sender.send(Ok(b"partial".to_vec())).unwrap();
+        drop(sender);
+        let mut wire = Vec::new();
+        assert!(resp.write_to(&mut wire).is_err());
+        assert!(!String::from_utf8(wire).unwrap().ends_with("0\r\n\r\n"));
+    }
 }
@@ -0,0 +1,859 @@
+//! Worker 清单文件（TOML）
+//!
+//! 声明 Worker 的脚本、路由、环境变量、密钥、KV 命名空间和 cron 触发器，
+//! 字段命名参考 Cloudflare 的 `wrangler.toml`。
+//!
+//! # 单 Worker
+//!
+//! ```toml
+//! name = "api"
+//! main = "worker.js"
+//! routes = ["/api/*"]
+//!
+//! [vars]
+//! API_BASE = "https://internal.example.com"
+//!
+//! [secrets]
+//! TOKEN = { env = "API_TOKEN" }
+//! SIGNING_KEY = { file = "secrets/signing.key" }
+//!
+//! [[kv_namespaces]]
+//! binding = "CACHE"
+//! id = "api-cache"            # ID 相同的命名空间在所有 Worker 之间共享，省略时等于 binding
+//! max_entries = 10000         # 内存命名空间的容量限制，超出时淘汰
+//! max_bytes = 67108864
+//! eviction = "lru"            # lru（默认）/ lfu
+//!
+//! [[kv_namespaces]]
+//! binding = "SESSIONS"
+//! backend = "file"            # 保存在日志文件中，重启后仍然存在
+//! path = "data/sessions.kvlog"
+//! fsync = "interval"          # always（默认）/ interval / never
+//! fsync_interval_ms = 1000
+//!
+//! [[kv_namespaces]]
+//! binding = "CATALOG"
+//! backend = "sqlite"          # 键很多时按前缀列出更快
+//! path = "data/catalog.sqlite"
+//!
+//! [triggers]
+//! crons = ["*/5 * * * *"]
+//!
+//! [assets]
+//! directory = "public"
+//! ```
+//!
+//! # 多 Worker
+//!
+//! ```toml
+//! [server]
+//! host = "0.0.0.0"
+//! port = 8787
+//! access_log = "json"   # combined（默认）/ json / off
+//! metrics = true        # 开启 /__raven/metrics
+//! shutdown_timeout = 30 # 关闭时等待进行中请求的秒数
+//! trusted_proxies = ["127.0.0.1", "10.0.0.0/8"]  # 采用这些代理的 X-Forwarded-* 头
+//! cache_dir = "cache"   # Cache API 存到磁盘（默认在内存中）
+//! queue_dir = "queues"  # 队列消息存到磁盘，重启后继续投递（默认在内存中）
+//! durable_objects_dir = "objects"  # Durable Object 存储目录（默认在内存中）
+//!
+//! [server.compression]
+//! min_size = 1024                      # 小于该字节数的响应不压缩
+//! encodings = ["zstd", "br", "gzip"]   # 客户端权重相同时按此顺序选择
+//!
+//! [server.tls]
+//! cert = "certs/default.crt"
+//! key = "certs/default.key"
+//! client_auth = "optional"
+//! client_ca = "certs/internal-ca.crt"
+//!
+//! [[server.tls.certificates]]
+//! hostnames = ["api.example.com"]
+//! cert = "certs/api.crt"
+//! key = "certs/api.key"
+//!
+//! [[workers]]
+//! name = "api"
+//! main = "api.js"
+//! routes = ["/api/*"]
+//! require_client_cert = true
+//!
+//! [[workers.services]]   # env.AUTH.fetch() 直接调用 auth Worker
+//! binding = "AUTH"
+//! service = "auth"
+//! timeout_ms = 500
+//!
+//! [[workers.queues.producers]]   # env.JOBS.send() 投递消息
+//! binding = "JOBS"
+//! queue = "jobs"
+//!
+//! [[workers.durable_objects]]   # env.ROOMS.getByName("lobby") 访问导出的 Room 类的实例
+//! binding = "ROOMS"
+//! class_name = "Room"
+//!
+//! [[workers]]
+//! name = "auth"
+//! main = "auth.js"
+//!
+//! [[workers]]
+//! name = "jobs"
+//! main = "jobs.js"
+//!
+//! [[workers.queues.consumers]]   # 消息交给 queue(batch, env, ctx)
+//! queue = "jobs"
+//! max_batch_size = 10
+//! max_retries = 3
+//! dead_letter_queue = "jobs-failed"
+//! retry_delay = 30                # 秒
+//!
+//! [[workers]]
+//! name = "site"
+//! main = "site.js"
+//! routes = ["/*"]
+//! ```
+//!
+//! 相对路径（`main`、密钥文件、证书、资源目录）相对于清单文件所在目录。
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::access_log::AccessLogFormat;
+use super::bindings::{EvictionPolicy, FsyncPolicy, KvLimits};
+use super::compression::{CompressionConfig, ContentEncoding};
+use super::config::{
+    AssetsConfig, DurableObjectConfig, KvNamespaceConfig, QueueConsumerConfig, QueueProducerConfig, Secret, ServerConfig,
+    ServiceBindingConfig, WorkerConfig, WorkerLimits,
+};
+use super::proxy::TrustedProxies;
+use super::scheduler::MissedRunPolicy;
+use super::tls::{CertificateConfig, ClientAuth, TlsConfig};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerSection {
+    host: Option<String>,
+    port: Option<u16>,
+    missed_run_policy: Option<String>,
+    test_scheduled: Option<bool>,
+    access_log: Option<String>,
+    metrics: Option<bool>,
+    /// 关闭时等待进行中请求的最长时间（秒）
+    shutdown_timeout: Option<u64>,
+    #[serde(default)]
+    trusted_proxies: Vec<String>,
+    /// Cache API 的磁盘缓存目录
+    cache_dir: Option<String>,
+    /// 队列的持久化目录
+    queue_dir: Option<String>,
+    /// Durable Object 存储目录
+    durable_objects_dir: Option<String>,
+    tls: Option<TlsSection>,
+    compression: Option<CompressionSection>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CompressionSection {
+    enabled: Option<bool>,
+    min_size: Option<usize>,
+    encodings: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsSection {
+    cert: String,
+    key: String,
+    #[serde(default)]
+    certificates: Vec<CertificateSection>,
+    client_auth: Option<String>,
+    client_ca: Option<String>,
+    /// 检查证书变化的间隔（秒），0 表示不自动检查
+    reload_interval: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CertificateSection {
+    hostnames: Vec<String>,
+    cert: String,
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkerSection {
+    name: Option<String>,
+    main: String,
+    #[serde(default)]
+    routes: Vec<String>,
+    #[serde(default)]
+    vars: HashMap<String, toml::Value>,
+    #[serde(default)]
+    secrets: HashMap<String, SecretSource>,
+    #[serde(default)]
+    kv_namespaces: Vec<KvNamespaceSection>,
+    #[serde(default)]
+    triggers: TriggersSection,
+    #[serde(default)]
+    limits: LimitsSection,
+    #[serde(default)]
+    require_client_cert: bool,
+    assets: Option<AssetsSection>,
+    #[serde(default)]
+    services: Vec<ServiceSection>,
+    #[serde(default)]
+    queues: QueuesSection,
+    #[serde(default)]
+    durable_objects: Vec<DurableObjectSection>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DurableObjectSection {
+    binding: String,
+    class_name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QueuesSection {
+    #[serde(default)]
+    producers: Vec<QueueProducerSection>,
+    #[serde(default)]
+    consumers: Vec<QueueConsumerSection>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QueueProducerSection {
+    binding: String,
+    queue: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QueueConsumerSection {
+    queue: String,
+    max_batch_size: Option<usize>,
+    max_retries: Option<u32>,
+    dead_letter_queue: Option<String>,
+    /// 重试前的等待时间（秒）
+    retry_delay: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServiceSection {
+    binding: String,
+    service: String,
+    /// 单次调用的超时时间（毫秒）
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AssetsSection {
+    directory: String,
+    binding: Option<String>,
+    #[serde(default)]
+    run_worker_first: bool,
+}
+
+/// 密钥来源
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SecretSource {
+    /// 从环境变量读取
+    env: Option<String>,
+    /// 从文件读取（去掉末尾换行）
+    file: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KvNamespaceSection {
+    binding: String,
+    id: Option<String>,
+    backend: Option<String>,
+    /// `file` 后端的日志文件或 `sqlite` 后端的数据库路径
+    path: Option<String>,
+    fsync: Option<String>,
+    fsync_interval_ms: Option<u64>,
+    /// `memory` 后端最多保存的键数
+    max_entries: Option<usize>,
+    /// `memory` 后端最多占用的字节数
+    max_bytes: Option<u64>,
+    eviction: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TriggersSection {
+    #[serde(default)]
+    crons: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitsSection {
+    max_body_size: Option<usize>,
+    max_form_part_size: Option<usize>,
+    loop_iteration_limit: Option<u64>,
+    recursion_limit: Option<usize>,
+    stack_size_limit: Option<usize>,
+}
+
+impl ServerConfig {
+    /// 从清单文件加载配置
+    pub fn from_manifest(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read manifest {}: {}", path, e))?;
+        let base_dir = Path::new(path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        Self::from_manifest_str(&text, &base_dir)
+            .map_err(|e| format!("Invalid manifest {}: {}", path, e))
+    }
+
+    /// 从清单内容加载配置，相对路径基于 `base_dir` 解析
+    pub fn from_manifest_str(text: &str, base_dir: &Path) -> Result<Self, String> {
+        let mut table: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+
+        let server: ServerSection = match table.remove("server") {
+            Some(value) => value.try_into().map_err(|e| format!("[server]: {}", e))?,
+            None => ServerSection::default(),
+        };
+
+        // 有 [[workers]] 时为多 Worker 清单，否则整个文件描述一个 Worker
+        let sections: Vec<WorkerSection> = match table.remove("workers") {
+            Some(value) => {
+                if !table.is_empty() {
+                    let keys: Vec<&str> = table.keys().map(|k| k.as_str()).collect();
+                    return Err(format!(
+                        "Top-level worker fields are not allowed together with [[workers]]: {}",
+                        keys.join(", ")
+                    ));
+                }
+                value.try_into().map_err(|e| format!("[[workers]]: {}", e))?
+            }
+            None => vec![toml::Value::Table(table)
+                .try_into()
+                .map_err(|e| e.to_string())?],
+        };
+
+        let mut config = ServerConfig::default();
+        if let Some(host) = server.host {
+            config.host = host;
+        }
+        if let Some(port) = server.port {
+            config.port = port;
+        }
+        if let Some(policy) = server.missed_run_policy {
+            config.missed_run_policy = MissedRunPolicy::parse(&policy)?;
+        }
+        if let Some(test_scheduled) = server.test_scheduled {
+            config.test_scheduled = test_scheduled;
+        }
+        if let Some(format) = server.access_log {
+            config.access_log = AccessLogFormat::parse(&format)?;
+        }
+        if let Some(metrics) = server.metrics {
+            config.metrics = metrics;
+        }
+        if let Some(secs) = server.shutdown_timeout {
+            config.shutdown_timeout = std::time::Duration::from_secs(secs);
+        }
+        config.trusted_proxies =
+            TrustedProxies::parse(&server.trusted_proxies).map_err(|e| format!("[server]: {}", e))?;
+        config.cache_dir = server.cache_dir.map(|dir| resolve_path(base_dir, &dir));
+        config.queue_dir = server.queue_dir.map(|dir| resolve_path(base_dir, &dir));
+        config.durable_objects_dir = server.durable_objects_dir.map(|dir| resolve_path(base_dir, &dir));
+        if let Some(compression) = server.compression {
+            config.compression = compression.into_config()?;
+        }
+        if let Some(tls) = server.tls {
+            config.tls = Some(tls.into_tls_config(base_dir)?);
+        }
+
+        let single = sections.len() == 1;
+        for (index, section) in sections.into_iter().enumerate() {
+            let mut worker = section.into_worker_config(base_dir)?;
+            if worker.name.is_empty() {
+                worker.name = if single {
+                    "default".to_string()
+                } else {
+                    format!("worker-{}", index)
+                };
+            }
+            // 单 Worker 清单未声明路由时匹配所有请求
+            if single && worker.routes.is_empty() {
+                worker.routes.push("/*".to_string());
+            }
+            config.workers.push(worker);
+        }
+
+        Ok(config)
+    }
+}
+
+impl WorkerSection {
+    fn into_worker_config(self, base_dir: &Path) -> Result<WorkerConfig, String> {
+        let name = self.name.unwrap_or_default();
+
+        let mut vars = HashMap::new();
+        for (key, value) in self.vars {
+            let value = match value {
+                toml::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            vars.insert(key, value);
+        }
+
+        let mut secrets = HashMap::new();
+        for (key, source) in self.secrets {
+            let value = source
+                .resolve(base_dir)
+                .map_err(|e| format!("Secret {} of worker '{}': {}", key, name, e))?;
+            secrets.insert(key, Secret::new(&value));
+        }
+
+        let mut kv_namespaces = Vec::new();
+        for ns in self.kv_namespaces {
+            let fsync = match ns.fsync.as_deref() {
+                None | Some("always") => FsyncPolicy::Always,
+                Some("interval") => {
+                    FsyncPolicy::Interval(std::time::Duration::from_millis(ns.fsync_interval_ms.unwrap_or(1000)))
+                }
+                Some("never") => FsyncPolicy::Never,
+                Some(other) => {
+                    return Err(format!(
+                        "KV namespace {} of worker '{}': unknown fsync policy '{}'",
+                        ns.binding, name, other
+                    ))
+                }
+            };
+            let eviction = match ns.eviction.as_deref() {
+                None | Some("lru") => EvictionPolicy::Lru,
+                Some("lfu") => EvictionPolicy::Lfu,
+                Some(other) => {
+                    return Err(format!(
+                        "KV namespace {} of worker '{}': unknown eviction policy '{}'",
+                        ns.binding, name, other
+                    ))
+                }
+            };
+            let backend = ns.backend.unwrap_or_else(|| "memory".to_string());
+            let bounded = ns.max_entries.is_some() || ns.max_bytes.is_some() || ns.eviction.is_some();
+            if bounded && backend != "memory" {
+                return Err(format!(
+                    "KV namespace {} of worker '{}': max_entries, max_bytes and eviction only apply to the memory backend",
+                    ns.binding, name
+                ));
+            }
+            if ns.max_entries == Some(0) || ns.max_bytes == Some(0) {
+                return Err(format!(
+                    "KV namespace {} of worker '{}': max_entries and max_bytes must be positive",
+                    ns.binding, name
+                ));
+            }
+            kv_namespaces.push(KvNamespaceConfig {
+                id: ns.id.unwrap_or_else(|| ns.binding.clone()),
+                binding: ns.binding,
+                backend,
+                path: ns.path.map(|path| resolve_path(base_dir, &path)),
+                fsync,
+                limits: KvLimits {
+                    max_entries: ns.max_entries,
+                    max_bytes: ns.max_bytes,
+                    eviction,
+                },
+            });
+        }
+
+        let mut queue_consumers = Vec::new();
+        for consumer in self.queues.consumers {
+            let defaults = QueueConsumerConfig::new(&consumer.queue);
+            if consumer.max_batch_size == Some(0) {
+                return Err(format!(
+                    "Queue consumer '{}' of worker '{}': max_batch_size must be positive",
+                    consumer.queue, name
+                ));
+            }
+            queue_consumers.push(QueueConsumerConfig {
+                max_batch_size: consumer.max_batch_size.unwrap_or(defaults.max_batch_size),
+                max_retries: consumer.max_retries.unwrap_or(defaults.max_retries),
+                dead_letter_queue: consumer.dead_letter_queue,
+                retry_delay: consumer
+                    .retry_delay
+                    .map(std::time::Duration::from_secs)
+                    .unwrap_or(defaults.retry_delay),
+                queue: consumer.queue,
+            });
+        }
+
+        let defaults = WorkerLimits::default();
+        let limits = WorkerLimits {
+            max_body_size: self.limits.max_body_size.unwrap_or(defaults.max_body_size),
+            max_form_part_size: self
+                .limits
+                .max_form_part_size
+                .unwrap_or(defaults.max_form_part_size),
+            loop_iteration_limit: self.limits.loop_iteration_limit,
+            recursion_limit: self.limits.recursion_limit,
+            stack_size_limit: self.limits.stack_size_limit,
+        };
+
+        Ok(WorkerConfig {
+            name,
+            script_path: resolve_path(base_dir, &self.main),
+            routes: self.routes,
+            crons: self.triggers.crons,
+            limits,
+            vars,
+            secrets,
+            kv_namespaces,
+            require_client_cert: self.require_client_cert,
+            assets: self.assets.map(|assets| AssetsConfig {
+                directory: resolve_path(base_dir, &assets.directory),
+                binding: assets.binding.unwrap_or_else(|| "ASSETS".to_string()),
+                run_worker_first: assets.run_worker_first,
+            }),
+            services: self
+                .services
+                .into_iter()
+                .map(|s| ServiceBindingConfig {
+                    binding: s.binding,
+                    service: s.service,
+                    timeout: s.timeout_ms.map(std::time::Duration::from_millis),
+                })
+                .collect(),
+            queue_producers: self
+                .queues
+                .producers
+                .into_iter()
+                .map(|p| QueueProducerConfig {
+                    binding: p.binding,
+                    queue: p.queue,
+                })
+                .collect(),
+            queue_consumers,
+            durable_objects: self
+                .durable_objects
+                .into_iter()
+                .map(|d| DurableObjectConfig {
+                    binding: d.binding,
+                    class_name: d.class_name,
+                })
+                .collect(),
+        })
+    }
+}
+
+impl CompressionSection {
+    fn into_config(self) -> Result<CompressionConfig, String> {
+        let defaults = CompressionConfig::default();
+        let encodings = match self.encodings {
+            Some(names) => names
+                .iter()
+                .map(|name| ContentEncoding::parse(name))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("[server.compression]: {}", e))?,
+            None => defaults.encodings,
+        };
+        Ok(CompressionConfig {
+            enabled: self.enabled.unwrap_or(defaults.enabled),
+            min_size: self.min_size.unwrap_or(defaults.min_size),
+            encodings,
+        })
+    }
+}
+
+impl TlsSection {
+    fn into_tls_config(self, base_dir: &Path) -> Result<TlsConfig, String> {
+        let mut tls = TlsConfig::new(&resolve_path(base_dir, &self.cert), &resolve_path(base_dir, &self.key));
+
+        for cert in self.certificates {
+            tls.certificates.push(CertificateConfig {
+                hostnames: cert.hostnames,
+                cert_path: resolve_path(base_dir, &cert.cert),
+                key_path: resolve_path(base_dir, &cert.key),
+            });
+        }
+
+        if let Some(mode) = self.client_auth {
+            tls.client_auth = ClientAuth::parse(&mode)?;
+        }
+        tls.client_ca_path = self.client_ca.map(|ca| resolve_path(base_dir, &ca));
+        if tls.client_auth != ClientAuth::None && tls.client_ca_path.is_none() {
+            return Err("[server.tls]: client_auth requires client_ca".to_string());
+        }
+
+        if let Some(secs) = self.reload_interval {
+            tls.reload_interval = (secs > 0).then(|| std::time::Duration::from_secs(secs));
+        }
+
+        Ok(tls)
+    }
+}
+
+impl SecretSource {
+    fn resolve(&self, base_dir: &Path) -> Result<String, String> {
+        match (&self.env, &self.file) {
+            (Some(var), None) => std::env::var(var)
+                .map_err(|_| format!("environment variable {} is not set", var)),
+            (None, Some(file)) => {
+                let path = resolve_path(base_dir, file);
+                fs::read_to_string(&path)
+                    .map(|s| s.trim_end_matches(['\r', '\n']).to_string())
+                    .map_err(|e| format!("failed to read {}: {}", path, e))
+            }
+            _ => Err("exactly one of `env` or `file` must be set".to_string()),
+        }
+    }
+}
+
+fn resolve_path(base_dir: &Path, path: &str) -> String {
+    let p = PathBuf::from(path);
+    if p.is_absolute() {
+        path.to_string()
+    } else {
+        base_dir.join(p).to_string_lossy().into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_worker_manifest() {
+        std::env::set_var("RAVEN_TEST_MANIFEST_TOKEN", "s3cret");
+
+        let manifest = r#"
+            name = "api"
+            main = "worker.js"
+
+            [vars]
+            API_BASE = "https://internal.example.com"
+            RETRIES = 3
+
+            [secrets]
+            TOKEN = { env = "RAVEN_TEST_MANIFEST_TOKEN" }
+
+            [[kv_namespaces]]
+            binding = "CACHE"
+            id = "api-cache"
+            max_entries = 500
+            eviction = "lfu"
+
+            [[kv_namespaces]]
+            binding = "SESSIONS"
+            backend = "file"
+            path = "data/sessions.kvlog"
+            fsync = "interval"
+            fsync_interval_ms = 250
+
+            [[kv_namespaces]]
+            binding = "CATALOG"
+            backend = "sqlite"
+            path = "data/catalog.sqlite"
+
+            [triggers]
+            crons = ["*/5 * * * *"]
+
+            [assets]
+            directory = "public"
+        "#;
+
+        let config = ServerConfig::from_manifest_str(manifest, Path::new("/srv/raven")).unwrap();
+        assert_eq!(config.workers.len(), 1);
+
+        let worker = &config.workers[0];
+        assert_eq!(worker.name, "api");
+        assert_eq!(worker.script_path, "/srv/raven/worker.js");
+        assert_eq!(worker.routes, vec!["/*"]);
+        assert_eq!(worker.vars["API_BASE"], "https://internal.example.com");
+        assert_eq!(worker.vars["RETRIES"], "3");
+        assert_eq!(worker.secrets["TOKEN"].expose(), "s3cret");
+        assert_eq!(
+            worker.kv_namespaces,
+            vec![
+                KvNamespaceConfig::memory("CACHE", "api-cache").with_limits(KvLimits {
+                    max_entries: Some(500),
+                    max_bytes: None,
+                    eviction: EvictionPolicy::Lfu,
+                }),
+                KvNamespaceConfig {
+                    fsync: FsyncPolicy::Interval(std::time::Duration::from_millis(250)),
+                    ..KvNamespaceConfig::file("SESSIONS", "SESSIONS", "/srv/raven/data/sessions.kvlog")
+                },
+                KvNamespaceConfig::sqlite("CATALOG", "CATALOG", "/srv/raven/data/catalog.sqlite"),
+            ]
+        );
+        assert_eq!(worker.crons, vec!["*/5 * * * *"]);
+        assert_eq!(worker.assets, Some(AssetsConfig::new("/srv/raven/public")));
+
+        // 密钥不会出现在 Debug 输出中
+        assert!(!format!("{:?}", worker).contains("s3cret"));
+    }
+
+    #[test]
+    fn test_multi_worker_manifest() {
+        let manifest = r#"
+            [server]
+            host = "0.0.0.0"
+            port = 9000
+            missed_run_policy = "run_all"
+            access_log = "json"
+            metrics = true
+            shutdown_timeout = 5
+            trusted_proxies = ["10.0.0.0/8"]
+            cache_dir = "cache"
+            queue_dir = "queues"
+            durable_objects_dir = "objects"
+
+            [server.compression]
+            min_size = 256
+            encodings = ["gzip"]
+
+            [[workers]]
+            name = "api"
+            main = "/opt/api.js"
+            routes = ["/api/*"]
+
+            [workers.limits]
+            max_body_size = 1024
+            max_form_part_size = 512
+
+            [[workers.services]]
+            binding = "SITE"
+            service = "site"
+            timeout_ms = 250
+
+            [[workers.queues.producers]]
+            binding = "JOBS"
+            queue = "jobs"
+
+            [[workers.durable_objects]]
+            binding = "ROOMS"
+            class_name = "Room"
+
+            [[workers]]
+            name = "site"
+            main = "site.js"
+            routes = ["/*"]
+
+            [[workers.queues.consumers]]
+            queue = "jobs"
+            max_retries = 5
+            dead_letter_queue = "jobs-failed"
+            retry_delay = 30
+        "#;
+
+        let config = ServerConfig::from_manifest_str(manifest, Path::new("conf")).unwrap();
+        assert_eq!(config.addr(), "0.0.0.0:9000");
+        assert_eq!(config.missed_run_policy, MissedRunPolicy::RunAll);
+        assert_eq!(config.access_log, AccessLogFormat::Json);
+        assert!(config.metrics);
+        assert_eq!(config.shutdown_timeout, std::time::Duration::from_secs(5));
+        assert!(config.trusted_proxies.contains("10.1.2.3".parse().unwrap()));
+        assert_eq!(config.cache_dir.as_deref(), Some("conf/cache"));
+        assert_eq!(config.queue_dir.as_deref(), Some("conf/queues"));
+        assert_eq!(config.durable_objects_dir.as_deref(), Some("conf/objects"));
+        assert!(config.compression.enabled);
+        assert_eq!(config.compression.min_size, 256);
+        assert_eq!(config.compression.encodings, vec![ContentEncoding::Gzip]);
+        assert_eq!(config.workers.len(), 2);
+        assert_eq!(config.workers[0].script_path, "/opt/api.js");
+        assert_eq!(config.workers[0].limits.max_body_size, 1024);
+        assert_eq!(config.workers[0].limits.max_form_part_size, 512);
+        assert_eq!(
+            config.workers[0].services,
+            vec![ServiceBindingConfig {
+                timeout: Some(std::time::Duration::from_millis(250)),
+                ..ServiceBindingConfig::new("SITE", "site")
+            }]
+        );
+        assert_eq!(
+            config.workers[0].queue_producers,
+            vec![QueueProducerConfig::new("JOBS", "jobs")]
+        );
+        assert_eq!(
+            config.workers[0].durable_objects,
+            vec![DurableObjectConfig::new("ROOMS", "Room")]
+        );
+        assert_eq!(config.workers[1].limits, WorkerLimits::default());
+        assert_eq!(
+            config.workers[1].queue_consumers,
+            vec![QueueConsumerConfig {
+                max_retries: 5,
+                retry_delay: std::time::Duration::from_secs(30),
+                ..QueueConsumerConfig::new("jobs").with_dead_letter_queue("jobs-failed")
+            }]
+        );
+        assert_eq!(config.workers[1].script_path, "conf/site.js");
+    }
+
+    #[test]
+    fn test_tls_manifest() {
+        let manifest = r#"
+            [server]
+            port = 8443
+
+            [server.tls]
+            cert = "certs/default.crt"
+            key = "certs/default.key"
+            client_auth = "optional"
+            client_ca = "/etc/raven/ca.crt"
+            reload_interval = 0
+
+            [[server.tls.certificates]]
+            hostnames = ["api.example.com"]
+            cert = "certs/api.crt"
+            key = "certs/api.key"
+
+            [[workers]]
+            name = "internal"
+            main = "internal.js"
+            routes = ["internal.example.com"]
+            require_client_cert = true
+        "#;
+
+        let config = ServerConfig::from_manifest_str(manifest, Path::new("/srv")).unwrap();
+        let tls = config.tls.unwrap();
+        assert_eq!(tls.cert_path, "/srv/certs/default.crt");
+        assert_eq!(tls.client_auth, ClientAuth::Optional);
+        assert_eq!(tls.client_ca_path.as_deref(), Some("/etc/raven/ca.crt"));
+        assert_eq!(tls.reload_interval, None);
+        assert_eq!(tls.certificates[0].hostnames, vec!["api.example.com"]);
+        assert_eq!(tls.certificates[0].key_path, "/srv/certs/api.key");
+        assert!(config.workers[0].require_client_cert);
+
+        let missing_ca = "main = \"a.js\"\n[server.tls]\ncert = \"a\"\nkey = \"b\"\nclient_auth = \"required\"";
+        assert!(ServerConfig::from_manifest_str(missing_ca, Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        // 缺少 main
+        assert!(ServerConfig::from_manifest_str("name = \"x\"", Path::new(".")).is_err());
+        // 未知字段
+        assert!(ServerConfig::from_manifest_str("main = \"a.js\"\nroute = \"/\"", Path::new(".")).is_err());
+        // 密钥来源不明确
+        let manifest = "main = \"a.js\"\n[secrets]\nA = {}";
+        assert!(ServerConfig::from_manifest_str(manifest, Path::new(".")).is_err());
+        // 缺失的环境变量
+        let manifest = "main = \"a.js\"\n[secrets]\nA = { env = \"RAVEN_TEST_MISSING_SECRET\" }";
+        assert!(ServerConfig::from_manifest_str(manifest, Path::new(".")).is_err());
+        // 容量限制只适用于内存命名空间
+        let manifest = "main = \"a.js\"\n[[kv_namespaces]]\nbinding = \"A\"\nbackend = \"sqlite\"\npath = \"a.db\"\nmax_entries = 1";
+        assert!(ServerConfig::from_manifest_str(manifest, Path::new(".")).is_err());
+        let manifest = "main = \"a.js\"\n[[kv_namespaces]]\nbinding = \"A\"\neviction = \"random\"";
+        assert!(ServerConfig::from_manifest_str(manifest, Path::new(".")).is_err());
+    }
+}
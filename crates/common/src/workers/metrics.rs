@@ -0,0 +1,263 @@
+//! 服务器指标
+//!
+//! 按 Worker 汇总请求数、请求耗时直方图、脚本错误和资源限制违规，
+//! 由 `WorkerServer` 通过 `/__raven/metrics` 以 Prometheus 文本格式导出。
+//! 绑定调用次数记录在各 Worker 的绑定注册表中，导出时一并输出。
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// 请求耗时直方图的桶上限（秒）
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// 作为 `method` 标签的请求方法，其他方法记为 `OTHER`，避免客户端制造任意多的标签值
+const KNOWN_METHODS: [&str; 9] = ["GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH"];
+
+/// 一个 Worker 的绑定调用次数：(Worker, [(绑定, 方法, 次数)])
+pub type WorkerBindingCalls = (String, Vec<(String, String, u64)>);
+
+/// 单个 Worker 的指标
+#[derive(Debug, Default)]
+struct WorkerMetrics {
+    /// (方法, 状态码) -> 请求数
+    requests: BTreeMap<(&'static str, u16), u64>,
+    /// 落入各桶的请求数（非累计，最后一项为 +Inf）
+    latency_buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    latency_sum: f64,
+    latency_count: u64,
+    /// 处理函数（fetch / scheduled / websocket）-> 错误数
+    script_errors: BTreeMap<String, u64>,
+    /// 限制类型 -> 违规次数
+    limit_violations: BTreeMap<&'static str, u64>,
+}
+
+/// 服务器指标
+#[derive(Debug, Default)]
+pub struct Metrics {
+    workers: BTreeMap<String, WorkerMetrics>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn worker(&mut self, worker: &str) -> &mut WorkerMetrics {
+        self.workers.entry(worker.to_string()).or_default()
+    }
+
+    /// 记录一个已完成的请求
+    pub fn record_request(&mut self, worker: &str, method: &str, status: u16, latency: Duration) {
+        let metrics = self.worker(worker);
+        *metrics
+            .requests
+            .entry((method_label(method), status))
+            .or_insert(0) += 1;
+
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        metrics.latency_buckets[bucket] += 1;
+        metrics.latency_sum += seconds;
+        metrics.latency_count += 1;
+    }
+
+    /// 记录 Worker 处理函数返回的错误
+    ///
+    /// 由资源限制引起的错误记为限制违规，其余记为脚本错误
+    pub fn record_error(&mut self, worker: &str, handler: &str, error: &str) {
+        match limit_violation(error) {
+            Some(kind) => self.record_limit_violation(worker, kind),
+            None => {
+                *self
+                    .worker(worker)
+                    .script_errors
+                    .entry(handler.to_string())
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// 记录一次资源限制违规（如 `max_body_size`）
+    pub fn record_limit_violation(&mut self, worker: &str, kind: &'static str) {
+        *self.worker(worker).limit_violations.entry(kind).or_insert(0) += 1;
+    }
+
+    /// 以 Prometheus 文本格式导出，`binding_calls` 为各 Worker 的绑定调用次数
+    pub fn render(&self, binding_calls: &[WorkerBindingCalls]) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP raven_worker_requests_total Requests handled by each worker.\n");
+        out.push_str("# TYPE raven_worker_requests_total counter\n");
+        for (worker, metrics) in &self.workers {
+            for ((method, status), count) in &metrics.requests {
+                writeln!(
+                    out,
+                    "raven_worker_requests_total{{worker=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                    escape_label(worker),
+                    escape_label(method),
+                    status,
+                    count
+                )
+                .ok();
+            }
+        }
+
+        out.push_str("# HELP raven_worker_request_duration_seconds Time from receiving a request to producing its response.\n");
+        out.push_str("# TYPE raven_worker_request_duration_seconds histogram\n");
+        for (worker, metrics) in &self.workers {
+            let worker = escape_label(worker);
+            let mut cumulative = 0;
+            for (i, count) in metrics.latency_buckets.iter().enumerate() {
+                cumulative += count;
+                let le = LATENCY_BUCKETS
+                    .get(i)
+                    .map(|le| le.to_string())
+                    .unwrap_or_else(|| "+Inf".to_string());
+                writeln!(
+                    out,
+                    "raven_worker_request_duration_seconds_bucket{{worker=\"{}\",le=\"{}\"}} {}",
+                    worker, le, cumulative
+                )
+                .ok();
+            }
+            writeln!(
+                out,
+                "raven_worker_request_duration_seconds_sum{{worker=\"{}\"}} {}",
+                worker, metrics.latency_sum
+            )
+            .ok();
+            writeln!(
+                out,
+                "raven_worker_request_duration_seconds_count{{worker=\"{}\"}} {}",
+                worker, metrics.latency_count
+            )
+            .ok();
+        }
+
+        out.push_str("# HELP raven_worker_script_errors_total Uncaught errors thrown by worker handlers.\n");
+        out.push_str("# TYPE raven_worker_script_errors_total counter\n");
+        for (worker, metrics) in &self.workers {
+            for (handler, count) in &metrics.script_errors {
+                writeln!(
+                    out,
+                    "raven_worker_script_errors_total{{worker=\"{}\",handler=\"{}\"}} {}",
+                    escape_label(worker),
+                    escape_label(handler),
+                    count
+                )
+                .ok();
+            }
+        }
+
+        out.push_str("# HELP raven_worker_limit_violations_total Requests rejected or aborted by worker limits.\n");
+        out.push_str("# TYPE raven_worker_limit_violations_total counter\n");
+        for (worker, metrics) in &self.workers {
+            for (kind, count) in &metrics.limit_violations {
+                writeln!(
+                    out,
+                    "raven_worker_limit_violations_total{{worker=\"{}\",limit=\"{}\"}} {}",
+                    escape_label(worker),
+                    kind,
+                    count
+                )
+                .ok();
+            }
+        }
+
+        out.push_str("# HELP raven_worker_binding_calls_total Calls made from worker scripts to native bindings.\n");
+        out.push_str("# TYPE raven_worker_binding_calls_total counter\n");
+        for (worker, calls) in binding_calls {
+            for (binding, method, count) in calls {
+                writeln!(
+                    out,
+                    "raven_worker_binding_calls_total{{worker=\"{}\",binding=\"{}\",method=\"{}\"}} {}",
+                    escape_label(worker),
+                    escape_label(binding),
+                    escape_label(method),
+                    count
+                )
+                .ok();
+            }
+        }
+
+        out
+    }
+}
+
+/// 根据错误信息判断是否由运行时限制引起，返回限制类型
+pub fn limit_violation(error: &str) -> Option<&'static str> {
+    if error.contains("Maximum loop iteration limit") {
+        Some("loop_iteration_limit")
+    } else if error.contains("exceeded maximum number of recursive calls") {
+        Some("recursion_limit")
+    } else if error.contains("exceeded maximum call stack length") {
+        Some("stack_size_limit")
+    } else {
+        None
+    }
+}
+
+/// 请求方法对应的 `method` 标签值
+fn method_label(method: &str) -> &'static str {
+    KNOWN_METHODS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(method))
+        .copied()
+        .unwrap_or("OTHER")
+}
+
+/// 转义 Prometheus 标签值中的 `\`、`"` 和换行
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let mut metrics = Metrics::new();
+        metrics.record_request("api", "GET", 200, Duration::from_millis(3));
+        metrics.record_request("api", "GET", 200, Duration::from_millis(30));
+        metrics.record_request("api", "POST", 500, Duration::from_secs(20));
+        metrics.record_request("api", "post", 500, Duration::from_secs(20));
+        metrics.record_request("api", "X-RANDOM-1", 404, Duration::from_millis(3));
+        metrics.record_request("api", "X-RANDOM-2", 404, Duration::from_millis(3));
+        metrics.record_error("api", "fetch", "TypeError: x is undefined");
+        metrics.record_error("api", "fetch", "RuntimeLimit: Maximum loop iteration limit 10 exceeded");
+        metrics.record_limit_violation("api", "max_body_size");
+
+        let calls = vec![(
+            "api".to_string(),
+            vec![("KV".to_string(), "get".to_string(), 4)],
+        )];
+        let text = metrics.render(&calls);
+
+        assert!(text.contains("raven_worker_requests_total{worker=\"api\",method=\"GET\",status=\"200\"} 2\n"));
+        assert!(text.contains("raven_worker_requests_total{worker=\"api\",method=\"POST\",status=\"500\"} 2\n"));
+        assert!(text.contains("raven_worker_requests_total{worker=\"api\",method=\"OTHER\",status=\"404\"} 2\n"));
+        assert!(!text.contains("X-RANDOM"));
+        assert!(text.contains("raven_worker_request_duration_seconds_bucket{worker=\"api\",le=\"0.005\"} 3\n"));
+        assert!(text.contains("raven_worker_request_duration_seconds_bucket{worker=\"api\",le=\"0.05\"} 4\n"));
+        assert!(text.contains("raven_worker_request_duration_seconds_bucket{worker=\"api\",le=\"10\"} 4\n"));
+        assert!(text.contains("raven_worker_request_duration_seconds_bucket{worker=\"api\",le=\"+Inf\"} 6\n"));
+        assert!(text.contains("raven_worker_request_duration_seconds_count{worker=\"api\"} 6\n"));
+        assert!(text.contains("raven_worker_script_errors_total{worker=\"api\",handler=\"fetch\"} 1\n"));
+        assert!(text.contains("raven_worker_limit_violations_total{worker=\"api\",limit=\"loop_iteration_limit\"} 1\n"));
+        assert!(text.contains("raven_worker_limit_violations_total{worker=\"api\",limit=\"max_body_size\"} 1\n"));
+        assert!(text.contains("raven_worker_binding_calls_total{worker=\"api\",binding=\"KV\",method=\"get\"} 4\n"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -1,13 +1,56 @@
 //! Workers 模块 - HTTP Server + Fetch 入口
 //!
 //! 基于核心 runtime，提供 Cloudflare Workers 风格的 HTTP 服务器。
-//! 使用 `export default { fetch() }` 作为入口。
+//! 使用 `export default { fetch() }` 作为入口，
+//! 配置 cron 触发器后还会调用 `scheduled()` 入口，队列 Consumer 的消息交给 `queue()` 入口。
+//! 一个服务器可以按路由和主机名挂载多个 Worker，
+//! Worker 的变量、密钥和绑定可以通过 TOML 清单声明，Worker 之间可通过 Service 绑定在进程内互相调用，
+//! 导出的类可作为 Durable Object 按名称寻址，同一实例的请求依次执行并拥有持久化的事务存储，
+//! 并可直接终止 TLS（HTTPS）、接受 WebSocket 连接、解析表单、用 `HTMLRewriter` 改写 HTML 和提供静态资源。
+//! Worker 可通过 `caches` 共享 HTTP 缓存，响应按 `Accept-Encoding` 自动压缩；服务器输出访问日志，并可通过 `/__raven/metrics` 导出 Prometheus 指标；
+//! 支持优雅关闭和不中断监听的重新加载。
 
+mod access_log;
+mod assets;
+mod cache;
+mod compression;
 pub mod bindings;
+mod config;
+mod connection;
+mod cron;
+mod durable;
+mod form_data;
+mod html_rewriter;
 mod http;
+mod manifest;
+mod proxy;
+mod queue_consumer;
+mod metrics;
+mod router;
+mod scheduler;
+mod service;
+mod tls;
+mod websocket;
 mod workers_runtime;
 mod server;
 
-pub use http::{HttpRequest, HttpResponse};
+pub use access_log::AccessLogFormat;
+pub use assets::AssetStore;
+pub use cache::{CacheBackend, CacheRequest, CachedResponse, DiskCacheBackend, HttpCache, MemoryCacheBackend};
+pub use compression::{CompressionConfig, ContentEncoding};
+pub use config::{
+    AssetsConfig, DurableObjectConfig, KvNamespaceConfig, QueueConsumerConfig, QueueProducerConfig, Secret, ServerConfig,
+    ServiceBindingConfig, WorkerConfig, WorkerLimits,
+};
+pub use cron::CronSchedule;
+pub use durable::DurableStore;
+pub use html_rewriter::{Content, HandlerSpec, HtmlRewriter, Mutations};
+pub use http::{FormPart, HttpRequest, HttpResponse, MultipartParser, RequestUrl};
+pub use proxy::TrustedProxies;
+pub use queue_consumer::MessageOutcome;
+pub use router::{RoutePattern, Router};
+pub use scheduler::{MissedRunPolicy, ScheduledRun, Scheduler};
+pub use tls::{CertificateConfig, ClientAuth, TlsAcceptor, TlsConfig, TlsInfo};
+pub use websocket::{Message, WebSocketEvent};
 pub use workers_runtime::WorkersRuntime;
-pub use server::{serve, serve_script, ServerConfig, WorkerServer};
+pub use server::{serve, serve_script, ServerHandle, WorkerServer};
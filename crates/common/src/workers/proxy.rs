@@ -0,0 +1,106 @@
+//! 受信任的反向代理
+//!
+//! 只有来自受信任代理的请求才会采用 `X-Forwarded-Proto`、`X-Forwarded-Host`
+//! 和 `X-Forwarded-For` 头，否则客户端可以随意伪造自己的地址和请求的 URL。
+
+use std::net::IpAddr;
+
+/// 一个 IP 地址或 CIDR 网段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// 解析 `10.0.0.0/8`、`::1` 等形式
+    fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid proxy address: '{}'", value);
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.trim().parse::<u8>().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 映射的 IPv6 地址（::ffff:a.b.c.d）按 IPv4 比较
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// 受信任的代理列表，默认为空（不信任任何转发头）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    networks: Vec<IpNetwork>,
+}
+
+impl TrustedProxies {
+    /// 不信任任何代理
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// 从地址或 CIDR 列表解析，如 `["127.0.0.1", "10.0.0.0/8"]`
+    pub fn parse<S: AsRef<str>>(values: &[S]) -> Result<Self, String> {
+        let networks = values
+            .iter()
+            .map(|v| IpNetwork::parse(v.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { networks })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+
+    /// 地址是否属于受信任的代理
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|net| net.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trusted_proxies() {
+        let proxies = TrustedProxies::parse(&["10.0.0.0/8", "192.168.1.1", "fd00::/8"]).unwrap();
+
+        assert!(proxies.contains("10.20.30.40".parse().unwrap()));
+        assert!(proxies.contains("192.168.1.1".parse().unwrap()));
+        assert!(!proxies.contains("192.168.1.2".parse().unwrap()));
+        assert!(proxies.contains("fd12::1".parse().unwrap()));
+        assert!(proxies.contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(!proxies.contains("8.8.8.8".parse().unwrap()));
+
+        assert!(TrustedProxies::parse(&["0.0.0.0/0"]).unwrap().contains("1.2.3.4".parse().unwrap()));
+        assert!(TrustedProxies::none().is_empty());
+        assert!(TrustedProxies::parse(&["10.0.0.0/33"]).is_err());
+        assert!(TrustedProxies::parse(&["localhost"]).is_err());
+    }
+}
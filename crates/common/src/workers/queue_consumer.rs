@@ -0,0 +1,202 @@
+//! 队列 Consumer
+//!
+//! 把队列中的消息按批交给 Worker 的 `queue(batch, env, ctx)` 入口。
+//! `message.ack()` / `message.retry()` 单独确认或重试一条消息，
+//! `batch.ackAll()` / `batch.retryAll()` 处理其余消息；都没有调用时，
+//! 处理函数成功则确认、抛出异常则重试。
+//! 交付次数超过 `max_retries + 1` 的消息转入死信队列，没有配置死信队列时丢弃。
+//! `retry()` 的 `delaySeconds` 不合法时整批消息按处理失败重试。
+
+use std::time::Duration;
+
+use boa_engine::{js_string, Context, JsNativeError, JsResult, JsValue, Source};
+use serde::Deserialize;
+
+use super::bindings::{parse_delay_seconds, QueueMessage, QueueRegistry};
+use super::config::QueueConsumerConfig;
+use super::workers_runtime::WorkersRuntime;
+
+/// 消息交给 Consumer 后等待确认的时间，超时（如进程崩溃）后重新投递
+const LEASE: Duration = Duration::from_secs(300);
+
+const QUEUE_JS: &str = r#"
+(function() {
+    class Message {
+        constructor(message) {
+            this.id = message.id;
+            this.timestamp = new Date(message.timestamp);
+            this.body = message.body;
+            this.attempts = message.attempts;
+            this._outcome = null;
+        }
+
+        ack() {
+            this._outcome = { ack: true };
+        }
+
+        retry(options) {
+            this._outcome = { ack: false, delaySeconds: options && options.delaySeconds };
+        }
+    }
+
+    class MessageBatch {
+        constructor(queue, messages) {
+            this.queue = queue;
+            this.messages = messages;
+            this._outcome = null;
+        }
+
+        ackAll() {
+            this._outcome = { ack: true };
+        }
+
+        retryAll(options) {
+            this._outcome = { ack: false, delaySeconds: options && options.delaySeconds };
+        }
+    }
+
+    globalThis.__raven_queue_batch = function(queue, messages) {
+        return new MessageBatch(queue, JSON.parse(messages).map(function(m) {
+            return new Message(m);
+        }));
+    };
+
+    globalThis.__raven_queue_outcomes = function(batch) {
+        return JSON.stringify(batch.messages.map(function(m) {
+            return m._outcome || batch._outcome;
+        }));
+    };
+})();
+"#;
+
+/// Consumer 对一条消息的处理结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageOutcome {
+    Ack,
+    /// 重试，`None` 时使用 Consumer 配置的 `retry_delay`
+    Retry(Option<Duration>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsOutcome {
+    ack: bool,
+    delay_seconds: Option<f64>,
+}
+
+/// 注册 `queue()` 入口使用的 `MessageBatch` 和 `Message`
+pub fn register_globals(context: &mut Context) -> Result<(), String> {
+    context
+        .eval(Source::from_bytes(QUEUE_JS))
+        .map_err(|e| format!("Failed to install queue consumer: {}", e))?;
+    Ok(())
+}
+
+/// 创建传给 `queue()` 的 `MessageBatch`
+pub fn create_batch(queue: &str, messages: &[QueueMessage], context: &mut Context) -> JsResult<JsValue> {
+    let json = serde_json::to_string(messages)
+        .map_err(|e| JsNativeError::error().with_message(e.to_string()))?;
+    call_global(
+        "__raven_queue_batch",
+        &[JsValue::from(js_string!(queue)), JsValue::from(js_string!(json))],
+        context,
+    )
+}
+
+/// 读取 Worker 对每条消息显式指定的处理结果
+pub fn read_outcomes(batch: &JsValue, context: &mut Context) -> JsResult<Vec<Option<MessageOutcome>>> {
+    let json = call_global("__raven_queue_outcomes", std::slice::from_ref(batch), context)?
+        .to_string(context)?
+        .to_std_string_escaped();
+    let outcomes: Vec<Option<JsOutcome>> = serde_json::from_str(&json)
+        .map_err(|e| JsNativeError::error().with_message(e.to_string()))?;
+    outcomes
+        .into_iter()
+        .map(|outcome| {
+            outcome
+                .map(|o| {
+                    if o.ack {
+                        return Ok(MessageOutcome::Ack);
+                    }
+                    let delay = o
+                        .delay_seconds
+                        .map(parse_delay_seconds)
+                        .transpose()
+                        .map_err(|e| JsNativeError::range().with_message(e))?;
+                    Ok(MessageOutcome::Retry(delay))
+                })
+                .transpose()
+        })
+        .collect()
+}
+
+fn call_global(name: &str, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let function = context.global_object().get(js_string!(name), context)?;
+    let function = function
+        .as_callable()
+        .ok_or_else(|| JsNativeError::typ().with_message(format!("{} is not installed", name)))?
+        .clone();
+    function.call(&JsValue::undefined(), args, context)
+}
+
+/// 从队列取出一批消息交给 Worker，并按处理结果确认、重试或转入死信队列
+///
+/// 返回交付的消息数，队列为空时为 0；Worker 处理失败时返回其错误（消息已按重试处理）。
+/// 转入死信队列或重试失败的消息保留在队列中，租期过后重新投递，其余消息照常确认后再返回错误
+pub fn consume(
+    runtime: &mut WorkersRuntime,
+    consumer: &QueueConsumerConfig,
+    queues: &QueueRegistry,
+) -> Result<usize, String> {
+    let queue = queues.get(&consumer.queue)?;
+    let messages = queue.receive(consumer.max_batch_size, LEASE)?;
+    if messages.is_empty() {
+        return Ok(0);
+    }
+
+    let (outcomes, result) = runtime.handle_queue(&consumer.queue, &messages);
+
+    let mut acked = Vec::new();
+    let mut retries = Vec::new();
+    let mut error = None;
+    for (index, message) in messages.iter().enumerate() {
+        let outcome = outcomes.get(index).copied().flatten().unwrap_or(match result {
+            Ok(()) => MessageOutcome::Ack,
+            Err(_) => MessageOutcome::Retry(None),
+        });
+        match outcome {
+            MessageOutcome::Ack => acked.push(message.id.clone()),
+            MessageOutcome::Retry(_) if message.attempts > consumer.max_retries => {
+                match &consumer.dead_letter_queue {
+                    Some(dlq) => {
+                        let sent = queues
+                            .get(dlq)
+                            .and_then(|dlq| dlq.send(message.body.clone(), Duration::ZERO));
+                        if let Err(e) = sent {
+                            error.get_or_insert(format!(
+                                "Failed to move message {} to dead letter queue {}: {}",
+                                message.id, dlq, e
+                            ));
+                            continue;
+                        }
+                    }
+                    None => eprintln!(
+                        "⚠️  Dropping message {} from queue {} after {} attempts",
+                        message.id, consumer.queue, message.attempts
+                    ),
+                }
+                acked.push(message.id.clone());
+            }
+            MessageOutcome::Retry(delay) => {
+                retries.push((message.id.clone(), delay.unwrap_or(consumer.retry_delay)));
+            }
+        }
+    }
+    // 确认和重试一起写回，持久化队列每批只重写一次文件
+    queue.settle(&acked, &retries)?;
+
+    match error {
+        Some(e) => Err(e),
+        None => result.map(|()| messages.len()),
+    }
+}
@@ -0,0 +1,188 @@
+//! Worker 路由
+//!
+//! 根据路由规则和 `Host` 头把请求分发到对应的 Worker。
+//!
+//! # 路由规则
+//!
+//! - `/api/*`：匹配任意主机下以 `/api/` 开头的路径
+//! - `/health`：精确匹配路径
+//! - `api.example.com`：匹配该主机下的所有路径
+//! - `*.example.com/v1/*`：匹配子域名和路径前缀
+//!
+//! 多条规则同时匹配时，带主机的规则优先，其次是更长的字面前缀。
+
+/// 已解析的路由规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutePattern {
+    host: Option<String>,
+    path: String,
+}
+
+impl RoutePattern {
+    /// 解析路由规则
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err("Empty route pattern".to_string());
+        }
+
+        let (host, path) = if pattern.starts_with('/') {
+            (None, pattern.to_string())
+        } else {
+            match pattern.find('/') {
+                Some(pos) => (Some(&pattern[..pos]), pattern[pos..].to_string()),
+                None => (Some(pattern), "/*".to_string()),
+            }
+        };
+
+        let host = host.map(|h| h.to_lowercase());
+        if let Some(h) = &host {
+            let misplaced_wildcard = h.chars().skip(1).any(|c| c == '*');
+            if h.is_empty() || misplaced_wildcard || (h.starts_with('*') && h != "*" && !h.starts_with("*.")) {
+                return Err(format!("Invalid host in route pattern: '{}'", pattern));
+            }
+        }
+
+        if path[..path.len() - 1].contains('*') {
+            return Err(format!(
+                "Wildcard is only allowed at the end of the path: '{}'",
+                pattern
+            ));
+        }
+
+        Ok(Self { host, path })
+    }
+
+    /// 判断请求是否匹配
+    ///
+    /// `host` 为去掉端口的 `Host` 头，`path` 为不含查询字符串的路径
+    pub fn matches(&self, host: Option<&str>, path: &str) -> bool {
+        if let Some(pattern) = &self.host {
+            let Some(host) = host else {
+                return false;
+            };
+            let host = host.to_lowercase();
+            let host_ok = if pattern == "*" {
+                true
+            } else if let Some(suffix) = pattern.strip_prefix("*.") {
+                host.ends_with(&format!(".{}", suffix))
+            } else {
+                host == *pattern
+            };
+            if !host_ok {
+                return false;
+            }
+        }
+
+        match self.path.strip_suffix('*') {
+            // `/api/*` 同时匹配 `/api`
+            Some(prefix) => {
+                path.starts_with(prefix)
+                    || (prefix.len() > 1 && prefix.ends_with('/') && path == &prefix[..prefix.len() - 1])
+            }
+            None => path == self.path,
+        }
+    }
+
+    /// 规则的优先级，越大越优先
+    fn specificity(&self) -> (bool, usize, bool, usize) {
+        let host_len = self
+            .host
+            .as_ref()
+            .map(|h| h.trim_start_matches('*').len())
+            .unwrap_or(0);
+        let exact = !self.path.ends_with('*');
+        let path_len = self.path.trim_end_matches('*').len();
+        (self.host.is_some(), host_len, exact, path_len)
+    }
+}
+
+/// 路由表
+#[derive(Debug, Clone, Default)]
+pub struct Router {
+    /// (规则, Worker 下标)
+    routes: Vec<(RoutePattern, usize)>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 为指定 Worker 添加路由规则
+    pub fn add(&mut self, pattern: &str, worker: usize) -> Result<(), String> {
+        self.routes.push((RoutePattern::parse(pattern)?, worker));
+        // 保持按优先级从高到低排序，匹配时取第一个
+        self.routes
+            .sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.specificity()));
+        Ok(())
+    }
+
+    /// 查找匹配的 Worker
+    pub fn find(&self, host: Option<&str>, path: &str) -> Option<usize> {
+        let host = host.map(strip_port);
+        self.routes
+            .iter()
+            .find(|(pattern, _)| pattern.matches(host, path))
+            .map(|(_, worker)| *worker)
+    }
+}
+
+/// 去掉 `Host` 头中的端口
+fn strip_port(host: &str) -> &str {
+    // IPv6 地址形如 [::1]:8080
+    if let Some(end) = host.find(']') {
+        return &host[..=end];
+    }
+    host.split(':').next().unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_pattern_matching() {
+        let api = RoutePattern::parse("/api/*").unwrap();
+        assert!(api.matches(None, "/api/users"));
+        assert!(api.matches(Some("any.host"), "/api"));
+        assert!(!api.matches(None, "/apis"));
+
+        let exact = RoutePattern::parse("/health").unwrap();
+        assert!(exact.matches(None, "/health"));
+        assert!(!exact.matches(None, "/health/db"));
+
+        let host = RoutePattern::parse("api.example.com").unwrap();
+        assert!(host.matches(Some("API.example.com"), "/anything"));
+        assert!(!host.matches(Some("www.example.com"), "/anything"));
+        assert!(!host.matches(None, "/anything"));
+
+        let wildcard = RoutePattern::parse("*.example.com/v1/*").unwrap();
+        assert!(wildcard.matches(Some("a.example.com"), "/v1/x"));
+        assert!(!wildcard.matches(Some("example.com"), "/v1/x"));
+
+        assert!(RoutePattern::parse("/a/*/b").is_err());
+        assert!(RoutePattern::parse("api.*.com/").is_err());
+    }
+
+    #[test]
+    fn test_router_specificity() {
+        let mut router = Router::new();
+        router.add("/*", 0).unwrap();
+        router.add("/api/*", 1).unwrap();
+        router.add("/api/admin/*", 2).unwrap();
+        router.add("admin.example.com", 3).unwrap();
+
+        assert_eq!(router.find(None, "/"), Some(0));
+        assert_eq!(router.find(None, "/api/users"), Some(1));
+        assert_eq!(router.find(Some("localhost:8787"), "/api/admin/x"), Some(2));
+        assert_eq!(router.find(Some("admin.example.com:443"), "/api/users"), Some(3));
+    }
+
+    #[test]
+    fn test_router_no_match() {
+        let mut router = Router::new();
+        router.add("/api/*", 0).unwrap();
+        assert_eq!(router.find(None, "/other"), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! Cron 触发器调度
+//!
+//! 根据配置的 cron 表达式计算到期的 `scheduled()` 调用。
+//! 调度器本身不持有线程，由 `WorkerServer` 的事件循环驱动：
+//! 事件循环根据 [`Scheduler::next_deadline`] 设置等待超时，
+//! 醒来后通过 [`Scheduler::due`] 取出所有到期的执行。
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::cron::CronSchedule;
+
+/// `RunAll` 策略下单个触发器一次最多补跑的次数
+const MAX_CATCH_UP_RUNS: usize = 100;
+
+/// 错过执行时间时的处理策略
+///
+/// 当 Worker 正忙于处理请求（单线程）导致调度延迟时，
+/// 可能有一个或多个执行时间已经过去。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissedRunPolicy {
+    /// 丢弃错过的执行，只在延迟不超过宽限期时执行最近一次
+    Skip,
+    /// 合并所有错过的执行，只执行最近一次
+    #[default]
+    RunOnce,
+    /// 按顺序补跑每一次错过的执行
+    RunAll,
+}
+
+impl MissedRunPolicy {
+    /// 从配置字符串解析（`skip` / `run_once` / `run_all`）
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().replace('-', "_").as_str() {
+            "skip" => Ok(Self::Skip),
+            "run_once" | "once" => Ok(Self::RunOnce),
+            "run_all" | "all" => Ok(Self::RunAll),
+            other => Err(format!("Unknown missed run policy: '{}'", other)),
+        }
+    }
+}
+
+/// 一次到期的 `scheduled()` 调用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledRun {
+    /// 触发的 cron 表达式
+    pub cron: String,
+    /// 计划执行时间（而非实际执行时间）
+    pub scheduled_time: DateTime<Utc>,
+}
+
+impl ScheduledRun {
+    /// 计划执行时间的毫秒时间戳（`event.scheduledTime`）
+    pub fn scheduled_time_millis(&self) -> i64 {
+        self.scheduled_time.timestamp_millis()
+    }
+}
+
+struct CronTrigger {
+    schedule: CronSchedule,
+    next_run: Option<DateTime<Utc>>,
+}
+
+/// Cron 调度器
+pub struct Scheduler {
+    triggers: Vec<CronTrigger>,
+    policy: MissedRunPolicy,
+    /// `Skip` 策略下允许的最大延迟
+    grace: Duration,
+}
+
+impl Scheduler {
+    /// 根据 cron 表达式列表创建调度器
+    pub fn new(crons: &[String], policy: MissedRunPolicy, now: DateTime<Utc>) -> Result<Self, String> {
+        let mut triggers = Vec::with_capacity(crons.len());
+        for expr in crons {
+            let schedule = CronSchedule::parse(expr)?;
+            let next_run = schedule.next_after(&now);
+            triggers.push(CronTrigger { schedule, next_run });
+        }
+
+        Ok(Self {
+            triggers,
+            policy,
+            grace: Duration::seconds(60),
+        })
+    }
+
+    /// 设置 `Skip` 策略的宽限期
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// 是否没有任何触发器
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    /// 所有触发器的 cron 表达式
+    pub fn crons(&self) -> Vec<&str> {
+        self.triggers.iter().map(|t| t.schedule.expr()).collect()
+    }
+
+    /// 最近一次需要执行的时间
+    pub fn next_deadline(&self) -> Option<DateTime<Utc>> {
+        self.triggers.iter().filter_map(|t| t.next_run).min()
+    }
+
+    /// 取出所有在 `now` 之前到期的执行，并推进各触发器的下一次执行时间
+    pub fn due(&mut self, now: DateTime<Utc>) -> Vec<ScheduledRun> {
+        let mut runs = Vec::new();
+
+        for trigger in &mut self.triggers {
+            let mut missed = Vec::new();
+            while let Some(next) = trigger.next_run {
+                if next > now {
+                    break;
+                }
+                missed.push(next);
+                trigger.next_run = trigger.schedule.next_after(&next);
+            }
+
+            let selected: Vec<DateTime<Utc>> = match self.policy {
+                MissedRunPolicy::Skip => missed
+                    .last()
+                    .filter(|t| now - **t <= self.grace)
+                    .copied()
+                    .into_iter()
+                    .collect(),
+                MissedRunPolicy::RunOnce => missed.last().copied().into_iter().collect(),
+                MissedRunPolicy::RunAll => {
+                    let skip = missed.len().saturating_sub(MAX_CATCH_UP_RUNS);
+                    missed.into_iter().skip(skip).collect()
+                }
+            };
+
+            runs.extend(selected.into_iter().map(|scheduled_time| ScheduledRun {
+                cron: trigger.schedule.expr().to_string(),
+                scheduled_time,
+            }));
+        }
+
+        runs.sort_by_key(|r| r.scheduled_time);
+        runs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, mi: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, h, mi, s).unwrap()
+    }
+
+    fn crons(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_next_deadline_and_due() {
+        let mut scheduler =
+            Scheduler::new(&crons(&["*/10 * * * *", "0 * * * *"]), MissedRunPolicy::RunOnce, at(10, 1, 0)).unwrap();
+
+        assert_eq!(scheduler.next_deadline(), Some(at(10, 10, 0)));
+        assert!(scheduler.due(at(10, 9, 59)).is_empty());
+
+        let runs = scheduler.due(at(10, 10, 1));
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].cron, "*/10 * * * *");
+        assert_eq!(runs[0].scheduled_time, at(10, 10, 0));
+        assert_eq!(scheduler.next_deadline(), Some(at(10, 20, 0)));
+    }
+
+    #[test]
+    fn test_missed_run_policies() {
+        let start = at(10, 0, 30);
+        let late = at(10, 5, 30);
+
+        let mut all = Scheduler::new(&crons(&["* * * * *"]), MissedRunPolicy::RunAll, start).unwrap();
+        let runs = all.due(late);
+        assert_eq!(runs.len(), 5);
+        assert_eq!(runs[0].scheduled_time, at(10, 1, 0));
+        assert_eq!(runs[4].scheduled_time, at(10, 5, 0));
+
+        let mut once = Scheduler::new(&crons(&["* * * * *"]), MissedRunPolicy::RunOnce, start).unwrap();
+        let runs = once.due(late);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].scheduled_time, at(10, 5, 0));
+
+        // 最近一次已延迟 30 秒，超过了 10 秒的宽限期
+        let mut skip = Scheduler::new(&crons(&["* * * * *"]), MissedRunPolicy::Skip, start)
+            .unwrap()
+            .with_grace(Duration::seconds(10));
+        assert!(skip.due(late).is_empty());
+        assert_eq!(skip.next_deadline(), Some(at(10, 6, 0)));
+        assert_eq!(skip.due(at(10, 6, 5)).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(MissedRunPolicy::parse("skip").unwrap(), MissedRunPolicy::Skip);
+        assert_eq!(MissedRunPolicy::parse("run-once").unwrap(), MissedRunPolicy::RunOnce);
+        assert_eq!(MissedRunPolicy::parse("RUN_ALL").unwrap(), MissedRunPolicy::RunAll);
+        assert!(MissedRunPolicy::parse("later").is_err());
+    }
+}
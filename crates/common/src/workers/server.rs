@@ -1,67 +1,235 @@
 //! Worker HTTP 服务器
 //!
-//! 使用标准库 TcpListener 实现简单的 HTTP 服务器。
-//! 注意：由于 boa_engine 的 Context 不是线程安全的，服务器采用单线程模式。
+//! 使用标准库 TcpListener 实现简单的 HTTP 服务器，可选 TLS 终止。
+//! 注意：由于 boa_engine 的 Context 不是线程安全的，JS 只在主线程执行。
+//! 每个连接由独立的线程完成 TLS 握手和请求解析，再通过 channel 交给主线程，
+//! 主线程在等待请求的同时负责按时触发 cron 调度。
+//! WebSocket 连接同样由连接线程收发帧，消息事件交给主线程分发到 JS。
+//!
+//! 一个服务器可以挂载多个 Worker，每个 Worker 拥有独立的运行时，
+//! 请求按路由规则和 `Host` 头分发，Worker 之间还可以通过 Service 绑定直接调用。
+//!
+//! 每个请求处理完成后按配置格式输出访问日志，并计入 `/__raven/metrics` 导出的指标。
+//!
+//! 通过 [`ServerHandle`] 或 SIGTERM/SIGINT 关闭服务器时，先停止接受新连接，
+//! 再处理完进行中的请求和 `waitUntil` 任务并刷新绑定；
+//! SIGHUP 会在不关闭监听端口的情况下重新读取配置和脚本。
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
-use std::net::{TcpListener, TcpStream};
+use std::io::BufReader;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{TimeZone, Utc};
 
-use super::http::{HttpRequest, HttpResponse};
+use super::access_log::AccessLogEntry;
+use super::assets::AssetStore;
+use super::bindings::QueueRegistry;
+use super::cache::{DiskCacheBackend, HttpCache};
+use super::config::{ServerConfig, WorkerConfig};
+use super::connection::Connection;
+use super::durable::DurableStore;
+use super::http::{HttpRequest, HttpResponse, RequestUrl};
+use super::metrics::{Metrics, WorkerBindingCalls};
+use super::router::Router;
+use super::queue_consumer;
+use super::scheduler::{ScheduledRun, Scheduler};
+use super::service::{ServiceRegistry, INTERNAL_HEADERS};
+use super::tls::TlsAcceptor;
+use super::websocket::{self, close_code, Outgoing, WebSocketEvent};
 use super::workers_runtime::WorkersRuntime;
 
-/// Worker 服务器配置
-#[derive(Debug, Clone)]
-pub struct ServerConfig {
-    /// 监听地址
-    pub host: String,
-    /// 监听端口
-    pub port: u16,
-    /// Worker 脚本路径
-    pub script_path: String,
+/// 手动触发 scheduled() 的内部路径（需开启 `test_scheduled`）
+const SCHEDULED_TRIGGER_PATH: &str = "/__raven/scheduled";
+
+/// Prometheus 指标的内部路径（需开启 `metrics`）
+const METRICS_PATH: &str = "/__raven/metrics";
+
+/// 读取请求的超时时间
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 关闭时检查进行中连接的间隔
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 有流式响应时主循环的轮询间隔，连接线程读走一块后尽快产生下一块
+const STREAM_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// 检查队列中延迟和重试消息的间隔
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 有 `KV.watch()` 回调时检查 KV 变更的间隔
+const KV_WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 连接线程发给主线程的事件
+enum ServerEvent {
+    /// 已解析的请求，响应通过 `reply` 发回连接线程
+    ///
+    /// WebSocket 升级请求会带上 `socket`，Worker 接受连接后通过它向客户端发送消息
+    Request {
+        request: Box<HttpRequest>,
+        /// 连接线程解析完请求的时间，用于计算包含排队在内的耗时
+        received: Instant,
+        reply: Sender<HttpResponse>,
+        socket: Option<Sender<Outgoing>>,
+    },
+    /// WebSocket 连接上的事件
+    WebSocket { id: u64, event: WebSocketEvent },
+    /// 停止接受新连接，处理完进行中的请求后退出 `run`
+    Shutdown,
+    /// 重新读取配置和脚本
+    Reload,
 }
 
-impl Default for ServerConfig {
-    fn default() -> Self {
-        Self {
-            host: "127.0.0.1".to_string(),
-            port: 8787,
-            script_path: "worker.js".to_string(),
-        }
+/// 控制运行中服务器的句柄，可以在其他线程使用
+#[derive(Clone)]
+pub struct ServerHandle {
+    events: Sender<ServerEvent>,
+}
+
+impl ServerHandle {
+    /// 优雅关闭：停止接受新连接，等待进行中的请求和 `waitUntil` 任务完成后 `run` 返回
+    ///
+    /// 关闭过程中再次调用会跳过等待，立即刷新绑定并退出
+    pub fn shutdown(&self) {
+        self.events.send(ServerEvent::Shutdown).ok();
+    }
+
+    /// 重新读取配置和脚本，监听端口保持不变
+    pub fn reload(&self) {
+        self.events.send(ServerEvent::Reload).ok();
     }
 }
 
-impl ServerConfig {
-    pub fn new(host: &str, port: u16, script_path: &str) -> Self {
-        Self {
-            host: host.to_string(),
-            port,
-            script_path: script_path.to_string(),
+/// 接收连接的线程与主线程共享的状态
+struct ListenerState {
+    /// 实际监听的地址
+    local_addr: SocketAddr,
+    /// 为 `false` 时接收线程退出并关闭监听端口
+    accepting: AtomicBool,
+    /// 尚未结束的连接数
+    in_flight: AtomicUsize,
+    /// 当前生效的 body 上限，重新加载后更新
+    max_body_size: AtomicUsize,
+    /// 当前生效的 TLS 握手器，重新加载后更新
+    tls: RwLock<Option<Arc<TlsAcceptor>>>,
+}
+
+impl ListenerState {
+    /// 停止接受新连接
+    fn stop(&self) {
+        self.accepting.store(false, Ordering::SeqCst);
+
+        // 接收线程阻塞在 accept 上，连接一次监听端口把它唤醒
+        let mut wake_addr = self.local_addr;
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(match wake_addr.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
         }
+        TcpStream::connect_timeout(&wake_addr, Duration::from_secs(1)).ok();
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// 进行中的连接，线程结束时计数减一
+struct InFlight(Arc<ListenerState>);
+
+impl InFlight {
+    fn new(state: &Arc<ListenerState>) -> Self {
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(Arc::clone(state))
     }
+}
 
-    pub fn addr(&self) -> String {
-        format!("{}:{}", self.host, self.port)
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
     }
 }
 
+/// 已挂载的 Worker
+struct MountedWorker {
+    config: WorkerConfig,
+    /// 与 Service 绑定共享，处理请求时可能被其他 Worker 借用
+    runtime: Rc<RefCell<WorkersRuntime>>,
+    /// 静态资源目录
+    assets: Option<AssetStore>,
+}
+
 /// Worker HTTP 服务器
 pub struct WorkerServer {
     config: ServerConfig,
-    runtime: WorkersRuntime,
+    workers: Vec<MountedWorker>,
+    router: Router,
+    tls: Option<Arc<TlsAcceptor>>,
+    /// WebSocket 连接 ID -> Worker 下标
+    sockets: HashMap<u64, usize>,
+    metrics: Metrics,
+    /// 清单文件路径，重新加载时重新读取
+    manifest_path: Option<String>,
+    /// 事件通道，发送端由连接线程和 `ServerHandle` 持有
+    events: Sender<ServerEvent>,
+    receiver: Receiver<ServerEvent>,
+    /// 所有 Worker 共享的 Cache API 缓存
+    cache: HttpCache,
+    /// Service 绑定可以调用的 Worker
+    services: ServiceRegistry,
+    /// 所有 Worker 共享的队列
+    queues: QueueRegistry,
+    /// 所有 Worker 的 Durable Object 存储
+    durable_objects: DurableStore,
 }
 
 impl WorkerServer {
-    /// 创建新的 Worker 服务器
+    /// 创建新的 Worker 服务器，加载配置中的所有 Worker
     pub fn new(config: ServerConfig) -> Result<Self, String> {
+        let mut server = Self::empty(config.clone());
+
+        let workers = config.worker_configs();
+        check_services(&workers)?;
+        check_queue_consumers(&workers)?;
+        for worker in workers {
+            let runtime = Self::load_worker(&worker)?;
+            server.mount(worker, runtime)?;
+        }
+
+        Ok(server)
+    }
+
+    /// 从清单文件创建服务器
+    pub fn from_manifest(path: &str) -> Result<Self, String> {
+        let mut server = Self::new(ServerConfig::from_manifest(path)?)?;
+        server.manifest_path = Some(path.to_string());
+        Ok(server)
+    }
+
+    /// 按配置创建运行时并加载 Worker 脚本
+    fn load_worker(worker: &WorkerConfig) -> Result<WorkersRuntime, String> {
+        let script = fs::read_to_string(&worker.script_path)
+            .map_err(|e| format!("Failed to read script {}: {}", worker.script_path, e))?;
+
         let mut runtime = WorkersRuntime::new();
+        runtime
+            .configure(worker)
+            .map_err(|e| format!("Failed to configure worker '{}': {}", worker.name, e))?;
 
         // 加载 Worker 脚本（会自动解析 import 并加载所需的绑定）
-        let script = fs::read_to_string(&config.script_path)
-            .map_err(|e| format!("Failed to read script {}: {}", config.script_path, e))?;
+        runtime
+            .load_worker(&script)
+            .map_err(|e| format!("Failed to load worker '{}': {}", worker.name, e))?;
 
-        runtime.load_worker(&script)?;
-
-        Ok(Self { config, runtime })
+        Ok(runtime)
     }
 
     /// 从脚本内容创建服务器
@@ -71,171 +239,2822 @@ impl WorkerServer {
         // 加载脚本（会自动解析 import 并加载所需的绑定）
         runtime.load_worker(script)?;
 
-        Ok(Self {
-            config: ServerConfig {
-                host: host.to_string(),
-                port,
-                script_path: String::new(),
-            },
-            runtime,
-        })
+        Self::from_runtime(runtime, ServerConfig::new(host, port, ""))
     }
 
     /// 从现有的 FetchRuntime 创建服务器
-    pub fn from_runtime(runtime: WorkersRuntime, config: ServerConfig) -> Self {
-        Self { config, runtime }
+    pub fn from_runtime(runtime: WorkersRuntime, config: ServerConfig) -> Result<Self, String> {
+        let mut server = Self::empty(config);
+        let mut worker = server.config.worker_configs().remove(0);
+        if worker.routes.is_empty() {
+            worker.routes = vec!["/*".to_string()];
+        }
+        server.mount(worker, runtime)?;
+        Ok(server)
+    }
+
+    /// 创建未挂载任何 Worker 的服务器
+    pub fn empty(config: ServerConfig) -> Self {
+        let (events, receiver) = mpsc::channel();
+        let cache = match &config.cache_dir {
+            Some(dir) => HttpCache::new(Arc::new(DiskCacheBackend::new(dir))),
+            None => HttpCache::memory(),
+        };
+        let queues = QueueRegistry::new(config.queue_dir.as_deref());
+        let durable_objects = DurableStore::new(config.durable_objects_dir.as_deref());
+        Self {
+            config,
+            workers: Vec::new(),
+            router: Router::new(),
+            tls: None,
+            sockets: HashMap::new(),
+            metrics: Metrics::new(),
+            manifest_path: None,
+            events,
+            receiver,
+            cache,
+            services: ServiceRegistry::new(),
+            queues,
+            durable_objects,
+        }
+    }
+
+    /// 挂载一个已加载脚本的 Worker
+    ///
+    /// `runtime` 应已通过 `WorkersRuntime::configure` 应用了 `config` 中的环境配置
+    pub fn mount(&mut self, config: WorkerConfig, runtime: WorkersRuntime) -> Result<(), String> {
+        self.mount_shared(config, Rc::new(RefCell::new(runtime)))
+    }
+
+    fn mount_shared(
+        &mut self,
+        config: WorkerConfig,
+        runtime: Rc<RefCell<WorkersRuntime>>,
+    ) -> Result<(), String> {
+        if self.workers.iter().any(|w| w.config.name == config.name) {
+            return Err(format!("Worker '{}' is already mounted", config.name));
+        }
+
+        // 路由和绑定都设置成功后才修改服务器，失败时不会留下指向不存在 Worker 的路由
+        let mut router = self.router.clone();
+        for route in &config.routes {
+            router.add(route, self.workers.len())?;
+        }
+        self.attach_shared(&config, &runtime)?;
+        self.router = router;
+        self.push_worker(config, runtime);
+        Ok(())
+    }
+
+    /// 为运行时设置所有 Worker 共享的缓存、Service、队列和 Durable Object 存储
+    fn attach_shared(&self, config: &WorkerConfig, runtime: &Rc<RefCell<WorkersRuntime>>) -> Result<(), String> {
+        let mut runtime = runtime.borrow_mut();
+        runtime.set_cache(self.cache.clone())?;
+        runtime.set_queues(&self.queues)?;
+        runtime.set_durable_store(self.durable_objects.clone())?;
+        runtime.set_services(self.services.clone(), &config.name);
+        runtime.set_limits(&config.limits);
+        Ok(())
+    }
+
+    /// 登记已设置好共享状态的 Worker，路由由调用方添加
+    fn push_worker(&mut self, config: WorkerConfig, runtime: Rc<RefCell<WorkersRuntime>>) {
+        self.services.register(&config.name, &runtime);
+        let assets = config
+            .assets
+            .as_ref()
+            .map(|assets| AssetStore::new(&assets.directory));
+        self.workers.push(MountedWorker {
+            config,
+            runtime,
+            assets,
+        });
+    }
+
+    /// TLS 握手器（未配置 TLS 时为 `None`）
+    ///
+    /// 返回的句柄可以在其他线程调用 `reload()` 更新证书
+    pub fn tls_acceptor(&mut self) -> Result<Option<Arc<TlsAcceptor>>, String> {
+        if self.tls.is_none() {
+            if let Some(tls) = &self.config.tls {
+                self.tls = Some(Arc::new(TlsAcceptor::new(tls.clone())?));
+            }
+        }
+        Ok(self.tls.clone())
+    }
+
+    /// 已挂载的 Worker 名称
+    pub fn worker_names(&self) -> Vec<&str> {
+        self.workers.iter().map(|w| w.config.name.as_str()).collect()
+    }
+
+    /// 获取控制句柄，用于从其他线程关闭或重新加载服务器
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            events: self.events.clone(),
+        }
     }
 
     /// 启动服务器（阻塞，单线程模式）
+    ///
+    /// 收到关闭请求（`ServerHandle::shutdown`、SIGTERM 或 SIGINT）后，
+    /// 处理完进行中的请求并刷新绑定再返回
     pub fn run(&mut self) -> Result<(), String> {
+        let mut schedulers = self.schedulers()?;
+
+        let tls = self.tls_acceptor()?;
+
         let addr = self.config.addr();
         let listener =
             TcpListener::bind(&addr).map_err(|e| format!("Failed to bind to {}: {}", addr, e))?;
+        let state = Arc::new(ListenerState {
+            local_addr: listener
+                .local_addr()
+                .map_err(|e| format!("Failed to get local address: {}", e))?,
+            accepting: AtomicBool::new(true),
+            in_flight: AtomicUsize::new(0),
+            max_body_size: AtomicUsize::new(self.max_body_size()),
+            tls: RwLock::new(tls),
+        });
 
-        println!("Worker server listening on http://{}", addr);
-        println!("Press Ctrl+C to stop");
+        self.print_banner(&state);
 
-        for stream in listener.incoming() {
-            match stream {
-                Ok(mut stream) => {
-                    if let Err(e) = self.handle_connection(&mut stream) {
-                        eprintln!("Error handling connection: {}", e);
+        // 接收连接的线程，每个连接再交给独立线程解析，JS 只在当前线程执行
+        let events = self.events.clone();
+        let accept_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !accept_state.accepting.load(Ordering::SeqCst) {
+                    break;
+                }
+                match stream {
+                    Ok(stream) => {
+                        let in_flight = InFlight::new(&accept_state);
+                        let tx = events.clone();
+                        let tls = accept_state.tls.read().unwrap().clone();
+                        let max_body_size = accept_state.max_body_size.load(Ordering::SeqCst);
+                        thread::spawn(move || {
+                            let _in_flight = in_flight;
+                            if let Err(e) = serve_connection(stream, tls, max_body_size, tx) {
+                                eprintln!("Error handling connection: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to accept connection: {}", e);
                     }
                 }
-                Err(e) => {
-                    eprintln!("Failed to accept connection: {}", e);
+            }
+        });
+
+        #[cfg(unix)]
+        let signals = self.handle_signals()?;
+
+        let mut queue_backlog = self.run_queues();
+        loop {
+            let next_deadline = schedulers
+                .iter()
+                .filter_map(|(_, s)| s.next_deadline())
+                .min();
+            let mut timeout =
+                next_deadline.map(|d| (d - Utc::now()).to_std().unwrap_or(Duration::ZERO));
+            // 有队列 Consumer 时定期检查延迟和重试的消息，上一轮有消息时立即继续消费
+            if self.has_queue_consumers() {
+                let poll = if queue_backlog { Duration::ZERO } else { QUEUE_POLL_INTERVAL };
+                timeout = Some(timeout.map_or(poll, |t| t.min(poll)));
+            }
+            if self.has_kv_watches() {
+                timeout = Some(timeout.map_or(KV_WATCH_POLL_INTERVAL, |t| t.min(KV_WATCH_POLL_INTERVAL)));
+            }
+            if self.has_streams() {
+                timeout = Some(timeout.map_or(STREAM_POLL_INTERVAL, |t| t.min(STREAM_POLL_INTERVAL)));
+            }
+
+            let received = match timeout {
+                Some(timeout) => self.receiver.recv_timeout(timeout),
+                None => self
+                    .receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            match received {
+                Ok(ServerEvent::Shutdown) => break,
+                Ok(ServerEvent::Reload) => match self.reload() {
+                    Ok(()) => {
+                        *state.tls.write().unwrap() = self.tls.clone();
+                        state
+                            .max_body_size
+                            .store(self.max_body_size(), Ordering::SeqCst);
+                        schedulers = self.schedulers().unwrap_or_else(|e| {
+                            eprintln!("Failed to schedule cron triggers: {}", e);
+                            Vec::new()
+                        });
+                        println!("🔄 已重新加载 {} 个 Worker", self.workers.len());
+                    }
+                    Err(e) => eprintln!("Reload failed, keeping current workers: {}", e),
+                },
+                Ok(event) => self.handle_event(event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            self.run_background_tasks();
+            queue_backlog = self.run_queues();
+
+            let now = Utc::now();
+            for (index, scheduler) in &mut schedulers {
+                for run in scheduler.due(now) {
+                    self.run_scheduled(*index, &run);
                 }
             }
         }
 
+        #[cfg(unix)]
+        signals.close();
+
+        self.shutdown(&state);
         Ok(())
     }
 
-    /// 处理单个连接
-    fn handle_connection(&mut self, stream: &mut TcpStream) -> Result<(), String> {
-        // 解析请求
-        let request = HttpRequest::from_stream(stream)
-            .map_err(|e| format!("Failed to parse request: {}", e))?;
+    /// 为配置了 cron 触发器的 Worker 创建调度器：(Worker 下标, 调度器)
+    fn schedulers(&mut self) -> Result<Vec<(usize, Scheduler)>, String> {
+        let mut schedulers = Vec::new();
+        for (index, worker) in self.workers.iter_mut().enumerate() {
+            let scheduler = Scheduler::new(
+                &worker.config.crons,
+                self.config.missed_run_policy,
+                Utc::now(),
+            )
+            .map_err(|e| format!("Worker '{}': {}", worker.config.name, e))?;
 
-        println!("{} {} {}", request.method, request.path, request.version);
+            if scheduler.is_empty() {
+                continue;
+            }
+            if !worker.runtime.borrow_mut().has_handler("scheduled") {
+                eprintln!(
+                    "⚠️  Worker {} 配置了 cron 触发器，但没有导出 scheduled 函数",
+                    worker.config.name
+                );
+            }
+            schedulers.push((index, scheduler));
+        }
+        Ok(schedulers)
+    }
 
-        // 调用 Worker 处理请求
-        let response = self.runtime.handle_request(&request, &self.config.addr()).unwrap_or_else(|e| {
-            eprintln!("Worker error: {}", e);
-            HttpResponse::error(500, &format!("Worker error: {}", e))
+    fn print_banner(&self, state: &ListenerState) {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        println!("Worker server listening on {}://{}", scheme, state.local_addr);
+        for worker in &self.workers {
+            println!("  📦 {} -> {}", worker.config.name, worker.config.routes.join(", "));
+            for cron in &worker.config.crons {
+                println!("     ⏰ cron: {}", cron);
+            }
+        }
+        println!("Press Ctrl+C to stop");
+    }
+
+    /// 把 SIGTERM/SIGINT 转为关闭事件，SIGHUP 转为重新加载事件
+    #[cfg(unix)]
+    fn handle_signals(&self) -> Result<signal_hook::iterator::Handle, String> {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+        use signal_hook::iterator::Signals;
+
+        let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP])
+            .map_err(|e| format!("Failed to register signal handlers: {}", e))?;
+        let handle = signals.handle();
+        let events = self.events.clone();
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                let event = if signal == SIGHUP {
+                    ServerEvent::Reload
+                } else {
+                    ServerEvent::Shutdown
+                };
+                if events.send(event).is_err() {
+                    break;
+                }
+            }
         });
+        Ok(handle)
+    }
 
-        // 发送响应
-        response
-            .write_to(stream)
-            .map_err(|e| format!("Failed to write response: {}", e))?;
+    /// 处理连接线程发来的请求和 WebSocket 事件
+    fn handle_event(&mut self, event: ServerEvent) {
+        match event {
+            ServerEvent::Request {
+                request,
+                received,
+                reply,
+                socket,
+            } => {
+                let response = self.respond(&request, received);
+                let response = self.accept_websocket(&request, response, socket);
+                // 连接线程可能已因客户端断开而退出
+                reply.send(response).ok();
+            }
+            ServerEvent::WebSocket { id, event } => {
+                self.handle_websocket_event(id, &event);
+            }
+            ServerEvent::Shutdown | ServerEvent::Reload => {}
+        }
+    }
 
-        Ok(())
+    /// 推进各 Worker 的 `waitUntil` 任务，分发 KV 变更，产生流式响应的下一批数据
+    fn run_background_tasks(&mut self) {
+        for worker in &mut self.workers {
+            worker.runtime.borrow_mut().pump_streams();
+            for e in worker.runtime.borrow_mut().run_wait_until() {
+                eprintln!("waitUntil error ({}): {}", worker.config.name, e);
+                self.metrics.record_error(&worker.config.name, "waitUntil", &e);
+            }
+            for e in worker.runtime.borrow_mut().run_kv_watches() {
+                eprintln!("KV watch error ({}): {}", worker.config.name, e);
+                self.metrics.record_error(&worker.config.name, "watch", &e);
+            }
+        }
     }
 
-    /// 处理单个请求（用于测试）
-    pub fn handle_request(&mut self, request: &HttpRequest) -> Result<HttpResponse, String> {
-        self.runtime.handle_request(request, &self.config.addr())
+    fn has_kv_watches(&self) -> bool {
+        self.workers.iter().any(|worker| worker.runtime.borrow_mut().has_kv_watches())
     }
-}
 
-/// 快速启动 Worker 服务器
-pub fn serve(script_path: &str, port: u16) -> Result<(), String> {
-    let config = ServerConfig::new("127.0.0.1", port, script_path);
-    let mut server = WorkerServer::new(config)?;
-    server.run()
-}
+    fn has_streams(&self) -> bool {
+        self.workers.iter().any(|worker| worker.runtime.borrow().has_streams())
+    }
 
-/// 从脚本内容启动服务器
-pub fn serve_script(script: &str, port: u16) -> Result<(), String> {
-    let mut server = WorkerServer::from_script(script, "127.0.0.1", port)?;
-    server.run()
-}
+    /// 停止接受新连接，等待进行中的请求和 `waitUntil` 任务完成，然后刷新绑定
+    ///
+    /// 最多等待 `shutdown_timeout`，期间再次收到关闭请求时立即结束等待
+    fn shutdown(&mut self, state: &ListenerState) {
+        println!("Shutting down, waiting for in-flight requests...");
+        state.stop();
+        let deadline = Instant::now() + self.config.shutdown_timeout;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::HashMap;
+        // WebSocket 连接不会自行结束，主动发送关闭帧
+        for id in self.sockets.keys() {
+            websocket::close(*id, close_code::GOING_AWAY, "Server shutting down");
+        }
 
-    #[test]
-    fn test_server_from_script() {
-        let script = r#"
-            export default {
-                fetch(request, env, ctx) {
-                    return new Response("Test Response", { status: 200 });
+        while state.in_flight() > 0 {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                eprintln!(
+                    "⚠️  {} connection(s) still open after {:?}",
+                    state.in_flight(),
+                    self.config.shutdown_timeout
+                );
+                break;
+            };
+            let poll = if self.has_streams() { STREAM_POLL_INTERVAL } else { DRAIN_POLL_INTERVAL };
+            match self.receiver.recv_timeout(remaining.min(poll)) {
+                Ok(ServerEvent::Shutdown) => {
+                    eprintln!("Forced shutdown");
+                    break;
                 }
+                Ok(event) => self.handle_event(event),
+                Err(_) => {}
             }
-        "#;
+            self.run_background_tasks();
+        }
+        self.run_background_tasks();
 
-        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        for worker in &mut self.workers {
+            let pending = worker.runtime.borrow_mut().pending_wait_until();
+            if pending > 0 {
+                eprintln!(
+                    "⚠️  Worker {} has {} unfinished waitUntil task(s)",
+                    worker.config.name, pending
+                );
+            }
+            if let Err(e) = worker.runtime.borrow_mut().flush_bindings() {
+                eprintln!("Failed to flush bindings of worker '{}': {}", worker.config.name, e);
+            }
+        }
+        println!("Server stopped");
+    }
 
-        let request = HttpRequest {
-            method: "GET".to_string(),
-            path: "/test".to_string(),
-            version: "HTTP/1.1".to_string(),
-            headers: HashMap::new(),
-            body: Vec::new(),
+    /// 重新读取配置和 Worker 脚本，替换已挂载的 Worker
+    ///
+    /// 从清单创建的服务器会重新读取清单，否则按当前 Worker 配置重新加载脚本。
+    /// 所有脚本、路由和 cron 表达式都有效时才替换，否则保留原来的 Worker。
+    /// 监听地址不会改变；没有脚本路径（通过 `mount` 挂载）的 Worker 保持原样。
+    pub fn reload(&mut self) -> Result<(), String> {
+        let (mut config, worker_configs) = match &self.manifest_path {
+            Some(path) => {
+                let config = ServerConfig::from_manifest(path)?;
+                let workers = config.worker_configs();
+                (config, workers)
+            }
+            None => (
+                self.config.clone(),
+                self.workers.iter().map(|w| w.config.clone()).collect(),
+            ),
         };
 
-        let response = server.handle_request(&request).unwrap();
-        assert_eq!(response.status, 200);
-        assert_eq!(String::from_utf8_lossy(&response.body), "Test Response");
+        // 先加载所有脚本并检查配置，出错时不影响当前的 Worker
+        check_services(&worker_configs)?;
+        check_queue_consumers(&worker_configs)?;
+        let mut router = Router::new();
+        let mut runtimes = Vec::new();
+        for (index, worker) in worker_configs.iter().enumerate() {
+            if worker_configs[..index].iter().any(|w| w.name == worker.name) {
+                return Err(format!("Worker '{}' is declared more than once", worker.name));
+            }
+            for route in &worker.routes {
+                router.add(route, index)?;
+            }
+            Scheduler::new(&worker.crons, config.missed_run_policy, Utc::now())
+                .map_err(|e| format!("Worker '{}': {}", worker.name, e))?;
+
+            if worker.script_path.is_empty() {
+                if !self.workers.iter().any(|w| w.config.name == worker.name) {
+                    return Err(format!("Worker '{}' has no script to load", worker.name));
+                }
+                runtimes.push(None);
+            } else {
+                runtimes.push(Some(Self::load_worker(worker)?));
+            }
+        }
+
+        let tls = match (&config.tls, &self.tls) {
+            (Some(tls), Some(current)) if current.config() == tls => {
+                current.reload()?;
+                Some(Arc::clone(current))
+            }
+            (Some(tls), _) => Some(Arc::new(TlsAcceptor::new(tls.clone())?)),
+            (None, _) => None,
+        };
+
+        if config.addr() != self.config.addr() {
+            eprintln!(
+                "⚠️  监听地址变更为 {} 需要重启服务器，继续使用 {}",
+                config.addr(),
+                self.config.addr()
+            );
+            config.host = self.config.host.clone();
+            config.port = self.config.port;
+        }
+
+        // 先为所有运行时设置共享状态，任何一个失败时当前的 Worker 保持不变
+        let mut mounted = Vec::with_capacity(worker_configs.len());
+        for (worker, runtime) in worker_configs.into_iter().zip(runtimes) {
+            let (runtime, old_index) = match runtime {
+                Some(runtime) => (Rc::new(RefCell::new(runtime)), None),
+                None => {
+                    let old_index = self
+                        .workers
+                        .iter()
+                        .position(|w| w.config.name == worker.name)
+                        .expect("checked above");
+                    (Rc::clone(&self.workers[old_index].runtime), Some(old_index))
+                }
+            };
+            self.attach_shared(&worker, &runtime)
+                .map_err(|e| format!("Failed to mount worker '{}': {}", worker.name, e))?;
+            mounted.push((worker, runtime, old_index));
+        }
+
+        let mut previous: Vec<Option<MountedWorker>> =
+            std::mem::take(&mut self.workers).into_iter().map(Some).collect();
+        let previous_sockets = std::mem::take(&mut self.sockets);
+        self.router = router;
+        self.services.clear();
+        self.config = config;
+        self.tls = tls;
+
+        // 旧下标 -> 新下标（保持原样的 Worker）
+        let mut kept = HashMap::new();
+        for (worker, runtime, old_index) in mounted {
+            if let Some(old_index) = old_index {
+                kept.insert(old_index, self.workers.len());
+                previous[old_index] = None;
+            }
+            self.push_worker(worker, runtime);
+        }
+
+        // 被替换的 Worker 上的 WebSocket 连接随旧运行时一起关闭，
+        // 未发送完的流式响应在旧运行时释放时中断
+        for (id, old_index) in previous_sockets {
+            match kept.get(&old_index) {
+                Some(new_index) => {
+                    self.sockets.insert(id, *new_index);
+                }
+                None => websocket::close(id, close_code::GOING_AWAY, "Worker reloaded"),
+            }
+        }
+
+        for worker in previous.into_iter().flatten() {
+            for e in worker.runtime.borrow_mut().run_wait_until() {
+                eprintln!("waitUntil error ({}): {}", worker.config.name, e);
+            }
+            if let Err(e) = worker.runtime.borrow_mut().flush_bindings() {
+                eprintln!("Failed to flush bindings of worker '{}': {}", worker.config.name, e);
+            }
+        }
+
+        Ok(())
     }
 
-    #[test]
-    fn test_request_properties() {
-        let script = r#"
-            export default {
-                fetch(request, env, ctx) {
-                    var url = request.url;
-                    var method = request.method;
-                    return new Response(method + " " + url, { status: 200 });
+    fn has_queue_consumers(&self) -> bool {
+        self.workers.iter().any(|w| !w.config.queue_consumers.is_empty())
+    }
+
+    /// 每个队列 Consumer 消费一批消息，返回是否交付了消息
+    pub fn run_queues(&mut self) -> bool {
+        let mut delivered = false;
+        for worker in &self.workers {
+            for consumer in &worker.config.queue_consumers {
+                let result = queue_consumer::consume(
+                    &mut worker.runtime.borrow_mut(),
+                    consumer,
+                    &self.queues,
+                );
+                match result {
+                    Ok(0) => {}
+                    Ok(count) => {
+                        println!(
+                            "📨 [{}] queue \"{}\": {} message(s)",
+                            worker.config.name, consumer.queue, count
+                        );
+                        delivered = true;
+                    }
+                    Err(e) => {
+                        eprintln!("Queue error ({}, {}): {}", worker.config.name, consumer.queue, e);
+                        self.metrics.record_error(&worker.config.name, "queue", &e);
+                        delivered = true;
+                    }
                 }
             }
-        "#;
+        }
+        delivered
+    }
 
-        let mut server = WorkerServer::from_script(script, "127.0.0.1", 8080).unwrap();
+    /// 执行一次到期的 cron 触发
+    fn run_scheduled(&mut self, index: usize, run: &ScheduledRun) {
+        let worker = &mut self.workers[index];
+        println!(
+            "⏰ [{}] scheduled \"{}\" @ {}",
+            worker.config.name,
+            run.cron,
+            run.scheduled_time.to_rfc3339()
+        );
+        if let Err(e) = worker
+            .runtime
+            .borrow_mut()
+            .handle_scheduled(&run.cron, run.scheduled_time_millis())
+        {
+            eprintln!("Scheduled error ({}, {}): {}", worker.config.name, run.cron, e);
+            self.metrics.record_error(&worker.config.name, "scheduled", &e);
+        }
+    }
 
-        let request = HttpRequest {
-            method: "POST".to_string(),
-            path: "/api/data".to_string(),
-            version: "HTTP/1.1".to_string(),
-            headers: HashMap::new(),
-            body: Vec::new(),
+    /// 把 Worker 返回的 WebSocket 绑定到连接线程
+    ///
+    /// Worker 只能在升级请求上返回 `webSocket`，否则响应 426
+    fn accept_websocket(
+        &mut self,
+        request: &HttpRequest,
+        response: HttpResponse,
+        socket: Option<Sender<Outgoing>>,
+    ) -> HttpResponse {
+        let Some(id) = response.websocket else {
+            return response;
         };
 
-        let response = server.handle_request(&request).unwrap();
-        assert_eq!(response.status, 200);
-        assert!(String::from_utf8_lossy(&response.body).contains("POST"));
-        assert!(String::from_utf8_lossy(&response.body).contains("/api/data"));
+        match (socket, self.find_worker(request)) {
+            (Some(socket), Some(index)) if response.status == 101 => {
+                websocket::attach(id, socket);
+                self.sockets.insert(id, index);
+                response
+            }
+            _ => {
+                websocket::detach(id);
+                HttpResponse::error(426, "Expected Upgrade: websocket")
+            }
+        }
     }
-    
-    #[test]
-    fn test_with_bindings() {
-        let script = r#"
-            import { KV } from 'raven/kv'
-            
-            export default {
-                fetch(request, env, ctx) {
-                    KV.put("test", "value");
-                    var value = KV.get("test");
-                    return new Response(value, { status: 200 });
-                }
+
+    /// 把 WebSocket 事件交给拥有该连接的 Worker
+    fn handle_websocket_event(&mut self, id: u64, event: &WebSocketEvent) {
+        let index = match event {
+            WebSocketEvent::Close { .. } => self.sockets.remove(&id),
+            WebSocketEvent::Message(_) => self.sockets.get(&id).copied(),
+        };
+        let Some(worker) = index.and_then(|i| self.workers.get_mut(i)) else {
+            // Worker 已被重新加载替换
+            if let WebSocketEvent::Close { .. } = event {
+                websocket::detach(id);
             }
-        "#;
+            return;
+        };
 
-        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        if let Err(e) = worker.runtime.borrow_mut().handle_websocket_event(id, event) {
+            eprintln!("WebSocket error ({}): {}", worker.config.name, e);
+            self.metrics.record_error(&worker.config.name, "websocket", &e);
+        }
+    }
 
-        let request = HttpRequest {
-            method: "GET".to_string(),
-            path: "/test".to_string(),
-            version: "HTTP/1.1".to_string(),
-            headers: HashMap::new(),
-            body: Vec::new(),
+    /// 所有 Worker 中最大的 body 限制
+    fn max_body_size(&self) -> usize {
+        self.workers
+            .iter()
+            .map(|w| w.config.limits.max_body_size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 处理一个已解析的请求，Worker 出错时返回 500
+    ///
+    /// 同时记录指标并输出访问日志，`received` 为收到请求的时间
+    fn respond(&mut self, request: &HttpRequest, received: Instant) -> HttpResponse {
+        let time = Utc::now();
+        let index = if self.is_internal_path(request) {
+            None
+        } else {
+            self.find_worker(request)
         };
 
-        let response = server.handle_request(&request).unwrap();
-        assert_eq!(response.status, 200);
-        assert_eq!(String::from_utf8_lossy(&response.body), "value");
+        // 调用 Worker 处理请求，流式 body 由连接线程边产生边发送
+        let mut response = self.dispatch(request, true).unwrap_or_else(|e| {
+            eprintln!("Worker error: {}", e);
+            if let Some(index) = index {
+                self.metrics
+                    .record_error(&self.workers[index].config.name, "fetch", &e);
+            }
+            HttpResponse::error(500, &format!("Worker error: {}", e))
+        });
+        self.config.compression.apply(request, &mut response);
+        let latency = received.elapsed();
+
+        let worker = index.map(|i| self.workers[i].config.name.as_str());
+        if let Some(name) = worker {
+            self.metrics
+                .record_request(name, &request.method, response.status, latency);
+            if response.status == 413 {
+                self.metrics.record_limit_violation(name, "max_body_size");
+            }
+        }
+
+        let entry = AccessLogEntry {
+            request,
+            client_ip: request.client_ip(&self.config.trusted_proxies),
+            status: response.status,
+            bytes: response.body.len(),
+            latency,
+            worker,
+            time,
+        };
+        if let Some(line) = entry.format(self.config.access_log) {
+            println!("{}", line);
+        }
+
+        response
+    }
+
+    /// 是否为服务器内部处理的路径（不交给 Worker）
+    fn is_internal_path(&self, request: &HttpRequest) -> bool {
+        let path = request.decoded_path();
+        (self.config.test_scheduled && path == SCHEDULED_TRIGGER_PATH)
+            || (self.config.metrics && path == METRICS_PATH)
+    }
+
+    /// 以 Prometheus 文本格式导出指标
+    fn render_metrics(&self) -> HttpResponse {
+        let binding_calls: Vec<WorkerBindingCalls> = self
+            .workers
+            .iter()
+            .map(|w| (w.config.name.clone(), w.runtime.borrow().binding_calls()))
+            .collect();
+
+        HttpResponse::ok(&self.metrics.render(&binding_calls))
+            .with_header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+    }
+
+    /// 处理单个请求（用于测试），流式 body 会被完整读出
+    pub fn handle_request(&mut self, request: &HttpRequest) -> Result<HttpResponse, String> {
+        self.dispatch(request, false)
+    }
+
+    /// 把请求交给匹配的 Worker，`streaming` 见 `WorkersRuntime::handle_request_streaming`
+    fn dispatch(&mut self, request: &HttpRequest, streaming: bool) -> Result<HttpResponse, String> {
+        // 截止时间和调用链只能由 Service 绑定设置
+        if INTERNAL_HEADERS.iter().any(|h| request.headers.contains_key(*h)) {
+            let mut request = request.clone();
+            for header in INTERNAL_HEADERS {
+                request.headers.remove(header);
+            }
+            return self.dispatch(&request, streaming);
+        }
+
+        let url = self.request_url(request);
+
+        if self.config.test_scheduled && url.path == SCHEDULED_TRIGGER_PATH {
+            return Ok(self.trigger_scheduled(request));
+        }
+        if self.config.metrics && url.path == METRICS_PATH {
+            return Ok(self.render_metrics());
+        }
+
+        let Some(index) = self.find_worker(request) else {
+            return Ok(HttpResponse::error(404, &format!("No worker matches {}", url.path)));
+        };
+
+        let worker = &mut self.workers[index];
+        if request.body.len() > worker.config.limits.max_body_size {
+            return Ok(HttpResponse::error(413, "Payload Too Large"));
+        }
+
+        let has_client_cert = request
+            .tls
+            .as_ref()
+            .is_some_and(|tls| tls.client_certificate.is_some());
+        if worker.config.require_client_cert && !has_client_cert {
+            return Ok(HttpResponse::error(403, "Client certificate required"));
+        }
+
+        // 静态资源优先于 fetch（除非配置了 run_worker_first）
+        if let Some(assets) = &worker.assets {
+            let run_worker_first = worker
+                .config
+                .assets
+                .as_ref()
+                .is_some_and(|a| a.run_worker_first);
+            if !run_worker_first {
+                if let Some(response) = assets.serve(request) {
+                    return Ok(response);
+                }
+            }
+            // 只提供静态资源的 Worker
+            if !worker.runtime.borrow_mut().has_handler("fetch") {
+                return Ok(HttpResponse::error(404, "Not Found"));
+            }
+        }
+
+        let mut runtime = worker.runtime.borrow_mut();
+        if streaming {
+            runtime.handle_request_streaming(request, &url)
+        } else {
+            runtime.handle_request_with_url(request, &url)
+        }
+    }
+
+    /// 客户端请求的 URL，缺少 `Host` 头时使用监听地址
+    fn request_url(&self, request: &HttpRequest) -> RequestUrl {
+        request.url(&self.config.addr(), &self.config.trusted_proxies)
+    }
+
+    /// 按路由查找处理请求的 Worker
+    fn find_worker(&self, request: &HttpRequest) -> Option<usize> {
+        let url = self.request_url(request);
+        self.router.find(Some(&url.host), &url.path)
+    }
+
+    /// 手动触发 scheduled()
+    ///
+    /// 支持 `worker`、`cron` 和 `time`（毫秒时间戳）三个查询参数。
+    /// 未指定 `worker` 时选择配置了该 cron 的 Worker（否则为第一个），
+    /// 未指定 `cron` 时使用该 Worker 配置的第一个 cron 表达式。
+    fn trigger_scheduled(&mut self, request: &HttpRequest) -> HttpResponse {
+        let cron = request.query_param("cron");
+
+        let index = match request.query_param("worker") {
+            Some(name) => match self.workers.iter().position(|w| w.config.name == name) {
+                Some(index) => index,
+                None => return HttpResponse::error(404, &format!("Unknown worker: {}", name)),
+            },
+            None => cron
+                .as_ref()
+                .and_then(|c| self.workers.iter().position(|w| w.config.crons.contains(c)))
+                .unwrap_or(0),
+        };
+
+        let Some(worker) = self.workers.get_mut(index) else {
+            return HttpResponse::error(404, "No worker mounted");
+        };
+
+        let cron = cron
+            .or_else(|| worker.config.crons.first().cloned())
+            .unwrap_or_default();
+
+        let scheduled_time = match request.query_param("time") {
+            Some(time) => match time.parse::<i64>() {
+                Ok(ms) => match Utc.timestamp_millis_opt(ms).single() {
+                    Some(t) => t,
+                    None => return HttpResponse::error(400, "Invalid time parameter"),
+                },
+                Err(_) => return HttpResponse::error(400, "Invalid time parameter"),
+            },
+            None => Utc::now(),
+        };
+
+        let run = ScheduledRun {
+            cron,
+            scheduled_time,
+        };
+
+        match worker
+            .runtime
+            .borrow_mut()
+            .handle_scheduled(&run.cron, run.scheduled_time_millis())
+        {
+            Ok(()) => HttpResponse::ok("Ran scheduled event"),
+            Err(e) => HttpResponse::error(500, &format!("Scheduled error: {}", e)),
+        }
+    }
+}
+
+/// 检查 Service 绑定的目标 Worker 都已声明
+fn check_services(workers: &[WorkerConfig]) -> Result<(), String> {
+    for worker in workers {
+        for service in &worker.services {
+            if !workers.iter().any(|w| w.name == service.service) {
+                return Err(format!(
+                    "Worker '{}': service binding '{}' targets unknown worker '{}'",
+                    worker.name, service.binding, service.service
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 检查每个队列最多只有一个 Consumer
+fn check_queue_consumers(workers: &[WorkerConfig]) -> Result<(), String> {
+    let mut consumers: HashMap<&str, &str> = HashMap::new();
+    for worker in workers {
+        for consumer in &worker.queue_consumers {
+            if let Some(other) = consumers.insert(&consumer.queue, &worker.name) {
+                return Err(format!(
+                    "Queue '{}' is consumed by both '{}' and '{}'",
+                    consumer.queue, other, worker.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 在连接线程中完成 TLS 握手、解析请求，并等待主线程返回响应
+fn serve_connection(
+    stream: TcpStream,
+    tls: Option<Arc<TlsAcceptor>>,
+    max_body_size: usize,
+    events: Sender<ServerEvent>,
+) -> Result<(), String> {
+    stream.set_read_timeout(Some(READ_TIMEOUT)).ok();
+
+    let mut conn = match tls {
+        Some(acceptor) => Connection::Tls(Box::new(acceptor.accept(stream)?)),
+        None => Connection::Plain(stream),
+    };
+
+    // 解析请求
+    let parsed = HttpRequest::from_reader(&mut BufReader::new(&mut conn), max_body_size);
+    let mut request = match parsed {
+        Ok(request) => request,
+        Err(e) if e == HttpRequest::PAYLOAD_TOO_LARGE => {
+            HttpResponse::error(413, "Payload Too Large")
+                .write_to(&mut conn)
+                .map_err(|e| format!("Failed to write response: {}", e))?;
+            conn.close();
+            return Ok(());
+        }
+        Err(e) => return Err(format!("Failed to parse request: {}", e)),
+    };
+    request.tls = conn.tls_info();
+    request.remote_addr = conn.tcp().peer_addr().ok();
+    let received = Instant::now();
+
+    // WebSocket 升级请求：先校验握手头，再交给 Worker 决定是否接受
+    let mut handshake = None;
+    let mut socket = None;
+    if websocket::is_upgrade_request(&request) {
+        match websocket::handshake_response(&request) {
+            Ok(response) => handshake = Some(response),
+            Err(e) => {
+                HttpResponse::error(400, &e)
+                    .write_to(&mut conn)
+                    .map_err(|e| format!("Failed to write response: {}", e))?;
+                conn.close();
+                return Ok(());
+            }
+        }
+        socket = Some(mpsc::channel());
+    }
+    let (socket_tx, socket_rx) = socket.unzip();
+
+    let (reply_tx, reply_rx) = mpsc::channel();
+    events
+        .send(ServerEvent::Request {
+            request: Box::new(request),
+            received,
+            reply: reply_tx,
+            socket: socket_tx,
+        })
+        .map_err(|_| "Server is shutting down".to_string())?;
+    let response = reply_rx
+        .recv()
+        .map_err(|_| "Server dropped the request".to_string())?;
+
+    if let (Some(id), Some(handshake), Some(outgoing)) = (response.websocket, handshake, socket_rx) {
+        handshake
+            .write_to(&mut conn)
+            .map_err(|e| format!("Failed to write handshake: {}", e))?;
+        websocket::run_session(&mut conn, outgoing, max_body_size, |event| {
+            events.send(ServerEvent::WebSocket { id, event }).ok();
+        });
+        return Ok(());
+    }
+
+    // 发送响应
+    response
+        .write_to(&mut conn)
+        .map_err(|e| format!("Failed to write response: {}", e))?;
+    conn.close();
+
+    Ok(())
+}
+
+/// 快速启动 Worker 服务器
+pub fn serve(script_path: &str, port: u16) -> Result<(), String> {
+    let config = ServerConfig::new("127.0.0.1", port, script_path);
+    let mut server = WorkerServer::new(config)?;
+    server.run()
+}
+
+/// 从脚本内容启动服务器
+pub fn serve_script(script: &str, port: u16) -> Result<(), String> {
+    let mut server = WorkerServer::from_script(script, "127.0.0.1", port)?;
+    server.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workers::{
+        AccessLogFormat, AssetsConfig, DurableObjectConfig, KvNamespaceConfig, QueueConsumerConfig, QueueProducerConfig,
+        ServiceBindingConfig, TlsInfo, WorkerLimits,
+    };
+    use crate::workers::bindings::KvLimits;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_server_from_script() {
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    return new Response("Test Response", { status: 200 });
+                }
+            }
+        "#;
+
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/test".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+            ..Default::default()
+        };
+
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(String::from_utf8_lossy(&response.body), "Test Response");
+    }
+
+    #[test]
+    fn test_request_properties() {
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    var url = request.url;
+                    var method = request.method;
+                    return new Response(method + " " + url, { status: 200 });
+                }
+            }
+        "#;
+
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 8080).unwrap();
+
+        let request = HttpRequest {
+            method: "POST".to_string(),
+            path: "/api/data".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+            ..Default::default()
+        };
+
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(response.status, 200);
+        assert!(String::from_utf8_lossy(&response.body).contains("POST"));
+        assert!(String::from_utf8_lossy(&response.body).contains("/api/data"));
+    }
+    
+    #[test]
+    fn test_form_data() {
+        let script = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    try {
+                        var form = await request.formData();
+                        var file = form.get("upload");
+                        var blob = new Blob(["ab", new Uint8Array([99])], { type: "Text/Plain" });
+                        return new Response([
+                            form.get("title"),
+                            file instanceof File,
+                            file.name,
+                            file.type,
+                            file.size,
+                            await file.text(),
+                            form.has("missing"),
+                            blob.type,
+                            await blob.slice(1).text()
+                        ].join("|"));
+                    } catch (e) {
+                        return new Response(String(e), { status: 400 });
+                    }
+                }
+            }
+        "#;
+
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+
+        let body = "--b0\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nhi\r\n--b0\r\nContent-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\nContent-Type: text/plain\r\n\r\nfile body\r\n--b0--\r\n";
+        let mut headers = HashMap::new();
+        headers.insert(
+            "content-type".to_string(),
+            "multipart/form-data; boundary=b0".to_string(),
+        );
+        let mut request = HttpRequest {
+            method: "POST".to_string(),
+            path: "/upload".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers,
+            body: body.as_bytes().to_vec(),
+            ..Default::default()
+        };
+
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response.body),
+            "hi|true|a.txt|text/plain|9|file body|false|text/plain|bc"
+        );
+
+        request.headers.insert("content-type".to_string(), "text/plain".to_string());
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(response.status, 400);
+        assert!(String::from_utf8_lossy(&response.body).contains("Unsupported form content type"));
+    }
+
+    #[test]
+    fn test_with_bindings() {
+        let script = r#"
+            import { KV } from 'raven/kv'
+            
+            export default {
+                fetch(request, env, ctx) {
+                    KV.put("test", "value");
+                    var value = KV.get("test");
+                    return new Response(value, { status: 200 });
+                }
+            }
+        "#;
+
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/test".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+            ..Default::default()
+        };
+
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(String::from_utf8_lossy(&response.body), "value");
+    }
+
+    #[test]
+    fn test_crypto_module() {
+        let script = r#"
+            import { CRYPTO } from 'raven/crypto'
+
+            const hex = (buffer) => Array.from(new Uint8Array(buffer), (b) => b.toString(16).padStart(2, "0")).join("");
+            const text = (buffer) => String.fromCharCode(...new Uint8Array(buffer));
+
+            export default {
+                fetch(request, env, ctx) {
+                    const mac = CRYPTO.hmac("SHA-256", "Jefe", "what do ya want for nothing?");
+                    const key = CRYPTO.randomBytes(32);
+                    const sealed = CRYPTO.aesGcmEncrypt(key, new Uint8Array([104, 105]), { additionalData: "v1" });
+                    const { publicKey, privateKey } = CRYPTO.ed25519GenerateKeyPair();
+                    const signature = CRYPTO.ed25519Sign(privateKey, "payload");
+                    const hash = CRYPTO.hashPassword("hunter2", { memoryCost: 1024, timeCost: 1 });
+                    return new Response(JSON.stringify({
+                        mac: hex(mac),
+                        macValid: CRYPTO.hmacVerify("SHA-256", "Jefe", "what do ya want for nothing?", mac),
+                        equal: CRYPTO.timingSafeEqual(new Uint8Array([1, 2]), new Uint8Array([1, 3])),
+                        plain: text(CRYPTO.aesGcmDecrypt(key, sealed, { additionalData: "v1" })),
+                        tampered: String(CRYPTO.aesGcmDecrypt(key, sealed)),
+                        signed: CRYPTO.ed25519Verify(publicKey, "payload", signature),
+                        forged: CRYPTO.ed25519Verify(publicKey, "payload!", signature),
+                        hash: text(hash).split("$")[1],
+                        password: CRYPTO.verifyPassword("hunter2", hash),
+                        wrongPassword: CRYPTO.verifyPassword("hunter3", text(hash)),
+                    }));
+                }
+            }
+        "#;
+
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        let response = server.handle_request(&get("/", None)).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert!(body["tampered"].as_str().unwrap().contains("Decryption failed"), "{}", body);
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "mac": "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                "macValid": true,
+                "equal": false,
+                "plain": "hi",
+                "tampered": body["tampered"],
+                "signed": true,
+                "forged": false,
+                "hash": "argon2id",
+                "password": true,
+                "wrongPassword": false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_scheduled_handler() {
+        let script = r#"
+            import { KV } from 'raven/kv'
+
+            export default {
+                async scheduled(event, env, ctx) {
+                    KV.put("last-cron", event.cron + "@" + event.scheduledTime);
+                },
+                fetch(request, env, ctx) {
+                    return new Response(KV.get("last-cron"), { status: 200 });
+                }
+            }
+        "#;
+
+        let mut runtime = WorkersRuntime::new();
+        runtime.load_worker(script).unwrap();
+        assert!(runtime.has_handler("scheduled"));
+        runtime.handle_scheduled("*/5 * * * *", 1700000000000).unwrap();
+
+        let request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+            ..Default::default()
+        };
+
+        let response = runtime.handle_request(&request, "127.0.0.1:0").unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response.body),
+            "*/5 * * * *@1700000000000"
+        );
+    }
+
+    #[test]
+    fn test_manual_scheduled_trigger() {
+        let script = r#"
+            import { KV } from 'raven/kv'
+
+            export default {
+                scheduled(event, env, ctx) {
+                    KV.put("last-cron", event.cron);
+                },
+                fetch(request, env, ctx) {
+                    return new Response(KV.get("last-cron") || "none", { status: 200 });
+                }
+            }
+        "#;
+
+        let mut runtime = WorkersRuntime::new();
+        runtime.load_worker(script).unwrap();
+        let mut config = ServerConfig::new("127.0.0.1", 0, "").with_cron("0 * * * *");
+        config.test_scheduled = true;
+        let mut server = WorkerServer::from_runtime(runtime, config).unwrap();
+
+        let mut request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/__raven/scheduled?cron=*%2F5+*+*+*+*".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: HashMap::new(),
+            body: Vec::new(),
+            ..Default::default()
+        };
+
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(response.status, 200);
+
+        request.path = "/".to_string();
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "*/5 * * * *");
+
+        // 未指定 cron 时使用配置的第一个
+        request.path = "/__raven/scheduled".to_string();
+        server.handle_request(&request).unwrap();
+        request.path = "/".to_string();
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "0 * * * *");
+    }
+
+    fn worker_runtime(body: &str) -> WorkersRuntime {
+        let script = format!(
+            r#"
+            export default {{
+                fetch(request, env, ctx) {{
+                    return new Response("{}", {{ status: 200 }});
+                }}
+            }}
+            "#,
+            body
+        );
+        let mut runtime = WorkersRuntime::new();
+        runtime.load_worker(&script).unwrap();
+        runtime
+    }
+
+    fn get(path: &str, host: Option<&str>) -> HttpRequest {
+        let mut headers = HashMap::new();
+        if let Some(host) = host {
+            headers.insert("host".to_string(), host.to_string());
+        }
+        HttpRequest {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers,
+            body: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_multiple_workers_dispatch() {
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        server
+            .mount(WorkerConfig::new("api", "").with_route("/api/*"), worker_runtime("api"))
+            .unwrap();
+        server
+            .mount(
+                WorkerConfig::new("admin", "").with_route("admin.example.com"),
+                worker_runtime("admin"),
+            )
+            .unwrap();
+        server
+            .mount(WorkerConfig::new("site", "").with_route("/*"), worker_runtime("site"))
+            .unwrap();
+
+        assert_eq!(server.worker_names(), vec!["api", "admin", "site"]);
+
+        let body = |server: &mut WorkerServer, path: &str, host: Option<&str>| {
+            let response = server.handle_request(&get(path, host)).unwrap();
+            String::from_utf8_lossy(&response.body).to_string()
+        };
+
+        assert_eq!(body(&mut server, "/api/users?id=1", None), "api");
+        assert_eq!(body(&mut server, "/index.html", Some("localhost:8787")), "site");
+        assert_eq!(body(&mut server, "/api/users", Some("admin.example.com")), "admin");
+
+        // 重复名称
+        assert!(server
+            .mount(WorkerConfig::new("api", ""), worker_runtime("dup"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_cache_api_shared() {
+        let script = |source: &str| {
+            let mut runtime = WorkersRuntime::new();
+            runtime.load_worker(source).unwrap();
+            runtime
+        };
+        let writer = script(
+            r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    var cache = await caches.open("pages");
+                    await cache.put("http://cache.test/page", new Response("cached body", {
+                        headers: { "Cache-Control": "max-age=60", "ETag": "\"p1\"" }
+                    }));
+                    await caches.default.put("http://cache.test/nostore", new Response("x", {
+                        headers: { "Cache-Control": "no-store" }
+                    }));
+                    return new Response("stored");
+                }
+            }
+            "#,
+        );
+        let reader = script(
+            r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    var cache = await caches.open("pages");
+                    var hit = await cache.match("http://cache.test/page");
+                    if (!hit) {
+                        return new Response("miss", { status: 404 });
+                    }
+                    var missing = await caches.default.match("http://cache.test/nostore");
+                    var deleted = await cache.delete("http://cache.test/page");
+                    var again = await cache.match("http://cache.test/page");
+                    hit.headers["x-extra"] = [missing === undefined, deleted, again === undefined].join(",");
+                    return hit;
+                }
+            }
+            "#,
+        );
+
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        server
+            .mount(WorkerConfig::new("writer", "").with_route("/write"), writer)
+            .unwrap();
+        server
+            .mount(WorkerConfig::new("reader", "").with_route("/read"), reader)
+            .unwrap();
+
+        assert_eq!(server.handle_request(&get("/read", None)).unwrap().status, 404);
+        assert_eq!(server.handle_request(&get("/write", None)).unwrap().status, 200);
+
+        let response = server.handle_request(&get("/read", None)).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(String::from_utf8_lossy(&response.body), "cached body");
+        assert_eq!(response.headers["x-raven-cache"], "HIT");
+        assert_eq!(response.headers["etag"], "\"p1\"");
+        assert_eq!(response.headers["x-extra"], "true,true,true");
+    }
+
+    #[test]
+    fn test_no_matching_worker() {
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        server
+            .mount(WorkerConfig::new("api", "").with_route("/api/*"), worker_runtime("api"))
+            .unwrap();
+
+        let response = server.handle_request(&get("/other", None)).unwrap();
+        assert_eq!(response.status, 404);
+    }
+
+    #[test]
+    fn test_worker_limits() {
+        let mut runtime = WorkersRuntime::new();
+        runtime
+            .load_worker(
+                r#"
+                export default {
+                    fetch(request, env, ctx) {
+                        while (true) {}
+                    }
+                }
+                "#,
+            )
+            .unwrap();
+
+        let limits = WorkerLimits {
+            max_body_size: 4,
+            loop_iteration_limit: Some(1000),
+            ..Default::default()
+        };
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        server
+            .mount(
+                WorkerConfig::new("loop", "").with_route("/*").with_limits(limits),
+                runtime,
+            )
+            .unwrap();
+
+        // 死循环被循环次数限制打断
+        assert!(server.handle_request(&get("/", None)).is_err());
+
+        let mut request = get("/", None);
+        request.body = b"too large".to_vec();
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(response.status, 413);
+    }
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let mut runtime = WorkersRuntime::new();
+        runtime
+            .load_worker(
+                r#"
+                import { KV } from 'raven/kv'
+
+                export default {
+                    fetch(request, env, ctx) {
+                        if (request.url.indexOf("/fail") !== -1) {
+                            throw new Error("boom");
+                        }
+                        KV.get("visits");
+                        return new Response("ok", { status: 200 });
+                    }
+                }
+                "#,
+            )
+            .unwrap();
+
+        let mut config = ServerConfig::new("127.0.0.1", 0, "");
+        config.metrics = true;
+        config.access_log = AccessLogFormat::Off;
+        let mut server = WorkerServer::from_runtime(runtime, config).unwrap();
+
+        let now = Instant::now();
+        assert_eq!(server.respond(&get("/", None), now).status, 200);
+        assert_eq!(server.respond(&get("/fail", None), now).status, 500);
+
+        let response = server.respond(&get("/__raven/metrics", None), now);
+        assert_eq!(response.status, 200);
+        let text = String::from_utf8_lossy(&response.body);
+        assert!(text.contains("raven_worker_requests_total{worker=\"default\",method=\"GET\",status=\"200\"} 1"));
+        assert!(text.contains("raven_worker_requests_total{worker=\"default\",method=\"GET\",status=\"500\"} 1"));
+        assert!(text.contains("raven_worker_request_duration_seconds_count{worker=\"default\"} 2"));
+        assert!(text.contains("raven_worker_script_errors_total{worker=\"default\",handler=\"fetch\"} 1"));
+        assert!(text.contains("raven_worker_binding_calls_total{worker=\"default\",binding=\"KV\",method=\"get\"} 1"));
+    }
+
+    #[test]
+    fn test_response_compression() {
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    var body = "compressible text ".repeat(200);
+                    if (request.url.endsWith("/manual")) {
+                        return new Response(body, { encodeBody: "manual" });
+                    }
+                    return new Response(body, { headers: { "content-type": "text/plain" } });
+                }
+            }
+        "#;
+
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        let mut headers = HashMap::new();
+        headers.insert("accept-encoding".to_string(), "gzip, br".to_string());
+        let mut request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers,
+            ..Default::default()
+        };
+
+        let response = server.respond(&request, Instant::now());
+        assert_eq!(response.headers["content-encoding"], "br");
+        assert_eq!(response.headers["vary"], "Accept-Encoding");
+        assert_eq!(response.headers["content-length"], response.body.len().to_string());
+        assert!(response.body.len() < 3600);
+
+        request.path = "/manual".to_string();
+        let response = server.respond(&request, Instant::now());
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert_eq!(response.body.len(), 3600);
+    }
+
+    #[test]
+    fn test_compression_ignores_worker_framing_headers() {
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    var body = "compressible text ".repeat(200);
+                    return new Response(body, {
+                        headers: {
+                            "content-type": "text/plain",
+                            "Transfer-Encoding": "chunked",
+                            "content-length": String(body.length),
+                        },
+                    });
+                }
+            }
+        "#;
+
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        let mut request = get("/", None);
+        request.headers.insert("accept-encoding".to_string(), "gzip".to_string());
+
+        let response = server.respond(&request, Instant::now());
+        assert_eq!(response.headers["content-encoding"], "gzip");
+        assert!(!response.headers.keys().any(|k| k.eq_ignore_ascii_case("transfer-encoding")));
+        assert_eq!(response.headers["content-length"], response.body.len().to_string());
+
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&response.body[..]), &mut decoded).unwrap();
+        assert_eq!(decoded, "compressible text ".repeat(200));
+
+        let mut wire = Vec::new();
+        response.write_to(&mut wire).unwrap();
+        let head_len = wire.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        assert_eq!(wire.len() - head_len, response.body.len());
+    }
+
+    #[test]
+    fn test_wait_until() {
+        let script = r#"
+            import { KV } from 'raven/kv'
+
+            export default {
+                fetch(request, env, ctx) {
+                    ctx.waitUntil(Promise.resolve().then(() => KV.put("audit", "done")));
+                    ctx.waitUntil(Promise.reject(new Error("audit failed")));
+                    return new Response(KV.get("audit") || "pending", { status: 200 });
+                }
+            }
+        "#;
+        let mut runtime = WorkersRuntime::new();
+        runtime.load_worker(script).unwrap();
+
+        let response = runtime.handle_request(&get("/", None), "127.0.0.1:0").unwrap();
+        assert_eq!(response.body, b"pending");
+        assert_eq!(runtime.pending_wait_until(), 2);
+
+        let errors = runtime.run_wait_until();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("audit failed"));
+        assert_eq!(runtime.pending_wait_until(), 0);
+
+        let response = runtime.handle_request(&get("/", None), "127.0.0.1:0").unwrap();
+        assert_eq!(response.body, b"done");
+    }
+
+    #[test]
+    fn test_graceful_shutdown() {
+        use std::io::{Read, Write};
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    return new Response("still serving", { status: 200 });
+                }
+            }
+        "#;
+
+        let (handle_tx, handle_rx) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let mut server = WorkerServer::from_script(script, "127.0.0.1", port).unwrap();
+            server.config.access_log = AccessLogFormat::Off;
+            handle_tx.send(server.handle()).unwrap();
+            server.run()
+        });
+        let handle = handle_rx.recv().unwrap();
+
+        let mut stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        // 请求还没发完时开始关闭，已接受的连接仍会得到响应
+        stream.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        thread::sleep(Duration::from_millis(200));
+        handle.shutdown();
+        thread::sleep(Duration::from_millis(200));
+        stream.write_all(b"Host: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("still serving"));
+
+        server.join().unwrap().unwrap();
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("raven-server-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script_path = dir.join("worker.js");
+        let script = |body: &str| {
+            format!(
+                "export default {{ fetch(request, env, ctx) {{ return new Response(\"{}\"); }} }}",
+                body
+            )
+        };
+        fs::write(&script_path, script("v1")).unwrap();
+
+        let path = script_path.to_string_lossy().into_owned();
+        let mut server = WorkerServer::new(ServerConfig::new("127.0.0.1", 0, &path)).unwrap();
+        server.mount(WorkerConfig::new("api", "").with_route("/api/*"), worker_runtime("api")).unwrap();
+        assert_eq!(server.handle_request(&get("/", None)).unwrap().body, b"v1");
+
+        fs::write(&script_path, script("v2")).unwrap();
+        server.reload().unwrap();
+        assert_eq!(server.handle_request(&get("/", None)).unwrap().body, b"v2");
+        // 没有脚本路径的 Worker 保持原样
+        assert_eq!(server.handle_request(&get("/api/x", None)).unwrap().body, b"api");
+
+        // 脚本有错误时保留原来的 Worker
+        fs::write(&script_path, "export default {").unwrap();
+        assert!(server.reload().is_err());
+        assert_eq!(server.handle_request(&get("/", None)).unwrap().body, b"v2");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_reload_mount_failure_keeps_workers() {
+        let dir = std::env::temp_dir().join(format!("raven-server-reload-mount-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = |body: &str| {
+            format!(
+                "export default {{ fetch(request, env, ctx) {{ return new Response(\"{}\"); }} }}",
+                body
+            )
+        };
+        fs::write(dir.join("a.js"), script("a1")).unwrap();
+        fs::write(dir.join("b.js"), script("b1")).unwrap();
+        let manifest = |producers: &str| {
+            format!(
+                "[server]\nqueue_dir = \"queues\"\n\n\
+                 [[workers]]\nname = \"a\"\nmain = \"a.js\"\nroutes = [\"/a\"]\n\n\
+                 [[workers]]\nname = \"b\"\nmain = \"b.js\"\nroutes = [\"/b\"]\n{}",
+                producers
+            )
+        };
+        let manifest_path = dir.join("raven.toml");
+        fs::write(&manifest_path, manifest("")).unwrap();
+        let mut server = WorkerServer::from_manifest(manifest_path.to_str().unwrap()).unwrap();
+
+        // 第二个 Worker 的队列名不合法，挂载失败时两个 Worker 都保持原样
+        fs::write(dir.join("a.js"), script("a2")).unwrap();
+        fs::write(&manifest_path, manifest("\n[[workers.queues.producers]]\nbinding = \"JOBS\"\nqueue = \"bad/name\"\n")).unwrap();
+        let err = server.reload().unwrap_err();
+        assert!(err.contains("Failed to mount worker 'b'"), "{}", err);
+        assert_eq!(server.handle_request(&get("/a", None)).unwrap().body, b"a1");
+        assert_eq!(server.handle_request(&get("/b", None)).unwrap().body, b"b1");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_mount_failure_adds_no_routes() {
+        let dir = std::env::temp_dir().join(format!("raven-server-mount-{}", rand::random::<u64>()));
+        let mut config = ServerConfig::new("127.0.0.1", 0, "");
+        config.queue_dir = Some(dir.to_string_lossy().into_owned());
+        let mut server = WorkerServer::empty(config);
+
+        let worker = WorkerConfig::new("bad", "")
+            .with_route("/*")
+            .with_queue_producer(QueueProducerConfig::new("JOBS", "bad/name"));
+        let mut runtime = worker_runtime("bad");
+        runtime.configure(&worker).unwrap();
+        assert!(server.mount(worker, runtime).is_err());
+
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(response.status, 404);
+        server.mount(WorkerConfig::new("ok", "").with_route("/*"), worker_runtime("ok")).unwrap();
+        assert_eq!(server.handle_request(&get("/", None)).unwrap().body, b"ok");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_require_client_cert() {
+        let mut config = WorkerConfig::new("internal", "").with_route("/*");
+        config.require_client_cert = true;
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        server.mount(config, worker_runtime("internal")).unwrap();
+
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(response.status, 403);
+
+        let mut request = get("/", None);
+        request.tls = Some(TlsInfo {
+            server_name: Some("internal.example.com".to_string()),
+            client_certificate: Some(vec![0x30]),
+        });
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(response.status, 200);
+    }
+
+    #[test]
+    fn test_websocket_echo() {
+        let script = r#"
+            var clients = [];
+
+            export default {
+                fetch(request, env, ctx) {
+                    if (request.headers.get("upgrade") !== "websocket") {
+                        return new Response("expected websocket", { status: 426 });
+                    }
+                    const [client, server] = Object.values(new WebSocketPair());
+                    server.accept();
+                    server.send("welcome");
+                    server.addEventListener("message", (event) => {
+                        server.send("echo: " + event.data);
+                    });
+                    server.addEventListener("close", (event) => {
+                        clients.splice(clients.indexOf(server), 1);
+                    });
+                    clients.push(server);
+                    return new Response(null, { status: 101, webSocket: client });
+                },
+                scheduled(event, env, ctx) {
+                    clients.forEach((ws) => ws.send("tick " + clients.length));
+                }
+            }
+        "#;
+
+        let mut runtime = WorkersRuntime::new();
+        runtime.load_worker(script).unwrap();
+
+        let mut request = get("/live", None);
+        request.headers.insert("upgrade".to_string(), "websocket".to_string());
+        let response = runtime.handle_request(&request, "127.0.0.1:0").unwrap();
+        assert_eq!(response.status, 101);
+        let id = response.websocket.unwrap();
+
+        // 握手前发送的消息在绑定连接后发出
+        let (tx, rx) = mpsc::channel();
+        websocket::attach(id, tx);
+        let text = |outgoing: Outgoing| match outgoing {
+            Outgoing::Message(crate::workers::Message::Text(text)) => text,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(text(rx.try_recv().unwrap()), "welcome");
+
+        let event = WebSocketEvent::Message(crate::workers::Message::Text("hi".to_string()));
+        runtime.handle_websocket_event(id, &event).unwrap();
+        assert_eq!(text(rx.try_recv().unwrap()), "echo: hi");
+
+        runtime.handle_scheduled("* * * * *", 0).unwrap();
+        assert_eq!(text(rx.try_recv().unwrap()), "tick 1");
+
+        let close = WebSocketEvent::Close {
+            code: 1000,
+            reason: String::new(),
+        };
+        runtime.handle_websocket_event(id, &close).unwrap();
+        runtime.handle_scheduled("* * * * *", 0).unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_websocket_without_upgrade() {
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    const pair = new WebSocketPair();
+                    pair[1].accept();
+                    return new Response(null, { status: 101, webSocket: pair[0] });
+                }
+            }
+        "#;
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+
+        let request = get("/", None);
+        let response = server.handle_request(&request).unwrap();
+        let response = server.accept_websocket(&request, response, None);
+        assert_eq!(response.status, 426);
+    }
+
+    #[test]
+    fn test_static_assets() {
+        let dir = std::env::temp_dir().join(format!("raven-server-assets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.html"), "<h1>static</h1>").unwrap();
+        fs::write(dir.join("logo.bin"), [0u8, 159, 146, 150]).unwrap();
+
+        let script = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    if (request.url.indexOf("/api/") !== -1) {
+                        return new Response("api", { status: 200 });
+                    }
+                    // 回退到静态资源
+                    const asset = await env.ASSETS.fetch(request);
+                    if (asset.status === 404) {
+                        return env.ASSETS.fetch("http://localhost/index.html");
+                    }
+                    return asset;
+                }
+            }
+        "#;
+        let directory = dir.to_string_lossy().into_owned();
+        let config = WorkerConfig::new("site", "")
+            .with_route("/*")
+            .with_assets(AssetsConfig::new(&directory));
+        let mut runtime = WorkersRuntime::new();
+        runtime.configure(&config).unwrap();
+        runtime.load_worker(script).unwrap();
+
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        server.mount(config, runtime).unwrap();
+
+        // 资源直接由服务器返回
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(response.body, b"<h1>static</h1>");
+        assert_eq!(response.headers["content-type"], "text/html");
+
+        let response = server.handle_request(&get("/api/users", None)).unwrap();
+        assert_eq!(response.body, b"api");
+
+        // 没有匹配的文件时调用 fetch，由 Worker 回退到 index.html（SPA）
+        let response = server.handle_request(&get("/app/settings", None)).unwrap();
+        assert_eq!(response.body, b"<h1>static</h1>");
+
+        // env.ASSETS.fetch 保留二进制内容
+        let mut config = WorkerConfig::new("site", "").with_route("/*");
+        let mut assets = AssetsConfig::new(&directory);
+        assets.run_worker_first = true;
+        config.assets = Some(assets);
+        let mut runtime = WorkersRuntime::new();
+        runtime.configure(&config).unwrap();
+        runtime.load_worker(script).unwrap();
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        server.mount(config, runtime).unwrap();
+        let response = server.handle_request(&get("/logo.bin", None)).unwrap();
+        assert_eq!(response.body, vec![0u8, 159, 146, 150]);
+        assert_eq!(response.headers["content-type"], "application/octet-stream");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_env_from_config() {
+        let script = r#"
+            import { CACHE } from 'raven/kv'
+
+            export default {
+                fetch(request, env, ctx) {
+                    env.CACHE.put("greeting", "hello");
+                    // 全局 import 与 env 上的绑定是同一个命名空间
+                    return new Response(
+                        env.API_BASE + "|" + env.TOKEN + "|" + CACHE.get("greeting"),
+                        { status: 200 }
+                    );
+                }
+            }
+        "#;
+
+        let config = WorkerConfig::new("api", "")
+            .with_route("/*")
+            .with_var("API_BASE", "https://internal")
+            .with_secret("TOKEN", "s3cret")
+            .with_kv_namespace(KvNamespaceConfig::memory("CACHE", "api-cache"));
+
+        let mut runtime = WorkersRuntime::new();
+        runtime.configure(&config).unwrap();
+        runtime.load_worker(script).unwrap();
+
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        server.mount(config, runtime).unwrap();
+
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response.body),
+            "https://internal|s3cret|hello"
+        );
+    }
+
+    #[test]
+    fn test_kv_api() {
+        let script = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    await env.KV.put("config", JSON.stringify({ theme: "dark" }), {
+                        metadata: { version: 2, tags: ["a", "b"] },
+                        expirationTtl: 600,
+                    });
+                    await env.KV.put("blob", new Uint8Array([0, 255, 7]));
+                    for (const n of [1, 2, 3]) {
+                        await env.KV.put("page:" + n, String(n));
+                    }
+
+                    const config = await env.KV.get("config", { type: "json" });
+                    const blob = await env.KV.get("blob", "arrayBuffer");
+                    const { value, metadata } = await env.KV.getWithMetadata("config", "json");
+
+                    const names = [];
+                    let cursor = undefined;
+                    let pages = 0;
+                    while (true) {
+                        const page = await env.KV.list({ prefix: "page:", limit: 2, cursor });
+                        pages++;
+                        names.push(...page.keys.map(k => k.name));
+                        if (page.list_complete) break;
+                        cursor = page.cursor;
+                    }
+                    const listed = (await env.KV.list({ prefix: "config" })).keys[0];
+
+                    return new Response(JSON.stringify({
+                        theme: config.theme,
+                        bytes: Array.from(new Uint8Array(blob)),
+                        value: value.theme,
+                        metadata,
+                        names,
+                        pages,
+                        expires: listed.expiration > Date.now() / 1000,
+                        listedMetadata: listed.metadata.version,
+                    }));
+                }
+            }
+        "#;
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        let config = WorkerConfig::new("", "")
+            .with_route("/*")
+            .with_kv_namespace(KvNamespaceConfig::memory("KV", "kv-api"));
+        service_worker("api", config, script, &mut server);
+
+        let response = server.handle_request(&get("/", None)).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "theme": "dark",
+                "bytes": [0, 255, 7],
+                "value": "dark",
+                "metadata": { "version": 2, "tags": ["a", "b"] },
+                "names": ["page:1", "page:2", "page:3"],
+                "pages": 2,
+                "expires": true,
+                "listedMetadata": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_kv_atomic_api() {
+        let script = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    const created = await env.KV.compareAndSwap("doc", null, "v1");
+                    const duplicate = await env.KV.compareAndSwap("doc", null, "x");
+                    const { version } = await env.KV.getWithMetadata("doc");
+                    const updated = await env.KV.compareAndSwap("doc", version, "v2");
+                    const stale = await env.KV.compareAndSwap("doc", version, "v3");
+
+                    await env.KV.increment("visits");
+                    const visits = await env.KV.increment("visits", 4);
+
+                    const commit = () => env.KV.atomic()
+                        .check("order:1", null)
+                        .put("order:1", "pending", { metadata: { items: 2 } })
+                        .increment("orders")
+                        .delete("doc")
+                        .commit();
+                    const first = await commit();
+                    const second = await commit();
+
+                    return new Response(JSON.stringify({
+                        created: created.ok,
+                        duplicate: duplicate.ok,
+                        updated: updated.ok && updated.version > version,
+                        stale: stale.ok,
+                        visits,
+                        first: first.ok,
+                        second: second.ok,
+                        orders: await env.KV.get("orders"),
+                        order: await env.KV.getWithMetadata("order:1", "text"),
+                        doc: await env.KV.get("doc"),
+                    }));
+                }
+            }
+        "#;
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        let config = WorkerConfig::new("", "")
+            .with_route("/*")
+            .with_kv_namespace(KvNamespaceConfig::memory("KV", "kv-atomic"));
+        service_worker("atomic", config, script, &mut server);
+
+        let response = server.handle_request(&get("/", None)).unwrap();
+        let mut body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert!(body["order"]["version"].is_string());
+        body["order"].as_object_mut().unwrap().remove("version");
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "created": true,
+                "duplicate": false,
+                "updated": true,
+                "stale": false,
+                "visits": 5,
+                "first": true,
+                "second": false,
+                "orders": "1",
+                "order": { "value": "pending", "metadata": { "items": 2 } },
+                "doc": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_kv_bulk_api() {
+        let script = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    await env.KV.putMany([
+                        { key: "user:1", value: "ann", metadata: { plan: "pro" } },
+                        { key: "user:2", value: "bob", expirationTtl: 3600 },
+                        { key: "user:3", value: "cat" },
+                    ]);
+                    await env.KV.deleteMany(["user:1", "user:3"]);
+                    const error = await env.KV.putMany([{ value: "no key" }]);
+                    const { keys } = await env.KV.list({ prefix: "user:" });
+                    return new Response(JSON.stringify({
+                        keys: keys.map((key) => key.name),
+                        expires: typeof keys[0].expiration,
+                        error,
+                    }));
+                }
+            }
+        "#;
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        let config = WorkerConfig::new("", "")
+            .with_route("/*")
+            .with_kv_namespace(KvNamespaceConfig::memory("KV", "kv-bulk"));
+        service_worker("bulk", config, script, &mut server);
+
+        let response = server.handle_request(&get("/", None)).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["keys"], serde_json::json!(["user:2"]));
+        assert_eq!(body["expires"], "number");
+        assert!(body["error"].as_str().unwrap().contains("string key"), "{}", body);
+    }
+
+    #[test]
+    fn test_kv_watch() {
+        let watcher = r#"
+            const seen = [];
+            let watch = null;
+
+            export default {
+                async fetch(request, env, ctx) {
+                    if (request.url.endsWith("/start")) {
+                        watch = env.CONFIG.watch("flags:", async (change) => {
+                            seen.push(change.type + ":" + change.key + "=" + (change.value ?? ""));
+                            if (change.key === "flags:boom") throw new Error("callback failed");
+                        });
+                        return new Response("watching");
+                    }
+                    if (request.url.endsWith("/stop")) {
+                        watch.unsubscribe();
+                        return new Response("stopped");
+                    }
+                    return new Response(seen.join(","));
+                }
+            }
+        "#;
+        let writer = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    const [key, value] = request.url.split("?")[1].split("=");
+                    if (value) {
+                        await env.FLAGS.put(key, value);
+                    } else {
+                        await env.FLAGS.delete(key);
+                    }
+                    return new Response("ok");
+                }
+            }
+        "#;
+        let id = format!("flags-{}", rand::random::<u64>());
+        let request = |server: &mut WorkerServer, path: &str| {
+            let response = server.handle_request(&get(path, None)).unwrap();
+            String::from_utf8_lossy(&response.body).into_owned()
+        };
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker(
+            "watcher",
+            WorkerConfig::new("", "")
+                .with_route("/watch/*")
+                .with_kv_namespace(KvNamespaceConfig::memory("CONFIG", &id)),
+            watcher,
+            &mut server,
+        );
+        service_worker(
+            "writer",
+            WorkerConfig::new("", "")
+                .with_route("/write")
+                .with_kv_namespace(KvNamespaceConfig::memory("FLAGS", &id)),
+            writer,
+            &mut server,
+        );
+
+        assert_eq!(request(&mut server, "/watch/start"), "watching");
+        request(&mut server, "/write?flags:beta=on");
+        request(&mut server, "/write?other=x");
+        request(&mut server, "/write?flags:beta=");
+        // 回调在事件循环中执行，之前还没有分发
+        assert_eq!(request(&mut server, "/watch/list"), "");
+
+        assert!(server.has_kv_watches());
+        server.run_background_tasks();
+        assert_eq!(request(&mut server, "/watch/list"), "put:flags:beta=on,delete:flags:beta=");
+
+        // 回调中的错误记录到指标中，不影响后续分发
+        request(&mut server, "/write?flags:boom=1");
+        server.run_background_tasks();
+        server.run_background_tasks();
+        let metrics = server.render_metrics();
+        assert!(
+            String::from_utf8_lossy(&metrics.body)
+                .contains("raven_worker_script_errors_total{worker=\"watcher\",handler=\"watch\"} 1")
+        );
+
+        assert_eq!(request(&mut server, "/watch/stop"), "stopped");
+        request(&mut server, "/write?flags:after=1");
+        assert!(!server.has_kv_watches());
+        server.run_background_tasks();
+        assert_eq!(request(&mut server, "/watch/list"), "put:flags:beta=on,delete:flags:beta=,put:flags:boom=1");
+    }
+
+    #[test]
+    fn test_kv_limits_and_stats() {
+        let script = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    for (const key of ["a", "b", "c"]) {
+                        await env.CACHE.put(key, "value");
+                    }
+                    await env.CACHE.get("a");
+                    await env.CACHE.get("b");
+                    return new Response(JSON.stringify(await env.CACHE.stats()));
+                }
+            }
+        "#;
+        let namespace = KvNamespaceConfig::memory("CACHE", &format!("limited-{}", rand::random::<u64>())).with_limits(
+            KvLimits {
+                max_entries: Some(2),
+                ..KvLimits::default()
+            },
+        );
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker("cache", WorkerConfig::new("", "").with_route("/*").with_kv_namespace(namespace), script, &mut server);
+
+        let response = server.handle_request(&get("/", None)).unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "entries": 2,
+                "bytes": 12,
+                "hits": 1,
+                "misses": 1,
+                "evictions": 1,
+                "maxEntries": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_persistent_kv_backends() {
+        let script = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    const count = Number((await env.STATS.get("visits")) || 0) + 1;
+                    await env.STATS.put("visits", String(count));
+                    return new Response(String(count));
+                }
+            }
+        "#;
+        let dir = std::env::temp_dir().join(format!("raven-kv-server-{}", rand::random::<u64>()));
+        let file = dir.join("stats.kvlog").to_string_lossy().into_owned();
+        let sqlite = dir.join("stats.sqlite").to_string_lossy().into_owned();
+
+        // 计数在重新创建服务器后继续累加
+        for namespace in [
+            KvNamespaceConfig::file("STATS", "stats-file", &file),
+            KvNamespaceConfig::sqlite("STATS", "stats-sqlite", &sqlite),
+        ] {
+            let config = WorkerConfig::new("api", "").with_route("/*").with_kv_namespace(namespace.clone());
+            for expected in ["1", "2"] {
+                let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+                service_worker("api", config.clone(), script, &mut server);
+                let response = server.handle_request(&get("/", None)).unwrap();
+                assert_eq!(String::from_utf8_lossy(&response.body), expected);
+            }
+
+            let missing = KvNamespaceConfig { id: format!("{}-missing", namespace.id), path: None, ..namespace };
+            let mut runtime = WorkersRuntime::new();
+            let err = runtime.configure(&WorkerConfig::new("api", "").with_kv_namespace(missing)).unwrap_err();
+            assert!(err.contains("has no path"), "{}", err);
+        }
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_shared_kv_namespaces() {
+        let writer = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    await env.SESSIONS.put("user", request.url.split("?")[1]);
+                    return new Response("ok");
+                }
+            }
+        "#;
+        let reader = r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    return new Response((await env.STORE.get("user")) || "none");
+                }
+            }
+        "#;
+        let id = format!("sessions-{}", rand::random::<u64>());
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker(
+            "writer",
+            WorkerConfig::new("", "")
+                .with_route("/write")
+                .with_kv_namespace(KvNamespaceConfig::memory("SESSIONS", &id)),
+            writer,
+            &mut server,
+        );
+        // 绑定名称不同，命名空间 ID 相同
+        service_worker(
+            "reader",
+            WorkerConfig::new("", "")
+                .with_route("/read")
+                .with_kv_namespace(KvNamespaceConfig::memory("STORE", &id)),
+            reader,
+            &mut server,
+        );
+
+        assert_eq!(server.handle_request(&get("/read", None)).unwrap().body, b"none");
+        assert_eq!(server.handle_request(&get("/write?alice", None)).unwrap().body, b"ok");
+        assert_eq!(server.handle_request(&get("/read", None)).unwrap().body, b"alice");
+
+        // 重新创建的运行时（如热重载）仍然看到同样的数据
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker(
+            "reader",
+            WorkerConfig::new("", "")
+                .with_route("/read")
+                .with_kv_namespace(KvNamespaceConfig::memory("STORE", &id)),
+            reader,
+            &mut server,
+        );
+        assert_eq!(server.handle_request(&get("/read", None)).unwrap().body, b"alice");
+
+        // 同一 ID 不能换成别的后端
+        let mut runtime = WorkersRuntime::new();
+        let config = WorkerConfig::new("", "").with_kv_namespace(KvNamespaceConfig::sqlite("STORE", &id, "/tmp/unused.sqlite"));
+        let err = runtime.configure(&config).unwrap_err();
+        assert!(err.contains("already open"), "{}", err);
+    }
+
+    #[test]
+    fn test_unsupported_kv_backend() {
+        let mut namespace = KvNamespaceConfig::memory("CACHE", "api-cache");
+        namespace.backend = "redis".to_string();
+        let config = WorkerConfig::new("api", "").with_kv_namespace(namespace);
+
+        let mut runtime = WorkersRuntime::new();
+        assert!(runtime.configure(&config).is_err());
+    }
+    fn service_worker(name: &str, config: WorkerConfig, script: &str, server: &mut WorkerServer) {
+        let config = WorkerConfig { name: name.to_string(), ..config };
+        let mut runtime = WorkersRuntime::new();
+        runtime.configure(&config).unwrap();
+        runtime.load_worker(script).unwrap();
+        server.mount(config, runtime).unwrap();
+    }
+
+    #[test]
+    fn test_service_bindings() {
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker(
+            "gateway",
+            WorkerConfig::new("", "")
+                .with_route("/*")
+                .with_service(ServiceBindingConfig::new("AUTH", "auth")),
+            r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    const response = await env.AUTH.fetch("https://auth.internal/check?user=1");
+                    return new Response(response.status + "|" + response.getBody());
+                }
+            }
+            "#,
+            &mut server,
+        );
+        service_worker(
+            "auth",
+            WorkerConfig::new("", "").with_route("auth.example.com"),
+            r#"
+            export default {
+                fetch(request, env, ctx) {
+                    return new Response(
+                        request.url + "|" +
+                        request.headers.get("x-request-id") + "|" +
+                        request.headers.get("x-raven-service-chain"),
+                        { status: 201 }
+                    );
+                }
+            }
+            "#,
+            &mut server,
+        );
+
+        let mut request = get("/", None);
+        request.headers.insert("x-request-id".to_string(), "req-42".to_string());
+        // 来自网络的调用链会被移除
+        request
+            .headers
+            .insert("x-raven-service-chain".to_string(), "auth".to_string());
+        let response = server.handle_request(&request).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response.body),
+            "201|https://auth.internal/check?user=1|req-42|gateway"
+        );
+    }
+
+    #[test]
+    fn test_service_binding_cycle() {
+        let script = |target: &str| {
+            format!(
+                r#"
+                export default {{
+                    async fetch(request, env, ctx) {{
+                        try {{
+                            const response = await env.{}.fetch("http://localhost/");
+                            return new Response(response.getBody(), {{ status: response.status }});
+                        }} catch (e) {{
+                            return new Response(e.message, {{ status: 508 }});
+                        }}
+                    }}
+                }}
+                "#,
+                target
+            )
+        };
+
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker(
+            "a",
+            WorkerConfig::new("", "")
+                .with_route("/*")
+                .with_service(ServiceBindingConfig::new("B", "b")),
+            &script("B"),
+            &mut server,
+        );
+        service_worker(
+            "b",
+            WorkerConfig::new("", "").with_service(ServiceBindingConfig::new("A", "a")),
+            &script("A"),
+            &mut server,
+        );
+
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(response.status, 508);
+        assert_eq!(
+            String::from_utf8_lossy(&response.body),
+            "Service call cycle detected: a -> b -> a"
+        );
+    }
+
+    #[test]
+    fn test_unknown_service_target() {
+        let worker =
+            WorkerConfig::new("api", "").with_service(ServiceBindingConfig::new("AUTH", "auth"));
+        let config = ServerConfig::new("127.0.0.1", 0, "").with_worker(worker);
+        let err = WorkerServer::new(config).err().unwrap();
+        assert!(err.contains("unknown worker 'auth'"), "{}", err);
+    }
+    #[test]
+    fn test_queue_producer_consumer() {
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker(
+            "api",
+            WorkerConfig::new("", "")
+                .with_route("/send")
+                .with_queue_producer(QueueProducerConfig::new("JOBS", "jobs")),
+            r#"
+            export default {
+                async fetch(request, env, ctx) {
+                    await env.JOBS.send({ n: 1 });
+                    await env.JOBS.sendBatch([{ body: { n: 2 } }, { body: { n: 3, fail: true } }]);
+                    return new Response("queued");
+                }
+            }
+            "#,
+            &mut server,
+        );
+        let mut consumer = QueueConsumerConfig::new("jobs").with_dead_letter_queue("jobs-failed");
+        consumer.max_retries = 1;
+        service_worker(
+            "jobs",
+            WorkerConfig::new("", "")
+                .with_route("/results")
+                .with_queue_consumer(consumer)
+                .with_queue_consumer(QueueConsumerConfig::new("jobs-failed")),
+            r#"
+            var seen = [];
+            export default {
+                fetch(request, env, ctx) {
+                    return new Response(seen.join(","));
+                },
+                queue(batch, env, ctx) {
+                    for (const message of batch.messages) {
+                        if (batch.queue === "jobs" && message.body.fail) {
+                            message.retry();
+                            continue;
+                        }
+                        seen.push(batch.queue + ":" + message.body.n + "@" + message.attempts);
+                    }
+                }
+            }
+            "#,
+            &mut server,
+        );
+
+        assert!(!server.run_queues());
+        let response = server.handle_request(&get("/send", None)).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "queued");
+
+        // 第一轮确认 1、2，重试 3；第二轮 3 超过重试次数转入死信队列并被消费
+        assert!(server.run_queues());
+        assert!(server.run_queues());
+        assert!(!server.run_queues());
+
+        let response = server.handle_request(&get("/results", None)).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response.body),
+            "jobs:1@1,jobs:2@1,jobs-failed:3@1"
+        );
+
+        let config = ServerConfig::new("127.0.0.1", 0, "")
+            .with_worker(WorkerConfig::new("a", "").with_queue_consumer(QueueConsumerConfig::new("jobs")))
+            .with_worker(WorkerConfig::new("b", "").with_queue_consumer(QueueConsumerConfig::new("jobs")));
+        let err = WorkerServer::new(config).err().unwrap();
+        assert!(err.contains("consumed by both"), "{}", err);
+    }
+
+    #[test]
+    fn test_queue_handler_failure_retries() {
+        let script = r#"
+            var calls = 0;
+            export default {
+                fetch(request, env, ctx) {
+                    return new Response(String(calls));
+                },
+                queue(batch, env, ctx) {
+                    calls++;
+                    for (const message of batch.messages) {
+                        if (message.body === "acked") {
+                            message.ack();
+                        }
+                    }
+                    throw new Error("boom");
+                }
+            }
+        "#;
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker(
+            "jobs",
+            WorkerConfig::new("", "")
+                .with_route("/*")
+                .with_queue_producer(QueueProducerConfig::new("JOBS", "jobs"))
+                .with_queue_consumer(QueueConsumerConfig::new("jobs")),
+            script,
+            &mut server,
+        );
+
+        let queue = server.queues.get("jobs").unwrap();
+        queue.send(serde_json::json!("acked"), Duration::ZERO).unwrap();
+        queue.send(serde_json::json!("retried"), Duration::ZERO).unwrap();
+
+        // 显式确认的消息不再投递，其余消息最多交付 max_retries + 1 次后丢弃
+        for _ in 0..4 {
+            assert!(server.run_queues());
+        }
+        assert!(!server.run_queues());
+        assert!(queue.is_empty());
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "4");
+    }
+
+    #[test]
+    fn test_queue_consumer_errors_keep_messages() {
+        let script = r#"
+            export default {
+                queue(batch, env, ctx) {
+                    for (const message of batch.messages) {
+                        if (message.body === "huge-delay") {
+                            message.retry({ delaySeconds: 1e30 });
+                        } else if (message.body === "dead") {
+                            message.retry();
+                        } else {
+                            message.ack();
+                        }
+                    }
+                }
+            }
+        "#;
+        let dir = std::env::temp_dir().join(format!("raven-queue-errors-{}", rand::random::<u64>()));
+        let mut config = ServerConfig::new("127.0.0.1", 0, "");
+        config.queue_dir = Some(dir.to_string_lossy().into_owned());
+        let mut server = WorkerServer::empty(config);
+        let mut failing = QueueConsumerConfig::new("failing").with_dead_letter_queue("bad/name");
+        failing.max_retries = 0;
+        service_worker(
+            "jobs",
+            WorkerConfig::new("", "")
+                .with_queue_consumer(QueueConsumerConfig::new("delays"))
+                .with_queue_consumer(failing),
+            script,
+            &mut server,
+        );
+
+        // 不合法的重试延迟不会让 Consumer 崩溃，整批消息按失败重试
+        let delays = server.queues.get("delays").unwrap();
+        delays.send(serde_json::json!("huge-delay"), Duration::ZERO).unwrap();
+        delays.send(serde_json::json!("ok"), Duration::ZERO).unwrap();
+        assert!(server.run_queues());
+        assert_eq!(delays.len(), 2);
+        assert_eq!(delays.receive(10, Duration::from_secs(30)).unwrap().len(), 2);
+
+        // 死信队列写入失败时，同一批中已处理的消息仍然被确认
+        let failing = server.queues.get("failing").unwrap();
+        failing.send(serde_json::json!("dead"), Duration::ZERO).unwrap();
+        failing.send(serde_json::json!("ok"), Duration::ZERO).unwrap();
+        assert!(server.run_queues());
+        assert_eq!(failing.len(), 1);
+        assert!(failing.receive(10, Duration::from_secs(30)).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_durable_object_counter() {
+        let script = r#"
+            export class Counter {
+                constructor(state, env) {
+                    this.state = state;
+                    this.loaded = 0;
+                    state.blockConcurrencyWhile(async () => {
+                        this.loaded = (await state.storage.get("count")) || 0;
+                    });
+                }
+
+                async fetch(request) {
+                    if (request.method === "POST") {
+                        // 读取和写入之间让出执行权，请求未串行化时两次递增会读到同一个值
+                        const count = (await this.state.storage.get("count")) || 0;
+                        await Promise.resolve();
+                        await this.state.storage.put("count", count + 1);
+                        return new Response(String(count + 1));
+                    }
+                    if (request.method === "DELETE") {
+                        await this.state.storage.transaction(async (txn) => {
+                            await txn.put("count", 100);
+                            txn.rollback();
+                        });
+                        try {
+                            await this.state.storage.transaction(async (txn) => {
+                                await txn.delete("count");
+                                throw new Error("abort");
+                            });
+                        } catch (e) {}
+                    }
+                    const count = await this.state.storage.get("count");
+                    return new Response(this.state.id.name + "=" + count + "/" + this.loaded);
+                }
+            }
+
+            export default {
+                async fetch(request, env, ctx) {
+                    const room = request.url.split("room=")[1];
+                    const stub = env.COUNTER.getByName(room);
+                    if (request.method === "POST") {
+                        const responses = await Promise.all([
+                            stub.fetch(request.url, { method: "POST" }),
+                            stub.fetch(request.url, { method: "POST" }),
+                        ]);
+                        return new Response(responses.map((r) => r.getBody()).join(","));
+                    }
+                    return stub.fetch(request.url, { method: request.method });
+                }
+            }
+        "#;
+        let dir = std::env::temp_dir().join(format!("raven-durable-server-{}", rand::random::<u64>()));
+        let mut config = ServerConfig::new("127.0.0.1", 0, "");
+        config.durable_objects_dir = Some(dir.to_string_lossy().into_owned());
+        let worker = WorkerConfig::new("", "")
+            .with_route("/*")
+            .with_durable_object(DurableObjectConfig::new("COUNTER", "Counter"));
+
+        let request = |method: &str, room: &str| HttpRequest {
+            method: method.to_string(),
+            ..get(&format!("/?room={}", room), Some("do.test"))
+        };
+
+        let mut server = WorkerServer::empty(config.clone());
+        service_worker("counter", worker.clone(), script, &mut server);
+        let response = server.handle_request(&request("POST", "a")).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "1,2");
+        let response = server.handle_request(&request("POST", "a")).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "3,4");
+        let response = server.handle_request(&request("GET", "b")).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "b=undefined/0");
+
+        // 回滚和抛出异常的事务不改变存储
+        let response = server.handle_request(&request("DELETE", "a")).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "a=4/0");
+
+        // 新的服务器从磁盘恢复实例状态
+        let mut server = WorkerServer::empty(config);
+        service_worker("counter", worker, script, &mut server);
+        let response = server.handle_request(&request("GET", "a")).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "a=4/4");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_durable_object_instances_are_evicted() {
+        let script = r#"
+            let constructed = 0;
+            export class Room {
+                constructor(state, env) {
+                    constructed++;
+                    this.state = state;
+                    this.hits = 0;
+                }
+
+                async fetch(request) {
+                    this.hits++;
+                    await this.state.storage.put("hits", ((await this.state.storage.get("hits")) || 0) + 1);
+                    return new Response(this.hits + "/" + (await this.state.storage.get("hits")));
+                }
+            }
+
+            export default {
+                async fetch(request, env, ctx) {
+                    const first = await env.ROOM.getByName("first").fetch("http://do/");
+                    for (let i = 0; i < 1000; i++) {
+                        await env.ROOM.getByName("room-" + i).fetch("http://do/");
+                    }
+                    // 最久未使用的实例被丢弃后重新构造，存储中的数据仍在
+                    const again = await env.ROOM.getByName("first").fetch("http://do/");
+                    return new Response(first.getBody() + "," + again.getBody() + "," + constructed);
+                }
+            }
+        "#;
+        let worker = WorkerConfig::new("", "")
+            .with_route("/*")
+            .with_durable_object(DurableObjectConfig::new("ROOM", "Room"));
+        let mut server = WorkerServer::empty(ServerConfig::new("127.0.0.1", 0, ""));
+        service_worker("rooms", worker, script, &mut server);
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "1/1,1/2,1002");
+    }
+
+    #[test]
+    fn test_html_rewriter_streams_output() {
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    const page = "<html><body>" + ("<p>item</p>" + "text ".repeat(2000)).repeat(12) + "</body></html>";
+                    return new HTMLRewriter()
+                        .on("p", { element(el) { el.setAttribute("class", "x"); } })
+                        .transform(new Response(page, { headers: { "content-type": "text/html" } }));
+                }
+            }
+        "#;
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        let response = server.respond(&get("/", None), Instant::now());
+        assert_eq!(response.status, 200);
+        assert!(!response.headers.contains_key("content-length"));
+        let stream = response.stream.unwrap();
+
+        // 还没有读取时只改写到通道填满为止，第一块已经可以发给客户端
+        let runtime = Rc::clone(&server.workers[0].runtime);
+        assert!(runtime.borrow_mut().pump_streams());
+        let first = stream.next_chunk().unwrap().unwrap();
+        assert!(String::from_utf8(first.clone()).unwrap().starts_with("<html><body><p class=\"x\">item</p>"));
+        assert!(runtime.borrow().has_streams());
+
+        let reader = thread::spawn(move || stream.collect());
+        while runtime.borrow_mut().pump_streams() {}
+        let mut body = first;
+        body.extend(reader.join().unwrap().unwrap());
+        let section = format!("<p class=\"x\">item</p>{}", "text ".repeat(2000));
+        let expected = format!("<html><body>{}</body></html>", section.repeat(12));
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+
+        // 流式响应不压缩；客户端断开后丢弃流
+        let mut request = get("/", None);
+        request.headers.insert("accept-encoding".to_string(), "gzip".to_string());
+        let response = server.respond(&request, Instant::now());
+        assert!(response.stream.is_some());
+        assert!(!response.headers.contains_key("content-encoding"));
+        drop(response);
+        assert!(!runtime.borrow_mut().pump_streams());
+
+        // 缓冲处理（如 Service 绑定）读出完整的 body
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert!(response.stream.is_none());
+        assert_eq!(String::from_utf8(response.body).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_html_rewriter() {
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    const page = new Response(
+                        "<html><body><a href=\"http://internal/x\">x</a><script>track()</script>" +
+                        "<h1 class=\"title\">Dash<!-- todo --></h1></body></html>",
+                        { headers: { "content-type": "text/html", "content-length": "999" } }
+                    );
+                    let titles = 0;
+                    const response = new HTMLRewriter()
+                        .on("a[href^='http://']", {
+                            element(el) {
+                                el.setAttribute("href", el.getAttribute("href").replace("http:", "https:"));
+                            }
+                        })
+                        .on("script", { element(el) { el.remove(); } })
+                        .on("h1.title", {
+                            element(el) {
+                                el.before("<div class=\"banner\">Staging</div>", { html: true });
+                                el.onEndTag((end) => end.after("<hr>", { html: true }));
+                            },
+                            text(chunk) {
+                                if (chunk.text) {
+                                    titles++;
+                                    chunk.replace(chunk.text.toUpperCase() + " & co");
+                                }
+                            },
+                            comments(comment) { comment.remove(); }
+                        })
+                        .onDocument({ end(end) { end.append("<!-- " + titles + " -->", { html: true }); } })
+                        .transform(page);
+                    return new Response(response.body, {
+                        status: response.status,
+                        headers: { "x-type": response.headers["content-type"], "x-length": String(response.headers["content-length"]) }
+                    });
+                }
+            }
+        "#;
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response.body),
+            "<html><body><a href=\"https://internal/x\">x</a>\
+             <div class=\"banner\">Staging</div><h1 class=\"title\">DASH &amp; co</h1><hr></body></html><!-- 1 -->"
+        );
+        assert_eq!(response.headers["x-type"], "text/html");
+        assert_eq!(response.headers["x-length"], "undefined");
+
+        // 二进制 body 按 UTF-8 解码，不是 UTF-8 时报错而不是改写坏
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    const rewriter = new HTMLRewriter().on("b", { element(el) { el.setAttribute("x", "1"); } });
+                    const utf8 = new Uint8Array([60, 98, 62, 195, 169, 60, 47, 98, 62]);
+                    const latin1 = new Uint8Array([60, 98, 62, 233, 60, 47, 98, 62]);
+                    const body = request.url.endsWith("/latin1") ? latin1 : utf8.buffer;
+                    return rewriter.transform(new Response(body));
+                }
+            }
+        "#;
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(String::from_utf8_lossy(&response.body), "<b x=\"1\">é</b>");
+        let error = server.handle_request(&get("/latin1", None)).unwrap_err();
+        assert!(error.contains("HTMLRewriter only supports UTF-8 bodies"), "{}", error);
+
+        // 流式响应在读取时才报错，已经发出的部分不会被改写坏
+        let response = server.respond(&get("/latin1", None), Instant::now());
+        let stream = response.stream.unwrap();
+        let reader = thread::spawn(move || stream.collect());
+        while server.workers[0].runtime.borrow_mut().pump_streams() {}
+        let error = reader.join().unwrap().unwrap_err();
+        assert!(error.contains("HTMLRewriter only supports UTF-8 bodies"), "{}", error);
+
+        let script = r#"
+            export default {
+                fetch(request, env, ctx) {
+                    try {
+                        new HTMLRewriter().on("a:hover", {});
+                        return new Response("accepted");
+                    } catch (e) {
+                        return new Response(e.message);
+                    }
+                }
+            }
+        "#;
+        let mut server = WorkerServer::from_script(script, "127.0.0.1", 0).unwrap();
+        let response = server.handle_request(&get("/", None)).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&response.body),
+            "Invalid selector 'a:hover': unsupported pseudo-class ':hover'"
+        );
     }
 }
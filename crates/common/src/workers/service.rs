@@ -0,0 +1,283 @@
+//! Service 绑定
+//!
+//! 同一个 `WorkerServer` 中的 Worker 可以通过 `env.AUTH.fetch(request)` 直接调用另一个 Worker，
+//! 请求在进程内交给目标 Worker 的运行时处理，不经过网络。
+//!
+//! 调用时传递请求 ID（`X-Request-Id`）、截止时间（`X-Raven-Deadline`，Unix 毫秒）和
+//! 调用链（`X-Raven-Service-Chain`），调用链中已出现目标 Worker 时拒绝调用，避免死循环。
+//! 后两个头只在 Worker 之间传递，来自网络的请求中的同名头会被服务器移除。
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use boa_engine::{
+    js_string, object::builtins::JsPromise, object::ObjectInitializer, Context, JsArgs,
+    JsNativeError, JsObject, JsResult, JsValue, NativeFunction,
+};
+use boa_gc::{Finalize, Trace};
+
+use crate::runtime::{get_current_bindings, set_current_bindings};
+use super::assets::{js_to_request, response_to_js};
+use super::config::ServiceBindingConfig;
+use super::http::{HttpRequest, HttpResponse};
+use super::proxy::TrustedProxies;
+use super::workers_runtime::WorkersRuntime;
+
+/// 请求 ID 头
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// 截止时间头（Unix 毫秒）
+pub const DEADLINE_HEADER: &str = "x-raven-deadline";
+/// 调用链头，按调用顺序列出经过的 Worker
+pub const CHAIN_HEADER: &str = "x-raven-service-chain";
+
+/// 只在 Worker 之间传递的头，服务器会从网络请求中移除
+pub const INTERNAL_HEADERS: [&str; 2] = [DEADLINE_HEADER, CHAIN_HEADER];
+
+/// 可被 Service 绑定调用的 Worker，由 `WorkerServer` 维护
+#[derive(Clone, Default)]
+pub struct ServiceRegistry {
+    workers: Rc<RefCell<HashMap<String, Weak<RefCell<WorkersRuntime>>>>>,
+}
+
+impl ServiceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记一个 Worker，注册表只持有弱引用
+    pub fn register(&self, name: &str, runtime: &Rc<RefCell<WorkersRuntime>>) {
+        self.workers
+            .borrow_mut()
+            .insert(name.to_string(), Rc::downgrade(runtime));
+    }
+
+    pub fn clear(&self) {
+        self.workers.borrow_mut().clear();
+    }
+
+    /// 把请求交给 Worker 处理
+    ///
+    /// 目标 Worker 正在处理请求（即调用链上游）时返回错误
+    pub fn call(&self, service: &str, request: &HttpRequest) -> Result<HttpResponse, String> {
+        let runtime = self
+            .workers
+            .borrow()
+            .get(service)
+            .and_then(|w| w.upgrade())
+            .ok_or_else(|| format!("Service '{}' is not available", service))?;
+        let mut runtime = runtime.try_borrow_mut().map_err(|_| {
+            format!(
+                "Service call cycle detected: '{}' is already handling this request",
+                service
+            )
+        })?;
+
+        // 目标运行时会切换当前线程的绑定注册表，返回后恢复为调用方的
+        let caller_bindings = get_current_bindings();
+        let mut url = request.url("localhost", &TrustedProxies::none());
+        if let Some((scheme, _)) = request.path.split_once("://") {
+            url.scheme = scheme.to_lowercase();
+        }
+        let result = runtime.handle_request_with_url(request, &url);
+        if let Some(bindings) = caller_bindings {
+            set_current_bindings(bindings);
+        }
+        result
+    }
+}
+
+/// 一次请求的调用上下文
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Invocation {
+    pub request_id: String,
+    /// 截止时间（Unix 毫秒）
+    pub deadline: Option<u64>,
+    /// 上游调用链
+    pub chain: Vec<String>,
+}
+
+impl Invocation {
+    /// 新的调用，生成请求 ID
+    pub fn new() -> Self {
+        Self {
+            request_id: generate_request_id(),
+            ..Default::default()
+        }
+    }
+
+    /// 从请求头恢复调用上下文，没有请求 ID 时生成一个
+    pub fn from_request(request: &HttpRequest) -> Self {
+        let header = |name: &str| {
+            request
+                .headers
+                .get(name)
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+        Self {
+            request_id: header(REQUEST_ID_HEADER)
+                .map(|id| id.to_string())
+                .unwrap_or_else(generate_request_id),
+            deadline: header(DEADLINE_HEADER).and_then(|d| d.parse().ok()),
+            chain: header(CHAIN_HEADER)
+                .map(|chain| chain.split(',').map(|w| w.trim().to_string()).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn generate_request_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// 原生函数捕获的 Service 绑定状态
+#[derive(Clone, Trace, Finalize)]
+struct ServiceHandle {
+    #[unsafe_ignore_trace]
+    registry: ServiceRegistry,
+    /// 发起调用的 Worker
+    #[unsafe_ignore_trace]
+    caller: Rc<str>,
+    /// 发起调用的 Worker 当前的调用上下文
+    #[unsafe_ignore_trace]
+    invocation: Rc<RefCell<Invocation>>,
+    #[unsafe_ignore_trace]
+    binding: ServiceBindingConfig,
+}
+
+impl ServiceHandle {
+    /// 按调用上下文补全请求头后调用目标 Worker
+    fn fetch(&self, mut request: HttpRequest) -> Result<HttpResponse, String> {
+        let service = &self.binding.service;
+        let invocation = self.invocation.borrow().clone();
+
+        let mut chain = invocation.chain;
+        chain.push(self.caller.to_string());
+        if chain.iter().any(|w| w == service) {
+            chain.push(service.clone());
+            return Err(format!("Service call cycle detected: {}", chain.join(" -> ")));
+        }
+
+        let now = unix_millis();
+        let timeout = self
+            .binding
+            .timeout
+            .map(|t| now.saturating_add(t.as_millis() as u64));
+        let deadline = match (invocation.deadline, timeout) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if deadline.is_some_and(|d| d <= now) {
+            return Err(format!("Deadline exceeded before calling service '{}'", service));
+        }
+
+        request
+            .headers
+            .insert(REQUEST_ID_HEADER.to_string(), invocation.request_id);
+        request
+            .headers
+            .insert(CHAIN_HEADER.to_string(), chain.join(", "));
+        match deadline {
+            Some(deadline) => {
+                request
+                    .headers
+                    .insert(DEADLINE_HEADER.to_string(), deadline.to_string());
+            }
+            None => {
+                request.headers.remove(DEADLINE_HEADER);
+            }
+        }
+
+        let response = self.registry.call(service, &request)?;
+        // 运行中的脚本无法中断，返回后再检查是否超时
+        if deadline.is_some_and(|d| unix_millis() > d) {
+            return Err(format!("Service '{}' exceeded its deadline", service));
+        }
+        Ok(response)
+    }
+}
+
+/// 创建 `env.BINDING` 对象，`fetch(request)` 返回目标 Worker 响应的 Promise
+pub fn create_binding(
+    binding: &ServiceBindingConfig,
+    registry: &ServiceRegistry,
+    caller: &str,
+    invocation: &Rc<RefCell<Invocation>>,
+    context: &mut Context,
+) -> JsObject {
+    let handle = ServiceHandle {
+        registry: registry.clone(),
+        caller: Rc::from(caller),
+        invocation: Rc::clone(invocation),
+        binding: binding.clone(),
+    };
+
+    let fetch_fn = NativeFunction::from_copy_closure_with_captures(
+        |_, args, handle: &ServiceHandle, context| {
+            let result = js_to_service_request(args.get_or_undefined(0), context)
+                .and_then(|request| {
+                    let response = handle
+                        .fetch(request)
+                        .map_err(|e| JsNativeError::error().with_message(e))?;
+                    response_to_js(&response, context)
+                });
+            Ok(JsPromise::from_result(result, context).into())
+        },
+        handle,
+    );
+
+    ObjectInitializer::new(context)
+        .function(fetch_fn, js_string!("fetch"), 1)
+        .build()
+}
+
+/// 把 JS 的 Request 对象或 URL 转换为请求，保留完整 URL 以便目标 Worker 看到原始主机名
+fn js_to_service_request(value: &JsValue, context: &mut Context) -> JsResult<HttpRequest> {
+    let url = match value.as_object() {
+        Some(obj) => obj.get(js_string!("url"), context)?,
+        None => value.clone(),
+    };
+    let mut request = js_to_request(value, context)?;
+    request.path = url.to_string(context)?.to_std_string_escaped();
+    if !request.path.starts_with('/') && !request.path.contains("://") {
+        return Err(JsNativeError::typ()
+            .with_message(format!("Invalid service request URL: '{}'", request.path))
+            .into());
+    }
+    Ok(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invocation_from_request() {
+        let mut request = HttpRequest::default();
+        let generated = Invocation::from_request(&request);
+        assert_eq!(generated.request_id.len(), 16);
+        assert_eq!(generated.deadline, None);
+        assert!(generated.chain.is_empty());
+
+        request.headers.insert(REQUEST_ID_HEADER.to_string(), "req-1".to_string());
+        request.headers.insert(DEADLINE_HEADER.to_string(), "1700000000000".to_string());
+        request.headers.insert(CHAIN_HEADER.to_string(), "gateway, auth".to_string());
+        assert_eq!(
+            Invocation::from_request(&request),
+            Invocation {
+                request_id: "req-1".to_string(),
+                deadline: Some(1_700_000_000_000),
+                chain: vec!["gateway".to_string(), "auth".to_string()],
+            }
+        );
+    }
+}
@@ -0,0 +1,507 @@
+//! TLS 终止（基于 rustls）
+//!
+//! - 默认证书 + 按 SNI 选择的附加证书（支持 `*.example.com` 通配）
+//! - 证书文件变化后自动重新加载，也可以通过 [`TlsAcceptor::reload`] 手动触发
+//! - 可选的客户端证书认证（mTLS），用于内部接口
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::TcpStream;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier};
+use rustls::sign::CertifiedKey;
+use rustls::{RootCertStore, ServerConnection, StreamOwned};
+
+/// TLS 连接
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// 客户端证书认证模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClientAuth {
+    /// 不请求客户端证书
+    #[default]
+    None,
+    /// 请求客户端证书，但允许没有证书的连接（由 Worker 的 `require_client_cert` 决定）
+    Optional,
+    /// 必须提供有效的客户端证书
+    Required,
+}
+
+impl ClientAuth {
+    /// 从配置字符串解析（`none` / `optional` / `required`）
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "optional" => Ok(Self::Optional),
+            "required" => Ok(Self::Required),
+            other => Err(format!("Unknown client auth mode: '{}'", other)),
+        }
+    }
+}
+
+/// 按 SNI 选择的证书
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateConfig {
+    /// 使用该证书的主机名，支持 `*.example.com`
+    pub hostnames: Vec<String>,
+    /// PEM 证书链路径
+    pub cert_path: String,
+    /// PEM 私钥路径
+    pub key_path: String,
+}
+
+/// TLS 配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// 默认 PEM 证书链路径（没有匹配的 SNI 证书时使用）
+    pub cert_path: String,
+    /// 默认 PEM 私钥路径
+    pub key_path: String,
+    /// 按 SNI 选择的附加证书
+    pub certificates: Vec<CertificateConfig>,
+    /// 客户端证书认证模式
+    pub client_auth: ClientAuth,
+    /// 用于校验客户端证书的 CA 证书路径（PEM）
+    pub client_ca_path: Option<String>,
+    /// 检查证书文件变化的间隔，`None` 表示只在手动 reload 时重新加载
+    pub reload_interval: Option<Duration>,
+}
+
+impl TlsConfig {
+    pub fn new(cert_path: &str, key_path: &str) -> Self {
+        Self {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            certificates: Vec::new(),
+            client_auth: ClientAuth::None,
+            client_ca_path: None,
+            reload_interval: Some(Duration::from_secs(30)),
+        }
+    }
+
+    /// 添加按 SNI 选择的证书
+    pub fn with_certificate(mut self, hostnames: &[&str], cert_path: &str, key_path: &str) -> Self {
+        self.certificates.push(CertificateConfig {
+            hostnames: hostnames.iter().map(|h| h.to_string()).collect(),
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+        });
+        self
+    }
+
+    /// 开启客户端证书认证
+    pub fn with_client_auth(mut self, mode: ClientAuth, ca_path: &str) -> Self {
+        self.client_auth = mode;
+        self.client_ca_path = Some(ca_path.to_string());
+        self
+    }
+
+    /// 所有需要监控变化的文件
+    fn watched_files(&self) -> Vec<&str> {
+        let mut files = vec![self.cert_path.as_str(), self.key_path.as_str()];
+        for cert in &self.certificates {
+            files.push(&cert.cert_path);
+            files.push(&cert.key_path);
+        }
+        if let Some(ca) = &self.client_ca_path {
+            files.push(ca);
+        }
+        files
+    }
+}
+
+/// TLS 连接信息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// 客户端通过 SNI 请求的主机名
+    pub server_name: Option<String>,
+    /// 已通过校验的客户端证书（DER）
+    pub client_certificate: Option<Vec<u8>>,
+}
+
+impl TlsInfo {
+    /// 从已完成握手的连接中提取信息
+    pub fn from_connection(conn: &ServerConnection) -> Self {
+        Self {
+            server_name: conn.server_name().map(|s| s.to_string()),
+            client_certificate: conn
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(|cert| cert.as_ref().to_vec()),
+        }
+    }
+}
+
+/// 按 SNI 选择证书
+struct SniResolver {
+    default: Arc<CertifiedKey>,
+    by_name: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl fmt::Debug for SniResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SniResolver")
+            .field("hostnames", &self.by_name.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl SniResolver {
+    fn find(&self, server_name: Option<&str>) -> Arc<CertifiedKey> {
+        if let Some(name) = server_name {
+            let name = name.to_lowercase();
+            if let Some(key) = self.by_name.get(&name) {
+                return key.clone();
+            }
+            // 通配证书只匹配一级子域名
+            if let Some((_, parent)) = name.split_once('.') {
+                if let Some(key) = self.by_name.get(&format!("*.{}", parent)) {
+                    return key.clone();
+                }
+            }
+        }
+        self.default.clone()
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.find(client_hello.server_name()))
+    }
+}
+
+struct AcceptorState {
+    server_config: Arc<rustls::ServerConfig>,
+    /// 上次加载时各文件的修改时间
+    modified: Vec<Option<SystemTime>>,
+    last_check: Instant,
+}
+
+/// TLS 握手器
+///
+/// 可以在多个连接线程间共享，证书更新对之后的新连接生效
+pub struct TlsAcceptor {
+    config: TlsConfig,
+    provider: Arc<CryptoProvider>,
+    state: RwLock<AcceptorState>,
+}
+
+impl TlsAcceptor {
+    /// 加载证书并创建握手器
+    pub fn new(config: TlsConfig) -> Result<Self, String> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let server_config = build_server_config(&config, &provider)?;
+        let modified = modified_times(&config);
+
+        Ok(Self {
+            config,
+            provider,
+            state: RwLock::new(AcceptorState {
+                server_config,
+                modified,
+                last_check: Instant::now(),
+            }),
+        })
+    }
+
+    /// 创建时使用的 TLS 配置
+    pub fn config(&self) -> &TlsConfig {
+        &self.config
+    }
+
+    /// 重新读取所有证书文件
+    ///
+    /// 加载失败时保留原来的证书并返回错误
+    pub fn reload(&self) -> Result<(), String> {
+        let server_config = build_server_config(&self.config, &self.provider)?;
+        let mut state = self.state.write().map_err(|e| e.to_string())?;
+        state.server_config = server_config;
+        state.modified = modified_times(&self.config);
+        state.last_check = Instant::now();
+        Ok(())
+    }
+
+    /// 当前生效的 rustls 配置，必要时检查文件变化并重新加载
+    fn current(&self) -> Arc<rustls::ServerConfig> {
+        if let Some(interval) = self.config.reload_interval {
+            let needs_check = self
+                .state
+                .read()
+                .map(|s| s.last_check.elapsed() >= interval)
+                .unwrap_or(false);
+
+            if needs_check {
+                let changed = {
+                    let mut state = self.state.write().unwrap();
+                    state.last_check = Instant::now();
+                    state.modified != modified_times(&self.config)
+                };
+                if changed {
+                    match self.reload() {
+                        Ok(()) => println!("🔐 TLS 证书已重新加载"),
+                        Err(e) => eprintln!("Failed to reload TLS certificates: {}", e),
+                    }
+                }
+            }
+        }
+
+        self.state.read().unwrap().server_config.clone()
+    }
+
+    /// 完成 TLS 握手
+    pub fn accept(&self, mut stream: TcpStream) -> Result<TlsStream, String> {
+        let mut conn = ServerConnection::new(self.current())
+            .map_err(|e| format!("Failed to create TLS connection: {}", e))?;
+
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)
+                .map_err(|e| format!("TLS handshake failed: {}", e))?;
+        }
+
+        Ok(StreamOwned::new(conn, stream))
+    }
+}
+
+fn modified_times(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    config
+        .watched_files()
+        .into_iter()
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn load_certified_key(
+    cert_path: &str,
+    key_path: &str,
+    provider: &CryptoProvider,
+) -> Result<Arc<CertifiedKey>, String> {
+    let certs = load_certs(cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("Failed to read private key {}: {}", key_path, e))?;
+    let signing_key = provider
+        .key_provider
+        .load_private_key(key)
+        .map_err(|e| format!("Unsupported private key {}: {}", key_path, e))?;
+    Ok(Arc::new(CertifiedKey::new(certs, signing_key)))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read certificates {}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn build_server_config(
+    config: &TlsConfig,
+    provider: &Arc<CryptoProvider>,
+) -> Result<Arc<rustls::ServerConfig>, String> {
+    let default = load_certified_key(&config.cert_path, &config.key_path, provider)?;
+    let mut by_name = HashMap::new();
+    for cert in &config.certificates {
+        let key = load_certified_key(&cert.cert_path, &cert.key_path, provider)?;
+        for hostname in &cert.hostnames {
+            by_name.insert(hostname.to_lowercase(), key.clone());
+        }
+    }
+    let resolver = Arc::new(SniResolver { default, by_name });
+
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Invalid TLS configuration: {}", e))?;
+
+    let builder = match config.client_auth {
+        ClientAuth::None => builder.with_no_client_auth(),
+        mode => {
+            let ca_path = config
+                .client_ca_path
+                .as_deref()
+                .ok_or("Client certificate authentication requires client_ca_path")?;
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("Invalid CA certificate in {}: {}", ca_path, e))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone());
+            let verifier = if mode == ClientAuth::Optional {
+                verifier.allow_unauthenticated()
+            } else {
+                verifier
+            };
+            let verifier = verifier
+                .build()
+                .map_err(|e| format!("Invalid client verifier: {}", e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+    };
+
+    let mut server_config = builder.with_cert_resolver(resolver);
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(server_config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::ServerName;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+
+    /// 测试用的临时目录
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("raven-tls-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// 生成 CA 以及由它签发的证书，返回 (证书路径, 私钥路径)
+    fn issue(dir: &Path, name: &str, hostnames: &[&str], ca: &(rcgen::Certificate, rcgen::KeyPair)) -> (String, String) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let params = rcgen::CertificateParams::new(
+            hostnames.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let cert = params.signed_by(&key, &ca.0, &ca.1).unwrap();
+        let cert_path = dir.join(format!("{}.crt", name));
+        let key_path = dir.join(format!("{}.key", name));
+        fs::write(&cert_path, cert.pem()).unwrap();
+        fs::write(&key_path, key.serialize_pem()).unwrap();
+        (
+            cert_path.to_string_lossy().into_owned(),
+            key_path.to_string_lossy().into_owned(),
+        )
+    }
+
+    fn new_ca(dir: &Path) -> ((rcgen::Certificate, rcgen::KeyPair), String) {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let cert = params.self_signed(&key).unwrap();
+        let path = dir.join("ca.crt");
+        fs::write(&path, cert.pem()).unwrap();
+        ((cert, key), path.to_string_lossy().into_owned())
+    }
+
+    /// 用 rustls 客户端连接并返回服务端证书链第一张证书
+    fn handshake(
+        acceptor: Arc<TlsAcceptor>,
+        ca_path: &str,
+        server_name: &str,
+        client_cert: Option<(&str, &str)>,
+    ) -> Result<(Vec<u8>, TlsInfo), String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut tls = acceptor.accept(stream)?;
+            let mut buf = [0u8; 4];
+            tls.read_exact(&mut buf).map_err(|e| e.to_string())?;
+            Ok::<_, String>(TlsInfo::from_connection(&tls.conn))
+        });
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(ca_path).unwrap() {
+            roots.add(cert).unwrap();
+        }
+        let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let client_config = match client_cert {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(load_certs(cert).unwrap(), PrivateKeyDer::from_pem_file(key).unwrap())
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+
+        let name = ServerName::try_from(server_name.to_string()).unwrap();
+        let conn = rustls::ClientConnection::new(Arc::new(client_config), name).unwrap();
+        let mut tls = StreamOwned::new(conn, TcpStream::connect(addr).unwrap());
+        let write_result = tls.write_all(b"ping").and_then(|_| tls.flush());
+        let peer = tls
+            .conn
+            .peer_certificates()
+            .and_then(|c| c.first())
+            .map(|c| c.as_ref().to_vec());
+
+        let info = server.join().unwrap()?;
+        write_result.map_err(|e| e.to_string())?;
+        Ok((peer.unwrap_or_default(), info))
+    }
+
+    #[test]
+    fn test_sni_selection_and_reload() {
+        let dir = temp_dir("sni");
+        let (ca, ca_path) = new_ca(&dir);
+        let (default_cert, default_key) = issue(&dir, "default", &["localhost"], &ca);
+        let (api_cert, api_key) = issue(&dir, "api", &["api.example.com"], &ca);
+        let (wild_cert, wild_key) = issue(&dir, "wild", &["*.internal.example.com"], &ca);
+
+        let config = TlsConfig::new(&default_cert, &default_key)
+            .with_certificate(&["api.example.com"], &api_cert, &api_key)
+            .with_certificate(&["*.internal.example.com"], &wild_cert, &wild_key);
+        let acceptor = Arc::new(TlsAcceptor::new(config).unwrap());
+
+        let der = |path: &str| load_certs(path).unwrap()[0].as_ref().to_vec();
+
+        let (peer, info) = handshake(acceptor.clone(), &ca_path, "api.example.com", None).unwrap();
+        assert_eq!(peer, der(&api_cert));
+        assert_eq!(info.server_name.as_deref(), Some("api.example.com"));
+        assert_eq!(info.client_certificate, None);
+
+        let (peer, _) = handshake(acceptor.clone(), &ca_path, "db.internal.example.com", None).unwrap();
+        assert_eq!(peer, der(&wild_cert));
+
+        let (peer, _) = handshake(acceptor.clone(), &ca_path, "localhost", None).unwrap();
+        assert_eq!(peer, der(&default_cert));
+
+        // 替换证书文件后手动 reload
+        issue(&dir, "api", &["api.example.com"], &ca);
+        acceptor.reload().unwrap();
+        let (peer, _) = handshake(acceptor.clone(), &ca_path, "api.example.com", None).unwrap();
+        assert_eq!(peer, der(&api_cert));
+
+        // 证书文件损坏时保留旧证书
+        fs::write(&api_cert, "garbage").unwrap();
+        assert!(acceptor.reload().is_err());
+        assert!(handshake(acceptor, &ca_path, "api.example.com", None).is_ok());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_client_certificate_auth() {
+        let dir = temp_dir("mtls");
+        let (ca, ca_path) = new_ca(&dir);
+        let (server_cert, server_key) = issue(&dir, "server", &["localhost"], &ca);
+        let (client_cert, client_key) = issue(&dir, "client", &["client"], &ca);
+
+        let required = TlsConfig::new(&server_cert, &server_key).with_client_auth(ClientAuth::Required, &ca_path);
+        let acceptor = Arc::new(TlsAcceptor::new(required).unwrap());
+        assert!(handshake(acceptor.clone(), &ca_path, "localhost", None).is_err());
+        let (_, info) =
+            handshake(acceptor, &ca_path, "localhost", Some((&client_cert, &client_key))).unwrap();
+        assert!(info.client_certificate.is_some());
+
+        let optional = TlsConfig::new(&server_cert, &server_key).with_client_auth(ClientAuth::Optional, &ca_path);
+        let acceptor = Arc::new(TlsAcceptor::new(optional).unwrap());
+        let (_, info) = handshake(acceptor, &ca_path, "localhost", None).unwrap();
+        assert!(info.client_certificate.is_none());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
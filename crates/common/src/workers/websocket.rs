@@ -0,0 +1,925 @@
+//! WebSocket 支持
+//!
+//! Worker 通过 `WebSocketPair` 接受 `Upgrade: websocket` 请求：
+//!
+//! ```js
+//! const [client, server] = Object.values(new WebSocketPair());
+//! server.accept();
+//! server.addEventListener("message", (event) => server.send("echo: " + event.data));
+//! return new Response(null, { status: 101, webSocket: client });
+//! ```
+//!
+//! 帧的编解码、ping/pong 和关闭握手在连接线程中完成，
+//! 消息和关闭事件通过 channel 交给主线程分发到 JS。
+//! JS 调用 `send()`/`close()` 时，数据经由主线程上的连接表发回对应的连接线程。
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use boa_engine::{
+    js_string, object::builtins::{AlignedVec, JsArrayBuffer}, Context, JsArgs, JsNativeError,
+    JsResult, JsValue, NativeFunction, Source,
+};
+use sha1::{Digest, Sha1};
+
+use super::connection::Connection;
+use super::http::{HttpRequest, HttpResponse};
+use super::workers_runtime::js_to_bytes;
+
+/// 握手时拼接在 `Sec-WebSocket-Key` 后的固定 GUID（RFC 6455）
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// 连接线程轮询待发送消息的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// 连接空闲多久后发送 ping，ping 之后同样时间内没有任何数据则断开
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 发出关闭帧后等待对端回应的时间
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 关闭码
+pub mod close_code {
+    pub const NORMAL: u16 = 1000;
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const NO_STATUS: u16 = 1005;
+    pub const ABNORMAL: u16 = 1006;
+    pub const INVALID_DATA: u16 = 1007;
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+}
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// WebSocket 消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// 连接线程上报给 Worker 的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketEvent {
+    Message(Message),
+    /// 连接已关闭（对端关闭、出错或超时），之后不会再有事件
+    Close { code: u16, reason: String },
+}
+
+/// Worker 发给连接线程的指令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outgoing {
+    Message(Message),
+    Close { code: u16, reason: String },
+}
+
+/// 判断请求是否为 WebSocket 升级请求
+pub fn is_upgrade_request(request: &HttpRequest) -> bool {
+    let header_has = |name: &str, token: &str| {
+        request.headers.get(name).is_some_and(|value| {
+            value
+                .split(',')
+                .any(|v| v.trim().eq_ignore_ascii_case(token))
+        })
+    };
+    request.method.eq_ignore_ascii_case("GET")
+        && header_has("upgrade", "websocket")
+        && header_has("connection", "upgrade")
+        && request.headers.contains_key("sec-websocket-key")
+}
+
+/// 根据 `Sec-WebSocket-Key` 计算 `Sec-WebSocket-Accept`
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// 构建 101 握手响应
+pub fn handshake_response(request: &HttpRequest) -> Result<HttpResponse, String> {
+    let key = request
+        .headers
+        .get("sec-websocket-key")
+        .ok_or("Missing Sec-WebSocket-Key")?;
+    if request.headers.get("sec-websocket-version").map(|v| v.trim()) != Some("13") {
+        return Err("Unsupported Sec-WebSocket-Version".to_string());
+    }
+
+    let mut response = HttpResponse::new(101, "Switching Protocols");
+    response.headers.clear();
+    response.headers.insert("upgrade".to_string(), "websocket".to_string());
+    response.headers.insert("connection".to_string(), "Upgrade".to_string());
+    response
+        .headers
+        .insert("sec-websocket-accept".to_string(), accept_key(key));
+    Ok(response)
+}
+
+/// 单个 WebSocket 帧
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    fn new(opcode: u8, payload: Vec<u8>) -> Self {
+        Self {
+            fin: true,
+            opcode,
+            payload,
+        }
+    }
+
+    fn close(code: u16, reason: &str) -> Self {
+        let mut payload = code.to_be_bytes().to_vec();
+        // 控制帧的 payload 不能超过 125 字节
+        let mut end = reason.len().min(123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        payload.extend_from_slice(&reason.as_bytes()[..end]);
+        Self::new(OP_CLOSE, payload)
+    }
+
+    fn from_message(message: &Message) -> Self {
+        match message {
+            Message::Text(text) => Self::new(OP_TEXT, text.as_bytes().to_vec()),
+            Message::Binary(data) => Self::new(OP_BINARY, data.clone()),
+        }
+    }
+
+    /// 编码为服务端发出的帧（不加掩码）
+    fn encode(&self) -> Vec<u8> {
+        let len = self.payload.len();
+        let mut out = Vec::with_capacity(len + 10);
+        out.push(if self.fin { 0x80 } else { 0 } | self.opcode);
+        if len < 126 {
+            out.push(len as u8);
+        } else if len <= u16::MAX as usize {
+            out.push(126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            out.push(127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+        out.extend_from_slice(&self.payload);
+        out
+    }
+
+    /// 解析关闭帧中的关闭码和原因
+    fn close_reason(&self) -> Result<(u16, String), (u16, &'static str)> {
+        match self.payload.len() {
+            0 => Ok((close_code::NO_STATUS, String::new())),
+            1 => Err((close_code::PROTOCOL_ERROR, "Invalid close frame")),
+            _ => {
+                let code = u16::from_be_bytes([self.payload[0], self.payload[1]]);
+                let reason = String::from_utf8(self.payload[2..].to_vec())
+                    .map_err(|_| (close_code::INVALID_DATA, "Invalid close reason"))?;
+                Ok((code, reason))
+            }
+        }
+    }
+}
+
+/// 增量解析客户端发来的帧
+struct FrameDecoder {
+    buffer: Vec<u8>,
+    max_payload: usize,
+}
+
+impl FrameDecoder {
+    fn new(max_payload: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_payload,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// 取出下一个完整的帧，数据不足时返回 `None`，协议错误时返回 (关闭码, 原因)
+    fn next_frame(&mut self) -> Result<Option<Frame>, (u16, &'static str)> {
+        let buf = &self.buffer;
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+
+        let fin = buf[0] & 0x80 != 0;
+        if buf[0] & 0x70 != 0 {
+            return Err((close_code::PROTOCOL_ERROR, "Reserved bits must be zero"));
+        }
+        let opcode = buf[0] & 0x0F;
+        let masked = buf[1] & 0x80 != 0;
+        if !masked {
+            return Err((close_code::PROTOCOL_ERROR, "Client frames must be masked"));
+        }
+
+        let (len, mut offset) = match buf[1] & 0x7F {
+            126 => {
+                if buf.len() < 4 {
+                    return Ok(None);
+                }
+                (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4)
+            }
+            127 => {
+                if buf.len() < 10 {
+                    return Ok(None);
+                }
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&buf[2..10]);
+                (u64::from_be_bytes(bytes), 10)
+            }
+            n => (n as u64, 2),
+        };
+
+        if opcode >= OP_CLOSE && (!fin || len > 125) {
+            return Err((close_code::PROTOCOL_ERROR, "Invalid control frame"));
+        }
+        if len > self.max_payload as u64 {
+            return Err((close_code::MESSAGE_TOO_BIG, "Message too big"));
+        }
+        let len = len as usize;
+
+        if buf.len() < offset + 4 + len {
+            return Ok(None);
+        }
+        let mask = [buf[offset], buf[offset + 1], buf[offset + 2], buf[offset + 3]];
+        offset += 4;
+
+        let payload = buf[offset..offset + len]
+            .iter()
+            .enumerate()
+            .map(|(i, b)| b ^ mask[i % 4])
+            .collect();
+        self.buffer.drain(..offset + len);
+
+        Ok(Some(Frame {
+            fin,
+            opcode,
+            payload,
+        }))
+    }
+}
+
+/// 在连接线程中运行 WebSocket 会话，直到连接关闭
+///
+/// 收到的消息和最终的关闭事件通过 `on_event` 上报；
+/// 自动回应 ping，空闲时发送 ping 检测连接是否存活。
+pub fn run_session(
+    conn: &mut Connection,
+    outgoing: Receiver<Outgoing>,
+    max_message_size: usize,
+    mut on_event: impl FnMut(WebSocketEvent),
+) {
+    conn.tcp().set_read_timeout(Some(POLL_INTERVAL)).ok();
+
+    let mut decoder = FrameDecoder::new(max_message_size);
+    // 分片消息：(opcode, 已收到的数据)
+    let mut fragments: Option<(u8, Vec<u8>)> = None;
+    let mut last_seen = Instant::now();
+    let mut ping_sent: Option<Instant> = None;
+    // 已发出关闭帧：(发出时间, 关闭码, 原因)
+    let mut closing: Option<(Instant, u16, String)> = None;
+    let mut buf = [0u8; 8192];
+
+    let (code, reason) = 'session: loop {
+        // 发送 Worker 的消息
+        loop {
+            let command = match outgoing.try_recv() {
+                Ok(command) => command,
+                Err(TryRecvError::Empty) => break,
+                // Worker 侧已释放连接
+                Err(TryRecvError::Disconnected) => Outgoing::Close {
+                    code: close_code::GOING_AWAY,
+                    reason: String::new(),
+                },
+            };
+            if closing.is_some() {
+                break;
+            }
+            let frame = match &command {
+                Outgoing::Message(message) => Frame::from_message(message),
+                Outgoing::Close { code, reason } => {
+                    closing = Some((Instant::now(), *code, reason.clone()));
+                    Frame::close(*code, reason)
+                }
+            };
+            if write_frame(conn, &frame).is_err() {
+                break 'session (close_code::ABNORMAL, "Write failed".to_string());
+            }
+        }
+
+        if let Some((since, code, reason)) = &closing {
+            if since.elapsed() > CLOSE_TIMEOUT {
+                break (*code, reason.clone());
+            }
+        }
+
+        // 读取客户端的帧
+        match conn.read(&mut buf) {
+            Ok(0) => break (close_code::ABNORMAL, "Connection closed".to_string()),
+            Ok(n) => {
+                decoder.push(&buf[..n]);
+                last_seen = Instant::now();
+                ping_sent = None;
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => break (close_code::ABNORMAL, e.to_string()),
+        }
+
+        loop {
+            let frame = match decoder.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err((code, reason)) => {
+                    write_frame(conn, &Frame::close(code, reason)).ok();
+                    break 'session (code, reason.to_string());
+                }
+            };
+
+            match frame.opcode {
+                OP_PING => {
+                    if write_frame(conn, &Frame::new(OP_PONG, frame.payload)).is_err() {
+                        break 'session (close_code::ABNORMAL, "Write failed".to_string());
+                    }
+                }
+                OP_PONG => {}
+                OP_CLOSE => {
+                    let (code, reason) = match frame.close_reason() {
+                        Ok(close) => close,
+                        Err((code, reason)) => (code, reason.to_string()),
+                    };
+                    // 对端先发起关闭时回应同样的关闭码
+                    if closing.is_none() {
+                        let reply = if code == close_code::NO_STATUS {
+                            Frame::new(OP_CLOSE, Vec::new())
+                        } else {
+                            Frame::close(code, "")
+                        };
+                        write_frame(conn, &reply).ok();
+                    }
+                    break 'session (code, reason);
+                }
+                OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                    let data = match (frame.opcode, fragments.take()) {
+                        (OP_CONTINUATION, Some((opcode, mut data))) => {
+                            data.extend_from_slice(&frame.payload);
+                            (opcode, data)
+                        }
+                        (OP_CONTINUATION, None) | (_, Some(_)) => {
+                            let reason = "Unexpected frame in fragmented message";
+                            write_frame(conn, &Frame::close(close_code::PROTOCOL_ERROR, reason)).ok();
+                            break 'session (close_code::PROTOCOL_ERROR, reason.to_string());
+                        }
+                        (opcode, None) => (opcode, frame.payload),
+                    };
+
+                    if data.1.len() > max_message_size {
+                        let reason = "Message too big";
+                        write_frame(conn, &Frame::close(close_code::MESSAGE_TOO_BIG, reason)).ok();
+                        break 'session (close_code::MESSAGE_TOO_BIG, reason.to_string());
+                    }
+                    if !frame.fin {
+                        fragments = Some(data);
+                        continue;
+                    }
+
+                    let message = match data {
+                        (OP_TEXT, bytes) => match String::from_utf8(bytes) {
+                            Ok(text) => Message::Text(text),
+                            Err(_) => {
+                                let reason = "Invalid UTF-8 in text message";
+                                write_frame(conn, &Frame::close(close_code::INVALID_DATA, reason)).ok();
+                                break 'session (close_code::INVALID_DATA, reason.to_string());
+                            }
+                        },
+                        (_, bytes) => Message::Binary(bytes),
+                    };
+                    on_event(WebSocketEvent::Message(message));
+                }
+                _ => {
+                    let reason = "Unknown opcode";
+                    write_frame(conn, &Frame::close(close_code::PROTOCOL_ERROR, reason)).ok();
+                    break 'session (close_code::PROTOCOL_ERROR, reason.to_string());
+                }
+            }
+        }
+
+        // 心跳
+        if closing.is_none() && last_seen.elapsed() > PING_INTERVAL {
+            match ping_sent {
+                None => {
+                    if write_frame(conn, &Frame::new(OP_PING, Vec::new())).is_err() {
+                        break (close_code::ABNORMAL, "Write failed".to_string());
+                    }
+                    ping_sent = Some(Instant::now());
+                }
+                Some(sent) if sent.elapsed() > PING_INTERVAL => {
+                    break (close_code::ABNORMAL, "Ping timeout".to_string());
+                }
+                Some(_) => {}
+            }
+        }
+    };
+
+    on_event(WebSocketEvent::Close { code, reason });
+    conn.close();
+}
+
+fn write_frame(conn: &mut Connection, frame: &Frame) -> std::io::Result<()> {
+    conn.write_all(&frame.encode())?;
+    conn.flush()
+}
+
+/// WebSocket ID，进程内唯一（多个 Worker 运行时共享同一个主线程）
+static NEXT_SOCKET_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 已接受的连接的发送端
+enum SocketSink {
+    /// 已 `accept()`，但握手尚未完成，先缓存消息
+    Pending(Vec<Outgoing>),
+    /// 已绑定到连接线程
+    Attached(Sender<Outgoing>),
+}
+
+// JS 只在主线程执行，连接表也只在主线程访问
+thread_local! {
+    static SOCKETS: RefCell<HashMap<u64, SocketSink>> = RefCell::new(HashMap::new());
+}
+
+/// 把已接受的 WebSocket 绑定到连接线程，并发出握手前缓存的消息
+pub fn attach(id: u64, sender: Sender<Outgoing>) {
+    SOCKETS.with(|sockets| {
+        let mut sockets = sockets.borrow_mut();
+        if let Some(SocketSink::Pending(queued)) = sockets.remove(&id) {
+            for command in queued {
+                sender.send(command).ok();
+            }
+        }
+        sockets.insert(id, SocketSink::Attached(sender));
+    });
+}
+
+/// 移除连接（连接关闭后调用）
+pub fn detach(id: u64) {
+    SOCKETS.with(|sockets| {
+        sockets.borrow_mut().remove(&id);
+    });
+}
+
+/// 由服务器主动关闭连接（如服务器关闭或 Worker 被重新加载）
+///
+/// 连接线程发出关闭帧后仍会上报 `Close` 事件
+pub fn close(id: u64, code: u16, reason: &str) {
+    send_outgoing(
+        id,
+        Outgoing::Close {
+            code,
+            reason: reason.to_string(),
+        },
+    )
+    .ok();
+}
+
+/// 丢弃未随响应返回的待握手连接（`keep` 为本次响应携带的连接）
+pub fn discard_pending(keep: Option<u64>) {
+    SOCKETS.with(|sockets| {
+        sockets.borrow_mut().retain(|id, sink| {
+            Some(*id) == keep || matches!(sink, SocketSink::Attached(_))
+        });
+    });
+}
+
+/// 发送指令给连接
+fn send_outgoing(id: u64, command: Outgoing) -> Result<(), String> {
+    SOCKETS.with(|sockets| {
+        let mut sockets = sockets.borrow_mut();
+        match sockets.get_mut(&id) {
+            Some(SocketSink::Pending(queued)) => {
+                queued.push(command);
+                Ok(())
+            }
+            Some(SocketSink::Attached(sender)) => sender.send(command).map_err(|_| {
+                sockets.remove(&id);
+                "WebSocket is closed".to_string()
+            }),
+            None => Err("WebSocket is not accepted or already closed".to_string()),
+        }
+    })
+}
+
+/// JS 侧的 WebSocket / WebSocketPair 实现
+const WEBSOCKET_JS: &str = r#"
+(function() {
+    var sockets = {};
+
+    class WebSocket {
+        constructor(id) {
+            this._id = id;
+            this._listeners = {};
+            this._accepted = false;
+            this.readyState = WebSocket.OPEN;
+        }
+
+        accept() {
+            if (this._accepted) {
+                throw new Error("WebSocket has already been accepted");
+            }
+            this._accepted = true;
+            sockets[this._id] = this;
+            __raven_ws_accept(this._id);
+        }
+
+        addEventListener(type, listener) {
+            (this._listeners[type] = this._listeners[type] || []).push(listener);
+        }
+
+        removeEventListener(type, listener) {
+            var listeners = this._listeners[type] || [];
+            var index = listeners.indexOf(listener);
+            if (index !== -1) {
+                listeners.splice(index, 1);
+            }
+        }
+
+        send(data) {
+            if (!this._accepted) {
+                throw new Error("WebSocket must be accepted before sending");
+            }
+            if (this.readyState !== WebSocket.OPEN) {
+                throw new Error("WebSocket is not open");
+            }
+            __raven_ws_send(this._id, data);
+        }
+
+        close(code, reason) {
+            if (this.readyState >= WebSocket.CLOSING) {
+                return;
+            }
+            __raven_ws_close(this._id, code === undefined ? 1000 : code, reason || "");
+            this.readyState = WebSocket.CLOSING;
+        }
+
+        _dispatch(event) {
+            var listeners = (this._listeners[event.type] || []).slice();
+            var handler = this["on" + event.type];
+            if (typeof handler === "function") {
+                listeners.push(handler);
+            }
+            var pending = [];
+            for (var i = 0; i < listeners.length; i++) {
+                pending.push(listeners[i].call(this, event));
+            }
+            return Promise.all(pending);
+        }
+    }
+
+    WebSocket.CONNECTING = 0;
+    WebSocket.OPEN = 1;
+    WebSocket.CLOSING = 2;
+    WebSocket.CLOSED = 3;
+
+    class WebSocketPair {
+        constructor() {
+            var id = __raven_ws_new_id();
+            this[0] = new WebSocket(id);
+            this[1] = new WebSocket(id);
+        }
+    }
+
+    globalThis.WebSocket = WebSocket;
+    globalThis.WebSocketPair = WebSocketPair;
+
+    globalThis.__raven_ws_dispatch = function(id, type, data, code, reason) {
+        var ws = sockets[id];
+        if (!ws) {
+            return undefined;
+        }
+        if (type === "message") {
+            return ws._dispatch({ type: "message", data: data });
+        }
+        delete sockets[id];
+        ws.readyState = WebSocket.CLOSED;
+        return ws._dispatch({
+            type: "close",
+            code: code,
+            reason: reason,
+            wasClean: code !== 1006
+        });
+    };
+})();
+"#;
+
+/// 注册 `WebSocketPair` 和 `WebSocket` 全局对象
+pub fn register_globals(context: &mut Context) -> Result<(), String> {
+    let natives: [(&str, usize, NativeFunction); 4] = [
+        (
+            "__raven_ws_new_id",
+            0,
+            NativeFunction::from_fn_ptr(|_, _, _| {
+                let id = NEXT_SOCKET_ID.fetch_add(1, Ordering::Relaxed);
+                Ok(JsValue::from(id as f64))
+            }),
+        ),
+        (
+            "__raven_ws_accept",
+            1,
+            NativeFunction::from_fn_ptr(|_, args, context| {
+                let id = socket_id(args, context)?;
+                SOCKETS.with(|sockets| {
+                    sockets
+                        .borrow_mut()
+                        .entry(id)
+                        .or_insert_with(|| SocketSink::Pending(Vec::new()));
+                });
+                Ok(JsValue::undefined())
+            }),
+        ),
+        (
+            "__raven_ws_send",
+            2,
+            NativeFunction::from_fn_ptr(|_, args, context| {
+                let id = socket_id(args, context)?;
+                let message = js_to_message(args.get_or_undefined(1), context)?;
+                send_outgoing(id, Outgoing::Message(message))
+                    .map_err(|e| JsNativeError::error().with_message(e))?;
+                Ok(JsValue::undefined())
+            }),
+        ),
+        (
+            "__raven_ws_close",
+            3,
+            NativeFunction::from_fn_ptr(|_, args, context| {
+                let id = socket_id(args, context)?;
+                let code = args.get_or_undefined(1).to_number(context)? as u16;
+                if code != close_code::NORMAL && !(3000..=4999).contains(&code) {
+                    return Err(JsNativeError::range()
+                        .with_message(format!("Invalid close code: {}", code))
+                        .into());
+                }
+                let reason = args
+                    .get_or_undefined(2)
+                    .to_string(context)?
+                    .to_std_string_escaped();
+                // 连接已断开时关闭是无操作
+                send_outgoing(id, Outgoing::Close { code, reason }).ok();
+                Ok(JsValue::undefined())
+            }),
+        ),
+    ];
+
+    for (name, length, function) in natives {
+        context
+            .register_global_builtin_callable(js_string!(name), length, function)
+            .map_err(|e| format!("Failed to register {}: {}", name, e))?;
+    }
+
+    context
+        .eval(Source::from_bytes(WEBSOCKET_JS))
+        .map_err(|e| format!("Failed to install WebSocket: {}", e))?;
+    Ok(())
+}
+
+/// 把事件分发给 JS 侧的 WebSocket，返回监听函数的返回值（可能是 Promise）
+pub fn dispatch(id: u64, event: &WebSocketEvent, context: &mut Context) -> Result<JsValue, String> {
+    let dispatch_fn = context
+        .global_object()
+        .get(js_string!("__raven_ws_dispatch"), context)
+        .map_err(|e| e.to_string())?;
+    let dispatch_fn = dispatch_fn
+        .as_callable()
+        .ok_or("WebSocket support is not installed")?
+        .clone();
+
+    let args = match event {
+        WebSocketEvent::Message(Message::Text(text)) => vec![
+            JsValue::from(id as f64),
+            JsValue::from(js_string!("message")),
+            JsValue::from(js_string!(text.as_str())),
+        ],
+        WebSocketEvent::Message(Message::Binary(data)) => {
+            let buffer = JsArrayBuffer::from_byte_block(AlignedVec::from_slice(0, data), context)
+                .map_err(|e| e.to_string())?;
+            vec![
+                JsValue::from(id as f64),
+                JsValue::from(js_string!("message")),
+                JsValue::from(buffer),
+            ]
+        }
+        WebSocketEvent::Close { code, reason } => vec![
+            JsValue::from(id as f64),
+            JsValue::from(js_string!("close")),
+            JsValue::undefined(),
+            JsValue::from(*code as i32),
+            JsValue::from(js_string!(reason.as_str())),
+        ],
+    };
+
+    dispatch_fn
+        .call(&JsValue::undefined(), &args, context)
+        .map_err(|e| format!("WebSocket handler error: {}", e))
+}
+
+/// 读取 JS `Response` 上 `webSocket` 字段对应的连接 ID
+pub fn response_socket_id(socket: &JsValue, context: &mut Context) -> Option<u64> {
+    let id = socket.as_object()?.get(js_string!("_id"), context).ok()?;
+    id.as_number().map(|n| n as u64)
+}
+
+fn socket_id(args: &[JsValue], context: &mut Context) -> JsResult<u64> {
+    Ok(args.get_or_undefined(0).to_number(context)? as u64)
+}
+
+/// 字符串作为文本消息，ArrayBuffer / TypedArray 作为二进制消息
+fn js_to_message(value: &JsValue, context: &mut Context) -> JsResult<Message> {
+    if let Some(s) = value.as_string() {
+        return Ok(Message::Text(s.to_std_string_escaped()));
+    }
+    if let Some(bytes) = js_to_bytes(value, context)? {
+        return Ok(Message::Binary(bytes));
+    }
+    Ok(Message::Text(value.to_string(context)?.to_std_string_escaped()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// 编码客户端帧（带掩码）
+    fn client_frame(opcode: u8, fin: bool, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut out = vec![if fin { 0x80 } else { 0 } | opcode];
+        if payload.len() < 126 {
+            out.push(0x80 | payload.len() as u8);
+        } else {
+            out.push(0x80 | 126);
+            out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        out.extend_from_slice(&mask);
+        out.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        out
+    }
+
+    /// 读取一个服务端帧：(opcode, payload)
+    fn read_server_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).unwrap();
+        let len = match header[1] & 0x7F {
+            126 => {
+                let mut ext = [0u8; 2];
+                stream.read_exact(&mut ext).unwrap();
+                u16::from_be_bytes(ext) as usize
+            }
+            n => n as usize,
+        };
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).unwrap();
+        (header[0] & 0x0F, payload)
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // RFC 6455 中的示例
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_upgrade_detection() {
+        let mut request = HttpRequest {
+            method: "GET".to_string(),
+            path: "/ws".to_string(),
+            ..Default::default()
+        };
+        assert!(!is_upgrade_request(&request));
+
+        request.headers.insert("upgrade".to_string(), "websocket".to_string());
+        request.headers.insert("connection".to_string(), "keep-alive, Upgrade".to_string());
+        request.headers.insert("sec-websocket-key".to_string(), "dGhlIHNhbXBsZSBub25jZQ==".to_string());
+        request.headers.insert("sec-websocket-version".to_string(), "13".to_string());
+        assert!(is_upgrade_request(&request));
+
+        let response = handshake_response(&request).unwrap();
+        assert_eq!(response.status, 101);
+        assert_eq!(
+            response.headers.get("sec-websocket-accept").map(String::as_str),
+            Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+        );
+    }
+
+    #[test]
+    fn test_frame_decoder() {
+        let mut decoder = FrameDecoder::new(1024);
+        let frame = client_frame(OP_TEXT, true, b"hello");
+
+        // 分两次到达
+        decoder.push(&frame[..3]);
+        assert_eq!(decoder.next_frame().unwrap(), None);
+        decoder.push(&frame[3..]);
+        let decoded = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(decoded.opcode, OP_TEXT);
+        assert_eq!(decoded.payload, b"hello");
+
+        let long = vec![7u8; 300];
+        decoder.push(&client_frame(OP_BINARY, true, &long));
+        assert_eq!(decoder.next_frame().unwrap().unwrap().payload, long);
+
+        // 未加掩码
+        decoder.push(&[0x81, 0x01, b'x']);
+        assert_eq!(
+            decoder.next_frame().unwrap_err().0,
+            close_code::PROTOCOL_ERROR
+        );
+
+        let mut small = FrameDecoder::new(4);
+        small.push(&client_frame(OP_TEXT, true, b"too long"));
+        assert_eq!(small.next_frame().unwrap_err().0, close_code::MESSAGE_TOO_BIG);
+    }
+
+    #[test]
+    fn test_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let (out_tx, out_rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+        let session = thread::spawn(move || {
+            let mut conn = Connection::Plain(stream);
+            run_session(&mut conn, out_rx, 1024, |event| {
+                event_tx.send(event).unwrap();
+            });
+        });
+
+        // 分片文本消息
+        client.write_all(&client_frame(OP_TEXT, false, b"hel")).unwrap();
+        client.write_all(&client_frame(OP_CONTINUATION, true, b"lo")).unwrap();
+        assert_eq!(
+            event_rx.recv().unwrap(),
+            WebSocketEvent::Message(Message::Text("hello".to_string()))
+        );
+
+        // ping 自动回应 pong
+        client.write_all(&client_frame(OP_PING, true, b"p")).unwrap();
+        assert_eq!(read_server_frame(&mut client), (OP_PONG, b"p".to_vec()));
+
+        out_tx
+            .send(Outgoing::Message(Message::Binary(vec![1, 2, 3])))
+            .unwrap();
+        assert_eq!(read_server_frame(&mut client), (OP_BINARY, vec![1, 2, 3]));
+
+        // 客户端发起关闭
+        let mut close = 4000u16.to_be_bytes().to_vec();
+        close.extend_from_slice(b"bye");
+        client.write_all(&client_frame(OP_CLOSE, true, &close)).unwrap();
+        assert_eq!(read_server_frame(&mut client).0, OP_CLOSE);
+        assert_eq!(
+            event_rx.recv().unwrap(),
+            WebSocketEvent::Close {
+                code: 4000,
+                reason: "bye".to_string()
+            }
+        );
+        session.join().unwrap();
+    }
+
+    #[test]
+    fn test_pending_messages_flushed_on_attach() {
+        let id = NEXT_SOCKET_ID.fetch_add(1, Ordering::Relaxed);
+        assert!(send_outgoing(id, Outgoing::Message(Message::Text("x".to_string()))).is_err());
+
+        SOCKETS.with(|s| s.borrow_mut().insert(id, SocketSink::Pending(Vec::new())));
+        send_outgoing(id, Outgoing::Message(Message::Text("early".to_string()))).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        attach(id, tx);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Outgoing::Message(Message::Text("early".to_string()))
+        );
+
+        drop(rx);
+        assert!(send_outgoing(id, Outgoing::Message(Message::Text("late".to_string()))).is_err());
+        detach(id);
+    }
+}
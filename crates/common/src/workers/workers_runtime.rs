@@ -3,15 +3,38 @@
 //! 基于核心 JsRuntime，添加 Cloudflare Workers 风格的 fetch() 入口支持
 
 use boa_engine::{
+    builtins::promise::PromiseState,
     class::{Class, ClassBuilder},
-    js_string, object::ObjectInitializer, property::Attribute, Context, JsArgs, JsData, JsObject,
-    JsString, JsValue, NativeFunction,
+    js_string,
+    object::{
+        builtins::{JsArray, JsPromise},
+        ObjectInitializer,
+    },
+    property::Attribute,
+    Context, JsArgs, JsData, JsNativeError, JsObject, JsString, JsValue, NativeFunction,
 };
 use boa_gc::{Finalize, Trace};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::Arc;
 
+use crate::runtime::bindings::NativeBinding;
+pub(super) use crate::runtime::js_to_bytes;
 use crate::runtime::JsRuntime;
-use super::http::{HttpRequest, HttpResponse};
+use super::assets;
+use super::bindings::{KvBinding, KvRegistry, Queue, QueueBinding, QueueMessage, QueueRegistry};
+use super::cache::{self, HttpCache};
+use super::config::{DurableObjectConfig, QueueProducerConfig, ServiceBindingConfig, WorkerConfig, WorkerLimits};
+use super::durable::{self, DurableStore};
+use super::form_data;
+use super::html_rewriter;
+use super::http::{parse_form, status_text, BodyStream, HttpRequest, HttpResponse, RequestUrl, StreamChunk};
+use super::proxy::TrustedProxies;
+use super::queue_consumer::{self, MessageOutcome};
+use super::service::{self, Invocation, ServiceRegistry};
+use super::websocket::{self, WebSocketEvent};
 
 /// JavaScript Response 类
 #[derive(Debug, Trace, Finalize, JsData)]
@@ -30,10 +53,14 @@ impl Class for JsResponseClass {
         args: &[JsValue],
         context: &mut Context,
     ) -> boa_engine::JsResult<Self> {
-        let body = args
-            .get_or_undefined(0)
-            .to_string(context)?
-            .to_std_string_escaped();
+        let body_arg = args.get_or_undefined(0);
+        let body = if body_arg.is_null_or_undefined() || html_rewriter::body_stream(body_arg).is_some() {
+            String::new()
+        } else if let Some(bytes) = js_to_bytes(body_arg, context)? {
+            String::from_utf8_lossy(&bytes).into_owned()
+        } else {
+            body_arg.to_string(context)?.to_std_string_escaped()
+        };
 
         let mut status = 200u16;
         let mut headers = HashMap::new();
@@ -107,12 +134,14 @@ impl Class for JsResponseClass {
     ) -> boa_engine::JsResult<()> {
         let data = Self::data_constructor(&JsValue::from(instance.clone()), args, context)?;
 
-        instance.set(
-            js_string!("body"),
-            JsValue::from(js_string!(data.body.clone())),
-            false,
-            context,
-        )?;
+        // 二进制 body 保留原始的 ArrayBuffer / TypedArray，流式 body 保留流本身
+        let body_arg = args.get_or_undefined(0);
+        let body = if html_rewriter::body_stream(body_arg).is_some() || js_to_bytes(body_arg, context)?.is_some() {
+            body_arg.clone()
+        } else {
+            JsValue::from(js_string!(data.body.clone()))
+        };
+        instance.set(js_string!("body"), body, false, context)?;
         instance.set(
             js_string!("status"),
             JsValue::from(data.status as i32),
@@ -139,6 +168,20 @@ impl Class for JsResponseClass {
         }
         instance.set(js_string!("headers"), headers_obj, false, context)?;
 
+        // 101 响应携带 WebSocketPair 的客户端一端
+        if let Some(init) = args.get_or_undefined(1).as_object() {
+            let socket = init.get(js_string!("webSocket"), context)?;
+            if !socket.is_null_or_undefined() {
+                instance.set(js_string!("webSocket"), socket, false, context)?;
+            }
+
+            // "manual" 表示 body 已由 Worker 编码，服务器不再压缩
+            let encode_body = init.get(js_string!("encodeBody"), context)?;
+            if !encode_body.is_null_or_undefined() {
+                instance.set(js_string!("encodeBody"), encode_body, false, context)?;
+            }
+        }
+
         Ok(())
     }
 }
@@ -148,6 +191,77 @@ impl Class for JsResponseClass {
 /// 基于核心 JsRuntime，添加 fetch() 入口支持
 pub struct WorkersRuntime {
     runtime: JsRuntime,
+    /// `env` 上的变量和密钥
+    env_vars: Vec<(String, String)>,
+    /// `env` 上的绑定名称
+    env_bindings: Vec<String>,
+    /// 静态资源绑定：(名称, 资源目录)
+    env_assets: Option<(String, String)>,
+    /// 缓存的 `env` 对象，配置变化时重建
+    env: Option<JsObject>,
+    /// `ctx.waitUntil()` 登记的尚未完成的 Promise
+    wait_until: JsArray,
+    /// `request.formData()` 中单个字段或文件的最大字节数
+    max_form_part_size: usize,
+    /// Worker 名称，用于 Service 调用链
+    name: String,
+    /// `env` 上的 Service 绑定
+    env_services: Vec<ServiceBindingConfig>,
+    /// Service 绑定可以调用的 Worker
+    services: ServiceRegistry,
+    /// 当前处理的请求的调用上下文（请求 ID、截止时间和调用链）
+    invocation: Rc<RefCell<Invocation>>,
+    /// `env` 上的队列 Producer
+    env_queues: Vec<QueueProducerConfig>,
+    /// `env` 上的 Durable Object 命名空间
+    env_durable_objects: Vec<DurableObjectConfig>,
+    /// 正在发给客户端的流式响应 body
+    streams: Vec<PendingStream>,
+}
+
+/// 流式响应 body 的连接线程一端缓冲的块数
+const STREAM_BUFFER: usize = 4;
+
+/// `pump_streams` 每次为每个流最多产生的块数，避免一个流占住主线程
+const STREAM_PUMP_CHUNKS: usize = 8;
+
+/// 正在发给客户端的流式 body
+struct PendingStream {
+    /// `transform()` 产生的流
+    source: JsObject,
+    sender: SyncSender<StreamChunk>,
+    /// 通道已满时暂存的一块
+    pending: Option<StreamChunk>,
+}
+
+impl PendingStream {
+    /// 产生并发送下一批块，返回流是否还没有结束
+    fn pump(&mut self, context: &mut Context) -> bool {
+        for _ in 0..STREAM_PUMP_CHUNKS {
+            let chunk = match self.pending.take() {
+                Some(chunk) => chunk,
+                None => match html_rewriter::read_chunk(&self.source, context) {
+                    Ok(Some(chunk)) if chunk.is_empty() => continue,
+                    Ok(Some(chunk)) => Ok(chunk),
+                    // 空块表示正常结束
+                    Ok(None) => Ok(Vec::new()),
+                    Err(e) => Err(format!("Response stream failed: {}", e)),
+                },
+            };
+            let last = !matches!(&chunk, Ok(chunk) if !chunk.is_empty());
+            match self.sender.try_send(chunk) {
+                Ok(()) if last => return false,
+                Ok(()) => {}
+                Err(TrySendError::Full(chunk)) => {
+                    self.pending = Some(chunk);
+                    return true;
+                }
+                // 客户端已断开
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        true
+    }
 }
 
 impl WorkersRuntime {
@@ -157,8 +271,150 @@ impl WorkersRuntime {
         
         // 注册 Response 类
         runtime.context.register_global_class::<JsResponseClass>().unwrap();
-        
-        Self { runtime }
+
+        // 注册 WebSocketPair
+        websocket::register_globals(&mut runtime.context).unwrap();
+
+        // 注册 FormData、Blob 和 File
+        form_data::register_globals(&mut runtime.context).unwrap();
+
+        // 注册 HTMLRewriter
+        html_rewriter::register_globals(&mut runtime.context).unwrap();
+
+        // 注册 queue() 入口使用的 MessageBatch
+        queue_consumer::register_globals(&mut runtime.context).unwrap();
+
+        // 注册 caches（独立运行时使用自己的内存缓存）
+        cache::register_globals(&mut runtime.context, HttpCache::memory()).unwrap();
+
+        // 注册 Durable Object（独立运行时使用自己的内存存储）
+        durable::register_globals(&mut runtime.context, DurableStore::memory()).unwrap();
+
+        let wait_until = JsArray::new(&mut runtime.context);
+
+        Self {
+            runtime,
+            env_vars: Vec::new(),
+            env_bindings: Vec::new(),
+            env_assets: None,
+            env: None,
+            wait_until,
+            max_form_part_size: WorkerLimits::default().max_form_part_size,
+            name: "default".to_string(),
+            env_services: Vec::new(),
+            services: ServiceRegistry::new(),
+            invocation: Rc::new(RefCell::new(Invocation::new())),
+            env_queues: Vec::new(),
+            env_durable_objects: Vec::new(),
+            streams: Vec::new(),
+        }
+    }
+
+    /// 按 Worker 配置设置资源限制、环境变量、密钥、KV 命名空间、队列 Producer 和 Durable Object
+    ///
+    /// 需要在 `load_worker` 之前调用，这样脚本中同名的 `import` 会复用这里注册的绑定
+    pub fn configure(&mut self, config: &WorkerConfig) -> Result<(), String> {
+        self.set_limits(&config.limits);
+
+        for (name, value) in &config.vars {
+            self.set_var(name, value);
+        }
+        for (name, secret) in &config.secrets {
+            self.set_var(name, secret.expose());
+        }
+
+        // 同一 ID 的命名空间在整个进程中共享一个存储
+        for ns in &config.kv_namespaces {
+            let store = KvRegistry::global().open(ns)?;
+            self.add_env_binding(Box::new(KvBinding::new(&ns.binding, Box::new(store))));
+        }
+
+        // 独立运行时使用自己的内存队列，`WorkerServer` 挂载时换成共享的队列
+        for producer in &config.queue_producers {
+            let queue = Arc::new(Queue::memory(&producer.queue));
+            self.add_env_binding(Box::new(QueueBinding::new(&producer.binding, queue)));
+        }
+        self.env_queues = config.queue_producers.clone();
+
+        if let Some(assets) = &config.assets {
+            self.set_assets(&assets.binding, &assets.directory);
+        }
+
+        self.name = config.name.clone();
+        self.env_services = config.services.clone();
+        self.env_durable_objects = config.durable_objects.clone();
+        self.env = None;
+
+        Ok(())
+    }
+
+    /// 在 `env` 上暴露静态资源绑定（`env.ASSETS.fetch(request)`）
+    pub fn set_assets(&mut self, binding: &str, directory: &str) {
+        self.env_assets = Some((binding.to_string(), directory.to_string()));
+        self.env = None;
+    }
+
+    /// 设置 `env` 上的变量
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        self.env_vars.retain(|(k, _)| k != name);
+        self.env_vars.push((name.to_string(), value.to_string()));
+        self.env = None;
+    }
+
+    /// 注册绑定并暴露在 `env` 上
+    pub fn add_env_binding(&mut self, binding: Box<dyn NativeBinding>) {
+        let name = binding.name().to_string();
+        self.runtime.register_binding(binding);
+        if !self.env_bindings.contains(&name) {
+            self.env_bindings.push(name);
+        }
+        self.env = None;
+    }
+
+    /// 应用资源限制
+    pub fn set_limits(&mut self, limits: &WorkerLimits) {
+        self.max_form_part_size = limits.max_form_part_size;
+        let runtime_limits = self.runtime.context.runtime_limits_mut();
+        if let Some(limit) = limits.loop_iteration_limit {
+            runtime_limits.set_loop_iteration_limit(limit);
+        }
+        if let Some(limit) = limits.recursion_limit {
+            runtime_limits.set_recursion_limit(limit);
+        }
+        if let Some(limit) = limits.stack_size_limit {
+            runtime_limits.set_stack_size_limit(limit);
+        }
+    }
+
+    /// 设置 Service 绑定可以调用的 Worker，`name` 为本 Worker 在调用链中的名称
+    pub fn set_services(&mut self, services: ServiceRegistry, name: &str) {
+        self.services = services;
+        self.name = name.to_string();
+        self.env = None;
+    }
+
+    /// 让队列 Producer 使用 `queues` 中的同名队列，`WorkerServer` 用它在 Worker 之间共享队列
+    pub fn set_queues(&mut self, queues: &QueueRegistry) -> Result<(), String> {
+        for producer in self.env_queues.clone() {
+            let queue = queues.get(&producer.queue)?;
+            self.add_env_binding(Box::new(QueueBinding::new(&producer.binding, queue)));
+        }
+        Ok(())
+    }
+
+    /// 设置 `caches` 使用的缓存，`WorkerServer` 用它让所有 Worker 共享同一个缓存
+    pub fn set_cache(&mut self, cache: HttpCache) -> Result<(), String> {
+        cache::set_backend(&mut self.runtime.context, cache)
+    }
+
+    /// 设置 Durable Object 使用的存储，`WorkerServer` 用它持久化所有 Worker 的实例
+    pub fn set_durable_store(&mut self, store: DurableStore) -> Result<(), String> {
+        durable::set_backend(&mut self.runtime.context, store)
+    }
+
+    /// 各绑定方法的调用次数：(绑定, 方法, 次数)
+    pub fn binding_calls(&self) -> Vec<(String, String, u64)> {
+        self.runtime.bindings().read().unwrap().call_counts()
     }
 
     /// 获取底层运行时的可变引用
@@ -167,8 +423,15 @@ impl WorkersRuntime {
     }
 
     /// 加载 Worker 脚本（使用 fetch 入口的包装）
+    ///
+    /// `export class Name` 导出的类（如 Durable Object）放在模块的同名属性上
     pub fn load_worker(&mut self, script: &str) -> Result<(), String> {
         self.runtime.load_script(script, |cleaned_script| {
+            let (cleaned_script, classes) = strip_class_exports(cleaned_script);
+            let class_exports: String = classes
+                .iter()
+                .map(|name| format!("\n                    exports.{0} = {0};", name))
+                .collect();
             format!(
                 r#"
                 var __worker_module__ = (function() {{
@@ -179,26 +442,305 @@ impl WorkersRuntime {
                         module.exports.default = obj;
                     }}
 
+                    {}
                     {}
 
                     return module.exports;
                 }})();
                 __worker_module__;
                 "#,
-                cleaned_script.replace("export default", "__export_default__(") + ")"
+                cleaned_script.replace("export default", "__export_default__(") + ")",
+                class_exports
             )
         })
     }
 
     /// 处理 HTTP 请求（调用 fetch 入口）
+    ///
+    /// `host` 为请求没有 `Host` 头时使用的主机名，不信任任何转发头
     pub fn handle_request(
         &mut self,
         request: &HttpRequest,
         host: &str,
     ) -> Result<HttpResponse, String> {
+        let url = request.url(host, &TrustedProxies::none());
+        self.handle_request_with_url(request, &url)
+    }
+
+    /// 处理 HTTP 请求，`request.url` 使用已解析的 `url`
+    ///
+    /// 流式 body（如 `HTMLRewriter.transform()` 的结果）会被完整读出
+    pub fn handle_request_with_url(
+        &mut self,
+        request: &HttpRequest,
+        url: &RequestUrl,
+    ) -> Result<HttpResponse, String> {
+        self.fetch(request, url, false)
+    }
+
+    /// 处理 HTTP 请求，流式 body 不读出，而是放在 `HttpResponse::stream` 中，
+    /// 由 `pump_streams` 逐块产生
+    pub fn handle_request_streaming(
+        &mut self,
+        request: &HttpRequest,
+        url: &RequestUrl,
+    ) -> Result<HttpResponse, String> {
+        self.fetch(request, url, true)
+    }
+
+    /// 为流式响应产生下一批数据，返回是否还有没有结束的流
+    ///
+    /// 客户端读得慢（通道已满）时暂停，客户端断开时丢弃对应的流
+    pub fn pump_streams(&mut self) -> bool {
+        if self.streams.is_empty() {
+            return false;
+        }
+        self.runtime.set_bindings_context();
+        let context = &mut self.runtime.context;
+        self.streams.retain_mut(|stream| stream.pump(context));
+        !self.streams.is_empty()
+    }
+
+    /// 是否有没有结束的流式响应
+    pub fn has_streams(&self) -> bool {
+        !self.streams.is_empty()
+    }
+
+    fn fetch(&mut self, request: &HttpRequest, url: &RequestUrl, streaming: bool) -> Result<HttpResponse, String> {
         // 设置当前线程的绑定注册表
         self.runtime.set_bindings_context();
 
+        let (worker_obj, fetch_fn) = self.get_handler("fetch")?;
+
+        // 构建 Request 对象
+        let js_request = self.create_js_request(request, &url.to_string())?;
+        *self.invocation.borrow_mut() = Invocation::from_request(request);
+
+        let env = self.create_env();
+        let ctx_obj = self.create_execution_context();
+
+        // 调用 fetch 函数
+        let result = fetch_fn
+            .call(
+                &JsValue::from(worker_obj),
+                &[
+                    JsValue::from(js_request),
+                    JsValue::from(env),
+                    JsValue::from(ctx_obj),
+                ],
+                &mut self.runtime.context,
+            )
+            .map_err(|e| format!("Failed to call fetch: {}", e))?;
+
+        let result = self.resolve_value(result)?;
+        let response = self.js_response_to_http(result, streaming);
+
+        // 本次请求中 accept() 但没有随响应返回的 WebSocket 不会再建立连接
+        websocket::discard_pending(response.as_ref().ok().and_then(|r| r.websocket));
+        response
+    }
+
+    /// 把 WebSocket 连接上的事件分发给 Worker 中对应的 WebSocket
+    pub fn handle_websocket_event(&mut self, id: u64, event: &WebSocketEvent) -> Result<(), String> {
+        self.runtime.set_bindings_context();
+
+        if let WebSocketEvent::Close { .. } = event {
+            websocket::detach(id);
+        }
+
+        let result = websocket::dispatch(id, event, &mut self.runtime.context)?;
+        self.resolve_value(result)?;
+        Ok(())
+    }
+
+    /// 推进 `ctx.waitUntil()` 登记的后台任务，移除已完成的任务
+    ///
+    /// 返回被拒绝的任务的错误信息
+    pub fn run_wait_until(&mut self) -> Vec<String> {
+        if self.pending_wait_until() == 0 {
+            return Vec::new();
+        }
+        self.runtime.set_bindings_context();
+
+        let mut errors = Vec::new();
+        let context = &mut self.runtime.context;
+        if let Err(e) = context.run_jobs() {
+            errors.push(format!("Failed to run jobs: {}", e));
+        }
+
+        let count = self.wait_until.length(context).unwrap_or(0);
+        for _ in 0..count {
+            let Ok(task) = self.wait_until.shift(context) else {
+                break;
+            };
+            match task.as_promise().map(|p| p.state()) {
+                Some(PromiseState::Pending) => {
+                    self.wait_until.push(task, context).ok();
+                }
+                Some(PromiseState::Rejected(e)) => {
+                    errors.push(format!("waitUntil task rejected: {}", e.display()));
+                }
+                _ => {}
+            }
+        }
+        errors
+    }
+
+    /// 把 KV 变更分发给 `KV.watch()` 登记的回调，返回回调抛出的错误
+    pub fn run_kv_watches(&mut self) -> Vec<String> {
+        if !self.has_kv_watches() {
+            return Vec::new();
+        }
+        self.runtime.set_bindings_context();
+
+        let context = &mut self.runtime.context;
+        let global = context.global_object();
+        let dispatched = global
+            .get(js_string!("__raven_kv_watch"), context)
+            .and_then(|watch| {
+                let dispatch = watch.as_object().map(|w| w.get(js_string!("dispatch"), context)).transpose()?;
+                match dispatch.as_ref().and_then(|d| d.as_callable()) {
+                    Some(dispatch) => dispatch.call(&watch, &[], context),
+                    None => Ok(JsValue::undefined()),
+                }
+            });
+
+        let mut errors = match dispatched {
+            Ok(result) => match result.as_object().and_then(|r| JsArray::from_object(r).ok()) {
+                Some(list) => {
+                    let len = list.length(context).unwrap_or(0);
+                    (0..len)
+                        .filter_map(|i| list.get(i, context).ok())
+                        .map(|e| e.display().to_string())
+                        .collect()
+                }
+                None => Vec::new(),
+            },
+            Err(e) => vec![format!("Failed to dispatch KV changes: {}", e)],
+        };
+        // 推进异步回调
+        if let Err(e) = context.run_jobs() {
+            errors.push(format!("Failed to run jobs: {}", e));
+        }
+        errors
+    }
+
+    /// 是否有 `KV.watch()` 登记的回调
+    pub fn has_kv_watches(&mut self) -> bool {
+        let context = &mut self.runtime.context;
+        let global = context.global_object();
+        let Ok(watch) = global.get(js_string!("__raven_kv_watch"), context) else {
+            return false;
+        };
+        watch
+            .as_object()
+            .and_then(|w| w.get(js_string!("callbacks"), context).ok())
+            .and_then(|callbacks| callbacks.as_object())
+            .and_then(|callbacks| callbacks.get(js_string!("size"), context).ok())
+            .and_then(|size| size.as_number())
+            .is_some_and(|size| size > 0.0)
+    }
+
+    /// 尚未完成的 `waitUntil` 任务数
+    pub fn pending_wait_until(&mut self) -> usize {
+        self.wait_until
+            .length(&mut self.runtime.context)
+            .unwrap_or(0) as usize
+    }
+
+    /// 刷新所有绑定（如把 KV 写入落盘）
+    pub fn flush_bindings(&self) -> Result<(), String> {
+        self.runtime
+            .bindings()
+            .read()
+            .map_err(|e| e.to_string())?
+            .flush()
+    }
+
+    /// 检查 Worker 是否导出了指定的处理函数（如 `fetch`、`scheduled`）
+    pub fn has_handler(&mut self, name: &str) -> bool {
+        self.get_handler(name).is_ok()
+    }
+
+    /// 处理 Cron 触发（调用 scheduled 入口）
+    ///
+    /// `scheduled_time` 为计划执行时间的毫秒时间戳
+    pub fn handle_scheduled(&mut self, cron: &str, scheduled_time: i64) -> Result<(), String> {
+        self.runtime.set_bindings_context();
+        *self.invocation.borrow_mut() = Invocation::new();
+
+        let (worker_obj, scheduled_fn) = self.get_handler("scheduled")?;
+
+        let no_retry_fn = NativeFunction::from_fn_ptr(|_, _, _| Ok(JsValue::undefined()));
+        let event = ObjectInitializer::new(&mut self.runtime.context)
+            .property(js_string!("type"), js_string!("scheduled"), Attribute::all())
+            .property(js_string!("cron"), js_string!(cron), Attribute::all())
+            .property(
+                js_string!("scheduledTime"),
+                JsValue::from(scheduled_time as f64),
+                Attribute::all(),
+            )
+            .function(no_retry_fn, js_string!("noRetry"), 0)
+            .build();
+
+        let env = self.create_env();
+        let ctx_obj = self.create_execution_context();
+
+        let result = scheduled_fn
+            .call(
+                &JsValue::from(worker_obj),
+                &[JsValue::from(event), JsValue::from(env), JsValue::from(ctx_obj)],
+                &mut self.runtime.context,
+            )
+            .map_err(|e| format!("Failed to call scheduled: {}", e))?;
+
+        self.resolve_value(result)?;
+        Ok(())
+    }
+
+    /// 把一批队列消息交给 `queue` 入口
+    ///
+    /// 返回 Worker 对每条消息显式指定的处理结果，以及处理函数本身是否成功
+    pub fn handle_queue(
+        &mut self,
+        queue: &str,
+        messages: &[QueueMessage],
+    ) -> (Vec<Option<MessageOutcome>>, Result<(), String>) {
+        self.runtime.set_bindings_context();
+        *self.invocation.borrow_mut() = Invocation::new();
+
+        let batch = match queue_consumer::create_batch(queue, messages, &mut self.runtime.context) {
+            Ok(batch) => batch,
+            Err(e) => return (Vec::new(), Err(format!("Failed to create batch: {}", e))),
+        };
+        let result = self.call_queue(&batch);
+        match queue_consumer::read_outcomes(&batch, &mut self.runtime.context) {
+            Ok(outcomes) => (outcomes, result),
+            // 处理结果不合法时整批按失败重试，不能默认确认
+            Err(e) => (Vec::new(), result.and(Err(format!("Invalid queue outcome: {}", e)))),
+        }
+    }
+
+    fn call_queue(&mut self, batch: &JsValue) -> Result<(), String> {
+        let (worker_obj, queue_fn) = self.get_handler("queue")?;
+
+        let env = self.create_env();
+        let ctx_obj = self.create_execution_context();
+
+        let result = queue_fn
+            .call(
+                &JsValue::from(worker_obj),
+                &[batch.clone(), JsValue::from(env), JsValue::from(ctx_obj)],
+                &mut self.runtime.context,
+            )
+            .map_err(|e| format!("Failed to call queue: {}", e))?;
+
+        self.resolve_value(result)?;
+        Ok(())
+    }
+
+    /// 获取默认导出对象及其上的处理函数
+    fn get_handler(&mut self, name: &str) -> Result<(JsObject, JsObject), String> {
         let module = self
             .runtime
             .loaded_module
@@ -214,49 +756,154 @@ impl WorkersRuntime {
 
         let worker_obj = default_export
             .as_object()
-            .ok_or("Default export is not an object")?;
+            .ok_or("Default export is not an object")?
+            .clone();
 
-        let fetch_fn = worker_obj
-            .get(js_string!("fetch"), &mut self.runtime.context)
-            .map_err(|e| format!("Failed to get fetch function: {}", e))?;
+        let handler = worker_obj
+            .get(JsString::from(name), &mut self.runtime.context)
+            .map_err(|e| format!("Failed to get {} function: {}", name, e))?;
 
-        let fetch_callable = fetch_fn.as_callable().ok_or("fetch is not a function")?;
+        let handler = handler
+            .as_callable()
+            .ok_or_else(|| format!("{} is not a function", name))?
+            .clone();
 
-        // 构建 Request 对象
-        let js_request = self.create_js_request(request, host)?;
+        Ok((worker_obj, handler))
+    }
+
+    /// 构建 env 对象（变量、密钥和绑定）
+    fn create_env(&mut self) -> JsObject {
+        if let Some(env) = &self.env {
+            return env.clone();
+        }
 
-        // 构建 env 对象（空对象，保持兼容性）
         let env = ObjectInitializer::new(&mut self.runtime.context).build();
+        for (name, value) in &self.env_vars {
+            env.set(
+                JsString::from(name.as_str()),
+                JsValue::from(js_string!(value.as_str())),
+                false,
+                &mut self.runtime.context,
+            )
+            .ok();
+        }
 
-        // 构建 context 对象
-        let wait_until_fn = NativeFunction::from_fn_ptr(|_, _, _| Ok(JsValue::undefined()));
+        for name in self.env_bindings.clone() {
+            if let Some(binding_obj) = self.runtime.create_binding_object(&name) {
+                env.set(
+                    JsString::from(name.as_str()),
+                    binding_obj,
+                    false,
+                    &mut self.runtime.context,
+                )
+                .ok();
+            }
+        }
+
+        if let Some((name, directory)) = &self.env_assets {
+            let binding_obj = assets::create_binding(directory, &mut self.runtime.context);
+            env.set(
+                JsString::from(name.as_str()),
+                binding_obj,
+                false,
+                &mut self.runtime.context,
+            )
+            .ok();
+        }
+
+        if !self.env_durable_objects.is_empty() {
+            let exports = self.runtime.loaded_module.as_ref().and_then(|m| m.as_object());
+            for config in self.env_durable_objects.clone() {
+                let class = exports.as_ref().and_then(|exports| {
+                    exports
+                        .get(JsString::from(config.class_name.as_str()), &mut self.runtime.context)
+                        .ok()
+                        .and_then(|class| class.as_constructor())
+                });
+                let Some(class) = class else {
+                    eprintln!(
+                        "⚠️  Durable Object class {} is not exported by worker {}",
+                        config.class_name, self.name
+                    );
+                    continue;
+                };
+                let ns = format!("{}/{}", self.name, config.class_name);
+                match durable::create_namespace(&ns, &class, &env, &mut self.runtime.context) {
+                    Ok(namespace) => {
+                        env.set(
+                            JsString::from(config.binding.as_str()),
+                            namespace,
+                            false,
+                            &mut self.runtime.context,
+                        )
+                        .ok();
+                    }
+                    Err(e) => eprintln!("⚠️  Failed to create Durable Object namespace {}: {}", ns, e),
+                }
+            }
+        }
+
+        for binding in &self.env_services {
+            let binding_obj = service::create_binding(
+                binding,
+                &self.services,
+                &self.name,
+                &self.invocation,
+                &mut self.runtime.context,
+            );
+            env.set(
+                JsString::from(binding.binding.as_str()),
+                binding_obj,
+                false,
+                &mut self.runtime.context,
+            )
+            .ok();
+        }
+
+        self.env = Some(env.clone());
+        env
+    }
+
+    /// 构建 context 对象
+    fn create_execution_context(&mut self) -> JsObject {
+        let wait_until_fn = NativeFunction::from_copy_closure_with_captures(
+            |_, args, tasks: &JsArray, context| {
+                let task = args.get_or_undefined(0);
+                if task.as_promise().is_some() {
+                    tasks.push(task.clone(), context)?;
+                }
+                Ok(JsValue::undefined())
+            },
+            self.wait_until.clone(),
+        );
         let pass_through_fn = NativeFunction::from_fn_ptr(|_, _, _| Ok(JsValue::undefined()));
 
-        let ctx_obj = ObjectInitializer::new(&mut self.runtime.context)
+        ObjectInitializer::new(&mut self.runtime.context)
             .function(wait_until_fn, js_string!("waitUntil"), 1)
             .function(pass_through_fn, js_string!("passThroughOnException"), 0)
-            .build();
+            .build()
+    }
 
-        // 调用 fetch 函数
-        let result = fetch_callable
-            .call(
-                &JsValue::from(worker_obj.clone()),
-                &[
-                    JsValue::from(js_request),
-                    JsValue::from(env),
-                    JsValue::from(ctx_obj),
-                ],
-                &mut self.runtime.context,
-            )
-            .map_err(|e| format!("Failed to call fetch: {}", e))?;
+    /// 如果返回值是 Promise，执行任务队列直到其完成
+    fn resolve_value(&mut self, value: JsValue) -> Result<JsValue, String> {
+        let Some(promise) = value.as_promise() else {
+            return Ok(value);
+        };
 
-        self.js_response_to_http(result)
+        self.runtime
+            .context
+            .run_jobs()
+            .map_err(|e| format!("Failed to run jobs: {}", e))?;
+
+        match promise.state() {
+            PromiseState::Fulfilled(v) => Ok(v),
+            PromiseState::Rejected(e) => Err(format!("Promise rejected: {}", e.display())),
+            PromiseState::Pending => Err("Promise did not settle".to_string()),
+        }
     }
 
     /// 创建 JS Request 对象
-    fn create_js_request(&mut self, request: &HttpRequest, host: &str) -> Result<JsObject, String> {
-        let url = request.url(host);
-
+    fn create_js_request(&mut self, request: &HttpRequest, url: &str) -> Result<JsObject, String> {
         let headers_data = ObjectInitializer::new(&mut self.runtime.context).build();
         for (key, value) in &request.headers {
             headers_data
@@ -301,10 +948,27 @@ impl WorkersRuntime {
 
         let body_text = request.body_text().unwrap_or_default();
 
+        // formData() 按需解析原始 body，解析失败时返回被拒绝的 Promise
+        let content_type = request
+            .headers
+            .get("content-type")
+            .cloned()
+            .unwrap_or_default();
+        let form_data_fn = NativeFunction::from_copy_closure_with_captures(
+            |_, _, (content_type, body, max_part_size): &(String, Vec<u8>, usize), context| {
+                let promise = match parse_form(content_type, body, *max_part_size) {
+                    Ok(parts) => JsPromise::from_result(form_data::to_js(&parts, context), context),
+                    Err(e) => JsPromise::reject(JsNativeError::typ().with_message(e), context),
+                };
+                Ok(promise.into())
+            },
+            (content_type, request.body.clone(), self.max_form_part_size),
+        );
+
         let js_request = ObjectInitializer::new(&mut self.runtime.context)
             .property(
                 js_string!("url"),
-                JsValue::from(js_string!(url.as_str())),
+                JsValue::from(js_string!(url)),
                 Attribute::all(),
             )
             .property(
@@ -313,6 +977,7 @@ impl WorkersRuntime {
                 Attribute::all(),
             )
             .property(js_string!("headers"), headers, Attribute::all())
+            .function(form_data_fn, js_string!("formData"), 0)
             .property(
                 js_string!("body"),
                 if body_text.is_empty() {
@@ -328,7 +993,9 @@ impl WorkersRuntime {
     }
 
     /// 将 JS Response 转换为 HTTP Response
-    fn js_response_to_http(&mut self, js_response: JsValue) -> Result<HttpResponse, String> {
+    ///
+    /// `streaming` 为 `false` 时读出流式 body 的全部内容
+    fn js_response_to_http(&mut self, js_response: JsValue, streaming: bool) -> Result<HttpResponse, String> {
         let response_obj = js_response.as_object().ok_or("Response is not an object")?;
 
         let status = response_obj
@@ -338,19 +1005,41 @@ impl WorkersRuntime {
             .map(|n| n as u16)
             .unwrap_or(200);
 
-        let body = response_obj
+        let body_value = response_obj
             .get(js_string!("body"), &mut self.runtime.context)
-            .ok()
-            .map(|v| {
+            .ok();
+        let mut stream = body_value.as_ref().and_then(html_rewriter::body_stream);
+        let body = match (&stream, body_value) {
+            (Some(source), _) if !streaming => {
+                let body = html_rewriter::read_all(source, &mut self.runtime.context)
+                    .map_err(|e| format!("Failed to read response body: {}", e))?;
+                stream = None;
+                body
+            }
+            (Some(_), _) | (None, None) => Vec::new(),
+            (None, Some(v)) => {
                 if v.is_null_or_undefined() {
-                    String::new()
+                    Vec::new()
                 } else if let Some(s) = v.as_string() {
-                    s.to_std_string_escaped()
+                    s.to_std_string_escaped().into_bytes()
+                } else if let Ok(Some(bytes)) = js_to_bytes(&v, &mut self.runtime.context) {
+                    bytes
                 } else {
-                    v.display().to_string()
+                    v.display().to_string().into_bytes()
                 }
-            })
-            .unwrap_or_default();
+            }
+        };
+
+        let socket = response_obj
+            .get(js_string!("webSocket"), &mut self.runtime.context)
+            .ok()
+            .and_then(|v| websocket::response_socket_id(&v, &mut self.runtime.context));
+
+        let manual_encoding = response_obj
+            .get(js_string!("encodeBody"), &mut self.runtime.context)
+            .ok()
+            .and_then(|v| v.as_string().map(|s| s.to_std_string_escaped()))
+            .is_some_and(|v| v == "manual");
 
         let mut headers = HashMap::new();
         if let Ok(js_headers) = response_obj.get(js_string!("headers"), &mut self.runtime.context) {
@@ -359,7 +1048,10 @@ impl WorkersRuntime {
                     for key in keys {
                         if let Ok(value) = headers_obj.get(key.clone(), &mut self.runtime.context) {
                             let key_str = key.to_string();
-                            if !key_str.starts_with('_') {
+                            // 分帧由服务器决定（按实际长度或分块发送），忽略 Worker 设置的分帧头
+                            let framing = key_str.eq_ignore_ascii_case("transfer-encoding")
+                                || key_str.eq_ignore_ascii_case("content-length");
+                            if !key_str.starts_with('_') && !framing {
                                 let value_str = if let Some(s) = value.as_string() {
                                     s.to_std_string_escaped()
                                 } else {
@@ -373,34 +1065,59 @@ impl WorkersRuntime {
             }
         }
 
-        let status_text = match status {
-            200 => "OK",
-            201 => "Created",
-            204 => "No Content",
-            301 => "Moved Permanently",
-            302 => "Found",
-            400 => "Bad Request",
-            401 => "Unauthorized",
-            403 => "Forbidden",
-            404 => "Not Found",
-            500 => "Internal Server Error",
-            _ => "Unknown",
-        };
-
-        let mut response = HttpResponse::new(status, status_text);
-        response.body = body.as_bytes().to_vec();
-        response.headers.insert(
-            "content-length".to_string(),
-            response.body.len().to_string(),
-        );
+        let mut response = HttpResponse::new(status, status_text(status));
+        response.body = body;
+        match stream {
+            Some(source) => {
+                let (sender, body) = BodyStream::channel(STREAM_BUFFER);
+                self.streams.push(PendingStream {
+                    source,
+                    sender,
+                    pending: None,
+                });
+                response.stream = Some(body);
+            }
+            None => {
+                response.headers.insert(
+                    "content-length".to_string(),
+                    response.body.len().to_string(),
+                );
+            }
+        }
         for (k, v) in headers {
             response.headers.insert(k, v);
         }
+        response.websocket = socket;
+        response.auto_encode = !manual_encoding;
 
         Ok(response)
     }
 }
 
+/// 把 `export class Name` 改为普通的类声明，返回改写后的脚本和导出的类名
+fn strip_class_exports(script: &str) -> (String, Vec<String>) {
+    let mut classes = Vec::new();
+    let lines: Vec<String> = script
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            let Some(rest) = trimmed.strip_prefix("export class ") else {
+                return line.to_string();
+            };
+            let name: String = rest
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+                .collect();
+            if name.is_empty() {
+                return line.to_string();
+            }
+            classes.push(name);
+            format!("{}{}", &line[..line.len() - trimmed.len()], &trimmed["export ".len()..])
+        })
+        .collect();
+    (lines.join("\n"), classes)
+}
+
 impl Default for WorkersRuntime {
     fn default() -> Self {
         Self::new()